@@ -0,0 +1,70 @@
+#!/usr/bin/python
+"""
+Convert genomes between JSON and the packed binary format, see "src/genome_codec.rs".
+
+The rust version of this controller accepts genomes in either format. Packed
+genomes are base64 strings, so they can be used anywhere that a JSON genome
+can be used: in seed files and in the mutate and crossover functions.
+
+Usage: pack_genome.py [--unpack] GENOME_FILE
+"""
+
+import base64
+import json
+import struct
+import sys
+
+_magic   = b"NPCG"
+_version = 1
+_header  = struct.Struct("<4sHHII")
+_node    = struct.Struct("<QfffB")
+_edge    = struct.Struct("<QQf")
+_activations = ["logistic", "tanh", "relu", "linear", "gaussian", "step"]
+
+def pack(genome):
+    """ Convert a JSON genome into a packed genome string. """
+    nodes = [gene for gene in genome if gene["type"] == "Node"]
+    edges = [gene for gene in genome if gene["type"] == "Edge"]
+    data  = [_header.pack(_magic, _version, 0, len(nodes), len(edges))]
+    for node in nodes:
+        data.append(_node.pack(int(node["name"]),
+                               float(node.get("slope", 1.0)),
+                               float(node.get("midpoint", 0.0)),
+                               float(node.get("time_constant", 0.0)),
+                               _activations.index(node.get("activation", "logistic"))))
+    for edge in edges:
+        data.append(_edge.pack(int(edge["presyn"]), int(edge["postsyn"]), float(edge["weight"])))
+    return base64.b64encode(b"".join(data)).decode("ascii")
+
+def unpack(genome):
+    """ Convert a packed genome string into a JSON genome. """
+    data = base64.b64decode(genome, validate=True)
+    magic, version, _, num_nodes, num_edges = _header.unpack_from(data)
+    if magic != _magic or version != _version:
+        raise ValueError("genome is not in the packed format")
+    if len(data) != _header.size + num_nodes * _node.size + num_edges * _edge.size:
+        raise ValueError("genome has the wrong length")
+    genome = []
+    offset = _header.size
+    for _ in range(num_nodes):
+        name, slope, midpoint, time_constant, activation = _node.unpack_from(data, offset)
+        genome.append({"name": name, "type": "Node", "slope": slope, "midpoint": midpoint,
+                       "time_constant": time_constant, "activation": _activations[activation]})
+        offset += _node.size
+    for _ in range(num_edges):
+        presyn, postsyn, weight = _edge.unpack_from(data, offset)
+        genome.append({"type": "Edge", "presyn": presyn, "postsyn": postsyn, "weight": weight})
+        offset += _edge.size
+    return genome
+
+if __name__ == "__main__":
+    args = sys.argv[1:]
+    if len(args) == 2 and args[0] == "--unpack":
+        with open(args[1], 'rt') as file:
+            print(json.dumps(unpack(json.load(file)), indent=4))
+    elif len(args) == 1:
+        with open(args[0], 'rt') as file:
+            print(json.dumps(pack(json.load(file))))
+    else:
+        print(__doc__.strip(), file=sys.stderr)
+        sys.exit(1)
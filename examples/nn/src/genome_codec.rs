@@ -0,0 +1,309 @@
+//! Compact binary format for neural network genomes.
+//!
+//! JSON genomes are easy to inspect and modify, but they are slow to send and
+//! parse for large networks. This format packs the nodes and edges of a
+//! network into fixed-width little-endian records:
+//!
+//! | Record | Size (bytes) | Fields |
+//! |---|---|---|
+//! | Header | 16 | magic `"NPCG"`, version (u16), reserved (u16), number of nodes (u32), number of edges (u32) |
+//! | Node | 21 | name (u64), slope (f32), midpoint (f32), time constant (f32), activation (u8) |
+//! | Edge | 20 | presyn (u64), postsyn (u64), weight (f32) |
+//!
+//! The header is followed by all of the nodes and then all of the edges.
+//!
+//! Genotypes are sent to controllers as a single line of text, so packed
+//! genomes travel as a JSON string of base64 text, see [Genome::to_genotype()].
+//! This string can be the genome of an individual, and it passes unchanged
+//! through birth messages and `npc_maker::ctrl::Controller::new_genotype()`.
+//! This controller uses [Genome::from_genotype()] to unpack it.
+
+use serde::{Deserialize, Serialize};
+
+/// Leading bytes of every packed genome.
+pub const MAGIC: [u8; 4] = *b"NPCG";
+
+/// Version of the format which this module reads and writes.
+pub const VERSION: u16 = 1;
+
+pub const HEADER_SIZE: usize = 16;
+pub const NODE_SIZE: usize = 21;
+pub const EDGE_SIZE: usize = 20;
+
+/// Transfer function of a node.
+#[derive(Serialize, Deserialize, Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Activation {
+    #[default]
+    Logistic = 0,
+    Tanh = 1,
+    Relu = 2,
+    Linear = 3,
+    Gaussian = 4,
+    Step = 5,
+}
+
+impl Activation {
+    pub fn from_u8(code: u8) -> Option<Self> {
+        Some(match code {
+            0 => Self::Logistic,
+            1 => Self::Tanh,
+            2 => Self::Relu,
+            3 => Self::Linear,
+            4 => Self::Gaussian,
+            5 => Self::Step,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Node {
+    pub name: u64,
+    pub slope: f32,
+    pub midpoint: f32,
+    pub time_constant: f32,
+    pub activation: Activation,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Edge {
+    pub presyn: u64,
+    pub postsyn: u64,
+    pub weight: f32,
+}
+
+/// Neural network genome, which can be packed into the binary format.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Genome {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+impl Genome {
+    /// Number of bytes in the packed format.
+    pub fn encoded_len(&self) -> usize {
+        HEADER_SIZE + NODE_SIZE * self.nodes.len() + EDGE_SIZE * self.edges.len()
+    }
+
+    /// Pack this genome into the binary format.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.encoded_len());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        bytes.extend_from_slice(&(self.nodes.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.edges.len() as u32).to_le_bytes());
+        for node in &self.nodes {
+            bytes.extend_from_slice(&node.name.to_le_bytes());
+            bytes.extend_from_slice(&node.slope.to_le_bytes());
+            bytes.extend_from_slice(&node.midpoint.to_le_bytes());
+            bytes.extend_from_slice(&node.time_constant.to_le_bytes());
+            bytes.push(node.activation as u8);
+        }
+        for edge in &self.edges {
+            bytes.extend_from_slice(&edge.presyn.to_le_bytes());
+            bytes.extend_from_slice(&edge.postsyn.to_le_bytes());
+            bytes.extend_from_slice(&edge.weight.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Unpack a genome from the binary format.
+    pub fn decode(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader(bytes);
+        let header = reader.take(HEADER_SIZE).ok_or("genome is too short")?;
+        if header[0..4] != MAGIC {
+            return Err("genome is not in the packed format".to_string());
+        }
+        let version = u16::from_le_bytes(header[4..6].try_into().unwrap());
+        if version != VERSION {
+            return Err(format!("unsupported genome format version {version}"));
+        }
+        let num_nodes = u32::from_le_bytes(header[8..12].try_into().unwrap()) as usize;
+        let num_edges = u32::from_le_bytes(header[12..16].try_into().unwrap()) as usize;
+        let expected = HEADER_SIZE as u64
+            + NODE_SIZE as u64 * num_nodes as u64
+            + EDGE_SIZE as u64 * num_edges as u64;
+        if bytes.len() as u64 != expected {
+            return Err(format!(
+                "genome has {} bytes, expected {expected}",
+                bytes.len()
+            ));
+        }
+        let mut genome = Self {
+            nodes: Vec::with_capacity(num_nodes),
+            edges: Vec::with_capacity(num_edges),
+        };
+        for _ in 0..num_nodes {
+            let name = reader.u64();
+            let slope = reader.f32();
+            let midpoint = reader.f32();
+            let time_constant = reader.f32();
+            let code = reader.take(1).unwrap()[0];
+            let activation = Activation::from_u8(code)
+                .ok_or(format!("node {name} has unknown activation {code}"))?;
+            genome.nodes.push(Node {
+                name,
+                slope,
+                midpoint,
+                time_constant,
+                activation,
+            });
+        }
+        for _ in 0..num_edges {
+            genome.edges.push(Edge {
+                presyn: reader.u64(),
+                postsyn: reader.u64(),
+                weight: reader.f32(),
+            });
+        }
+        Ok(genome)
+    }
+
+    /// Pack this genome into a JSON string, for sending to controllers.
+    pub fn to_genotype(&self) -> String {
+        format!("\"{}\"", encode_base64(&self.encode()))
+    }
+
+    /// Unpack a genome from a JSON string, see [Genome::to_genotype()].
+    pub fn from_genotype(genotype: &str) -> Result<Self, String> {
+        let text: String = serde_json::from_str(genotype).map_err(|error| error.to_string())?;
+        Self::decode(&decode_base64(&text)?)
+    }
+}
+
+/// Sequential reads from a buffer whose length was already checked.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Some(head)
+    }
+
+    fn u64(&mut self) -> u64 {
+        u64::from_le_bytes(self.take(8).unwrap().try_into().unwrap())
+    }
+
+    fn f32(&mut self) -> f32 {
+        f32::from_le_bytes(self.take(4).unwrap().try_into().unwrap())
+    }
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encode bytes as base64 text, using the standard alphabet with padding.
+pub fn encode_base64(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let block = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | (*chunk.get(2).unwrap_or(&0) as u32);
+        for index in 0..4 {
+            if index <= chunk.len() {
+                text.push(BASE64[(block >> (18 - 6 * index) & 63) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+/// Decode base64 text, see [encode_base64()].
+pub fn decode_base64(text: &str) -> Result<Vec<u8>, String> {
+    let text = text.trim().as_bytes();
+    if text.len() % 4 != 0 {
+        return Err("base64 text has an invalid length".to_string());
+    }
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    for (index, chunk) in text.chunks(4).enumerate() {
+        let last = index + 1 == text.len() / 4;
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return Err("base64 text has invalid padding".to_string());
+        }
+        let mut block = 0u32;
+        for &c in &chunk[..4 - padding] {
+            let value = BASE64
+                .iter()
+                .position(|&x| x == c)
+                .ok_or(format!("invalid base64 character {:?}", c as char))?;
+            block = block << 6 | value as u32;
+        }
+        block <<= 6 * padding;
+        bytes.extend_from_slice(&block.to_be_bytes()[1..4 - padding]);
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64() {
+        for (bytes, text) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(encode_base64(bytes.as_bytes()), text);
+            assert_eq!(decode_base64(text).unwrap(), bytes.as_bytes());
+        }
+        assert!(decode_base64("Zm9").is_err());
+        assert!(decode_base64("Zg==Zm9v").is_err());
+        assert!(decode_base64("Zm9*").is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let genome = Genome {
+            nodes: vec![
+                Node {
+                    name: 0,
+                    slope: 1.0,
+                    midpoint: 0.0,
+                    time_constant: 0.0,
+                    activation: Activation::Logistic,
+                },
+                Node {
+                    name: 1000,
+                    slope: 2.5,
+                    midpoint: -0.5,
+                    time_constant: 0.1,
+                    activation: Activation::Tanh,
+                },
+            ],
+            edges: vec![Edge {
+                presyn: 0,
+                postsyn: 1000,
+                weight: -1.25,
+            }],
+        };
+        let bytes = genome.encode();
+        assert_eq!(bytes.len(), genome.encoded_len());
+        assert_eq!(Genome::decode(&bytes).unwrap(), genome);
+        let genotype = genome.to_genotype();
+        assert!(genotype.starts_with('"') && !genotype.contains('\n'));
+        assert_eq!(Genome::from_genotype(&genotype).unwrap(), genome);
+        // Corrupt genomes are rejected.
+        assert!(Genome::decode(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Genome::decode(&bytes[..10]).is_err());
+        let mut bad_activation = bytes.clone();
+        bad_activation[HEADER_SIZE + NODE_SIZE - 1] = 99;
+        assert!(Genome::decode(&bad_activation).is_err());
+        assert!(Genome::from_genotype("[]").is_err());
+        assert_eq!(
+            Genome::decode(&Genome::default().encode()).unwrap(),
+            Genome::default()
+        );
+    }
+}
@@ -1,8 +1,28 @@
 //! Example controller - artificial neural network
 //!
 //! This file demonstrates how to implement a control system for the NPC Maker.
+//!
+//! The network is a continuous time recurrent neural network. All of the nodes
+//! are updated at the same time using the previous state of the network, so the
+//! edges may form cycles and loops. Each node has its own activation function
+//! and time constant. Nodes with a time constant of zero respond immediately,
+//! otherwise they move towards their activation at a rate of `dt / time_constant`.
+//!
+//! The node names are the GINs of the environment's interfaces. Interfaces
+//! without a node are ignored, and their outputs are zero.
+//!
+//! Binary inputs (set_binary) are arrays of little-endian 64-bit floats, which
+//! are written to consecutive GINs starting at the given GIN.
+//!
+//! Genotypes are either JSON arrays of nodes and edges, or the packed binary
+//! format from "genome_codec.rs" which is much faster for large networks.
 
-use ctrl_api::{poll, Message};
+// The encoder is only used by the tests, the genomes are packed by "pack_genome.py".
+#[allow(dead_code)]
+mod genome_codec;
+
+use genome_codec::Activation;
+use npc_maker::ctrl::{main_loop, Capability, API};
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::io;
@@ -13,129 +33,208 @@ pub fn logistic(value: f64, slope: f64, midpoint: f64) -> f64 {
     1.0 / (1.0 + (-x).exp())
 }
 
+fn activate(activation: Activation, value: f64, slope: f64, midpoint: f64) -> f64 {
+    let x = slope * (value - midpoint);
+    match activation {
+        Activation::Logistic => logistic(value, slope, midpoint),
+        Activation::Tanh => x.tanh(),
+        Activation::Relu => x.max(0.0),
+        Activation::Linear => x,
+        Activation::Gaussian => (-x * x).exp(),
+        Activation::Step => (x >= 0.0) as u8 as f64,
+    }
+}
+
+fn default_slope() -> f64 {
+    1.0
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(tag = "type")]
 enum Chromosome {
     Node {
         name: u64,
+        #[serde(default)]
         midpoint: f64,
+        #[serde(default = "default_slope")]
         slope: f64,
+        #[serde(default)]
+        time_constant: f64,
+        #[serde(default)]
+        activation: Activation,
     },
 
     Edge {
-        #[serde(default)]
-        name: u64,
         presyn: u64,
         postsyn: u64,
         weight: f64,
     },
 }
 
-impl Chromosome {
-    pub fn name(&self) -> u64 {
-        match self {
-            Self::Node { name, .. } => *name,
-            Self::Edge { name, .. } => *name,
-        }
-    }
+/// Convert a genome from the packed binary format.
+fn unpack(genome: genome_codec::Genome) -> Vec<Chromosome> {
+    let nodes = genome.nodes.into_iter().map(|node| Chromosome::Node {
+        name: node.name,
+        midpoint: node.midpoint.into(),
+        slope: node.slope.into(),
+        time_constant: node.time_constant.into(),
+        activation: node.activation,
+    });
+    let edges = genome.edges.into_iter().map(|edge| Chromosome::Edge {
+        presyn: edge.presyn,
+        postsyn: edge.postsyn,
+        weight: edge.weight.into(),
+    });
+    nodes.chain(edges).collect()
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Node {
+    activation: Activation,
+    slope: f64,
+    midpoint: f64,
+    time_constant: f64,
 }
 
 #[derive(Debug, Default)]
 struct NeuralNetwork {
     /// Maps name to index
-    names: HashMap<u64, u64>,
+    names: HashMap<u64, usize>,
 
-    nodes: Vec<(f64, f64)>,
+    nodes: Vec<Node>,
 
     state: Vec<f64>,
 
-    edges: Vec<(u64, u64, f64)>,
+    edges: Vec<(usize, usize, f64)>,
 }
 
 impl NeuralNetwork {
-    pub fn new(&mut self, genotype: &str) {
-        //
-        let mut genotype: Vec<Chromosome> = serde_json::from_str(genotype).unwrap();
-        genotype.sort_unstable_by_key(|x| x.name());
-        //
-        self.names = genotype
-            .iter()
-            .enumerate()
-            .filter_map(|(idx, chrom)| match chrom {
-                Chromosome::Node { .. } => Some((chrom.name(), idx as u64)),
-                Chromosome::Edge { .. } => None,
-            })
-            .collect();
-        //
-        self.nodes = genotype
-            .iter()
-            .filter_map(|chrom| match chrom {
-                Chromosome::Node {
-                    slope, midpoint, ..
-                } => Some((*slope, *midpoint)),
-                Chromosome::Edge { .. } => None,
-            })
-            .collect();
-        //
-        self.state = vec![0.0; self.nodes.len()];
-        //
-        self.edges = genotype
-            .iter()
-            .filter_map(|chrom| match chrom {
-                Chromosome::Node { .. } => None,
-                Chromosome::Edge {
-                    presyn,
-                    postsyn,
-                    weight,
-                    ..
-                } => Some((self.names[presyn], self.names[postsyn], *weight)),
-            })
-            .collect();
+    fn parse(genotype: &str) -> Result<Self, String> {
+        let genotype: Vec<Chromosome> = if genotype.trim_start().starts_with('"') {
+            unpack(genome_codec::Genome::from_genotype(genotype)?)
+        } else {
+            serde_json::from_str(genotype).map_err(|err| err.to_string())?
+        };
+        let mut nn = Self::default();
+        for chrom in &genotype {
+            if let Chromosome::Node {
+                name,
+                midpoint,
+                slope,
+                time_constant,
+                activation,
+            } = *chrom
+            {
+                if time_constant.is_nan() || time_constant < 0.0 {
+                    return Err(format!(
+                        "node {name} has invalid time constant {time_constant}"
+                    ));
+                }
+                if nn.names.insert(name, nn.nodes.len()).is_some() {
+                    return Err(format!("duplicate node {name}"));
+                }
+                nn.nodes.push(Node {
+                    activation,
+                    slope,
+                    midpoint,
+                    time_constant,
+                });
+            }
+        }
+        for chrom in &genotype {
+            if let Chromosome::Edge {
+                presyn,
+                postsyn,
+                weight,
+            } = *chrom
+            {
+                let index = |node| {
+                    nn.names
+                        .get(&node)
+                        .copied()
+                        .ok_or(format!("edge to missing node {node}"))
+                };
+                nn.edges.push((index(presyn)?, index(postsyn)?, weight));
+            }
+        }
+        nn.state = vec![0.0; nn.nodes.len()];
+        Ok(nn)
+    }
+
+    fn set_value(&mut self, gin: u64, value: f64) {
+        if let Some(&index) = self.names.get(&gin) {
+            self.state[index] = value;
+        }
     }
 
-    pub fn reset(&mut self) {
+    fn get_value(&self, gin: u64) -> f64 {
+        self.names.get(&gin).map_or(0.0, |&index| self.state[index])
+    }
+}
+
+impl API for NeuralNetwork {
+    fn new(&mut self, genotype: String) {
+        *self = Self::parse(&genotype).unwrap_or_else(|err| panic!("invalid genotype: {err}"));
+    }
+
+    fn validate(&mut self, genotype: &str) -> Result<(), String> {
+        Self::parse(genotype).map(|_| ())
+    }
+
+    fn reset(&mut self) {
         self.state.fill(0.0);
     }
 
-    pub fn advance(&mut self, _dt: f64) {
-        let mut next_state = vec![0.0; self.nodes.len()];
+    fn advance(&mut self, dt: f64) {
+        let mut inputs = vec![0.0; self.nodes.len()];
         for (presyn, postsyn, weight) in self.edges.iter().copied() {
-            next_state[postsyn as usize] += weight * self.state[presyn as usize];
+            inputs[postsyn] += weight * self.state[presyn];
         }
-        for ((slope, midpoint), value) in self.nodes.iter().copied().zip(&mut next_state) {
-            *value = logistic(*value, slope, midpoint);
+        for ((node, state), input) in self.nodes.iter().zip(&mut self.state).zip(inputs) {
+            let target = activate(node.activation, input, node.slope, node.midpoint);
+            if node.time_constant <= dt {
+                *state = target;
+            } else {
+                *state += (target - *state) * dt / node.time_constant;
+            }
         }
-        self.state = next_state;
     }
-}
 
-fn main() -> Result<(), io::Error> {
-    let mut nn = NeuralNetwork::default();
-    loop {
-        let message = poll()?;
-        match message {
-            Message::New { genotype } => {
-                nn.new(&genotype);
-            }
-            Message::Reset => {
-                nn.reset();
-            }
-            Message::SetInput { gin, value } => {
-                nn.state[gin as usize] = value.parse().unwrap();
-            }
-            Message::GetOutput { gin } => {
-                ctrl_api::send_output(gin, nn.state[gin as usize].to_string())?;
-            }
-            Message::Advance { dt } => {
-                nn.advance(dt);
-            }
+    fn set_input(&mut self, gin: u64, value: String) {
+        let value = self
+            .parse_f64(gin, &value)
+            .unwrap_or_else(|err| panic!("{err}"));
+        self.set_value(gin, value);
+    }
 
-            Message::Quit => break,
+    fn set_binary(&mut self, gin: u64, bytes: Vec<u8>) {
+        assert!(
+            bytes.len() % 8 == 0,
+            "binary input gin {gin} is not an array of 64-bit floats"
+        );
+        for (offset, value) in bytes.chunks_exact(8).enumerate() {
+            let value = f64::from_le_bytes(value.try_into().unwrap());
+            self.set_value(gin + offset as u64, value);
+        }
+    }
 
-            Message::Environment { .. } | Message::Population { .. } => {}
+    fn get_output(&mut self, gin: u64) -> String {
+        self.format_f64(self.get_value(gin))
+    }
 
-            _ => panic!("unsupported operation: {message:?}"),
-        }
+    fn set_input_f64(&mut self, gin: u64, value: f64) {
+        self.set_value(gin, value);
     }
-    Ok(())
+
+    fn get_output_f64(&mut self, gin: u64) -> f64 {
+        self.get_value(gin)
+    }
+
+    fn capabilities(&mut self) -> Vec<Capability> {
+        vec![Capability::SetBinary, Capability::Validate]
+    }
+}
+
+fn main() -> Result<(), io::Error> {
+    main_loop(NeuralNetwork::default())
 }
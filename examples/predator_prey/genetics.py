@@ -0,0 +1,55 @@
+"""
+Genetic operators for the genomes of the example neural network controller,
+see "examples/nn/nn.py".
+
+Genomes are lists of nodes and edges. Every gene has a unique integer name,
+and the names of the nodes are the GINs of the environment's interfaces.
+"""
+
+import copy
+import random
+
+weight_mutation = 0.2 # Standard deviation of the changes to the weights.
+add_node_rate   = 0.03
+add_edge_rate   = 0.05
+
+def _next_name(genome):
+    return max(gene["name"] for gene in genome) + 1
+
+def mutate(genome):
+    """ Perturb the weights, and occasionally add a hidden node or an edge. """
+    genome = copy.deepcopy(genome)
+    for gene in genome:
+        if gene["type"] == "Edge":
+            gene["weight"] += random.gauss(0.0, weight_mutation)
+    nodes = [gene["name"] for gene in genome if gene["type"] == "Node"]
+    edges = [gene for gene in genome if gene["type"] == "Edge"]
+    # Split an edge in two by inserting a hidden node in the middle of it.
+    if edges and random.random() < add_node_rate:
+        edge   = random.choice(edges)
+        hidden = _next_name(genome)
+        genome.append({"name": hidden, "type": "Node", "slope": 1.0, "midpoint": 0.0})
+        genome.append({"name": hidden + 1, "type": "Edge", "presyn": edge["presyn"], "postsyn": hidden, "weight": 1.0})
+        genome.append({"name": hidden + 2, "type": "Edge", "presyn": hidden, "postsyn": edge["postsyn"], "weight": edge["weight"]})
+        edge["weight"] = 0.0
+    # Connect two random nodes.
+    if random.random() < add_edge_rate:
+        genome.append({"name": _next_name(genome), "type": "Edge",
+                       "presyn": random.choice(nodes), "postsyn": random.choice(nodes),
+                       "weight": random.gauss(0.0, 1.0)})
+    return genome
+
+def crossover(parents):
+    """
+    Combine the genes of the parents. The structure of the child is inherited
+    from the first parent. Edges which connect the same nodes in every parent
+    inherit their weight from a random parent.
+    """
+    def key(gene):
+        return (gene["name"], gene["type"], gene.get("presyn"), gene.get("postsyn"))
+    genomes = [{key(gene): gene for gene in parent} for parent in parents]
+    child   = copy.deepcopy(parents[0])
+    for gene in child:
+        if gene["type"] == "Edge" and all(key(gene) in genome for genome in genomes):
+            gene["weight"] = random.choice(genomes)[key(gene)]["weight"]
+    return child
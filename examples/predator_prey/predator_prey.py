@@ -0,0 +1,342 @@
+#!/usr/bin/python
+"""
+Predator-Prey Environment
+
+Two populations share a square arena whose edges wrap around. The predators
+chase the prey, and the prey try to escape. Many individuals of both
+populations are alive at the same time, and each individual has its own
+controller.
+
+Predators score one point for each prey that they catch. Predators lose energy
+over time and they starve when their energy runs out. Catching a prey restores
+a predator's energy. Prey score the number of seconds that they survive. All
+individuals die of old age after the "lifespan" setting.
+
+When an individual dies, it is replaced by a new individual of the same
+population. Some of the replacements are bred from two of the living
+individuals (using the "Mate" message) and the rest are requested from the
+evolution service (using the "New" message). The parents are chosen by a
+tournament between living individuals, so successful individuals are more
+likely to reproduce while they're still alive.
+
+Before each individual dies, the environment reports its final statistics
+using the "Info" message.
+
+A reasonable initial seed genotype for both populations is to connect every
+input directly to both outputs, for the example neural network controller at
+"examples/nn/nn.py". See the file "seed_genome.json".
+"""
+
+from npc_maker import env as env_api
+from npc_maker.ctrl import Controller
+import json
+import math
+import random
+import time
+
+dt          = 0.1   # Seconds per time step.
+idle_fps    = 30
+catch_range = 0.5   # Meters.
+max_speed   = {"predator": 1.2, "prey": 1.0}                     # Meters / second.
+max_turn    = {"predator": math.radians(180), "prey": math.radians(270)} # Radians / second.
+report_rate = 1.0   # Seconds between status reports in graphical mode.
+
+bias_gin      = 0
+sin_gin       = 1
+cos_gin       = 2
+distance_gin  = 3
+internal_gin  = 4 # Predator energy or prey age.
+turn_gin      = 5
+speed_gin     = 6
+
+class Body:
+    """
+    An individual who is alive in the arena.
+    """
+    def __init__(self, env_spec, name, population, controller, genome, parents=None, **extra):
+        self.name       = name # UUID string.
+        self.population = population
+        self.controller = controller
+        self.genome     = genome
+        self.parents    = parents
+        self.x          = 0.0
+        self.y          = 0.0
+        self.heading    = 0.0
+        self.age        = 0.0
+        self.energy     = 1.0
+        self.catches    = 0
+        self.distance   = 0.0
+        self.ctrl       = Controller(env_spec["spec"], population, controller)
+        self.ctrl.new(json.dumps(genome))
+
+    def fitness(self):
+        """ Fitness of a living individual, for choosing parents. """
+        if self.population == "predator":
+            return self.catches
+        else:
+            return self.age
+
+    def save(self):
+        return {
+            "name":       self.name,
+            "population": self.population,
+            "controller": self.controller,
+            "genome":     self.genome,
+            "parents":    self.parents,
+            "x":          self.x,
+            "y":          self.y,
+            "heading":    self.heading,
+            "age":        self.age,
+            "energy":     self.energy,
+            "catches":    self.catches,
+            "distance":   self.distance,
+        }
+
+    @classmethod
+    def load(cls, env_spec, data):
+        self = cls(env_spec, data["name"], data["population"], data["controller"],
+                   data["genome"], data["parents"])
+        for field in ("x", "y", "heading", "age", "energy", "catches", "distance"):
+            setattr(self, field, data[field])
+        return self
+
+class PredatorPrey:
+    def __init__(self, env_spec, mode, **settings):
+        self.env_spec   = env_spec
+        self.mode       = mode
+        self.size       = settings["arena"]
+        self.lifespan   = settings["lifespan"]
+        self.starvation = settings["starvation"]
+        self.mating     = settings["mating"]
+        self.capacity   = {"predator": settings["predators"], "prey": settings["prey"]}
+        self.pending    = {"predator": 0, "prey": 0} # Number of births requested but not yet received.
+        self.bodies     = {} # Name -> Body
+        self.state      = "Stop"
+        self.time       = 0.0
+        self.eaten      = 0
+        self.stopping   = False
+        self.last_report = 0.0
+
+    def is_running(self):
+        return self.state in ("Start", "Stop")
+
+    def census(self, population):
+        return [body for body in self.bodies.values() if body.population == population]
+
+    def request_individuals(self):
+        """
+        Keep each population at its capacity, by mating the living individuals
+        or by requesting new individuals from the evolution service.
+        """
+        if self.state != "Start":
+            return
+        for population, capacity in self.capacity.items():
+            alive = self.census(population)
+            while len(alive) + self.pending[population] < capacity:
+                if len(alive) >= 2 and random.random() < self.mating:
+                    env_api.mate(self.tournament(alive).name, self.tournament(alive).name)
+                else:
+                    env_api.new(population)
+                self.pending[population] += 1
+
+    @staticmethod
+    def tournament(candidates, size=3):
+        return max(random.sample(candidates, min(size, len(candidates))), key=Body.fitness)
+
+    def birth(self, request):
+        body = Body(self.env_spec, **request)
+        body.x       = random.uniform(0, self.size)
+        body.y       = random.uniform(0, self.size)
+        body.heading = random.uniform(-math.pi, math.pi)
+        self.bodies[body.name] = body
+        self.pending[body.population] = max(0, self.pending[body.population] - 1)
+
+    def death(self, body, cause):
+        if body.population == "predator":
+            env_api.score(body.name, body.catches)
+        else:
+            env_api.score(body.name, round(body.age, 1))
+        env_api.info(body.name, {
+            "age":      round(body.age, 1),
+            "distance": round(body.distance, 1),
+            "catches":  body.catches,
+            "cause":    cause,})
+        env_api.death(body.name)
+        body.ctrl.quit()
+        del self.bodies[body.name]
+
+    def offset(self, source, target):
+        """ Returns the shortest displacement between two bodies, across the wrapped edges. """
+        half = self.size / 2
+        dx = (target.x - source.x + half) % self.size - half
+        dy = (target.y - source.y + half) % self.size - half
+        return dx, dy
+
+    def sense(self, body):
+        """ Returns the direction and normalized distance to the nearest opponent. """
+        opponent = "prey" if body.population == "predator" else "predator"
+        nearest  = None
+        for other in self.census(opponent):
+            dx, dy = self.offset(body, other)
+            distance = math.hypot(dx, dy)
+            if nearest is None or distance < nearest[0]:
+                nearest = (distance, dx, dy)
+        if nearest is None:
+            return 0.0, 1.0, 1.0
+        distance, dx, dy = nearest
+        angle = math.atan2(dy, dx) - body.heading
+        return math.sin(angle), math.cos(angle), min(1.0, distance / (self.size / 2))
+
+    def advance(self):
+        """ Run the arena forward one time step. """
+        bodies = list(self.bodies.values())
+        # Sense the world before anything moves.
+        for body in bodies:
+            sin, cos, distance = self.sense(body)
+            if body.population == "predator":
+                internal = body.energy
+            else:
+                internal = body.age / self.lifespan
+            body.ctrl.set_input(bias_gin,     1.0)
+            body.ctrl.set_input(sin_gin,      sin)
+            body.ctrl.set_input(cos_gin,      cos)
+            body.ctrl.set_input(distance_gin, distance)
+            body.ctrl.set_input(internal_gin, internal)
+            body.ctrl.advance(dt)
+        # Move the bodies.
+        for body in bodies:
+            if not body.ctrl.is_alive():
+                env_api.eprint(f"controller for {body.name} exited unexpectedly")
+                self.death(body, "controller exited")
+                continue
+            outputs = body.ctrl.get_outputs([turn_gin, speed_gin])
+            turn    = max(0.0, min(1.0, float(outputs[turn_gin])))
+            speed   = max(0.0, min(1.0, float(outputs[speed_gin])))
+            speed  *= max_speed[body.population]
+            body.heading  += 2.0 * (turn - 0.5) * max_turn[body.population] * dt
+            body.heading   = (body.heading + math.pi) % (2 * math.pi) - math.pi
+            body.x         = (body.x + math.cos(body.heading) * speed * dt) % self.size
+            body.y         = (body.y + math.sin(body.heading) * speed * dt) % self.size
+            body.distance += speed * dt
+            body.age      += dt
+        # The predators catch any prey within range.
+        for predator in self.census("predator"):
+            for prey in self.census("prey"):
+                if math.hypot(*self.offset(predator, prey)) <= catch_range:
+                    predator.catches += 1
+                    predator.energy   = 1.0
+                    self.eaten       += 1
+                    self.death(prey, "caught")
+        # The predators slowly starve.
+        for predator in self.census("predator"):
+            predator.energy -= dt / self.starvation
+            if predator.energy <= 0.0:
+                self.death(predator, "starved")
+        # Everyone eventually dies of old age.
+        for body in list(self.bodies.values()):
+            if body.age >= self.lifespan:
+                self.death(body, "old age")
+        self.time += dt
+
+    def report(self):
+        """ Print a summary of the arena, in place of graphics. """
+        if self.time - self.last_report < report_rate:
+            return
+        self.last_report = self.time
+        predators = self.census("predator")
+        prey      = self.census("prey")
+        best      = max((body.catches for body in predators), default=0)
+        oldest    = max((body.age for body in prey), default=0.0)
+        env_api.eprint(f"time {self.time:.0f}s, predators {len(predators)}, prey {len(prey)}, "
+                       f"eaten {self.eaten}, most catches {best}, oldest prey {oldest:.0f}s")
+
+    def save(self, path):
+        with open(path, 'wt') as file:
+            json.dump({
+                "time":   self.time,
+                "eaten":  self.eaten,
+                "bodies": [body.save() for body in self.bodies.values()],
+            }, file)
+
+    def load(self, path):
+        with open(path, 'rt') as file:
+            data = json.load(file)
+        self.clear()
+        self.time   = data["time"]
+        self.eaten  = data["eaten"]
+        for body in data["bodies"]:
+            body = Body.load(self.env_spec, body)
+            self.bodies[body.name] = body
+
+    def clear(self):
+        for body in self.bodies.values():
+            body.ctrl.quit()
+        self.bodies = {}
+
+    def handle(self, request):
+        """ Respond to a request from the management program. Returns False to exit. """
+        if isinstance(request, dict):
+            if "Birth" in request:
+                self.birth(request["Birth"])
+            elif "Save" in request:
+                self.save(request["Save"])
+                env_api.ack(request)
+            elif "Load" in request:
+                self.load(request["Load"])
+                env_api.ack(request)
+            elif "Message" in request:
+                env_api.ack(request)
+            else:
+                env_api.eprint("Unrecognized request:", request)
+
+        elif request == "Start":
+            self.state = "Start"
+            env_api.ack(request)
+
+        elif request == "Stop":
+            # Finish evaluating the living individuals, and then acknowledge.
+            self.state = "Stop"
+            self.stopping = True
+
+        elif request == "Pause":
+            self.state = "Pause"
+            env_api.ack(request)
+
+        elif request == "Resume":
+            self.state = "Start"
+            env_api.ack(request)
+
+        elif request == "Heartbeat":
+            env_api.ack(request)
+
+        elif request == "Quit":
+            self.clear()
+            env_api.ack(request)
+            return False
+
+        else:
+            env_api.eprint("Unrecognized request:", request)
+        return True
+
+    def main(self):
+        while True:
+            while (request := env_api.poll()) is not None:
+                if not self.handle(request):
+                    return
+            #
+            if self.is_running() and self.bodies:
+                self.advance()
+            self.request_individuals()
+            if self.stopping and not self.bodies and not any(self.pending.values()):
+                self.stopping = False
+                env_api.ack("Stop")
+            #
+            if self.mode == "graphical":
+                self.report()
+                time.sleep(dt if self.is_running() and self.bodies else 1 / idle_fps)
+            elif not (self.is_running() and self.bodies):
+                time.sleep(1 / idle_fps) # Don't excessively busy loop.
+
+if __name__ == "__main__":
+    env_spec, mode, settings = env_api.get_args()
+    PredatorPrey(env_spec, mode, **settings).main()
@@ -2,22 +2,23 @@
 Solve the XOR environment using the NN controller.
 
 This tests all combinations of programming languages.
+The rust programs are skipped if they have not been built.
 """
 
-from npc_maker.env import Environment
-from npc_maker.evo import API
+from npc_maker.matrix import Matrix
 from pathlib import Path
 
 repo = Path(__file__).parent.parent.parent
 
 environments = [
     repo.joinpath("examples/xor/xor.env"),
-    # repo.joinpath("examples/xor/xor_rust.env"),
+    repo.joinpath("examples/xor/xor_rust.env"),
 ]
 
 controllers = [
     repo.joinpath("examples/nn/nn.py"),
-    # repo.joinpath("examples/nn/target/debug/logistic"),
+    repo.joinpath("examples/nn/target/release/nn"),
+    repo.joinpath("examples/nn/target/debug/nn"),
 ]
 
 solution = [
@@ -31,28 +32,13 @@ solution = [
     {"name": 10, "type": "Edge", "presyn": 0, "postsyn": 3, "weight": 1.0},
     {"name": 11, "type": "Edge", "presyn": 1, "postsyn": 3, "weight": 1.0}]
 
-class Dispatcher(API):
-    def __init__(self, ctrl_cmd):
-        self.ctrl_cmd = ctrl_cmd
-    def birth(self, parents):
-        return {"controller":self.ctrl_cmd, "genome": solution}
-    def death(self, indiv):
-        score = float(indiv.get_score())
-        assert score >= 15.0
-        raise StopIteration
-
 def test_solution():
+    matrix = Matrix(timeout=60)
     for env_path in environments:
-        for ctrl_cmd in controllers:
-            print("Testing:", env_path, ctrl_cmd)
-            disp  = Dispatcher(ctrl_cmd)
-            env   = Environment({"xor": disp}, env_path)
-            env.start()
-            while True:
-                try:
-                    env.poll()
-                except StopIteration:
-                    break
+        matrix.add_environment(env_path, "xor", solution, min_score=15.0)
+    for ctrl_cmd in controllers:
+        matrix.add_controller(ctrl_cmd)
+    matrix.check()
 
 if __name__ == "__main__":
     test_solution()
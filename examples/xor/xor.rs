@@ -76,8 +76,8 @@ fn main() {
                 let genotype = serde_json::to_string(&genotype).unwrap();
                 ctrl.new_genotype(&genotype).unwrap();
                 let score = xor_test(ctrl, mode == env_api::Mode::Graphical);
-                env_api::report_score(Some(&population), Some(individual), score).unwrap();
-                env_api::report_death(Some(&population), Some(individual)).unwrap();
+                env_api::report_score(individual, score).unwrap();
+                env_api::report_death(individual).unwrap();
                 env_api::request_new(Some(&population)).unwrap();
             }
         }
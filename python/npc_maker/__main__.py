@@ -0,0 +1,181 @@
+"""
+Command line tools for the NPC Maker.
+
+Usage: python -m npc_maker <command> [arguments...]
+"""
+
+import argparse
+import json
+import sys
+
+def _run(args):
+    from npc_maker.run import Experiment
+    experiment = Experiment(args.config, resume=args.resume, output=args.output,
+                            mode=("graphical" if args.graphical else None))
+    run_args = {"record": args.record, "replay": args.replay, "farm": args.farm}
+    if args.monitor is None:
+        experiment.run(**run_args)
+    else:
+        from npc_maker.monitor import Monitor
+        with Monitor(experiment, port=args.monitor) as monitor:
+            host, port = monitor.get_address()
+            print(f"monitoring at http://{host}:{port}/summary", file=sys.stderr)
+            experiment.run(**run_args)
+    print(f"finished after {experiment.deaths} deaths and {experiment.get_elapsed():.1f} seconds")
+
+def _check(args):
+    from npc_maker.run import load_config
+    config = load_config(args.config)
+    print(json.dumps(config, default=str, indent=4))
+
+def _dry_run(args):
+    from npc_maker.dryrun import dry_run
+    report = dry_run(args.config, timeout=args.timeout, settle=args.settle)
+    print(report)
+    if not report.passed():
+        sys.exit(1)
+
+def _worker(args):
+    from npc_maker.farm import Worker
+    worker = Worker(args.farm, args.environment, name=args.name)
+    if args.once:
+        worker.run()
+    else:
+        worker.serve_forever(retry=args.retry)
+
+def _inspect(args):
+    from npc_maker.evo import Individual
+    individual = Individual.load(args.individual)
+    fields = [
+        ("name",        individual.get_name()),
+        ("uuid",        individual.get_uuid()),
+        ("environment", individual.get_environment()),
+        ("population",  individual.get_population()),
+        ("controller",  individual.get_controller()),
+        ("ascension",   individual.get_ascension()),
+        ("birth date",  individual.get_birth_date()),
+        ("death date",  individual.get_death_date()),
+        ("queue time",  individual.get_queue_time()),
+        ("eval time",   individual.get_evaluation_time()),
+        ("parents",     individual.get_parents()),
+        ("parent uuids", ", ".join(individual.get_parent_uuids() or []) or None),
+        ("children",    individual.get_children()),
+        ("score",       individual.get_score()),
+        ("scores",      ", ".join(str(score) for score in individual.get_scores() or []) or None),
+        ("rescored",    individual.get_rescored()),
+        ("species",     individual.get_species()),
+    ]
+    for key, value in fields:
+        if value is not None:
+            if key == "controller":
+                value = " ".join(str(arg) for arg in value)
+            elif key.endswith("time"):
+                value = f"{value:.3f} seconds"
+            print(f"{key + ':':<13} {value}")
+    for key, value in sorted((individual.get_provenance() or {}).items()):
+        print(f"provenance {key}: {value}")
+    for key, value in sorted(individual.get_info().items()):
+        print(f"info {key}: {value}")
+    for key, value in sorted(individual.get_extras().items()):
+        print(f"extra {key}: {json.dumps(value)}")
+    genome = individual.get_genome()
+    if args.pretty:
+        print("genome:")
+        print(json.dumps(genome, indent=4, sort_keys=True))
+    if args.extract is not None:
+        with open(args.extract, 'wt') as file:
+            json.dump(genome, file, indent=(4 if args.pretty else None))
+
+def _compare(args):
+    from npc_maker.compare import compare, format_markdown
+    report = compare(args.a, args.b, score=args.score, samples=args.samples)
+    if args.format == "json":
+        text = json.dumps(report, indent=4) + "\n"
+    else:
+        text = format_markdown(report)
+    if args.output is None:
+        sys.stdout.write(text)
+    else:
+        with open(args.output, 'wt') as file:
+            file.write(text)
+
+def _genealogy(args):
+    from npc_maker.genealogy import Genealogy
+    genealogy = Genealogy(*args.directories, population_size=args.population_size)
+    text = genealogy.to_dot() if args.format == "dot" else genealogy.to_gexf()
+    if args.output is None:
+        sys.stdout.write(text)
+    else:
+        with open(args.output, 'wt') as file:
+            file.write(text)
+
+def main(argv=None):
+    parser = argparse.ArgumentParser(prog="npc_maker", description="Command line tools for the NPC Maker.")
+    commands = parser.add_subparsers(dest="command", required=True)
+
+    run = commands.add_parser("run", help="run an evolution experiment from a configuration file")
+    run.add_argument("config", help="experiment configuration file (.toml or .json)")
+    run.add_argument("--resume", action="store_true", help="continue a previous run of the experiment")
+    run.add_argument("--graphical", action="store_true", help="show the environment's graphical output")
+    run.add_argument("--output", metavar="PATH", help="override the configuration's output directory")
+    run.add_argument("--record", metavar="PATH", help="record every environment message and random seed")
+    run.add_argument("--replay", metavar="PATH", help="re-drive the experiment from a recording, without the environment")
+    run.add_argument("--monitor", type=int, metavar="PORT", help="serve the experiment's status over HTTP")
+    run.add_argument("--farm", metavar="HOST:PORT", help="listen for remote workers instead of running the environment")
+    run.set_defaults(function=_run)
+
+    check = commands.add_parser("check", help="validate an experiment configuration file without running it")
+    check.add_argument("config", help="experiment configuration file (.toml or .json)")
+    check.set_defaults(function=_check)
+
+    dry_run = commands.add_parser("dry-run", help="launch the environment and controllers once to check their wiring")
+    dry_run.add_argument("config", help="experiment configuration file (.toml or .json)")
+    dry_run.add_argument("--timeout", type=float, default=30, metavar="SECONDS",
+                         help="time to wait for each program to respond")
+    dry_run.add_argument("--settle", type=float, default=2, metavar="SECONDS",
+                         help="time to keep the environment running after the births")
+    dry_run.set_defaults(function=_dry_run)
+
+    worker = commands.add_parser("worker", help="run environments on behalf of a remote experiment")
+    worker.add_argument("farm", metavar="HOST:PORT", help="network address of the experiment's farm")
+    worker.add_argument("environment", help="environment specification file (.env)")
+    worker.add_argument("--name", help="name of this worker, by default the hostname")
+    worker.add_argument("--once", action="store_true", help="run one environment and then exit")
+    worker.add_argument("--retry", type=float, default=5.0, metavar="SECONDS", help="delay between attempts to connect")
+    worker.set_defaults(function=_worker)
+
+    inspect = commands.add_parser("inspect", help="print the contents of a saved individual")
+    inspect.add_argument("individual", help="saved individual file (.json)")
+    inspect.add_argument("--pretty", action="store_true", help="print the genome as indented JSON")
+    inspect.add_argument("--extract", metavar="PATH", help="write the genome to a separate file")
+    inspect.set_defaults(function=_inspect)
+
+    compare = commands.add_parser("compare", help="compare the saved populations of two experiments")
+    compare.add_argument("a", help="population directory of the first experiment")
+    compare.add_argument("b", help="population directory of the second experiment")
+    compare.add_argument("--format", choices=("json", "markdown"), default="markdown", help="report format")
+    compare.add_argument("--output", metavar="PATH", help="write the report to a file instead of stdout")
+    compare.add_argument("--score", default="score", metavar="FIELD", help="info field to use as the score")
+    compare.add_argument("--samples", type=int, default=100, metavar="N",
+                         help="number of genomes from each population to measure the similarity of")
+    compare.set_defaults(function=_compare)
+
+    genealogy = commands.add_parser("genealogy", help="draw the family tree of the saved individuals")
+    genealogy.add_argument("directories", nargs="+", help="directories of saved individuals, searched recursively")
+    genealogy.add_argument("--format", choices=("dot", "gexf"), default="dot", help="graph format")
+    genealogy.add_argument("--output", metavar="PATH", help="write the graph to a file instead of stdout")
+    genealogy.add_argument("--population-size", type=int, metavar="N",
+                           help="number of individuals in each generation, for numbering the generations")
+    genealogy.set_defaults(function=_genealogy)
+
+    args = parser.parse_args(argv)
+    try:
+        args.function(args)
+    except (ValueError, OSError, RuntimeError) as error:
+        print(f"npc_maker {args.command}: {error}", file=sys.stderr)
+        sys.exit(1)
+    except KeyboardInterrupt:
+        sys.exit(130)
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,284 @@
+"""
+Batch scheduler backend, for running environments on SLURM and PBS clusters.
+
+The batch farm submits jobs to the cluster's scheduler, and each job runs a
+worker which connects back to the farm, see "npc_maker.farm". The farm keeps the
+requested number of jobs in the queue, and resubmits jobs as they finish. The
+output of each job is written to a log file, and when a job finishes before its
+worker joins the farm then the tail of its log is shown to help diagnose it.
+
+The submit scripts are made from a template, which is a shell script with
+these placeholders (see python's "string.Template"):
+    $name       Name of the job.
+    $log        Path of the job's log file.
+    $directory  Working directory of the job.
+    $options    Scheduler directives, one per line.
+    $command    Command line which runs the worker.
+"""
+
+from npc_maker.farm import Farm
+from pathlib import Path
+import collections
+import re
+import shlex
+import socket
+import string
+import subprocess
+import sys
+import time
+
+__all__ = (
+    "Scheduler",
+    "Slurm",
+    "PBS",
+    "BatchFarm",
+    "get_scheduler",
+)
+
+class Scheduler:
+    """
+    Abstract class for submitting and monitoring jobs with a batch scheduler.
+    """
+    template = ""
+
+    directive = ""
+
+    def submit(self, script_path):
+        """ Submit a job script. Returns the job's identifier. """
+        raise NotImplementedError(type(self).__name__ + ".submit() is not implemented")
+
+    def status(self, job_ids):
+        """
+        Check on the given jobs.
+
+        Returns a dictionary of job identifier to state: "pending", "running", or "finished".
+        """
+        raise NotImplementedError(type(self).__name__ + ".status() is not implemented")
+
+    def cancel(self, job_ids):
+        """ Remove the given jobs from the queue and stop them if they are running, ignoring any errors. """
+        raise NotImplementedError(type(self).__name__ + ".cancel() is not implemented")
+
+    def format_options(self, options):
+        """ Format a list of scheduler options as directives for a submit script. """
+        return "\n".join(f"{self.directive} {option}" for option in options)
+
+    @staticmethod
+    def _run(command):
+        result = subprocess.run(command, capture_output=True, text=True)
+        if result.returncode != 0:
+            raise RuntimeError(f"command {shlex.join(command)} failed: {result.stderr.strip()}")
+        return result.stdout
+
+class Slurm(Scheduler):
+    """ The SLURM workload manager. """
+    template = """#!/bin/bash
+#SBATCH --job-name=$name
+#SBATCH --output=$log
+#SBATCH --chdir=$directory
+$options
+$command
+"""
+
+    directive = "#SBATCH"
+
+    def submit(self, script_path):
+        output = self._run(["sbatch", "--parsable", str(script_path)])
+        return output.strip().split(";")[0]
+
+    def status(self, job_ids):
+        states = {job_id: "finished" for job_id in job_ids}
+        if not job_ids:
+            return states
+        output = self._run(["squeue", "--noheader", "--format=%i %T", "--jobs=" + ",".join(job_ids)])
+        for line in output.splitlines():
+            job_id, _, state = line.strip().partition(" ")
+            if job_id in states:
+                if state in ("PENDING", "CONFIGURING", "REQUEUED", "SUSPENDED"):
+                    states[job_id] = "pending"
+                elif state in ("RUNNING", "COMPLETING"):
+                    states[job_id] = "running"
+        return states
+
+    def cancel(self, job_ids):
+        if job_ids:
+            subprocess.run(["scancel"] + list(job_ids), capture_output=True)
+
+class PBS(Scheduler):
+    """ The PBS family of batch schedulers: PBS Pro, OpenPBS, and Torque. """
+    template = """#!/bin/bash
+#PBS -N $name
+#PBS -o $log
+#PBS -j oe
+$options
+cd $directory
+$command
+"""
+
+    directive = "#PBS"
+
+    def submit(self, script_path):
+        return self._run(["qsub", str(script_path)]).strip()
+
+    def status(self, job_ids):
+        states = {job_id: "finished" for job_id in job_ids}
+        for job_id in job_ids:
+            # Finished jobs are unknown to qstat, which then exits with an error.
+            result = subprocess.run(["qstat", "-f", job_id], capture_output=True, text=True)
+            match = re.search(r"job_state\s*=\s*(\w)", result.stdout)
+            if result.returncode != 0 or not match:
+                continue
+            state = match.group(1)
+            if state in ("Q", "H", "W", "T", "S"):
+                states[job_id] = "pending"
+            elif state in ("R", "E"):
+                states[job_id] = "running"
+        return states
+
+    def cancel(self, job_ids):
+        if job_ids:
+            subprocess.run(["qdel"] + list(job_ids), capture_output=True)
+
+def get_scheduler(name):
+    """ Get a scheduler by its name: "slurm" or "pbs". """
+    schedulers = {"slurm": Slurm, "pbs": PBS}
+    try:
+        return schedulers[str(name).strip().lower()]()
+    except KeyError:
+        raise ValueError(f"unrecognized batch scheduler \"{name}\"") from None
+
+class BatchFarm(Farm):
+    """
+    Farm which submits its own workers as jobs to a batch scheduler.
+
+    This has the same interface as the Environment class.
+    """
+    def __init__(self, services, env_spec, scheduler, jobs, directory,
+                 address="0.0.0.0:0", hostname=None, template=None, options=(),
+                 python=sys.executable, poll_interval=10, max_failures=3, **kwargs):
+        """
+        Argument services and env_spec are the same as for the Environment class.
+
+        Argument scheduler is a Scheduler instance, or the name of one: "slurm" or "pbs".
+
+        Argument jobs is the number of jobs to keep submitted at all times.
+
+        Argument directory is where to write the submit scripts and the job logs.
+
+        Argument address is the network address to listen on for the workers.
+                 By default this uses any available port.
+
+        Argument hostname is the name which the compute nodes use to reach
+                 this computer, by default this computer's hostname.
+
+        Argument template is an optional path to a submit script template.
+                 By default this uses the scheduler's built-in template.
+
+        Argument options is a list of scheduler options, for example: ["--time=2:00:00", "--mem=4G"]
+
+        Argument python is the python interpreter which the jobs use to run the worker.
+
+        Argument poll_interval is the number of seconds between checks on the status of the jobs.
+
+        Argument max_failures is the number of jobs in a row which may finish
+                 without ever joining the farm, before giving up with an error.
+
+        The remaining keyword arguments are for the Farm class constructor.
+        """
+        super().__init__(services, env_spec, address, **kwargs)
+        if not isinstance(scheduler, Scheduler):
+            scheduler = get_scheduler(scheduler)
+        self.scheduler      = scheduler
+        self.num_jobs       = int(jobs)
+        self.directory      = Path(directory).resolve()
+        self.hostname       = socket.getfqdn() if hostname is None else str(hostname)
+        self.template       = scheduler.template if template is None else Path(template).read_text()
+        self.options        = list(options)
+        self.python         = str(python)
+        self.poll_interval  = float(poll_interval)
+        self.max_failures   = int(max_failures)
+        self.jobs           = {} # Job identifier -> job name
+        self.failures       = 0
+        self._joined        = set()
+        self._counter       = 0
+        self._last_check    = 0.0
+        self.directory.mkdir(parents=True, exist_ok=True)
+
+    def get_jobs(self):
+        """ Returns a dictionary of the submitted job identifiers and their job names. """
+        return dict(self.jobs)
+
+    def get_log_path(self, name):
+        """ Returns the path of the log file for the given job name. """
+        return self.directory.joinpath(name + ".log")
+
+    def _submit(self):
+        self._counter += 1
+        name = f"npc_maker-{self.env_spec['name']}-{self._counter}"
+        name = re.sub(r"[^\w.-]", "_", name)
+        port = self.get_address()[1]
+        command = [self.python, "-m", "npc_maker", "worker", f"{self.hostname}:{port}",
+                   str(self.env_spec["spec"]), "--once", "--name", name]
+        script = string.Template(self.template).substitute(
+            name        = name,
+            log         = self.get_log_path(name),
+            directory   = self.directory,
+            options     = self.scheduler.format_options(self.options),
+            command     = shlex.join(command))
+        script_path = self.directory.joinpath(name + ".sh")
+        script_path.write_text(script)
+        job_id = self.scheduler.submit(script_path)
+        self.jobs[job_id] = name
+
+    def _join(self, connection):
+        self._joined.add(connection.name.rpartition("@")[0])
+        super()._join(connection)
+
+    def _check_jobs(self):
+        states = self.scheduler.status(list(self.jobs))
+        for job_id, state in states.items():
+            if state != "finished":
+                continue
+            name = self.jobs.pop(job_id)
+            if name in self._joined:
+                self.failures = 0
+                continue
+            self.failures += 1
+            print(f"npc_maker batch: job {job_id} ({name}) finished without joining the farm", file=self.stderr)
+            log = self._log_tail(name)
+            if log:
+                print(log, file=self.stderr)
+            if self.failures >= self.max_failures:
+                raise RuntimeError(f"{self.failures} jobs in a row failed to join the farm, see: {self.directory}")
+
+    def _log_tail(self, name, lines=20):
+        try:
+            with open(self.get_log_path(name), 'rt', errors="replace") as file:
+                return "".join(collections.deque(file, maxlen=lines)).rstrip()
+        except OSError:
+            return ""
+
+    def poll(self):
+        """
+        Submit jobs, check on the status of the jobs, and check for messages from the workers.
+
+        This function is non-blocking and should be called periodically.
+        """
+        if not self._has_quit and time.monotonic() - self._last_check > self.poll_interval:
+            self._last_check = time.monotonic()
+            self._check_jobs()
+            while len(self.jobs) < self.num_jobs:
+                self._submit()
+        super().poll()
+
+    def quit(self):
+        """ Quit the environments on all of the workers and cancel the jobs which have not started yet. """
+        super().quit()
+        pending = [job_id for job_id, state in self.scheduler.status(list(self.jobs)).items() if state == "pending"]
+        self.scheduler.cancel(pending)
+
+    def wait(self, timeout=None):
+        """ Wait for all of the workers to disconnect, and then cancel any jobs which are still running. """
+        super().wait(timeout)
+        self.scheduler.cancel(list(self.jobs))
+        self.jobs = {}
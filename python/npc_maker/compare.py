@@ -0,0 +1,327 @@
+"""
+Compare the results of two experiments, for judging A/B tests of their settings.
+
+Each experiment is summarized by one of its population directories, which is
+the experiment's output directory joined with the population's name. The
+directory contains the current population, the leaderboard, and the statistics
+log. This module compares:
+
+* Score distributions of the current populations, and the probability that an
+  individual from the first population outscores one from the second.
+* Leaderboard overlap, which is the number of genomes on both leaderboards.
+* Shared lineages, which are genomes saved in both directories, such as common
+  seeds and elites which survived in both experiments.
+* Genome similarity, both within each population (its diversity) and between
+  the two populations.
+
+Genomes are matched by their content, because the names of individuals are
+unique to each experiment.
+
+Example:
+
+    report = compare("results_a/xor", "results_b/xor")
+    print(format_markdown(report))
+
+From the command line:
+
+    python -m npc_maker compare results_a/xor results_b/xor --format markdown
+"""
+
+from npc_maker.evo import CorruptFileError, Individual
+from pathlib import Path
+import bisect
+import hashlib
+import itertools
+import json
+import math
+import random
+
+__all__ = (
+    "compare",
+    "format_markdown",
+    "genome_similarity",
+    "Summary",
+)
+
+def _genome_hash(genome):
+    """ Returns the SHA-256 hash of the genome's canonical serialization. """
+    text = json.dumps(genome, sort_keys=True, separators=(',', ':'), default=str)
+    return hashlib.sha256(text.encode("utf-8")).hexdigest()
+
+def _leaves(genome, path=()):
+    """ Flatten a JSON object into (path, value) pairs, with floats rounded to three decimals. """
+    if isinstance(genome, dict):
+        for key, value in genome.items():
+            yield from _leaves(value, path + (str(key),))
+    elif isinstance(genome, list):
+        for index, value in enumerate(genome):
+            yield from _leaves(value, path + (index,))
+    elif isinstance(genome, float):
+        yield (path, round(genome, 3))
+    else:
+        yield (path, genome)
+
+def genome_similarity(genome_a, genome_b):
+    """
+    Default measure of the similarity of two genomes, between 0 and 1.
+
+    Genomes are flattened into their leaf values and their locations in the
+    JSON structure, and the similarity is the fraction of the leaves which are
+    shared by both genomes (the Jaccard index). Real numbers are rounded to
+    three decimal places before they are compared.
+    """
+    leaves_a = set(_leaves(genome_a))
+    leaves_b = set(_leaves(genome_b))
+    union = len(leaves_a | leaves_b)
+    if not union:
+        return 1.0
+    return len(leaves_a & leaves_b) / union
+
+def _quantile(data, fraction):
+    """ Linearly interpolated quantile of sorted data. """
+    position = fraction * (len(data) - 1)
+    lower = math.floor(position)
+    upper = min(lower + 1, len(data) - 1)
+    return data[lower] + (data[upper] - data[lower]) * (position - lower)
+
+def _distribution(scores):
+    """ Summarize a list of scores, which may contain None. """
+    valid = sorted(score for score in scores if score is not None)
+    summary = {"count": len(scores), "unscored": len(scores) - len(valid)}
+    if not valid:
+        return summary
+    mean = sum(valid) / len(valid)
+    summary["mean"]   = mean
+    summary["stdev"]  = math.sqrt(sum((x - mean) ** 2 for x in valid) / len(valid))
+    summary["min"]    = valid[0]
+    summary["q1"]     = _quantile(valid, 0.25)
+    summary["median"] = _quantile(valid, 0.50)
+    summary["q3"]     = _quantile(valid, 0.75)
+    summary["max"]    = valid[-1]
+    return summary
+
+def _probability_greater(scores_a, scores_b):
+    """
+    Probability that a random score from A is greater than a random score from B,
+    counting ties as half (the Vargha-Delaney A statistic).
+    Returns None if either side has no scores.
+    """
+    scores_a = [x for x in scores_a if x is not None]
+    scores_b = sorted(x for x in scores_b if x is not None)
+    if not scores_a or not scores_b:
+        return None
+    wins = 0.0
+    for score in scores_a:
+        below = bisect.bisect_left(scores_b, score)
+        equal = bisect.bisect_right(scores_b, score) - below
+        wins += below + 0.5 * equal
+    return wins / (len(scores_a) * len(scores_b))
+
+def _mean_similarity(pairs, similarity):
+    values = [similarity(a, b) for a, b in pairs]
+    return sum(values) / len(values) if values else None
+
+def _diversity(genomes, similarity):
+    """ One minus the mean similarity of every pair of distinct genomes, or None if there are too few genomes. """
+    mean = _mean_similarity(itertools.combinations(genomes, 2), similarity)
+    return None if mean is None else 1.0 - mean
+
+def _clean_score(individual, score):
+    value = individual.get_custom_score(score)
+    try:
+        value = float(value)
+    except (TypeError, ValueError):
+        return None
+    return None if math.isnan(value) else value
+
+class Summary:
+    """
+    Saved data of one population directory.
+
+    Attributes population and leaderboard are lists of pairs of (score, genome),
+    where the score is None if the individual was not scored.
+    Attribute statistics is the list of entries in the statistics log.
+    """
+    def __init__(self, path, score="score"):
+        """
+        Argument path is the population directory.
+                 If it has no "population" subdirectory then the individuals
+                 are loaded from the directory itself.
+
+        Argument score is an optional custom scoring function,
+                 see "Individual.get_custom_score()".
+        """
+        self.path = Path(path).expanduser()
+        if not self.path.is_dir():
+            raise ValueError(f"population directory \"{self.path}\" does not exist")
+        population_dir = self.path.joinpath("population")
+        if not population_dir.is_dir():
+            population_dir = self.path
+        self.population  = self._load(population_dir, score)
+        self.leaderboard = self._load(self.path.joinpath("leaderboard"), score)
+        self.leaderboard.sort(key=lambda entry: -math.inf if entry[0] is None else entry[0], reverse=True)
+        self.statistics  = []
+        statistics_path  = self.path.joinpath("statistics.ndjson")
+        if statistics_path.exists():
+            with open(statistics_path, 'rt') as file:
+                for line in file:
+                    try:
+                        self.statistics.append(json.loads(line))
+                    except json.JSONDecodeError:
+                        pass # The last line may be partially written.
+
+    @staticmethod
+    def _load(directory, score):
+        if not directory.is_dir():
+            return []
+        entries = []
+        for path in sorted(directory.iterdir()):
+            if path.suffix.lower() != ".json":
+                continue
+            try:
+                individual = Individual.load(path)
+            except CorruptFileError:
+                continue
+            entries.append((_clean_score(individual, score), individual.get_genome()))
+        return entries
+
+    def genomes(self):
+        """ Returns every saved genome, indexed by the hash of its contents. """
+        return {_genome_hash(genome): genome for _, genome in self.population + self.leaderboard}
+
+def _final_generation(summary):
+    if not summary.statistics:
+        return None
+    last = summary.statistics[-1]
+    return {key: last.get(key) for key in ("generation", "best", "mean", "worst")}
+
+def compare(path_a, path_b, score="score", samples=100, similarity=genome_similarity):
+    """
+    Compare two population directories.
+
+    Argument score is an optional custom scoring function,
+             see "Individual.get_custom_score()".
+
+    Argument samples is the maximum number of genomes from each population to
+             measure the similarity of. Similarity takes quadratic time.
+
+    Argument similarity is a function for comparing genomes: f(genome, genome) -> float,
+             which returns a number between 0 (different) and 1 (identical).
+
+    Returns a JSON-encodable dictionary.
+    """
+    a = Summary(path_a, score)
+    b = Summary(path_b, score)
+    scores_a = [entry[0] for entry in a.population]
+    scores_b = [entry[0] for entry in b.population]
+    dist_a   = _distribution(scores_a)
+    dist_b   = _distribution(scores_b)
+    # Leaderboard overlap.
+    leaders_a = {_genome_hash(genome) for _, genome in a.leaderboard}
+    leaders_b = {_genome_hash(genome) for _, genome in b.leaderboard}
+    leaders_union = leaders_a | leaders_b
+    # Shared lineages.
+    genomes_a = a.genomes()
+    genomes_b = b.genomes()
+    shared    = genomes_a.keys() & genomes_b.keys()
+    # Genome similarity, on a random sample of each population.
+    rng      = random.Random(0)
+    sample_a = [genome for _, genome in a.population]
+    sample_b = [genome for _, genome in b.population]
+    if len(sample_a) > samples: sample_a = rng.sample(sample_a, samples)
+    if len(sample_b) > samples: sample_b = rng.sample(sample_b, samples)
+    def genome_size(genomes):
+        return sum(len(list(_leaves(genome))) for genome in genomes) / len(genomes) if genomes else None
+    return {
+        "a": str(a.path),
+        "b": str(b.path),
+        "scores": {
+            "a": dist_a,
+            "b": dist_b,
+            "mean_difference":     (dist_a["mean"] - dist_b["mean"]) if "mean" in dist_a and "mean" in dist_b else None,
+            "probability_a_wins":  _probability_greater(scores_a, scores_b),
+            "final_generation_a":  _final_generation(a),
+            "final_generation_b":  _final_generation(b),
+        },
+        "leaderboard": {
+            "size_a":   len(a.leaderboard),
+            "size_b":   len(b.leaderboard),
+            "best_a":   a.leaderboard[0][0] if a.leaderboard else None,
+            "best_b":   b.leaderboard[0][0] if b.leaderboard else None,
+            "overlap":  len(leaders_a & leaders_b),
+            "jaccard":  len(leaders_a & leaders_b) / len(leaders_union) if leaders_union else None,
+        },
+        "lineages": {
+            "genomes_a":      len(genomes_a),
+            "genomes_b":      len(genomes_b),
+            "shared_genomes": len(shared),
+        },
+        "genomes": {
+            "size_a":       genome_size(sample_a),
+            "size_b":       genome_size(sample_b),
+            "diversity_a":  _diversity(sample_a, similarity),
+            "diversity_b":  _diversity(sample_b, similarity),
+            "similarity":   _mean_similarity(itertools.product(sample_a, sample_b), similarity),
+        },
+    }
+
+def _cell(value):
+    if value is None:
+        return "-"
+    elif isinstance(value, float):
+        return f"{value:.4g}"
+    else:
+        return str(value)
+
+def format_markdown(report):
+    """ Format the report from "compare()" as a Markdown document. """
+    def table(rows):
+        lines = ["| | A | B |", "| :-- | --: | --: |"]
+        lines.extend(f"| {name} | {_cell(x)} | {_cell(y)} |" for name, x, y in rows)
+        return lines
+    scores = report["scores"]
+    final_a = scores["final_generation_a"] or {}
+    final_b = scores["final_generation_b"] or {}
+    stats = ("count", "unscored", "mean", "stdev", "min", "q1", "median", "q3", "max")
+    board = report["leaderboard"]
+    lineages = report["lineages"]
+    genomes = report["genomes"]
+    lines = [
+        "# Population Comparison",
+        "",
+        f"* A: `{report['a']}`",
+        f"* B: `{report['b']}`",
+        "",
+        "## Scores",
+        "",
+        *table([(name, scores["a"].get(name), scores["b"].get(name)) for name in stats]),
+        "",
+        f"Mean difference (A - B): {_cell(scores['mean_difference'])}",
+        "",
+        f"Probability that A outscores B: {_cell(scores['probability_a_wins'])}",
+        "",
+        "## Final Generation",
+        "",
+        *table([(name, final_a.get(name), final_b.get(name)) for name in ("generation", "best", "mean", "worst")]),
+        "",
+        "## Leaderboard",
+        "",
+        *table([("size", board["size_a"], board["size_b"]), ("best", board["best_a"], board["best_b"])]),
+        "",
+        f"Genomes on both leaderboards: {board['overlap']} (Jaccard index {_cell(board['jaccard'])})",
+        "",
+        "## Lineages",
+        "",
+        *table([("saved genomes", lineages["genomes_a"], lineages["genomes_b"])]),
+        "",
+        f"Genomes saved in both: {lineages['shared_genomes']}",
+        "",
+        "## Genomes",
+        "",
+        *table([("mean size", genomes["size_a"], genomes["size_b"]),
+                ("diversity", genomes["diversity_a"], genomes["diversity_b"])]),
+        "",
+        f"Mean similarity between A and B: {_cell(genomes['similarity'])}",
+        "",
+    ]
+    return "\n".join(lines)
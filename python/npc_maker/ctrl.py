@@ -5,9 +5,11 @@ Controller API, for making and using control systems.
 from pathlib import Path
 import errno
 import json
+import os
 import shlex
 import subprocess
 import sys
+import time
 
 __all__ = (
     "Controller",
@@ -52,7 +54,7 @@ class Controller:
 
     Each controller instance is executed in a subprocesses.
     """
-    def __init__(self, environment, population, command, stderr=sys.stderr):
+    def __init__(self, environment, population, command, stderr=sys.stderr, env=None, cwd=None, cpu_affinity=None, memory_limit=None):
         """
         Argument environment is the path of the environment specification file.
 
@@ -64,17 +66,48 @@ class Controller:
 
         Argument stderr is the file descriptor to use for the subprocess's stderr channel.
                  By default, the controller will inherit this process's stderr channel.
+
+        Argument env is an optional dictionary of environment variables to set for the
+                 controller program, in addition to the variables which it inherits.
+
+        Argument cwd is the working directory for the controller program.
+                 By default, the controller will inherit this process's working directory.
+
+        Argument cpu_affinity is an optional list of CPUs to run the controller program on.
+                 Pinning environments and their controllers to nearby CPUs reduces latency.
+                 This is only supported on linux.
+
+        Argument memory_limit is an optional maximum number of bytes of virtual
+                 memory for the controller program. This is only supported on unix.
         """
         if isinstance(environment, dict):
             environment = environment["spec"]
         self.environment    = Path(environment)
         self.population     = str(population)
         self.command        = _clean_ctrl_command(command)
+        if cpu_affinity is not None:
+            cpu_affinity    = set(int(cpu) for cpu in cpu_affinity)
+        if memory_limit is not None:
+            memory_limit    = int(memory_limit)
+        def setup_process():
+            if cpu_affinity is not None:
+                os.sched_setaffinity(0, cpu_affinity)
+            if memory_limit is not None:
+                import resource
+                resource.setrlimit(resource.RLIMIT_AS, (memory_limit, memory_limit))
+        if cpu_affinity is not None or memory_limit is not None:
+            preexec_fn      = setup_process
+        else:
+            preexec_fn      = None
         self._ctrl          = subprocess.Popen(self.command,
             stdin           = subprocess.PIPE,
             stdout          = subprocess.PIPE,
-            stderr          = stderr)
+            stderr          = stderr,
+            env             = None if env is None else {**os.environ, **env},
+            cwd             = cwd,
+            preexec_fn      = preexec_fn)
         # 
+        self._epigenome     = {}
         self._ctrl.stdin.write("E{}\n".format(self.environment).encode("utf-8"))
         self._ctrl.stdin.write("P{}\n".format(self.population).encode("utf-8"))
 
@@ -102,6 +135,25 @@ class Controller:
         """
         return " ".join(str(arg) for arg in self.command)
 
+    def get_epigenome(self):
+        """
+        Get the epigenetic data which the controller has published about the current individual.
+
+        Controllers may publish epigenetic data at any time, but it is only
+        received while waiting for outputs or responses from the controller.
+        Environments should forward this data to the NPC Maker, see "npc_maker.env.info()".
+        """
+        return self._epigenome
+
+    def _receive_epigenome(self, message):
+        """
+        Returns True if the message was epigenetic data.
+        """
+        if not message.startswith(b"D"):
+            return False
+        self._epigenome.update(json.loads(message[1:]))
+        return True
+
     def __repr__(self):
         return "<npc_maker.env_api.Instance: {}>".format(repr(self.get_command()))
 
@@ -114,8 +166,66 @@ class Controller:
         >>> import json
         >>> genome = json.dumps(genome)
         """
+        self._epigenome.clear()
         self._ctrl.stdin.write("N{}\n".format(genome).encode("utf-8"))
 
+    def swap(self, genome):
+        """
+        Replace the genome while preserving the controller's internal state,
+        such as learned weights, wherever the old and new models are compatible.
+
+        Controllers which do not support this discard the current model,
+        the same as the new() method.
+
+        The genome should already be encoded as a JSON string.
+        """
+        self._ctrl.stdin.write("K{}\n".format(genome).encode("utf-8"))
+
+    def ping(self):
+        """
+        Check that the controller is responding.
+
+        Returns the round trip time in seconds. This method blocks on IO.
+        """
+        start_time = time.monotonic()
+        self._ctrl.stdin.write(b"H\n")
+        self._ctrl.stdin.flush()
+        while True:
+            message = self._ctrl.stdout.readline()
+            if not message:
+                raise EOFError("controller closed its stdout")
+            if self._receive_epigenome(message): continue
+            _check_error(message)
+            if message.strip() == b"H:":
+                return time.monotonic() - start_time
+
+    def validate_genome(self, genome):
+        """
+        Check if the controller can load a genome, without loading it.
+
+        Returns None if the genome is valid, otherwise the controller's error
+        message. This method blocks on IO.
+        """
+        self._ctrl.stdin.write("V{}\n".format(genome).encode("utf-8"))
+        self._ctrl.stdin.flush()
+        while True:
+            message = self._ctrl.stdout.readline()
+            if not message:
+                raise EOFError("controller closed its stdout")
+            if self._receive_epigenome(message): continue
+            _check_error(message)
+            message = message.decode("utf-8").strip()
+            if message.startswith("V:"):
+                return message[2:] or None
+
+    def seed(self, seed):
+        """
+        Seed the controller's random number generator, for reproducible evaluations.
+        Call this before calling new().
+        """
+        seed = int(seed)
+        self._ctrl.stdin.write("G{}\n".format(seed).encode("utf-8"))
+
     def reset(self):
         """
         Reset the control system to its initial state.
@@ -178,8 +288,11 @@ class Controller:
         # Receive the outputs.
         outputs = {}
         while len(outputs) < len(gin_list):
-            message = self._ctrl.stdout.readline().lstrip()
+            message = self._ctrl.stdout.readline().strip()
             if not message: continue
+            if message.startswith(b"#"): continue
+            if self._receive_epigenome(message): continue
+            _check_error(message)
             gin, value   = message.split(b":", maxsplit=1)
             gin          = int(gin)
             outputs[gin] = value.decode("utf-8")
@@ -211,7 +324,7 @@ class Controller:
         message_type = str(message_type).strip().upper()
         assert len(message_type) == 1
         assert message_type not in "EPNRXIBOSLQ"
-        self._ctrl.stdin.write("{}{}\n".format(message_type, message_body).encode("utf-8"))
+        self._ctrl.stdin.write("{}:{}\n".format(message_type, message_body).encode("utf-8"))
 
     def quit(self):
         """
@@ -248,6 +361,49 @@ class API:
         """
         raise TypeError("abstract method called")
 
+    def swap(self, genome):
+        """
+        Optional Method
+
+        Replace the genome while preserving the internal state of the model,
+        for Lamarckian evolution and plastic networks. The controller should
+        keep whatever state is compatible with the new genome.
+        The genome has been decoded from JSON into a python object.
+        By default this discards the current model, the same as new().
+        """
+        self.new(genome)
+
+    def validate(self, genome) -> str:
+        """
+        Optional Method
+
+        Check if a genome is valid, without loading it.
+        The genome has been decoded from JSON into a python object.
+        Returns None if the genome is valid, otherwise a description of the problem.
+        By default all genomes are accepted.
+        """
+        return None
+
+    def seed(self, seed: int):
+        """
+        Optional Method
+
+        Seed the controller's random number generator. This is called before
+        new() for evaluations which should be reproducible.
+        Controllers which are not stochastic can ignore it.
+        """
+        pass
+
+    def select(self, agent: int):
+        """
+        Optional Method
+
+        Direct all subsequent messages to the given agent. Only controllers
+        which host many agents in a single program need to implement this.
+        Agent zero is selected at startup.
+        """
+        raise TypeError("unsupported operation")
+
     def reset(self):
         """
         Abstract Method
@@ -327,6 +483,8 @@ class API:
         in use by the protocol.
 
         Argument message_body is a UTF-8 string.
+
+        Returns the response, which is sent to the environment if it requested one.
         """
         raise TypeError(f"unsupported operation (message type \"{message_type}\")")
 
@@ -343,6 +501,21 @@ _buffer      = b""
 _environment = None
 _population  = None
 
+def _capabilities(controller):
+    """
+    Find which of the optional API methods the controller has implemented.
+    """
+    methods = {"save": "save", "load": "load", "binary": "set_binary", "validate": "validate", "select": "select", "swap": "swap"}
+    cls = type(controller)
+    return [name for name, method in methods.items() if getattr(cls, method) is not getattr(API, method)]
+
+def _check_error(message):
+    """
+    Raise an exception if the message is an error report from the controller.
+    """
+    if message.startswith(b"E:"):
+        raise RuntimeError("controller error: " + message[2:].decode("utf-8").strip())
+
 def _readline():
     global _stdin, _buffer
     if _stdin is None:
@@ -360,11 +533,11 @@ def _readline():
     return line
 
 def _parse_message():
-    # Ignore leading white space and empty lines.
+    # Ignore leading white space, empty lines, and comments.
     while True:
         message = _readline()
         message = message.lstrip()
-        if message:
+        if message and not message.startswith("#"):
             break
         # 
     msg_type = message[0].upper()
@@ -412,6 +585,28 @@ def main(controller):
     if type(controller) is type and issubclass(controller, API):
         controller = controller()
     assert isinstance(controller, API)
+    try:
+        _main_loop(controller)
+    except EOFError:
+        raise
+    except Exception as error:
+        # Tell the environment, instead of leaving it waiting for a reply.
+        message = f"{type(error).__name__}: {error}".replace("\n", " ")
+        print("E:" + message, flush=True)
+        raise
+
+def _send_output(controller, gin):
+    value = str(controller.get_output(gin))
+    assert '\n' not in value
+    reply = f"{gin}:{value}"
+    try:
+        print(reply, flush=True)
+    except ValueError:
+        if sys.stdout.closed:
+            raise EOFError("stdout closed")
+
+def _main_loop(controller):
+    global _stdin, _environment, _population
     while True:
         msg_type, msg_body = _parse_message()
 
@@ -420,16 +615,18 @@ def main(controller):
             gin = int(gin)
             controller.set_input(gin, value)
 
+        elif msg_type == "M":
+            for _ in range(int(msg_body)):
+                gin, value = _readline().split(":", maxsplit=1)
+                controller.set_input(int(gin), value)
+
         elif msg_type == "O":
-            gin   = int(msg_body)
-            value = str(controller.get_output(gin))
-            assert '\n' not in value
-            reply = f"{gin}:{value}"
-            try:
-                print(reply, flush=True)
-            except ValueError:
-                if sys.stdout.closed:
-                    raise EOFError("stdout closed")
+            _send_output(controller, int(msg_body))
+
+        elif msg_type == "W":
+            for gin in msg_body.split(","):
+                if gin.strip():
+                    _send_output(controller, int(gin))
 
         elif msg_type == "B":
             gin, num_bytes  = msg_body.split(":")
@@ -444,6 +641,26 @@ def main(controller):
             dt = float(msg_body)
             controller.advance(dt)
 
+        elif msg_type == "G":
+            controller.seed(int(msg_body))
+
+        elif msg_type == "U":
+            controller.select(int(msg_body))
+
+        elif msg_type == "V":
+            try:
+                error = controller.validate(json.loads(msg_body))
+            except json.JSONDecodeError as decode_error:
+                error = str(decode_error)
+            error = "" if error is None else (str(error) or "invalid genome")
+            print("V:" + error.replace("\n", " "), flush=True)
+
+        elif msg_type == "H":
+            print("H:", flush=True)
+
+        elif msg_type == "C":
+            print("C:" + ",".join(_capabilities(controller)), flush=True)
+
         elif msg_type == "R":
             controller.reset()
 
@@ -451,15 +668,23 @@ def main(controller):
             genome = json.loads(msg_body)
             controller.new(genome)
 
+        elif msg_type == "K":
+            genome = json.loads(msg_body)
+            controller.swap(genome)
+
         elif msg_type == "E":
             _environment = Path(msg_body)
 
         elif msg_type == "P":
             _population = msg_body
 
+        elif msg_type == "F":
+            # Decline the binary value encodings.
+            print("F:text", flush=True)
+
         elif msg_type == "S":
             save_path = Path(msg_body)
-            controller.load(save_path)
+            controller.save(save_path)
 
         elif msg_type == "L":
             load_path = Path(msg_body)
@@ -470,4 +695,11 @@ def main(controller):
             break
 
         else:
-            controller.message(msg_type, msg_body)
+            if ":" not in msg_body:
+                raise ValueError(f"malformed custom message of type \"{msg_type}\": {msg_body!r}")
+            msg_id, msg_body = msg_body.split(":", maxsplit=1)
+            response = controller.message(msg_type, msg_body)
+            if msg_id:
+                response = "" if response is None else str(response)
+                assert '\n' not in response
+                print(f"{msg_type}{msg_id}:{response}", flush=True)
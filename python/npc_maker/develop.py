@@ -0,0 +1,146 @@
+"""
+Development, the process which grows an individual's genome into its phenome.
+
+The genome is the genetic material which evolution mutates and recombines,
+and the phenome is what the controller receives in place of the genome.
+By default the genome is sent to the controller unchanged. A development
+transforms each genome into a phenome before the individual is born, for
+example by decoding an indirect encoding or by pruning the unused genes.
+
+The environment develops each new individual just before sending it, see the
+"develop" argument of the Environment class. The environment keeps running
+while individuals are developing. Parallel developments develop several
+individuals at the same time, using a pool of worker threads or processes.
+
+If a genome fails to develop then the individual dies without being born.
+Its score is None and the error message is saved in its info under the key
+"development_error".
+
+Example experiment configuration:
+
+    [populations.xor]
+    develop = ["my_development:decode", "my_development:prune"]
+    develop_workers = 4
+"""
+
+import concurrent.futures
+
+__all__ = (
+    "DevelopmentError",
+    "Development",
+    "Function",
+    "Pipeline",
+    "Parallel",
+    "as_development",
+)
+
+class DevelopmentError(Exception):
+    """ Raised when a genome fails to develop. """
+
+class Development:
+    """
+    Abstract class for transforming genomes into phenomes.
+    """
+    def develop(self, genome):
+        """
+        Abstract method, transform a genome into a phenome.
+        Both the genome and the phenome are JSON encodable objects.
+        Raise an exception if the genome can not develop.
+        """
+        raise TypeError("abstract method called")
+
+    def submit(self, genome):
+        """
+        Start developing a genome.
+
+        Returns a "concurrent.futures.Future" of the phenome.
+        By default this develops the genome immediately, in the calling thread.
+        """
+        future = concurrent.futures.Future()
+        try:
+            future.set_result(self.develop(genome))
+        except Exception as error:
+            future.set_exception(error)
+        return future
+
+    def close(self):
+        """ Release any resources which this development is holding, such as worker threads. """
+
+class Function(Development):
+    """
+    Development which calls a function: f(genome) -> phenome
+    """
+    def __init__(self, function):
+        self.function = function
+
+    def develop(self, genome):
+        return self.function(genome)
+
+    def __repr__(self):
+        return getattr(self.function, "__qualname__", repr(self.function))
+
+class Pipeline(Development):
+    """
+    Composition of several developments, which are applied in order.
+    The phenome of each stage is the genome of the next stage.
+    """
+    def __init__(self, *stages):
+        """
+        Argument stages are Development instances or functions: f(genome) -> phenome
+        """
+        self.stages = [as_development(stage) for stage in stages]
+
+    def develop(self, genome):
+        for index, stage in enumerate(self.stages):
+            try:
+                genome = stage.develop(genome)
+            except Exception as error:
+                raise DevelopmentError(f"stage {index} ({stage!r}) failed: {error}") from error
+        return genome
+
+    def close(self):
+        for stage in self.stages:
+            stage.close()
+
+class Parallel(Development):
+    """
+    Develop several genomes at the same time, using a pool of worker threads or processes.
+    """
+    def __init__(self, development, workers, processes=False):
+        """
+        Argument development is a Development instance or a function: f(genome) -> phenome
+
+        Argument workers is the maximum number of genomes to develop at the same time.
+
+        Argument processes selects worker processes instead of threads.
+                 Processes are not limited by python's global interpreter lock,
+                 but the development must be picklable.
+        """
+        self.development = as_development(development)
+        if processes:
+            self._executor = concurrent.futures.ProcessPoolExecutor(max_workers=int(workers))
+        else:
+            self._executor = concurrent.futures.ThreadPoolExecutor(max_workers=int(workers))
+
+    def develop(self, genome):
+        return self.development.develop(genome)
+
+    def submit(self, genome):
+        return self._executor.submit(self.development.develop, genome)
+
+    def close(self):
+        self._executor.shutdown(wait=False, cancel_futures=True)
+        self.development.close()
+
+def as_development(development):
+    """
+    Convert a function or a Development subclass into a Development instance.
+    """
+    if isinstance(development, Development):
+        return development
+    elif isinstance(development, type) and issubclass(development, Development):
+        return development()
+    elif callable(development):
+        return Function(development)
+    else:
+        raise TypeError(f"expected a development, found {development!r}")
@@ -0,0 +1,287 @@
+"""
+Dry run of an experiment, for checking that its programs are wired together correctly.
+
+The "check" command validates the configuration file, but it does not run any
+of the programs. A dry run launches each controller once, performs the
+handshake, loads the population's seed genome, and checks that the controller
+accepts every interface which the environment specification declares. Then it
+launches the environment, births one individual into each population, and
+shuts everything down. This catches mismatches between the environment
+specification and the controllers before committing to a long experiment.
+
+Dry runs do not write to the experiment's output directory.
+
+Usage: python -m npc_maker dry-run experiment.toml
+"""
+
+from npc_maker.ctrl import Controller
+from npc_maker.env import Environment, Specification
+from npc_maker.evo import API
+from npc_maker.matrix import _program_exists
+from npc_maker.run import _make_developments, _make_initial_genome, load_config
+import json
+import sys
+import threading
+import time
+
+__all__ = (
+    "dry_run",
+    "Check",
+    "Report",
+)
+
+class Check:
+    """
+    Outcome of one step of a dry run.
+
+    Attribute name describes what was checked.
+    Attribute passed is True if the check succeeded.
+    Attribute message explains the outcome.
+    """
+    def __init__(self, name, passed, message=""):
+        self.name    = str(name)
+        self.passed  = bool(passed)
+        self.message = str(message)
+
+    def __str__(self):
+        line = f"{'PASSED' if self.passed else 'FAILED':6} {self.name}"
+        if self.message:
+            line += f": {self.message}"
+        return line
+
+class Report:
+    """ Diagnostic report of a dry run, which is a list of checks. """
+    def __init__(self):
+        self.checks = []
+
+    def add(self, name, passed, message=""):
+        check = Check(name, passed, message)
+        self.checks.append(check)
+        return check.passed
+
+    def passed(self):
+        """ Returns True if every check passed. """
+        return all(check.passed for check in self.checks)
+
+    def failures(self):
+        return [check for check in self.checks if not check.passed]
+
+    def __str__(self):
+        lines = [str(check) for check in self.checks]
+        lines.append(f"{len(self.checks) - len(self.failures())} of {len(self.checks)} checks passed")
+        return "\n".join(lines)
+
+def _call(controller, timeout, function, *args):
+    """
+    Call a blocking controller method, and kill the controller if it does not return in time.
+    """
+    result = {}
+    def target():
+        try:
+            result["value"] = function(*args)
+        except BaseException as error:
+            result["error"] = error
+    thread = threading.Thread(target=target, daemon=True)
+    thread.start()
+    thread.join(timeout)
+    if thread.is_alive():
+        controller._ctrl.kill()
+        raise TimeoutError(f"controller did not respond within {timeout} seconds")
+    if "error" in result:
+        raise result["error"]
+    return result.get("value")
+
+_CONTROLLER_ERRORS = (RuntimeError, EOFError, OSError, ValueError)
+
+class _Probe:
+    """ Controller with the genome loaded, which is restarted after every error. """
+    def __init__(self, env_spec, population, command, genome, timeout, stderr):
+        self.args       = (env_spec, population, command)
+        self.genome     = json.dumps(genome)
+        self.timeout    = timeout
+        self.stderr     = stderr
+        self.controller = None
+
+    def start(self):
+        self.close()
+        self.controller = Controller(*self.args, stderr=self.stderr)
+        self.controller.new(self.genome)
+
+    def accepts(self, gin):
+        """ Returns None if the controller accepts the GIN as either an input or an output, otherwise the error. """
+        error = None
+        for request in (self._write, self._read):
+            try:
+                if self.controller is None or self.controller._ctrl.poll() is not None:
+                    self.start()
+                _call(self.controller, self.timeout, request, gin)
+                return None
+            except _CONTROLLER_ERRORS as request_error:
+                error = request_error
+                self.close()
+        return error
+
+    def _write(self, gin):
+        self.controller.set_input(gin, "0")
+        self.controller.ping()
+
+    def _read(self, gin):
+        self.controller.get_outputs([gin])
+
+    def close(self):
+        if self.controller is not None:
+            try:
+                self.controller.quit()
+            except OSError:
+                pass
+            try:
+                self.controller._ctrl.wait(self.timeout)
+            except Exception:
+                self.controller._ctrl.kill()
+            self.controller = None
+
+def _seed_genome(config, pop):
+    seed = _make_initial_genome(pop, config["directory"])
+    return seed() if callable(seed) else seed
+
+def _check_controller(report, env_spec, name, pop, genome, timeout, stderr):
+    command = pop["controller"]
+    where   = f"population \"{name}\""
+    if not report.add(f"{where} controller program", _program_exists(command[0]), command[0]):
+        return
+    try:
+        controller = Controller(env_spec["spec"], name, command, stderr=stderr)
+    except OSError as error:
+        report.add(f"{where} controller launch", False, error)
+        return
+    try:
+        elapsed = _call(controller, timeout, controller.ping)
+        report.add(f"{where} controller handshake", True, f"responded in {elapsed:.3f} seconds")
+        problem = _call(controller, timeout, controller.validate_genome, json.dumps(genome))
+        report.add(f"{where} controller accepts the seed genome", problem is None, problem or "")
+    except _CONTROLLER_ERRORS as error:
+        report.add(f"{where} controller handshake", False, error)
+        return
+    finally:
+        try:
+            controller.quit()
+        except OSError:
+            pass
+    pop_spec = next(spec for spec in env_spec["populations"] if spec["name"] == name)
+    probe = _Probe(env_spec["spec"], name, command, genome, timeout, stderr)
+    try:
+        for interface in pop_spec["interfaces"]:
+            error = probe.accepts(interface["gin"])
+            report.add(f"{where} controller accepts GIN {interface['gin']} ({interface['name']})",
+                       error is None, "" if error is None else error)
+    finally:
+        probe.close()
+
+class _Once(API):
+    """ Evolution service which gives one individual to the environment. """
+    def __init__(self, controller, genome):
+        self.controller = controller
+        self.genome     = genome
+        self.given      = False
+        self.dead       = None
+
+    def birth(self, parents):
+        if self.given:
+            raise StopIteration
+        self.given = True
+        return {"controller": self.controller, "genome": self.genome}
+
+    def death(self, individual):
+        self.dead = individual
+
+def _check_environment(report, config, env_spec, genomes, timeout, settle, stderr):
+    if not report.add("environment program", _program_exists(env_spec["path"]), env_spec["path"]):
+        return
+    services = {name: _Once(pop["controller"], genomes[name]) for name, pop in config["populations"].items()}
+    develop  = _make_developments(config)
+    env      = None
+    try:
+        env = Environment(services, config["environment"], "headless", config["settings"],
+                          stderr=stderr, timeout=timeout, develop=develop)
+        env.start()
+        report.add("environment launch", True)
+        start    = time.monotonic()
+        born     = {}
+        deadline = start + timeout
+        while len(born) < len(services) or time.monotonic() < max(born.values(), default=0) + settle:
+            if not env.is_alive():
+                break
+            if time.monotonic() > deadline:
+                break
+            try:
+                env.poll()
+            except StopIteration:
+                pass
+            individuals = list(env.get_outstanding().values())
+            individuals.extend(service.dead for service in services.values() if service.dead is not None)
+            for individual in individuals:
+                if individual.get_birth_date() is not None:
+                    born.setdefault(individual.get_population(), time.monotonic())
+            if all(service.dead for service in services.values()):
+                break
+            time.sleep(0.01)
+        for name, service in services.items():
+            where = f"population \"{name}\""
+            if not service.given:
+                report.add(f"{where} birth", False, f"the environment did not request an individual within {timeout} seconds")
+                continue
+            report.add(f"{where} birth", name in born, "" if name in born else "the individual was not sent")
+            if service.dead is not None:
+                report.add(f"{where} evaluation", True, f"the individual died with score {service.dead.get_score()}")
+        alive = env.is_alive()
+        report.add("environment running", alive,
+                   "" if alive else f"the environment exited with code {env._process.returncode}")
+    except Exception as error:
+        report.add("environment", False, f"{type(error).__name__}: {error}")
+    finally:
+        if env is not None and env.is_alive():
+            env.quit()
+            env.wait(timeout)
+        for development in develop.values():
+            development.close()
+
+def dry_run(config, timeout=30, settle=2, stderr=sys.stderr):
+    """
+    Check that the experiment's environment and controllers work together,
+    without running the experiment.
+
+    Argument config is an experiment configuration, or the path of one.
+
+    Argument timeout is the number of seconds to wait for each program to respond.
+
+    Argument settle is the number of seconds to keep the environment running
+             after the individuals are born, to check that it does not crash.
+
+    Returns a Report.
+    """
+    if not isinstance(config, dict):
+        config = load_config(config)
+    timeout = float(timeout)
+    settle  = float(settle)
+    report  = Report()
+    env_spec = Specification(config["environment"])
+    genomes  = {}
+    develop  = _make_developments(config)
+    try:
+        for name, pop in config["populations"].items():
+            genome = _seed_genome(config, pop)
+            genomes[name] = genome
+            if name in develop:
+                try:
+                    genome = develop[name].submit(genome).result(timeout)
+                    report.add(f"population \"{name}\" develops the seed genome", True)
+                except Exception as error:
+                    report.add(f"population \"{name}\" develops the seed genome", False,
+                               f"{type(error).__name__}: {error}")
+                    continue
+            _check_controller(report, env_spec, name, pop, genome, timeout, stderr)
+    finally:
+        for development in develop.values():
+            development.close()
+    _check_environment(report, config, env_spec, genomes, timeout, settle, stderr)
+    return report
@@ -6,19 +6,25 @@ All global functions in this module are for implementing environment programs.
 
 from pathlib import Path
 import npc_maker.ctrl
+import npc_maker.develop
 import npc_maker.evo
 import collections
 import datetime
+import itertools
 import json
+import math
 import os
+import re
 import shlex
 import subprocess
 import sys
+import threading
 import time
 
 __all__ = (
     "Specification",
     "Environment",
+    "WireLog",
     # "Remote",
     "eprint",
     "get_args",
@@ -41,15 +47,7 @@ def Specification(env_spec_path):
     """
     # Clean up the filesystem path argument.
     env_spec_path = Path(env_spec_path).expanduser().resolve()
-    # Read the file into memory.
-    with open(env_spec_path, 'rt') as env_spec_file:
-        env_spec_data = env_spec_file.read()
-    # Parse the file into a JSON object.
-    try:
-        env_spec = json.loads(env_spec_data)
-    except json.decoder.JSONDecodeError as err:
-        raise ValueError(f"JSON syntax error in \"{env_spec_path}\" {err}")
-    # 
+    env_spec = _read_env_spec(env_spec_path, [])
     _env_spec_check_fields(env_spec, ("name", "path", "populations",), ("spec",))
     # Automatically save the env_spec path into the env_spec.
     env_spec["spec"] = env_spec_path
@@ -73,7 +71,7 @@ def Specification(env_spec_path):
     assert isinstance(env_spec["name"], str)
     assert isinstance(env_spec["populations"], list)
     assert isinstance(env_spec["settings"], list)
-    assert isinstance(env_spec["description"], str)
+    env_spec["description"] = _clean_description(env_spec["description"])
     # Check population objects.
     # assert len(env_spec["populations"]) > 0
     for pop in env_spec["populations"]:
@@ -87,7 +85,7 @@ def Specification(env_spec_path):
         # Check the population's data types.
         assert isinstance(pop["name"], str)
         assert isinstance(pop["interfaces"], list)
-        assert isinstance(pop["description"], str)
+        pop["description"] = _clean_description(pop["description"])
         # Check the interface objects.
         for interface in pop["interfaces"]:
             _env_spec_check_fields(interface, ("gin", "name",))
@@ -95,13 +93,42 @@ def Specification(env_spec_path):
                 ("desc", "description"),
                 ("descr", "description"),])
             if "description" not in interface: interface["description"] = ""
+            if "unit"        not in interface: interface["unit"]        = None
             assert isinstance(interface["name"], str)
+            assert interface["unit"] is None or isinstance(interface["unit"], str)
             assert isinstance(interface["gin"], int)
-            assert isinstance(interface["description"], str)
+            interface["description"] = _clean_description(interface["description"])
         # Check interface names are unique.
         interface_names = [interface["name"] for interface in pop["interfaces"]]
         if len(interface_names) != len(set(interface_names)):
             raise ValueError("duplicate interface names in population specification")
+        # Check the module objects.
+        if "modules" not in pop: pop["modules"] = []
+        assert isinstance(pop["modules"], list)
+        for module in pop["modules"]:
+            _env_spec_check_fields(module, ("name",))
+            if "description" not in module: module["description"] = ""
+            module["description"] = _clean_description(module["description"])
+            if "interfaces"  not in module: module["interfaces"]  = []
+            assert isinstance(module["name"], str)
+            assert isinstance(module["interfaces"], list)
+            for name in module["interfaces"]:
+                if name not in interface_names:
+                    raise ValueError(f"module \"{module['name']}\" has unknown interface \"{name}\"")
+        module_names = [module["name"] for module in pop["modules"]]
+        if len(module_names) != len(set(module_names)):
+            raise ValueError("duplicate module names in population specification")
+        module_interfaces = [name for module in pop["modules"] for name in module["interfaces"]]
+        if len(module_interfaces) != len(set(module_interfaces)):
+            raise ValueError("interface is in multiple modules in population specification")
+        # Check the declared telemetry.
+        if "telemetry" not in pop: pop["telemetry"] = []
+        assert isinstance(pop["telemetry"], list)
+        for item in pop["telemetry"]:
+            _clean_telemetry(item)
+        telemetry_names = [item["name"] for item in pop["telemetry"]]
+        if len(telemetry_names) != len(set(telemetry_names)):
+            raise ValueError("duplicate telemetry name in population specification")
     # Check population names are unique.
     population_names = [pop["name"] for pop in env_spec["populations"]]
     if len(population_names) != len(set(population_names)):
@@ -116,6 +143,60 @@ def Specification(env_spec_path):
     # 
     return env_spec
 
+def _read_env_spec(env_spec_path, stack):
+    """
+    Read a specification file and merge it with the base specification which it extends.
+    Argument stack contains the files which are currently being read, for detecting cycles.
+    """
+    if env_spec_path in stack:
+        raise ValueError(f"cyclic \"extends\" in \"{env_spec_path}\"")
+    # Read the file into memory.
+    with open(env_spec_path, 'rt') as env_spec_file:
+        env_spec_data = env_spec_file.read()
+    # Parse the file into a JSON object.
+    try:
+        env_spec = json.loads(env_spec_data)
+    except json.decoder.JSONDecodeError as err:
+        raise ValueError(f"JSON syntax error in \"{env_spec_path}\" {err}")
+    extends = env_spec.pop("extends", None)
+    if extends is None:
+        return env_spec
+    if not isinstance(extends, str):
+        raise ValueError(f"\"extends\" is not a file path in \"{env_spec_path}\"")
+    extends = Path(extends)
+    base = _read_env_spec(env_spec_path.parent.joinpath(extends).resolve(), stack + [env_spec_path])
+    # The environment program's path is relative to the file which contains it.
+    if isinstance(base.get("path"), str) and not Path(base["path"]).is_absolute():
+        base["path"] = str(extends.parent.joinpath(base["path"]))
+    return _merge_env_spec(base, env_spec, "")
+
+def _merge_env_spec(base, overrides, field):
+    """
+    Merge the overrides into the base. Populations, settings, modules, and
+    telemetry are matched by name, and interfaces are matched by gin.
+    """
+    key = {"populations": "name", "settings": "name", "modules": "name", "telemetry": "name",
+           "interfaces": "gin"}.get(field)
+    if isinstance(base, dict) and isinstance(overrides, dict):
+        for field, value in overrides.items():
+            if field in base:
+                base[field] = _merge_env_spec(base[field], value, field)
+            else:
+                base[field] = value
+        return base
+    elif isinstance(base, list) and isinstance(overrides, list) and key is not None:
+        for item in overrides:
+            existing = [index for index, base_item in enumerate(base)
+                        if isinstance(item, dict) and isinstance(base_item, dict)
+                        and key in item and base_item.get(key) == item[key]]
+            if existing:
+                base[existing[0]] = _merge_env_spec(base[existing[0]], item, "")
+            else:
+                base.append(item)
+        return base
+    else:
+        return overrides
+
 def _env_spec_check_fields(json_object, require_fields=(), reserved_fields=()):
     # Check that it's a JSON object.
     if not isinstance(json_object, dict):
@@ -136,6 +217,34 @@ def _alias_fields(json_object, aliases):
                     f"duplicate fields: \"{abrv}\" and \"{attr}\" in environment specification")
             json_object[attr] = json_object.pop(abrv)
 
+def _clean_description(description):
+    """
+    Descriptions are either a string or a dict of translations keyed by language tag.
+    Lists of strings are concatenated together.
+    """
+    if isinstance(description, list):
+        return "".join(description)
+    elif isinstance(description, dict):
+        return {str(tag): _clean_description(text) for tag, text in description.items()}
+    assert isinstance(description, str)
+    return description
+
+def get_description(description, locale="en"):
+    """
+    Get a description from the environment specification in the given language,
+    for example "de" or "de-AT".
+
+    Falls back to the base language ("de-AT" to "de"), then to English ("en"),
+    and then to any available translation.
+    """
+    if not isinstance(description, dict):
+        return description
+    language = locale.replace("_", "-").split("-")[0]
+    for tag in (locale, language, "en"):
+        if tag in description:
+            return description[tag]
+    return next(iter(description.values()), "")
+
 def _clean_settings(item):
     """ Settings items are strictly / rigidly structured. """
     _env_spec_check_fields(item, ("name", "type", "default",))
@@ -144,7 +253,7 @@ def _clean_settings(item):
     _alias_fields(item, [
         ("desc", "description"),
         ("descr", "description"),])
-    item["description"] = item.get("description", "")
+    item["description"] = _clean_description(item.get("description", ""))
     num_fields += 1
 
     # Normalize the type aliases.
@@ -152,7 +261,10 @@ def _clean_settings(item):
     elif item["type"] == "int":   item["type"] = "Integer"
     elif item["type"] == "bool":  item["type"] = "Boolean"
     elif item["type"] == "enum":  item["type"] = "Enumeration"
-    assert item["type"] in ("Real", "Integer", "Boolean", "Enumeration")
+    elif item["type"] == "path":  item["type"] = "FilePath"
+    elif item["type"] == "list":  item["type"] = "List"
+    elif item["type"] == "color": item["type"] = "Color"
+    assert item["type"] in ("Real", "Integer", "Boolean", "Enumeration", "FilePath", "List", "Color")
 
     # Clean each type variant.
     if item["type"] == "Boolean":
@@ -162,9 +274,9 @@ def _clean_settings(item):
         _env_spec_check_fields(item, ("minimum", "maximum",))
         num_fields += 2
         if item["type"] == "Real":
-            item["default"] = float(item["default"])
-            item["minimum"] = float(item["minimum"])
-            item["maximum"] = float(item["maximum"])
+            item["default"] = _parse_f64(item["default"])
+            item["minimum"] = _parse_f64(item["minimum"])
+            item["maximum"] = _parse_f64(item["maximum"])
         elif item["type"] == "Integer":
             item["default"] = int(item["default"])
             item["minimum"] = int(item["minimum"])
@@ -180,11 +292,67 @@ def _clean_settings(item):
         assert len(item["values"]) == len(set(item["values"]))
         assert item["default"] in item["values"]
 
+    elif item["type"] == "FilePath":
+        item["default"]    = str(item["default"])
+        item["extensions"] = [str(ext) for ext in item.get("extensions", [])]
+        item["exists"]     = bool(item.get("exists", False))
+        num_fields += 2
+
+    elif item["type"] == "List":
+        item["default"] = [str(value) for value in item["default"]]
+
+    elif item["type"] == "Color":
+        item["default"] = str(item["default"])
+        _parse_color(item["default"])
+
     if len(item) > num_fields:
         name = item["name"]
         raise ValueError(
             f"unexpected attributes on setting \"{name}\" in environment specification")
 
+_TELEMETRY_TYPES = {"float": "Real", "int": "Integer", "bool": "Boolean", "string": "String", "str": "String"}
+
+def _clean_telemetry(item):
+    """ Telemetry items declare the info keys which the environment reports about each individual. """
+    _env_spec_check_fields(item, ("name",))
+    _alias_fields(item, [
+        ("desc", "description"),
+        ("descr", "description"),])
+    for field in item:
+        if field not in ("name", "type", "unit", "frequency", "description"):
+            raise ValueError(f"unrecognized field \"{field}\" in telemetry \"{item['name']}\"")
+    assert isinstance(item["name"], str)
+    item["type"] = _TELEMETRY_TYPES.get(item.get("type", "String"), item.get("type", "String"))
+    if item["type"] not in ("Real", "Integer", "Boolean", "String"):
+        raise ValueError(f"unrecognized type \"{item['type']}\" for telemetry \"{item['name']}\"")
+    item["unit"] = item.get("unit")
+    assert item["unit"] is None or isinstance(item["unit"], str)
+    item["frequency"] = item.get("frequency")
+    if item["frequency"] is not None and not float(item["frequency"]) > 0:
+        raise ValueError(f"telemetry \"{item['name']}\" frequency must be positive")
+    item["description"] = _clean_description(item.get("description", ""))
+
+def _check_telemetry(pop_spec, info):
+    """
+    Check the info which an environment reported about an individual against
+    its population's declared telemetry. Populations which do not declare
+    their telemetry accept any info. Raises a ValueError.
+    """
+    telemetry = {item["name"]: item for item in pop_spec["telemetry"]}
+    if not telemetry:
+        return
+    for key, value in info.items():
+        if key not in telemetry:
+            raise ValueError(f"undeclared telemetry \"{key}\" in population \"{pop_spec['name']}\"")
+        data_type = telemetry[key]["type"]
+        value = str(value).strip()
+        try:
+            if   data_type == "Real":    float(value)
+            elif data_type == "Integer": int(value)
+            elif data_type == "Boolean": assert value.lower() in ("true", "false")
+        except (ValueError, AssertionError):
+            raise ValueError(f"telemetry \"{key}\" is not of type {data_type}, found \"{value}\"") from None
+
 def _cast_env_settings(env_spec, settings):
     """ Cast the command line argument settings to the data type specified in the environment specification. """
     settings_list = env_spec.get("settings")
@@ -195,7 +363,7 @@ def _cast_env_settings(env_spec, settings):
         if spec := settings_dict.get(name):
             data_type = spec.get("type")
             if data_type == "Real" or data_type == "float":
-                settings[name] = float(value)
+                settings[name] = _parse_f64(value)
             elif data_type == "Integer" or data_type == "int":
                 settings[name] = int(value)
             elif data_type == "Boolean" or data_type == "bool":
@@ -206,6 +374,79 @@ def _cast_env_settings(env_spec, settings):
                 settings[name] = bool(value)
             elif data_type == "Enumeration" or data_type == "enum":
                 settings[name] = str(value)
+            elif data_type == "FilePath" or data_type == "path":
+                settings[name] = _check_file_path(spec, str(value))
+            elif data_type == "List" or data_type == "list":
+                settings[name] = _parse_list(value)
+            elif data_type == "Color" or data_type == "color":
+                _parse_color(value)
+                settings[name] = str(value)
+
+def _parse_f64(value):
+    """
+    Evaluate a number, symbol, or simple expression, for example "-pi/2" or "2*tau".
+
+    The symbols are "inf", "nan", "pi", "tau", and "e". Expressions are products
+    and quotients evaluated from left to right, with an optional leading minus sign.
+    """
+    if not isinstance(value, str):
+        return float(value)
+    text = value.strip()
+    sign = 1.0
+    if text.startswith("-"):
+        sign = -1.0
+        text = text[1:]
+    symbols = {"inf": math.inf, "nan": math.nan, "pi": math.pi, "tau": math.tau, "e": math.e}
+    result = 1.0
+    for index, term in enumerate(re.split(r"([*/])", text)):
+        if index % 2 == 1:
+            operator = term
+            continue
+        term = term.strip()
+        if term in symbols:
+            term = symbols[term]
+        elif term and term[0] not in "+-":
+            term = float(term)
+        else:
+            raise ValueError(f"invalid number \"{value}\"")
+        if index > 0 and operator == "/":
+            result /= term
+        else:
+            result *= term
+    return sign * result
+
+def _check_file_path(spec, value):
+    """ Check the file extension and existence of a FilePath setting. """
+    path = Path(value)
+    extensions = spec.get("extensions", [])
+    if extensions and path.suffix.lstrip(".").lower() not in [ext.lower() for ext in extensions]:
+        raise ValueError(f"setting \"{spec['name']}\" must have one of the file extensions {extensions}")
+    if spec.get("exists", False) and not path.exists():
+        raise ValueError(f"setting \"{spec['name']}\" file not found \"{path}\"")
+    return value
+
+def _parse_list(value):
+    """ Parse a List setting, which is either a JSON array of strings or a comma separated list. """
+    if isinstance(value, list):
+        return [str(item) for item in value]
+    value = value.strip()
+    if value.startswith("["):
+        value = json.loads(value)
+        assert isinstance(value, list) and all(isinstance(item, str) for item in value)
+        return value
+    elif not value:
+        return []
+    else:
+        return [item.strip() for item in value.split(",")]
+
+def _parse_color(value):
+    """ Parse a Color setting, in the format "#RRGGBB" or "#RGB", into a tuple of (red, green, blue). """
+    hex_digits = value.strip().removeprefix("#")
+    if not value.strip().startswith("#") or len(hex_digits) not in (3, 6):
+        raise ValueError(f"not a color \"{value}\", expected \"#RRGGBB\"")
+    if len(hex_digits) == 3:
+        hex_digits = "".join(digit * 2 for digit in hex_digits)
+    return tuple(int(hex_digits[index : index + 2], 16) for index in (0, 2, 4))
 
 def _help_message(env_spec):
     # Usage.
@@ -213,7 +454,7 @@ def _help_message(env_spec):
 
     # Title and description.
     message = env_spec["name"] + " Environment\n\n"
-    desc = env_spec.get("description", "")
+    desc = get_description(env_spec.get("description", ""))
     if desc:
         message += desc + "\n\n"
 
@@ -241,7 +482,7 @@ def _help_message(env_spec):
             elif item["type"] == "Enumeration":
                 line += ", ".join(item["values"])
             line += " | "
-            line += item["description"]
+            line += get_description(item["description"])
             message += line + "\n"
     return message
 
@@ -301,7 +542,9 @@ class Environment:
                 for population, service in dispatchers.items()}
 
     def __init__(self, services, env_spec, mode='graphical', settings={},
-                 stderr=sys.stderr, timeout=None):
+                 stderr=sys.stderr, timeout=None, record=None, replay=None,
+                 crash_dir=None, crash_history=100, develop=None, wire_log=None,
+                 validate_telemetry=False):
         """
         Start running an environment program.
 
@@ -323,6 +566,38 @@ class Environment:
         Argument timeout is number of seconds to wait for a response from the
                 environment before declaring it dead and raising a TimeoutError.
                 Optional, if missing or None then this will wait forever.
+
+        Argument record is an optional file path or Recording object for
+                 recording every message sent to and received from the environment.
+
+        Argument replay is an optional file path of a recording to play back
+                 instead of running the environment program. The environment
+                 program is not started, instead the recorded messages are
+                 received in the same order as they originally were.
+
+        Argument crash_dir is an optional directory for saving crash reports.
+                 If the environment program exits unexpectedly or times out,
+                 then this saves a report into a new subdirectory, containing:
+                 the last messages sent to and received from the environment,
+                 the individuals who were alive in it, its exit status, and
+                 the tail of its stderr channel (if stderr is sys.stderr).
+
+        Argument crash_history is the number of messages in each direction
+                 and the number of lines of stderr to keep for crash reports.
+
+        Argument develop is an optional dict of developments, indexed by population name,
+                 which transform each individual's genome into the phenome which
+                 is sent to the environment. See "npc_maker.develop".
+
+        Argument wire_log is an optional file path or WireLog object for
+                 logging every message sent to and received from the environment.
+                 Unlike recordings, wire logs are appended to and rotated, and
+                 several environments may share one wire log.
+
+        Argument validate_telemetry checks the info which the environment
+                 reports about each individual against the telemetry declared
+                 in the environment specification, and raises a ValueError
+                 for undeclared keys and for values of the wrong type.
         """
         # Load the environment specification from file.
         self.env_spec = Specification(env_spec)
@@ -355,17 +630,42 @@ class Environment:
             settings_list.append(str(key))
             settings_list.append(str(value))
         # 
-        self._process = subprocess.Popen(
-            [self.env_spec["path"], self.env_spec["spec"], self.mode] + settings_list,
-            stdin  = subprocess.PIPE,
-            stdout = subprocess.PIPE,
-            stderr = stderr)
-        os.set_blocking(self._process.stdout.fileno(), False)
+        self.crash_dir   = None if crash_dir is None else Path(crash_dir)
+        self._has_quit   = False
+        self._crashed    = False
+        self._stderr     = None
+        capture_stderr   = self.crash_dir is not None and stderr is sys.stderr
+        if replay is not None:
+            self._process = _Replay(replay)
+        else:
+            self._process = self._spawn(
+                [self.env_spec["path"], self.env_spec["spec"], self.mode] + settings_list,
+                subprocess.PIPE if capture_stderr else stderr)
+        if record is not None:
+            self._recording = record if isinstance(record, Recording) else Recording(record)
+            self._process.stdin  = _RecordedPipe(self._process.stdin,  self._recording, "send")
+            self._process.stdout = _RecordedPipe(self._process.stdout, self._recording, "recv")
+        self.instance_id = next(_instance_ids)
+        if wire_log is not None:
+            self._wire_log = wire_log if isinstance(wire_log, WireLog) else WireLog(wire_log)
+            instance = _WireLogInstance(self._wire_log, self.instance_id)
+            self._process.stdin  = _RecordedPipe(self._process.stdin,  instance, "send")
+            self._process.stdout = _RecordedPipe(self._process.stdout, instance, "recv")
+        if self.crash_dir is not None:
+            self._history = _History(crash_history)
+            self._process.stdin  = _RecordedPipe(self._process.stdin,  self._history, "send")
+            self._process.stdout = _RecordedPipe(self._process.stdout, self._history, "recv")
+            if capture_stderr:
+                self._stderr = collections.deque(maxlen=crash_history)
+                threading.Thread(target=_tee_stderr, args=(self._process.stderr, self._stderr), daemon=True).start()
         # 
         self.timeout = None if timeout is None else float(timeout)
         self.watchdog = time.time()
+        self.validate_telemetry = bool(validate_telemetry)
         # 
         self.outstanding = {}
+        self.develop     = {str(pop): npc_maker.develop.as_development(dev) for pop, dev in (develop or {}).items()}
+        self._developing = [] # Pairs of (individual, future phenome)
 
     def is_alive(self):
         """
@@ -373,6 +673,24 @@ class Environment:
         """
         return self._process.returncode is None
 
+    def _spawn(self, command, stderr):
+        """
+        Start the environment program.
+
+        Returns an object with the same interface as "subprocess.Popen",
+        with non-blocking pipes for its stdin and stdout.
+        """
+        process = subprocess.Popen(command,
+            stdin  = subprocess.PIPE,
+            stdout = subprocess.PIPE,
+            stderr = stderr,
+            # Run in a separate process group so that pressing Ctrl-C in
+            # the terminal does not interrupt the environment, instead the
+            # NPC Maker decides how to shut it down.
+            process_group = 0 if os.name == "posix" else None)
+        os.set_blocking(process.stdout.fileno(), False)
+        return process
+
     def __del__(self):
         if hasattr(self, "_process"): # Guard against crashes in __init__.
             try:
@@ -419,8 +737,16 @@ class Environment:
 
     def is_alive(self):
         """ Check if the environment program's computer process is still executing. """
+        self._check_exit()
         return self._process.poll() is None
 
+    def _check_exit(self):
+        """ Save a crash report if the environment exited unexpectedly. """
+        if not self._has_quit and not self._crashed and self._process.poll() is not None:
+            self._crashed = True
+            if self.crash_dir is not None:
+                self.crash_report(self.crash_dir, "exited unexpectedly")
+
     def start(self):
         """
         Request to start the environment.
@@ -453,12 +779,70 @@ class Environment:
         """
         Request to quit the environment.
         """
+        self._has_quit = True
+        for development in self.develop.values():
+            development.close()
         try:
             self._process.stdin.write(b'"Quit"\n')
             self._process.stdin.flush()
         except BrokenPipeError:
             pass
 
+    def crash_report(self, crash_dir, reason=""):
+        """
+        Save a report about the current state of the environment, for diagnosing crashes.
+
+        This is called automatically if the "crash_dir" argument was given.
+
+        Returns the path of the new report directory.
+        """
+        name = datetime.datetime.now(datetime.timezone.utc).strftime("%Y%m%d-%H%M%S-%f")
+        path = Path(crash_dir).joinpath(name)
+        path.mkdir(parents=True)
+        report = {
+            "reason":       reason,
+            "time":         _timestamp(),
+            "environment":  self.env_spec["name"],
+            "env_spec":     str(self.env_spec["spec"]),
+            "mode":         self.mode,
+            "settings":     self.settings,
+            "exit_code":    self._process.poll(),
+            "outstanding":  list(self.outstanding.keys()),
+        }
+        with open(path.joinpath("report.json"), 'wt') as file:
+            json.dump(report, file, indent=4)
+        history = getattr(self, "_history", None)
+        if history is not None:
+            for direction in ("send", "recv"):
+                with open(path.joinpath(direction + ".txt"), 'wt') as file:
+                    file.writelines(line + "\n" for line in history.lines[direction])
+        if self._stderr is not None:
+            with open(path.joinpath("stderr.txt"), 'wt') as file:
+                file.writelines(list(self._stderr))
+        if self.outstanding:
+            individuals = path.joinpath("individuals")
+            individuals.mkdir()
+            for individual in self.outstanding.values():
+                saved_path = individual.path
+                individual.save(individuals)
+                individual.path = saved_path
+        return path
+
+    def wait(self, timeout=None):
+        """
+        Wait for the environment program to exit, for example after calling "quit()".
+
+        Argument timeout is the number of seconds to wait before forcibly
+                 terminating the environment program.
+
+        Returns the environment program's exit code.
+        """
+        try:
+            return self._process.wait(timeout)
+        except subprocess.TimeoutExpired:
+            self._process.terminate()
+            return self._process.wait()
+
     def save(self, path):
         """
         Request to save the environment to the given path.
@@ -495,7 +879,7 @@ class Environment:
         pop     = individual.get_population()
         name    = individual.get_name()
         ctrl    = individual.get_controller()
-        genome  = individual.get_genome()
+        genome  = individual.get_genome() if individual.phenome is None else individual.phenome
         parents = individual.get_parents()
         if ctrl is None:
             raise ValueError("indiviual is missing controller")
@@ -506,6 +890,59 @@ class Environment:
             .format(env, pop, name, json.dumps(ctrl), json.dumps(genome), json.dumps(parents))
             .encode("utf-8"))
         individual.birth_date = _timestamp()
+        individual._send_time = time.monotonic()
+        if individual._request_time is not None:
+            individual.queue_time = individual._send_time - individual._request_time
+
+    def _request_birth(self, population, parents, request_time=None):
+        """
+        Respond to the environment's request for a new individual.
+        Subclasses may override this to defer the request, see "npc_maker.pool".
+        Does not flush.
+
+        Argument request_time is the time.monotonic() when the environment
+                 made the request, by default now.
+        """
+        if request_time is None:
+            request_time = time.monotonic()
+        child = self.services[population].birth(parents)
+        if not isinstance(child, npc_maker.evo.Individual):
+            child = npc_maker.evo.Individual(**child)
+        child.environment   = self.env_spec["name"]
+        child.population    = population
+        child._request_time = request_time
+        development = self.develop.get(population)
+        if development is None:
+            self._birth(child)
+        else:
+            self.outstanding[child.get_name()] = child
+            self._developing.append((child, development.submit(child.get_genome())))
+
+    def _finish_developing(self):
+        """
+        Send the individuals who have finished developing.
+        Individuals who failed to develop die without being born.
+        Does not flush.
+        """
+        developing = []
+        for child, future in self._developing:
+            if not future.done():
+                developing.append((child, future))
+                continue
+            try:
+                child.phenome = future.result()
+            except Exception as error:
+                name = child.get_name()
+                print(f"npc_maker: individual {name} failed to develop: {error}", file=sys.stderr)
+                self.outstanding.pop(name)
+                child.score = None
+                child.info["development_error"] = str(error)
+                child.death_date = _timestamp()
+                child.name = None
+                self.services[child.get_population()].death(child)
+            else:
+                self._birth(child)
+        self._developing = developing
 
     def poll(self):
         """
@@ -513,24 +950,21 @@ class Environment:
 
         This function is non-blocking and should be called periodically.
         """
-        def make_child(pop, parents):
-            service = self.services[pop]
-            child = service.birth(parents)
-            if not isinstance(child, npc_maker.evo.Individual):
-                child = npc_maker.evo.Individual(**child)
-            child.environment   = self.env_spec["name"]
-            child.population    = pop
-            return child
-
+        if self._developing:
+            self._finish_developing()
         # Limit the number of messages received to avoid blocking the main thread.
         for _ in range(100):
             # Check for messages.
             message = self._process.stdout.readline().strip()
             if not message:
+                self._check_exit()
                 # Check for environment timeout.
                 if self.timeout:
                     elapsed_time = time.time() - self.watchdog
                     if elapsed_time > 1.5 * self.timeout:
+                        if self.crash_dir is not None and not self._crashed:
+                            self._crashed = True
+                            self.crash_report(self.crash_dir, "timed out")
                         raise TimeoutError("environment timed out")
                     elif elapsed_time > 0.5 * self.timeout:
                         self._process.stdin.write(b'"Heartbeat"\n')
@@ -549,16 +983,14 @@ class Environment:
                         pop = all_populations[0]["name"]
                     else:
                         raise ValueError("missing field \"populations\"")
-                child = make_child(pop, [])
-                self._birth(child)
+                self._request_birth(pop, [])
 
             elif "Mate" in message:
                 parents = message["Mate"]
                 parents = [self.outstanding[p] for p in parents]
                 pop     = parents[0].get_population()
                 assert all(p.get_population() == pop for p in parents)
-                child   = make_child(pop, parents)
-                self._birth(child)
+                self._request_birth(pop, parents)
 
             elif "Score" in message:
                 score       = message["Score"]
@@ -570,12 +1002,18 @@ class Environment:
                 info        = message["Info"]
                 name        = message["name"]
                 individual  = self.outstanding[name]
+                if self.validate_telemetry:
+                    population = individual.get_population()
+                    pop_spec = next(pop for pop in self.env_spec["populations"] if pop["name"] == population)
+                    _check_telemetry(pop_spec, info)
                 individual.info.update(info)
 
             elif "Death" in message:
                 name                    = message["Death"]
                 individual              = self.outstanding.pop(name)
-                individual.deathdate    = _timestamp()
+                individual.death_date   = _timestamp()
+                if individual._send_time is not None:
+                    individual.evaluation_time = time.monotonic() - individual._send_time
                 individual.name         = None
                 population              = individual.get_population()
                 self.services[population].death(individual)
@@ -640,6 +1078,258 @@ class Environment:
         Triggered by "ack" responses.
         """
 
+class Recording:
+    """
+    Log of all of the messages between an environment and the NPC Maker.
+
+    Recordings are NDJSON files, with one JSON object per line. The first line
+    is a header with arbitrary metadata, for example the seed of the random
+    number generator. Each following line has the fields:
+        "time": the UTC timestamp of the message,
+        "direction": either "send" (to the environment) or "recv" (from the environment),
+        "line": the message, as it was sent or received.
+    """
+    def __init__(self, path, header={}):
+        self.path = Path(path)
+        self._file = open(self.path, 'wt')
+        self._file.write(json.dumps(dict(header)) + "\n")
+        self._file.flush()
+
+    def write(self, direction, line):
+        """ Append a message to the recording. """
+        event = {"time": time.time(), "direction": direction, "line": line}
+        self._file.write(json.dumps(event) + "\n")
+
+    def flush(self):
+        if not self._file.closed:
+            self._file.flush()
+
+    def close(self):
+        self._file.close()
+
+    @staticmethod
+    def read(path):
+        """
+        Read a recording.
+        Returns the pair of (header, events).
+        """
+        with open(path, 'rt') as file:
+            lines = [json.loads(line) for line in file if line.strip()]
+        if not lines:
+            raise ValueError(f"empty recording, in file: {path}")
+        return (lines[0], lines[1:])
+
+class WireLog:
+    """
+    Append-only log of all of the messages between environments and the NPC Maker,
+    for finding out what was actually said during a failed run.
+
+    Wire logs are NDJSON files, with one JSON object per line:
+        "time": the UTC timestamp of the message,
+        "instance": the "instance_id" of the Environment which sent or received it,
+        "direction": either "send" (to the environment) or "recv" (from the environment),
+        "line": the message, as it was sent or received.
+
+    When the file grows larger than max_bytes it is renamed with the suffix ".1",
+    the previous ".1" file is renamed to ".2", and so on up to the given number
+    of backups, and the oldest file is deleted.
+    """
+    def __init__(self, path, max_bytes=100_000_000, backups=5):
+        self.path      = Path(path)
+        self.max_bytes = None if max_bytes is None else int(max_bytes)
+        self.backups   = int(backups)
+        self._lock     = threading.Lock()
+        self._file     = open(self.path, 'at', buffering=1)
+
+    def write(self, instance, direction, line):
+        """ Append a message to the log. """
+        event = json.dumps({"time": time.time(), "instance": instance, "direction": direction, "line": line})
+        with self._lock:
+            if self._file.closed:
+                return
+            if self.max_bytes is not None and self._file.tell() + len(event) + 1 > self.max_bytes:
+                self._rotate()
+            self._file.write(event + "\n")
+
+    def _rotate(self):
+        self._file.close()
+        backup = lambda index: self.path.with_name(f"{self.path.name}.{index}")
+        if self.backups > 0:
+            backup(self.backups).unlink(missing_ok=True)
+            for index in range(self.backups - 1, 0, -1):
+                if backup(index).exists():
+                    backup(index).rename(backup(index + 1))
+            self.path.rename(backup(1))
+        else:
+            self.path.unlink(missing_ok=True)
+        self._file = open(self.path, 'at', buffering=1)
+
+    def flush(self):
+        with self._lock:
+            if not self._file.closed:
+                self._file.flush()
+
+    def close(self):
+        with self._lock:
+            self._file.close()
+
+    @staticmethod
+    def read(path):
+        """ Read a wire log, without its rotated backups. Returns a list of events. """
+        with open(path, 'rt') as file:
+            return [json.loads(line) for line in file if line.strip()]
+
+_instance_ids = itertools.count()
+
+class _WireLogInstance:
+    """ Adapter for writing one environment's messages into a shared wire log. """
+    def __init__(self, wire_log, instance):
+        self.wire_log = wire_log
+        self.instance = instance
+
+    def write(self, direction, line):
+        self.wire_log.write(self.instance, direction, line)
+
+    def flush(self):
+        self.wire_log.flush()
+
+class _History:
+    """ The most recent messages in each direction, for crash reports. """
+    def __init__(self, maxlen):
+        self.lines = {"send": collections.deque(maxlen=maxlen),
+                      "recv": collections.deque(maxlen=maxlen)}
+
+    def write(self, direction, line):
+        self.lines[direction].append(line)
+
+    def flush(self):
+        pass
+
+def _tee_stderr(pipe, tail):
+    """ Copy the environment's stderr to this program's stderr, and keep its tail. """
+    for line in iter(pipe.readline, b""):
+        line = line.decode("utf-8", errors="replace")
+        tail.append(line)
+        sys.stderr.write(line)
+        sys.stderr.flush()
+
+class _RecordedPipe:
+    """
+    Wrapper around one of the environment's standard I/O channels,
+    which copies every message into a recording.
+    """
+    def __init__(self, pipe, recording, direction):
+        self._pipe      = pipe
+        self._recording = recording
+        self._direction = direction
+
+    def write(self, data):
+        for line in data.decode("utf-8").splitlines():
+            self._recording.write(self._direction, line)
+        return self._pipe.write(data)
+
+    def readline(self):
+        data = self._pipe.readline()
+        if data and data.strip():
+            self._recording.write(self._direction, data.decode("utf-8").strip())
+        return data
+
+    def flush(self):
+        self._recording.flush()
+        self._pipe.flush()
+
+    def close(self):
+        self._recording.flush()
+        self._pipe.close()
+
+class _Replay:
+    """
+    Stand-in for the environment's subprocess, which plays back a recording.
+
+    Each recorded message from the environment is only received after all of
+    the messages which preceded it have been sent, so that the messages are
+    replayed in the same order as they were recorded. Individuals get new
+    names when they're born, so the names in the recording are translated.
+    """
+    def __init__(self, path):
+        self.header, events = Recording.read(path)
+        self.returncode = None
+        self._received  = collections.deque()   # Pairs of (number of sends before it, line)
+        self._births    = collections.deque()   # Recorded names, in order of birth
+        self._names     = {}                    # Recorded name -> new name
+        self._num_sent  = 0
+        num_sends = 0
+        for event in events:
+            if event["direction"] == "send":
+                message = json.loads(event["line"])
+                if message == "Heartbeat":
+                    continue
+                num_sends += 1
+                if isinstance(message, dict) and "Birth" in message:
+                    self._births.append(message["Birth"]["name"])
+            else:
+                self._received.append((num_sends, event["line"]))
+        self.stdin  = _ReplayInput(self)
+        self.stdout = _ReplayOutput(self)
+
+    def _send(self, line):
+        message = json.loads(line)
+        if message == "Heartbeat":
+            return
+        self._num_sent += 1
+        if isinstance(message, dict) and "Birth" in message and self._births:
+            self._names[self._births.popleft()] = message["Birth"]["name"]
+
+    def _receive(self):
+        if not self._received:
+            self.returncode = 0
+            return b""
+        (num_sends, line) = self._received[0]
+        if num_sends > self._num_sent:
+            return b""
+        self._received.popleft()
+        message = self._translate(json.loads(line))
+        return (json.dumps(message) + "\n").encode("utf-8")
+
+    def _translate(self, message):
+        if isinstance(message, str):
+            return self._names.get(message, message)
+        elif isinstance(message, list):
+            return [self._translate(item) for item in message]
+        elif isinstance(message, dict):
+            return {key: self._translate(value) for key, value in message.items()}
+        else:
+            return message
+
+    def poll(self):
+        return self.returncode
+
+    def terminate(self):
+        self.returncode = 0
+
+    def wait(self, timeout=None):
+        self.returncode = 0
+        return self.returncode
+
+class _ReplayInput:
+    def __init__(self, replay):
+        self._replay = replay
+    def write(self, data):
+        for line in data.decode("utf-8").splitlines():
+            self._replay._send(line)
+    def flush(self):
+        pass
+    def close(self):
+        pass
+
+class _ReplayOutput:
+    def __init__(self, replay):
+        self._replay = replay
+    def readline(self):
+        return self._replay._receive()
+    def close(self):
+        pass
+
 class Remote(Environment):
     """
     Run an instance of an environment over an SSH connection.
@@ -766,7 +1456,10 @@ def ack(message):
     if "Birth" in message:
         pass # Birth messages shouldn't be acknowledged.
     else:
-        assert message in ("Heartbeat","Load","Message","Pause","Quit","Resume","Save","Start","Stop")
+        if isinstance(message, dict):
+            assert len(message) == 1 and next(iter(message)) in ("Load","Message","Save")
+        else:
+            assert message in ("Heartbeat","Pause","Quit","Resume","Start","Stop")
         response = json.dumps({"Ack": message})
         _try_print(response)
 
@@ -4,24 +4,252 @@ Evolution API, for making and using evolution services.
 
 from os.path import getmtime
 from pathlib import Path
+import atexit
 import collections
 import copy
-import heapq
+import functools
+import hashlib
+import npc_maker.initial
+import npc_maker.replacement
+import npc_maker.selection
 import json
 import random
 import math
+import os
+import queue
 import shlex
+import shutil
+import statistics
 import tempfile
+import threading
+import time
 import uuid
 
 __all__ = (
+    "CorruptFileError",
     "Individual",
     "API",
     "Recorder",
     "Replayer",
     "Evolution",
+    "Coevolution",
 )
 
+class CorruptFileError(ValueError):
+    """ Raised when loading a saved file which was truncated or modified. """
+
+def _dump_path(obj):
+    """ Convert paths to strings for JSON serialization. """
+    if isinstance(obj, Path):
+        return str(obj)
+    else:
+        raise TypeError
+
+def _checksum(data):
+    """ Returns the checksum of a JSON object, which is the SHA-256 hash of its canonical serialization. """
+    text = json.dumps(data, default=_dump_path, sort_keys=True, separators=(",", ":"))
+    return "sha256:" + hashlib.sha256(text.encode("utf-8")).hexdigest()
+
+def _file_hash(path):
+    """ Returns the SHA-256 hash of a file's contents. """
+    with open(path, 'rb') as file:
+        return "sha256:" + hashlib.sha256(file.read()).hexdigest()
+
+def _link_or_copy(source, destination):
+    """ Hard link the file, or copy it if the file system does not support links. """
+    try:
+        os.link(source, destination)
+    except OSError:
+        shutil.copy2(source, destination)
+
+def _presolve_score(individual, score_function):
+    """
+    Call the user defined parts of a custom score function, so that they are
+    not called while holding a lock. Returns a score function which gives the
+    same score for the individual, see "Individual.get_custom_score()".
+    """
+    if isinstance(score_function, (list, tuple)):
+        return [_presolve_score(individual, objective) for objective in score_function]
+    elif callable(score_function):
+        score = score_function(individual)
+        return lambda _individual: score
+    else:
+        return score_function
+
+def _fsync_dir(path):
+    """ Flush a directory to disk, so that the files which were renamed into it are durable. """
+    if os.name == "nt":
+        return # Windows can not open directories.
+    fd = os.open(path, os.O_RDONLY)
+    try:
+        os.fsync(fd)
+    finally:
+        os.close(fd)
+
+def _write_atomic(path, text):
+    """
+    Write to a temporary file and then rename it, so that the file is never
+    left half written if this program is interrupted or the computer crashes.
+    """
+    temp = path.with_suffix(".tmp")
+    with open(temp, 'wt') as file:
+        file.write(text)
+        file.flush()
+        os.fsync(file.fileno())
+    os.replace(temp, path)
+    _fsync_dir(path.parent)
+
+class _FileQueue:
+    """
+    Orders the file operations of an evolution service, so that they can be
+    performed outside of its lock.
+
+    Operations are submitted while holding the lock, and are then performed
+    either by the calling thread or by a background thread. Operations on the
+    same file are always performed in the order that they were submitted.
+    Errors from the background thread are raised by the next method call.
+    """
+    def __init__(self, capacity=0):
+        self._order = {} # Path -> deque of the pending operations on the file
+        self._done  = threading.Condition()
+        self._error = None
+        self._queue = None
+        if capacity:
+            self._queue = queue.Queue(maxsize=int(capacity))
+            thread = threading.Thread(target=self._run, name="npc_maker writer", daemon=True)
+            thread.start()
+            # Finish writing before the program exits.
+            atexit.register(self._queue.join)
+
+    @property
+    def background(self):
+        return self._queue is not None
+
+    def busy(self):
+        """ Are any operations pending? """
+        with self._done:
+            return bool(self._order)
+
+    def submit(self, kind, path, text=None):
+        """
+        Register an operation: "read" loads an Individual, "write" saves the
+        text, "append" adds the text to the end of the file, and "unlink"
+        deletes the file. Returns the operation for
+        "perform()". Writes and deletions are queued for the background thread
+        if there is one, blocking while the queue is full.
+        """
+        self.check()
+        operation = (kind, path, text)
+        with self._done:
+            self._order.setdefault(path, collections.deque()).append(operation)
+        if self.background and kind != "read":
+            self._queue.put(operation)
+        return operation
+
+    def perform(self, operations):
+        """
+        Perform operations, waiting for any earlier operations on the same files.
+        Operations which are queued for the background thread are skipped.
+        Returns a list of the individuals which were read.
+        """
+        results = []
+        error   = None
+        for operation in operations:
+            if self.background and operation[0] != "read":
+                continue
+            try:
+                result = self._execute(operation)
+            except Exception as exception:
+                error = error or exception
+            else:
+                if result is not None:
+                    results.append(result)
+        if error is not None:
+            raise error
+        return results
+
+    def _execute(self, operation):
+        kind, path, text = operation
+        with self._done:
+            while self._order[path][0] is not operation:
+                self._done.wait()
+        try:
+            if kind == "read":
+                return Individual.load(path)
+            elif kind == "write":
+                _write_atomic(path, text)
+            elif kind == "append":
+                with open(path, 'at') as file:
+                    file.write(text)
+            elif kind == "unlink":
+                path.unlink()
+        finally:
+            with self._done:
+                self._order[path].popleft()
+                if not self._order[path]:
+                    del self._order[path]
+                self._done.notify_all()
+
+    def _run(self):
+        while True:
+            operation = self._queue.get()
+            try:
+                self._execute(operation)
+            except Exception as error:
+                with self._done:
+                    if self._error is None:
+                        self._error = error
+            finally:
+                self._queue.task_done()
+
+    def check(self):
+        """ Raise the first error which occurred in the background since the last check. """
+        with self._done:
+            error, self._error = self._error, None
+        if error is not None:
+            raise error
+
+    def flush(self):
+        """ Wait for all pending operations to finish. """
+        if self.background:
+            self._queue.join()
+        with self._done:
+            while self._order:
+                self._done.wait()
+        self.check()
+
+def _float_score(score):
+    """ Convert a score to a float, or None if it is not a number. """
+    try:
+        score = float(score)
+    except (TypeError, ValueError):
+        return None
+    return None if math.isnan(score) else score
+
+def _leader_score(score):
+    """ Convert a score to a float, or a tuple of floats, where invalid scores are negative infinity. """
+    if isinstance(score, (list, tuple)):
+        return tuple(_leader_score(objective) for objective in score)
+    score = _float_score(score)
+    return -math.inf if score is None else score
+
+def _load_checked(path):
+    """
+    Read a JSON object from a file, and verify its checksum if it has one.
+    The checksum is removed from the returned object.
+    """
+    try:
+        with open(path, 'rt') as file:
+            data = json.load(file)
+    except (json.JSONDecodeError, UnicodeDecodeError) as error:
+        raise CorruptFileError(f"corrupt file, it is truncated or it is not JSON: {error}, in file: {path}") from None
+    if not isinstance(data, dict):
+        raise CorruptFileError(f"corrupt file, expected a JSON object, in file: {path}")
+    checksum = data.pop("checksum", None)
+    if checksum is not None and checksum != _checksum(data):
+        raise CorruptFileError(f"corrupt file, the checksum does not match its contents, in file: {path}")
+    return data
+
 def _clean_ctrl_command(command):
     if command is None:
         return None
@@ -54,12 +282,22 @@ class Individual:
                 birth_date=None,
                 death_date=None,
                 ascension=None,
+                evaluation_time=None,
+                queue_time=None,
+                provenance=None,
+                species=None,
+                parent_uuids=None,
+                scores=None,
+                rescored=None,
+                settings_hash=None,
                 **extras):
         self.name           = str(uuid.uuid4())
+        self.uuid           = self.name
         self.environment    = str(environment) if environment is not None else None
         self.population     = str(population) if population is not None else None
         self.controller     = _clean_ctrl_command(controller)
         self.genome         = genome
+        self.phenome        = None
         self.score          = score
         self.info           = dict(info)
         self.parents        = parents
@@ -67,8 +305,18 @@ class Individual:
         self.birth_date     = birth_date
         self.death_date     = death_date
         self.ascension      = ascension
+        self.evaluation_time = evaluation_time
+        self.queue_time     = queue_time
+        self.provenance     = provenance
+        self.species        = species
+        self.scores         = None if scores is None else list(scores)
+        self.rescored       = rescored
+        self.settings_hash  = settings_hash
+        self.parent_uuids   = None if parent_uuids is None else [str(parent) for parent in parent_uuids]
         self.extras         = {}
         self.path           = None
+        self._request_time  = None # time.monotonic() of the environment's request
+        self._send_time     = None # time.monotonic() of sending the individual
 
     def get_environment(self):
         """
@@ -90,6 +338,16 @@ class Individual:
         """
         return self.name
 
+    def get_uuid(self):
+        """
+        Get this individual's permanent name, which is a UUID string.
+
+        This is the same as the individual's name, except that it is kept
+        after the individual dies, for tracing its lineage.
+        Returns None for individuals which were saved without one.
+        """
+        return self.uuid
+
     def get_controller(self):
         """
         Get the command line invocation for the controller program.
@@ -105,6 +363,15 @@ class Individual:
         """
         return self.genome
 
+    def get_phenome(self):
+        """
+        Get the developed form of this individual's genome, which was sent to
+        the environment in place of the genome, see "npc_maker.develop".
+
+        Returns None if this individual has not developed.
+        """
+        return self.phenome
+
     def get_score(self):
         """
         Get the most recently assigned score,
@@ -112,6 +379,43 @@ class Individual:
         """
         return self.score
 
+    def get_scores(self):
+        """
+        Get the score of every evaluation of this individual, or None if it
+        was only evaluated once. The score is the mean of these scores.
+
+        Evolution services can evaluate the members of their population
+        several times, see the argument "reevaluate" of "npc_maker.evo.Evolution".
+        """
+        return self.scores
+
+    def get_confidence_interval(self, confidence=0.95):
+        """
+        Get the confidence interval of the mean score, as a pair of (low, high).
+        This assumes that the scores are normally distributed.
+        Returns None if this individual was evaluated fewer than two times.
+        """
+        if not self.scores or len(self.scores) < 2:
+            return None
+        mean  = sum(self.scores) / len(self.scores)
+        stdev = statistics.stdev(self.scores)
+        error = statistics.NormalDist().inv_cdf(0.5 + confidence / 2) * stdev / math.sqrt(len(self.scores))
+        return (mean - error, mean + error)
+
+    def get_rescored(self):
+        """
+        Get the generation when this individual was last rescored,
+        or None if it was never rescored, see "npc_maker.evo.Evolution.rescore()".
+        """
+        return self.rescored
+
+    def get_settings_hash(self):
+        """
+        Get the hash of the environment settings which this individual was
+        scored with, or None if they were not recorded.
+        """
+        return self.settings_hash
+
     def get_custom_score(self, score_function):
         """
         Apply a custom scoring function to this individual.
@@ -125,8 +429,12 @@ class Individual:
             * The word "ascension",
             * A key in the individual's info dictionary. The corresponding value
               will be converted in to a float.
+            * A list of any of the above, for multi-objective optimization.
+              This returns a tuple with one value for each objective.
         """
-        if callable(score_function):
+        if isinstance(score_function, (list, tuple)):
+            return tuple(self.get_custom_score(objective) for objective in score_function)
+        elif callable(score_function):
             return score_function(self)
         elif not score_function or score_function == "score":
             return self.score
@@ -158,6 +466,15 @@ class Individual:
         """
         return self.parents
 
+    def get_parent_uuids(self):
+        """
+        Get the permanent names of this individual's parents, see "get_uuid()".
+
+        Returns a list of UUID strings, which is empty for individuals who were
+        made from scratch, or None if the individual's parents are unknown.
+        """
+        return self.parent_uuids
+
     def get_children(self):
         """
         How many children does this individual have?
@@ -178,6 +495,42 @@ class Individual:
         """
         return self.death_date
 
+    def get_evaluation_time(self):
+        """
+        How many seconds was this individual alive in the environment?
+        Measured from sending it to the environment until its death.
+        Returns None if this individual has not yet died.
+        """
+        return self.evaluation_time
+
+    def get_queue_time(self):
+        """
+        How many seconds did the environment wait for this individual?
+        Measured from the environment's request for a new individual until
+        sending it, which includes any time spent waiting in the queue of an
+        environment pool and developing the genome.
+        Returns None if this individual has not yet been born.
+        """
+        return self.queue_time
+
+    def get_provenance(self):
+        """
+        Get the hashes of the programs which evaluated this individual,
+        as a dictionary with the keys "environment" and "controller",
+        or None if the programs were not pinned.
+        """
+        return self.provenance
+
+    def get_species(self):
+        """
+        Get the name of the species which this individual belongs to,
+        or None if its population is not divided into species.
+
+        The attribute "individual.species" is set by the evolution service,
+        see "npc_maker.speciation".
+        """
+        return self.species
+
     def get_ascension(self):
         """
         How many individuals died before this individual?
@@ -204,25 +557,43 @@ class Individual:
         """
         return self.path
 
-    def save(self, path):
+    def save(self, path, pretty=False):
         """
         Serialize this individual to JSON and write it to a file.
 
         Argument path is the directory to save in.
 
+        Argument pretty writes the metadata as indented JSON with sorted keys,
+                 followed by the genome on a single line, for human inspection
+                 and stable diffs. Otherwise the whole file is compact JSON.
+
+        The file contains a checksum of its contents, which is verified when
+        it is loaded. Files which were edited by hand will fail to load unless
+        their "checksum" field is removed.
+
         The filename will be either the individual's name or its ascension number,
         and the file extension will be ".json"
 
         Returns the save file's path.
         """
+        filename, text = self._serialize(pretty)
+        path = Path(path).joinpath(filename)
+        _write_atomic(path, text)
+        self.path = path
+        return path
+
+    def _filename(self):
+        """ Returns the name of this individual's save file, without the file extension. """
         if self.name is not None:
-            filename = self.name
+            return self.name
         elif self.ascension is not None:
-            filename = str(self.ascension)
+            return str(self.ascension)
         else:
             raise ValueError("individual has neither name nor ascension")
-        path = Path(path)
-        path = path.joinpath(filename + ".json")
+
+    def _serialize(self, pretty=False):
+        """ Returns the pair of (filename, file contents) for saving this individual. """
+        filename = self._filename()
         # Required fields.
         data = {"genome": self.genome}
         # Optional fields.
@@ -232,32 +603,40 @@ class Individual:
         if self.controller is not None:  data["controller"]  = self.controller
         if self.death_date is not None:  data["death_date"]  = self.death_date
         if self.environment is not None: data["environment"] = self.environment
+        if self.evaluation_time is not None: data["evaluation_time"] = self.evaluation_time
         if self.info is not None:        data["info"]        = self.info
         if self.name is not None:        data["name"]        = self.name
         if self.parents is not None:     data["parents"]     = self.parents
+        if self.parent_uuids is not None: data["parent_uuids"] = self.parent_uuids
         if self.population is not None:  data["population"]  = self.population
+        if self.provenance is not None:  data["provenance"]  = self.provenance
+        if self.queue_time is not None:  data["queue_time"]  = self.queue_time
         if self.score is not None:       data["score"]       = self.score
+        if self.scores is not None:      data["scores"]      = self.scores
+        if self.rescored is not None:    data["rescored"]    = self.rescored
+        if self.settings_hash is not None: data["settings_hash"] = self.settings_hash
+        if self.species is not None:     data["species"]     = self.species
+        if self.uuid is not None:        data["uuid"]        = self.uuid
         # Unofficial fields.
         data.update(self.extras)
-        # Convert paths to strings for JSON serialization.
-        def dump_path(obj):
-            if isinstance(obj, Path):
-                return str(obj)
-            else:
-                raise TypeError
-        # 
-        with open(path, 'wt') as file:
-            json.dump(data, file, default=dump_path)
-        self.path = path
-        return path
+        data["checksum"] = _checksum(data)
+        if pretty:
+            genome   = json.dumps(data.pop("genome"), default=_dump_path)
+            metadata = json.dumps(data, default=_dump_path, indent=4, sort_keys=True)
+            metadata = metadata[:-2] + ",\n" if data else "{\n"
+            text = f'{metadata}    "genome": {genome}\n}}\n'
+        else:
+            text = json.dumps(data, default=_dump_path)
+        return (filename + ".json", text)
 
     def load(path, **kwargs):
         """
         Load a previously saved individual.
+
+        Raises a CorruptFileError if the file was truncated or modified.
         """
         path = Path(path)
-        with open(path, 'rt') as file:
-            data = json.load(file)
+        data = _load_checked(path)
         # 
         individual = Individual(data.pop("genome"), **kwargs)
         individual.path = path
@@ -271,11 +650,20 @@ class Individual:
         individual.controller  = data.pop("controller",  individual.controller)
         individual.death_date  = data.pop("death_date",  individual.death_date)
         individual.environment = data.pop("environment", individual.environment)
+        individual.evaluation_time = data.pop("evaluation_time", individual.evaluation_time)
         individual.info        = data.pop("info",        individual.info)
         individual.name        = data.pop("name",        individual.name)
         individual.parents     = data.pop("parents",     individual.parents)
+        individual.parent_uuids = data.pop("parent_uuids", individual.parent_uuids)
         individual.population  = data.pop("population",  individual.population)
+        individual.provenance  = data.pop("provenance",  individual.provenance)
+        individual.queue_time  = data.pop("queue_time",  individual.queue_time)
         individual.score       = data.pop("score",       individual.score)
+        individual.scores      = data.pop("scores",      individual.scores)
+        individual.rescored    = data.pop("rescored",    individual.rescored)
+        individual.settings_hash = data.pop("settings_hash", individual.settings_hash)
+        individual.species     = data.pop("species",     individual.species)
+        individual.uuid        = data.pop("uuid",        None)
         # Convert controller program from string to path.
         if individual.controller is not None:
             individual.controller[0] = Path(individual.controller[0])
@@ -317,7 +705,7 @@ class Recorder(API):
     another evolution API instance
     """
     def __init__(self, service, path=None, leaderboard=None,
-                 score="score", filters={}):
+                 score="score", filters={}, sharing=None, comparator=None, pretty=False):
         """
         Argument service is the underlying evolution API instance to record from.
 
@@ -337,6 +725,24 @@ class Recorder(API):
                  * The key is the name of the directory to save to.
                  * The value is a callable function: f(individual) -> bool,
                    where returning True will save the individual, False will reject it.
+
+        Argument sharing is an optional "npc_maker.sharing.FitnessSharing",
+                 which ranks the leaderboard by the scores after fitness sharing
+                 between the members of the leaderboard. The saved scores are
+                 not changed.
+
+        Argument comparator is an optional function for ordering the leaderboard:
+                 f(individual, individual) -> int, which returns a negative
+                 number if the first individual is worse than the second, zero if
+                 they are equal, or a positive number if it is better.
+                 By default individuals are compared by their scores, where
+                 missing and NaN scores are the worst.
+                 In either case ties are broken by ascension, so the older
+                 individual ranks higher. This keeps the leaderboard the same
+                 after it is reloaded.
+
+        Argument pretty saves the individuals in the human readable format,
+                 see "Individual.save()".
         """
         """
         Argument hall_of_fame is the number of individuals in each generation /
@@ -368,12 +774,18 @@ class Recorder(API):
         # self.hall_of_fame   = int(hall_of_fame) if hall_of_fame is not None else 0
         self.score          = score
         self.filters        = dict(filters)
+        self.sharing        = sharing
+        self._features      = {} # Ascension -> fitness sharing feature
+        self.comparator     = comparator
+        self._leaders       = {} # Ascension -> Individual, for the comparator
+        self.pretty         = bool(pretty)
         # self.statistics     = dict(statistics)
         # self.histograms     = dict(histograms)
 
         assert isinstance(service, API)
         assert self._path.is_dir()
         assert self.leaderboard >= 0
+        assert self.comparator is None or callable(self.comparator)
         # assert self.hall_of_fame >= 0
 
         if self.leaderboard: self._load_leaderboard()
@@ -395,21 +807,118 @@ class Recorder(API):
     def death(self, individual):
         """"""
         self.service.death(individual)
+        # The service did not accept the individual, for example a re-evaluation
+        # of a member which was already replaced.
+        if individual.get_ascension() is None:
+            return
 
         score = individual.get_custom_score(self.score)
+        if isinstance(score, tuple) and None in score:
+            score = None
 
-        if self.leaderboard:  self._update_leaderboard(individual, score)
+        # Individuals without a score can not be ranked.
+        if self.leaderboard and score is not None:  self._update_leaderboard(individual, score)
         # if self.hall_of_fame: self._update_hall_of_fame(individual, score)
 
         for filter_name, filter_function in self.filters.items():
             if filter_function(individual):
-                individual.save(self._path.joinpath(filter_name))
+                individual.save(self._path.joinpath(filter_name), self.pretty)
 
         # for statistic_name, statistic_data in self.statistics.items():
         #     1/0
         # for histogram_name, histogram_data in self.histograms.items():
         #     1/0
 
+    def rescore(self, uuid, score):
+        """
+        Replace the score of an individual in the underlying evolution service
+        and on the leaderboard, see "npc_maker.evo.Evolution.rescore()".
+        Individuals which are only on the leaderboard are also rescored.
+
+        Returns the updated individual.
+        Raises a KeyError if the individual was not found.
+        """
+        individual = None
+        if hasattr(self.service, "rescore"):
+            try:
+                individual = self.service.rescore(uuid, score)
+            except KeyError:
+                pass
+        if individual is None:
+            individual = self._find_leader(uuid)
+            if individual is None:
+                raise KeyError(uuid)
+            individual.score  = score
+            individual.scores = None
+        custom_score = individual.get_custom_score(self.score)
+        if self.leaderboard and custom_score is not None:
+            self._update_leaderboard(individual, custom_score)
+        return individual
+
+    def sample_opponents(self, amount, weighting="uniform", rating="elo"):
+        """
+        Sample opponents from the leaderboard, for competitive coevolution and self-play.
+
+        Argument amount is the number of opponents, sampled with replacement.
+
+        Argument weighting is one of:
+                 "uniform", every member of the leaderboard is equally likely.
+                 "recency", members are likely in proportion to the order
+                 which they died in, so recent champions are the most likely.
+                 "elo", members are likely in proportion to 10^(rating/400).
+                 Members without a rating are given the lowest rating.
+
+        Argument rating is a custom score function for the Elo ratings,
+                 by default the info key "elo".
+
+        Returns a list of new individuals with copies of the opponents' genomes,
+        ready to be birthed into an environment. They are marked as opponents,
+        so evolution services do not add them to the population when they die.
+        """
+        if not self.leaderboard:
+            raise ValueError("leaderboard is disabled")
+        amount = int(amount)
+        assert amount >= 0
+        path    = self.get_leaderboard_path()
+        leaders = [Individual.load(path.joinpath(str(-entry.neg_asc) + ".json"))
+                   for entry in sorted(self._leaderboard_data, key=lambda entry: -entry.neg_asc)]
+        if not leaders or not amount:
+            return []
+        if weighting == "uniform":
+            weights = None
+        elif weighting == "recency":
+            weights = list(range(1, len(leaders) + 1))
+        elif weighting == "elo":
+            ratings = [_float_score(leader.get_custom_score(rating)) for leader in leaders]
+            known   = [x for x in ratings if x is not None]
+            lowest  = min(known, default=0.0)
+            ratings = [lowest if x is None else x for x in ratings]
+            weights = [10 ** ((x - max(ratings)) / 400) for x in ratings]
+        else:
+            raise ValueError(f"unrecognized opponent weighting {weighting!r}")
+        opponents = []
+        for leader in random.choices(leaders, weights=weights, k=amount):
+            opponent = Individual(
+                    genome=copy.deepcopy(leader.get_genome()),
+                    population=leader.get_population(),
+                    controller=leader.get_controller(),
+                    parents=1,
+                    parent_uuids=[] if leader.get_uuid() is None else [leader.get_uuid()])
+            opponent.extras["opponent"] = leader.get_uuid()
+            opponents.append(opponent)
+        return opponents
+
+    def _find_leader(self, uuid):
+        """ Load the individual on the leaderboard with the given UUID, or return None. """
+        if not self.leaderboard:
+            return None
+        for entry in self._leaderboard_data:
+            individual = Individual.load(self.get_leaderboard_path().joinpath(str(-entry.neg_asc) + ".json"))
+            if individual.get_uuid() == uuid:
+                individual.name = None
+                return individual
+        return None
+
     _LeaderEntryType = collections.namedtuple("_LeaderEntry", ("score", "neg_asc"))
 
     def _LeaderEntry(self, individual):
@@ -429,28 +938,59 @@ class Recorder(API):
             if path.suffix.lower() == ".json":
                 individual = Individual.load(path)
                 self._leaderboard_data.append(self._LeaderEntry(individual))
-        heapq.heapify(self._leaderboard_data)
+                if self.sharing is not None:
+                    self._features[individual.get_ascension()] = self.sharing.feature(individual)
+                if self.comparator is not None:
+                    self._leaders[individual.get_ascension()] = individual
 
     def _update_leaderboard(self, individual, score):
         path = self.get_leaderboard_path()
-        heapq.heappush(self._leaderboard_data, self._LeaderEntry(individual))
+        if self.sharing is not None:
+            self._features[individual.get_ascension()] = self.sharing.feature(individual)
+        if self.comparator is not None:
+            self._leaders[individual.get_ascension()] = individual
+        # Members which were evaluated again replace their previous entry.
+        self._leaderboard_data = [entry for entry in self._leaderboard_data
+                                  if entry.neg_asc != -individual.ascension]
+        self._leaderboard_data.append(self._LeaderEntry(individual))
         save_this_individual = True
         while len(self._leaderboard_data) > self.leaderboard:
-            (_score, neg_asc) = heapq.heappop(self._leaderboard_data)
+            worst = self._ranking()[-1]
+            self._leaderboard_data.remove(worst)
+            neg_asc = worst.neg_asc
+            self._features.pop(-neg_asc, None)
+            self._leaders.pop(-neg_asc, None)
             path.joinpath(str(-neg_asc) + ".json").unlink(missing_ok=True)
             if neg_asc == -individual.ascension:
                 save_this_individual = False
         if save_this_individual:
-            individual.save(path)
+            individual.save(path, self.pretty)
+
+    def _ranking(self):
+        """ Returns the leaderboard entries sorted from best to worst, after any fitness sharing. """
+        if self.comparator is None:
+            keys = [_leader_score(entry.score) for entry in self._leaderboard_data]
+        else:
+            compare = functools.cmp_to_key(self.comparator)
+            keys = [compare(self._leaders[-entry.neg_asc]) for entry in self._leaderboard_data]
+        if self.sharing is not None:
+            features = [self._features[-entry.neg_asc] for entry in self._leaderboard_data]
+            shared   = self.sharing.share(features, [entry.score for entry in self._leaderboard_data])
+            keys     = [(_leader_score(score), key) for score, key in zip(shared, keys)]
+        ranking = sorted(zip(keys, self._leaderboard_data),
+                         key=lambda pair: (pair[0], pair[1].neg_asc), reverse=True)
+        return [entry for _, entry in ranking]
 
     def get_leaderboard(self):
         """
         The leaderboard is a list of pairs of (path, score).
         It is sorted descending so leaderboard[0] is the best individual.
+        With fitness sharing the leaderboard is sorted by the shared scores,
+        but the scores in the list are the raw scores.
         """
-        self._leaderboard_data.sort()
-        return [(self.path.joinpath(str(-neg_asc) + ".json"), score)
-                for (score, neg_asc) in reversed(self._leaderboard_data)]
+        path = self.get_leaderboard_path()
+        return [(path.joinpath(str(-neg_asc) + ".json"), score)
+                for (score, neg_asc) in self._ranking()]
 
     def get_best(self):
         """
@@ -463,7 +1003,7 @@ class Recorder(API):
             raise ValueError("leaderboard is disabled")
         if not self._leaderboard_data:
             return None
-        (_score, neg_asc) = max(self._leaderboard_data)
+        (_score, neg_asc) = self._ranking()[0]
         path = self.get_leaderboard_path().joinpath(str(-neg_asc) + ".json")
         best = Individual.load(path)
         return best
@@ -563,6 +1103,26 @@ class Evolution(API):
     Maximizing:
         Continuously add new individuals to the population by replacing the
         lowest scoring individual in the population.
+
+    Frozen:
+        Never change the population. New individuals are unmodified copies of
+        the population's members, and dead individuals are not added to it.
+        This is useful for evaluating a fixed population, for example a
+        benchmark, in a new environment. Deaths are still recorded in the
+        statistics log and by any Recorder which wraps this service.
+
+    Custom:
+        A user defined replacement policy chooses which members to replace.
+        The continuous and maximizing strategies are also replacement
+        policies, see the module `npc_maker.replacement`.
+
+    This class is thread safe: "birth()" and "death()" may be called from
+    several threads at once. The population is updated while holding a lock,
+    and the individuals' files are read and written after releasing it.
+    The seed, mutate, crossover, and custom score functions are called without
+    holding the lock, so they must be thread safe too. The functions which
+    manage the population, such as the selection, speciation, fitness sharing,
+    and replacement policies, are called while holding the lock.
     """
     def __init__(self, controller, seed, mutate=None, crossover=None, allow_mating=True,
                  path=None,
@@ -570,13 +1130,24 @@ class Evolution(API):
                  population_size=1000,
                  elites=0,
                  select=None,
-                 score="score"):
+                 score="score",
+                 speciation=None,
+                 sharing=None,
+                 checkpoints=0,
+                 background_writes=0,
+                 random_seed=None,
+                 reevaluate=0.0,
+                 max_evaluations=10,
+                 pretty=False):
         """
         Argument controller is the command line invocation for the controller program.
 
         Argument seed is the initial genetic material to begin evolution from.
                  It can be either a JSON-encodable object,
                  or a function which returns a JSON encodable object.
+                 It is used whenever there are no parents to reproduce.
+                 See the module `npc_maker.initial` for generators of
+                 initial genomes.
 
         Argument mutate is an optional function for transforming the genome:
                     f(genome) -> genome
@@ -597,27 +1168,104 @@ class Evolution(API):
         Argument path is an optional directory for saving the working state of
                  this evolution service.
 
-        Argument population_type is one of "generation", "continuous", or "maximizing".
+        Argument population_type is one of "generation", "continuous", "maximizing", or "frozen",
+                 or a custom replacement policy, see "npc_maker.replacement".
 
         Argument population_size is the maximum number of individuals allowed in
                  the mating pool at once.
 
         Argument elites is the number of high scoring individuals to be cloned
                  (without modification) into each new generation.
+                 The first births of each generation are the elites of the
+                 previous generation. This only applies to the "generation"
+                 population type.
 
-        Argument select is a mate selection algorithm.
-                 See the `mate_selection` package for more information.
+        Argument select is a mate selection algorithm, or the name of one,
+                 or a dictionary with its name and parameters.
+                 See the module `npc_maker.selection` for more information.
+                 By default every individual is equally likely to be selected.
 
         Argument score is an optional custom scoring function.
+                 A list of scoring functions optimizes several objectives
+                 at once, using the NSGA-II algorithm: mates are selected by
+                 the "nsga2" selection algorithm by default, and "maximizing"
+                 populations keep the individuals in the best non-dominated
+                 fronts with the largest crowding distances.
+                 See the module `npc_maker.selection` for more information.
+
+        Argument speciation is an optional "npc_maker.speciation.Speciation",
+                 which divides the population into species of similar genomes.
+                 Mates are selected using the scores after fitness sharing,
+                 and stagnant species do not reproduce.
+
+        Argument sharing is an optional "npc_maker.sharing.FitnessSharing",
+                 which divides the scores of similar individuals by the number
+                 of them, for mate selection. The saved scores are not changed.
+
+        Argument checkpoints is the number of checkpoints to keep. If positive
+                 then a checkpoint is saved at the end of every generation,
+                 see "checkpoint()".
+
+        Argument background_writes is the maximum number of files which may be
+                 waiting to be written by a background thread. If positive then
+                 "death()" returns without waiting for the individual to be
+                 saved, and any errors are raised by the next call to "birth()"
+                 or "death()". If zero then individuals are saved immediately.
+                 Outside changes to the population directory are not detected
+                 while background writes are enabled.
+
+        Argument reevaluate is the probability that each birth is a copy of an
+                 existing member of the mating pool, for evaluating it again.
+                 When a re-evaluation dies its score is added to the member's
+                 list of scores, and the member's score becomes the mean of
+                 them, see "Individual.get_scores()". The mean score is used
+                 for mate selection and by any Recorder which wraps this
+                 service. Re-evaluations do not count towards generations.
+                 Custom score functions are applied to the mean score and to
+                 the info from the member's first evaluation.
+
+        Argument max_evaluations is the number of evaluations after which
+                 members are no longer re-evaluated.
+
+        Argument random_seed makes this evolution service reproducible.
+                 If given then all of its random decisions, including mate
+                 selection, initial genomes from "npc_maker.initial", and the
+                 UUIDs of new individuals, are drawn from its own random number
+                 generator with the given seed. A "npc_maker.novelty.Novelty"
+                 wrapper also uses this generator.
+                 Otherwise they are drawn from the "random" module.
+                 The user provided functions are responsible for their own
+                 random numbers.
+
+        Argument pretty saves the population in the human readable format,
+                 see "Individual.save()".
         """
         # Clean up and save the arguments.
         self.controller     = _clean_ctrl_command(controller)
+        self.rng            = random if random_seed is None else random.Random(random_seed)
+        self._seeded        = random_seed is not None
+        if self._seeded and isinstance(seed, npc_maker.initial.InitialGenome):
+            # Copy the generator so that the caller's instance keeps its own random numbers.
+            seed            = copy.copy(seed)
+            seed.rng        = self.rng
         self.seed           = seed
         self.mutate         = mutate
         self.crossover      = crossover
         assert callable(self.mutate) or self.mutate is None
         assert callable(self.crossover) or self.crossover is None
         self.allow_mating   = bool(allow_mating)
+        self.reevaluate     = float(reevaluate)
+        self.max_evaluations = int(max_evaluations)
+        assert 0.0 <= self.reevaluate <= 1.0
+        assert self.max_evaluations >= 1
+        self.speciation     = speciation
+        if select is None and isinstance(score, (list, tuple)):
+            select = "nsga2"
+        if isinstance(select, (str, dict)):
+            select = npc_maker.selection.from_config(select)
+        if self._seeded and isinstance(select, npc_maker.selection.Selection):
+            select          = copy.copy(select)
+            select.rng      = self.rng
         if path is not None:
             path = Path(path)
             self.path       = path.joinpath("population")
@@ -628,12 +1276,43 @@ class Evolution(API):
         self.ascension_counter = 0
         self.path.mkdir(parents=True, exist_ok=True)
         # 
+        if isinstance(population_type, type):
+            population_type = population_type()
+        policy = None
         if   population_type == "generation":   PopClass = _Population
         elif population_type == "continuous":   PopClass = _Continuous
         elif population_type == "maximizing":   PopClass = _Maximizing
+        elif population_type == "frozen":       PopClass = _Frozen
+        elif hasattr(population_type, "evict"): PopClass = _Replacement; policy = population_type
         else: raise ValueError("unrecognized population type")
-        self._population = PopClass(
-            self.path, select, score, population_size, elites)
+        assert background_writes >= 0
+        self._lock       = threading.RLock()
+        self._files      = _FileQueue(background_writes)
+        population_args  = (self.path, select, score, population_size, elites, pretty, speciation, sharing,
+                            self._files, self.rng)
+        if policy is None:
+            self._population = PopClass(*population_args)
+        else:
+            self._population = PopClass(*population_args, policy=policy)
+        self._perform(self._population.take_operations())
+        # Resume counting from any individuals which were previously saved in the path.
+        for entry in self._population.data:
+            if entry.ascension is not None:
+                self.ascension_counter = max(self.ascension_counter, entry.ascension + 1)
+        # The statistics log has one line of JSON for each generation.
+        self.statistics_path    = (self.path if path is None else path).joinpath("statistics.ndjson")
+        self.checkpoints_path   = None if path is None else path.joinpath("checkpoints")
+        self.checkpoints        = int(checkpoints)
+        assert self.checkpoints >= 0
+        if self.checkpoints and path is None:
+            raise ValueError("checkpoints require a path")
+        self._generation_scores = []
+        self._elite_queue       = []
+        self._elite_generation  = None
+        self._generation_times  = [] # Pairs of (evaluation_time, queue_time)
+        self._generation_genomes = [] # Random sample of the genomes, for measuring diversity
+        self._generation_reevaluations = 0
+        self.settings_hash      = None
 
     def get_path(self):
         """
@@ -648,40 +1327,168 @@ class Evolution(API):
         """
         return self.controller
 
+    def flush(self):
+        """ Wait for any pending file operations to finish, and raise any errors which occurred. """
+        self._files.flush()
+
+    def _perform(self, operations):
+        """ Perform file operations, after releasing the lock. """
+        self._files.perform(operations)
+        if operations and not self._files.background:
+            with self._lock:
+                # Watch the directory for outside changes after all of the files are written.
+                if not self._files.busy():
+                    self._population._scan_time = getmtime(self.path)
+
     def birth(self, parents):
         """"""
-        if self.allow_mating and len(parents):
-            pass # Environment has already selected the parents.
-        else:
-            # Evolutionary algorithm will select the parents.
-            if self.get_generation() > 1:
-                parents = self._population.sample()
-                parents = [Individual.load(path) for path in parents]
+        with self._lock:
+            self._files.check()
+            kind, paths = self._choose_parents(parents)
+            reads = [self._files.submit("read", path) for path in paths]
+        loaded = self._files.perform(reads)
+        if kind == "clone":
+            return self._clone(loaded[0])
+        if kind == "reevaluate":
+            return self._reevaluation(loaded[0])
+        seed = None
+        if kind == "mates":
+            parents = loaded
+        elif kind == "seed":
+            if callable(self.seed):
+                seed = self.seed()
             else:
-                if callable(self.seed):
-                    seed = self.seed()
-                else:
-                    seed = copy.deepcopy(self.seed)
-                if not isinstance(seed, Individual):
-                    seed = Individual(genome=seed)
-                parents = [seed]
+                seed = copy.deepcopy(self.seed)
+            if not isinstance(seed, Individual):
+                seed = Individual(genome=seed)
+            parents = [seed]
+        # Seed genomes are not recorded as parents.
+        lineage = [] if parents[0] is seed else [parent.get_uuid() for parent in parents]
 
         # Sexual reproduction
         if self.crossover is not None:
-            genome = self.crossover(parents)
+            genome = self.crossover([parent.get_genome() for parent in parents])
         else:
             # Asexual Reproduction, randomly select one of the parents to clone.
-            path = random.choice(parents)
-            genome = Individual.load(path).get_genome()
+            genome = self.rng.choice(parents).get_genome()
 
         # 
         if self.mutate is not None:
             genome = self.mutate(genome)
 
-        return Individual(
+        return self._new_individual(
                 genome=genome,
-                controller=self.controller,
-                parents=len(parents))
+                parents=len(parents),
+                parent_uuids=[uuid for uuid in lineage if uuid is not None])
+
+    def _choose_parents(self, parents):
+        """
+        Returns a pair of (kind, paths) where kind is one of:
+        "clone" to copy the individual at the path without modification,
+        "reevaluate" to evaluate the individual at the path again,
+        "mates" to reproduce the individuals at the paths,
+        "given" to reproduce the parents which the environment selected,
+        or "seed" to start from the seed genome.
+        """
+        if isinstance(self._population, _Frozen):
+            member = self._population.sample()
+            if member is not None:
+                # Frozen populations are evaluated without any modifications.
+                return "clone", [self.rng.choice(member)]
+            return "seed", []
+        if self.allow_mating and len(parents):
+            return "given", [] # Environment has already selected the parents.
+        elite = self._next_elite()
+        if elite is not None:
+            return "clone", [elite]
+        if self.reevaluate and self.rng.random() < self.reevaluate:
+            candidates = [entry.path for entry in self._population.mating_pool()
+                          if entry.evaluations < self.max_evaluations]
+            if candidates:
+                return "reevaluate", [self.rng.choice(candidates)]
+        # Evolutionary algorithm will select the parents.
+        mates = self._population.sample() if self.get_generation() > 1 else None
+        if mates is None:
+            return "seed", []
+        # Mates which are paired with themselves reproduce asexually.
+        return "mates", list(dict.fromkeys(mates))
+
+    def _clone(self, original):
+        """ Returns an unmodified copy of a saved individual. """
+        return self._new_individual(
+                genome=original.get_genome(),
+                parents=1,
+                parent_uuids=[] if original.get_uuid() is None else [original.get_uuid()])
+
+    def _reevaluation(self, member):
+        """ Returns a copy of a member of the population, for evaluating it again. """
+        individual = self._new_individual(
+                genome=member.get_genome(),
+                parents=member.get_parents(),
+                parent_uuids=member.get_parent_uuids())
+        individual.extras["reevaluate"] = member.get_ascension()
+        return individual
+
+    def _merge_reevaluation(self, individual):
+        """
+        Add the score of a re-evaluation to its member of the population, and
+        then update the re-evaluation to match the member.
+        If the member was already replaced then its re-evaluation is discarded.
+        """
+        ascension = individual.extras.pop("reevaluate")
+        while True:
+            with self._lock:
+                self._files.check()
+                entry = self._population.find(ascension)
+                if entry is None:
+                    return
+                reads = [self._files.submit("read", entry.path)]
+            member = self._files.perform(reads)[0]
+            with self._lock:
+                # Load the member again if it changed while it was being read.
+                if self._population.find(ascension) is not entry:
+                    continue
+                self._update_reevaluation(individual, member)
+                operations = self._population.take_operations()
+            self._perform(operations)
+            return
+
+    def _update_reevaluation(self, individual, member):
+        """ Merge the re-evaluation into the member which was loaded, while holding the lock. """
+        member.name = None
+        scores = member.get_scores()
+        if scores is None:
+            scores = [_float_score(member.get_score())]
+        scores = [x for x in scores if x is not None]
+        score  = _float_score(individual.get_score())
+        if score is not None:
+            member.scores = scores + [score]
+            member.score  = sum(member.scores) / len(member.scores)
+            if isinstance(individual.get_score(), str):
+                # Keep the same type of score as the environment reports.
+                member.score = str(member.score)
+            self._population.update(member)
+        individual.ascension    = member.ascension
+        individual.uuid         = member.uuid
+        individual.parent_uuids = member.parent_uuids
+        individual.species      = member.species
+        individual.score        = member.score
+        individual.scores       = member.scores
+        self._generation_reevaluations += 1
+
+    def _new_individual(self, **kwargs):
+        individual = Individual(controller=self.controller, **kwargs)
+        if self._seeded:
+            individual.name = individual.uuid = str(uuid.UUID(int=self.rng.getrandbits(128), version=4))
+        return individual
+
+    def _next_elite(self):
+        """ Returns the path of the next elite to clone into the current generation, or None. """
+        generation = self.get_generation()
+        if generation != self._elite_generation:
+            self._elite_generation = generation
+            self._elite_queue = self._population.elite_paths() if generation > 1 else []
+        return self._elite_queue.pop(0) if self._elite_queue else None
 
     def _assign_ascension(self, individual):
         if individual.ascension is None:
@@ -692,8 +1499,350 @@ class Evolution(API):
 
     def death(self, individual):
         """"""
+        if "reevaluate" in individual.extras:
+            self._merge_reevaluation(individual)
+            return
+        checkpoint = False
+        score = _presolve_score(individual, self._population.score)
+        with self._lock:
+            self._files.check()
+            if "opponent" in individual.extras:
+                operations = [] # Opponents from the leaderboard are not members of the population.
+            else:
+                operations = self._add_member(individual, score)
+                size = self._population.size
+                checkpoint = bool(self.checkpoints and size and (individual.ascension + 1) % size == 0)
+            operations = self._population.take_operations() + operations
+        self._perform(operations)
+        if checkpoint:
+            self.checkpoint()
+
+    def _add_member(self, individual, score):
+        """
+        Returns the file operations for the statistics log.
+
+        Argument score is the population's score function, from "_presolve_score()".
+        """
         self._assign_ascension(individual)
-        self._population.death(individual)
+        if self.settings_hash is not None:
+            individual.settings_hash = self.settings_hash
+        if self.speciation is not None:
+            individual.species = self.speciation.assign(individual)
+        self._population.death(individual, score)
+        if self.speciation is not None:
+            size = self._population.size
+            self.speciation.death(individual, individual.get_custom_score(score),
+                                  individual.ascension // size if size else 0)
+        return self._record_statistics(individual, score)
+
+    def set_settings(self, settings):
+        """
+        Record the environment's settings, which can be any JSON object.
+        New members are marked with a hash of the settings, and members which
+        were scored with different settings are stale, see "get_stale()".
+        """
+        with self._lock:
+            self.settings_hash = None if settings is None else _checksum(settings)
+
+    def get_stale(self, generations=None):
+        """
+        Returns a list of the members of the population which are due to be
+        rescored, oldest first. Members are stale if their score is at least
+        the given number of generations old, or if they were scored with
+        different environment settings than the current ones, see "set_settings()".
+
+        Score each member again and then pass its new score to "rescore()".
+        """
+        with self._lock:
+            self._files.check()
+            reads = [self._files.submit("read", entry.path)
+                     for entry in sorted(self._population.data, key=lambda entry: entry.ascension)]
+            generation = self.get_generation()
+            size       = self._population.size
+            settings   = self.settings_hash
+        stale = []
+        for member in self._files.perform(reads):
+            member.name = None
+            if member.get_rescored() is not None:
+                scored = member.get_rescored()
+            else:
+                scored = member.get_ascension() // size if size else 0
+            if ((generations is not None and generation - scored >= generations) or
+                    (settings is not None and member.get_settings_hash() != settings)):
+                stale.append(member)
+        return stale
+
+    def rescore(self, uuid, score):
+        """
+        Replace the score of a member of the population, for example after
+        evaluating it again with new environment settings.
+        Any scores from re-evaluations are discarded.
+
+        Argument uuid is the member's permanent name, see "Individual.get_uuid()".
+
+        Returns the updated member.
+        Raises a KeyError if the member is not in the population.
+        """
+        with self._lock:
+            self._files.check()
+            entry = self._population.find_uuid(uuid)
+            if entry is None:
+                raise KeyError(uuid)
+            member = self._population.load(entry.path)
+            member.name          = None
+            member.score         = score
+            member.scores        = None
+            member.rescored      = self.get_generation()
+            member.settings_hash = self.settings_hash
+            self._population.update(member)
+            operations = self._population.take_operations()
+        self._perform(operations)
+        return member
+
+    def get_checkpoints(self):
+        """ Returns the identifiers of the saved checkpoints, oldest first. """
+        if self.checkpoints_path is None or not self.checkpoints_path.is_dir():
+            return []
+        return sorted(int(path.name) for path in self.checkpoints_path.iterdir()
+                      if path.is_dir() and path.name.isdigit())
+
+    def checkpoint(self):
+        """
+        Save a snapshot of the population, and discard the oldest checkpoints
+        beyond the number to keep.
+
+        Each checkpoint is a numbered directory inside of the directory
+        "checkpoints", which contains a copy of every individual in the
+        population and the file "checkpoint.json" with the state of this
+        evolution service and a manifest of the individuals' files.
+        Checkpoints are written to a temporary directory and then renamed,
+        so that they are never left half written.
+
+        Returns the identifier of the new checkpoint.
+        """
+        with self._lock:
+            temp, metadata = self._snapshot()
+        return self._publish_checkpoint(temp, metadata)
+
+    def _snapshot(self):
+        """
+        Link the files of the population into a temporary directory.
+        Members are saved by replacing their files, so the links keep the
+        contents which they had at this time.
+        """
+        if self.checkpoints_path is None:
+            raise ValueError("checkpoints require a path")
+        self._population._scan()
+        self._perform(self._population.take_operations())
+        self.flush()
+        self.checkpoints_path.mkdir(exist_ok=True)
+        # Count the temporary directories too, in case another checkpoint is being published.
+        names = (path.name.split(".")[0] for path in self.checkpoints_path.iterdir())
+        checkpoint_id = max((int(name) for name in names if name.isdigit()), default=-1) + 1
+        temp = self.checkpoints_path.joinpath(f"{checkpoint_id}.tmp")
+        shutil.rmtree(temp, ignore_errors=True)
+        temp.mkdir()
+        for path in sorted(self.path.iterdir()):
+            if path.suffix.lower() == ".json":
+                _link_or_copy(path, temp.joinpath(path.name))
+        statistics_lines = 0
+        if self.statistics_path.exists():
+            with open(self.statistics_path, 'rt') as file:
+                statistics_lines = sum(1 for line in file if line.endswith("\n"))
+        metadata = {
+            "checkpoint":        checkpoint_id,
+            "time":              time.time(),
+            "generation":        self.get_generation(),
+            "ascension_counter": self.ascension_counter,
+            "statistics_lines":  statistics_lines,
+        }
+        return temp, metadata
+
+    def _publish_checkpoint(self, temp, metadata):
+        """ Write the manifest of a snapshot and rename it, without holding the lock. """
+        checkpoint_id = metadata["checkpoint"]
+        metadata["manifest"] = {path.name: _file_hash(path) for path in sorted(temp.iterdir())}
+        metadata["checksum"] = _checksum(metadata)
+        with open(temp.joinpath("checkpoint.json"), 'wt') as file:
+            json.dump(metadata, file, indent=4)
+            file.flush()
+            os.fsync(file.fileno())
+        _fsync_dir(temp)
+        os.replace(temp, self.checkpoints_path.joinpath(str(checkpoint_id)))
+        _fsync_dir(self.checkpoints_path)
+        if self.checkpoints:
+            for old_id in self.get_checkpoints()[:-self.checkpoints]:
+                shutil.rmtree(self.checkpoints_path.joinpath(str(old_id)), ignore_errors=True)
+        return checkpoint_id
+
+    def restore(self, checkpoint_id):
+        """
+        Replace the population with a previously saved checkpoint.
+
+        The statistics log is truncated to the generations which were complete
+        when the checkpoint was saved. Raises a CorruptFileError if any of the
+        checkpoint's files are missing or were modified.
+        """
+        with self._lock:
+            self._restore(checkpoint_id)
+
+    def _restore(self, checkpoint_id):
+        if self.checkpoints_path is None:
+            raise ValueError("checkpoints require a path")
+        source = self.checkpoints_path.joinpath(str(int(checkpoint_id)))
+        if not source.is_dir():
+            raise ValueError(f"checkpoint {checkpoint_id} does not exist")
+        self.flush()
+        metadata = _load_checked(source.joinpath("checkpoint.json"))
+        for name, digest in metadata["manifest"].items():
+            path = source.joinpath(name)
+            if not path.exists() or _file_hash(path) != digest:
+                raise CorruptFileError(f"corrupt checkpoint, the file does not match its manifest: {path}")
+        # Assemble the restored population next to the current one, and then swap them.
+        restored = self.path.with_name(self.path.name + ".restore")
+        discard  = self.path.with_name(self.path.name + ".discard")
+        shutil.rmtree(restored, ignore_errors=True)
+        shutil.rmtree(discard,  ignore_errors=True)
+        restored.mkdir()
+        for name in metadata["manifest"]:
+            _link_or_copy(source.joinpath(name), restored.joinpath(name))
+        _fsync_dir(restored)
+        os.replace(self.path, discard)
+        os.replace(restored, self.path)
+        _fsync_dir(self.path.parent)
+        shutil.rmtree(discard)
+        if self.statistics_path.exists():
+            with open(self.statistics_path, 'rt') as file:
+                lines = file.readlines()[:metadata["statistics_lines"]]
+            with open(self.statistics_path, 'wt') as file:
+                file.writelines(lines)
+        self.ascension_counter = metadata["ascension_counter"]
+        self._generation_scores.clear()
+        self._generation_times.clear()
+        self._generation_genomes.clear()
+        self._generation_reevaluations = 0
+        self._elite_queue.clear()
+        self._elite_generation = None
+        self._population._scan_time = None
+        self._population._scan()
+        self._perform(self._population.take_operations())
+
+    def _record_statistics(self, individual, score):
+        """ Returns the file operations for appending to the statistics log. """
+        score = individual.get_custom_score(score)
+        if isinstance(score, tuple):
+            # Multi-objective scores are summarized by their first objective.
+            score = score[0] if score else None
+        try:
+            score = float(score)
+        except (TypeError, ValueError):
+            score = math.nan
+        self._generation_scores.append(score)
+        self._generation_times.append((individual.evaluation_time, individual.queue_time))
+        # Reservoir sample of the genomes.
+        if len(self._generation_genomes) < self.DIVERSITY_SAMPLES:
+            self._generation_genomes.append(individual.get_genome())
+        else:
+            index = self.rng.randrange(len(self._generation_scores))
+            if index < self.DIVERSITY_SAMPLES:
+                self._generation_genomes[index] = individual.get_genome()
+        size = self._population.size
+        if not size or (individual.ascension + 1) % size != 0:
+            return []
+        scores = [x for x in self._generation_scores if not math.isnan(x)]
+        mean   = sum(scores) / len(scores) if scores else None
+        entry = {
+            "generation":  individual.ascension // size,
+            "individuals": len(self._generation_scores),
+            "evaluations": individual.ascension + 1,
+            "unscored":    len(self._generation_scores) - len(scores),
+            "reevaluations": self._generation_reevaluations,
+            "best":        max(scores, default=None),
+            "mean":        mean,
+            "worst":       min(scores, default=None),
+            "stdev":       math.sqrt(sum((x - mean) ** 2 for x in scores) / len(scores)) if scores else None,
+            "diversity":   self._diversity(self._generation_genomes),
+        }
+        for column, field in enumerate(("evaluation_time", "queue_time")):
+            times = [x[column] for x in self._generation_times if x[column] is not None]
+            entry["mean_" + field] = sum(times) / len(times) if times else None
+            entry["max_"  + field] = max(times, default=None)
+        if self.speciation is not None:
+            entry["species"] = len(self.speciation.species)
+        self._generation_scores.clear()
+        self._generation_times.clear()
+        self._generation_genomes.clear()
+        self._generation_reevaluations = 0
+        return [self._files.submit("append", self.statistics_path, json.dumps(entry) + "\n")]
+
+    DIVERSITY_SAMPLES = 30
+
+    @staticmethod
+    def _diversity(genomes):
+        from npc_maker.compare import _diversity, genome_similarity
+        return _diversity(genomes, genome_similarity)
+
+    def get_statistics_path(self):
+        """
+        Returns the path of the statistics log.
+
+        Each line of the log is a JSON object which summarizes one generation,
+        with the fields:
+
+        * "generation", "individuals" in the generation, "evaluations" in
+          total including previous generations, and "unscored" individuals.
+        * "reevaluations" of existing members during the generation.
+        * "best", "mean", "worst", and "stdev" (standard deviation) of the
+          scores, or of the first objective of multi-objective scores.
+        * "diversity", which is one minus the mean similarity of a random
+          sample of the genomes, see "npc_maker.compare.genome_similarity()".
+        * "mean_evaluation_time", "max_evaluation_time", "mean_queue_time",
+          and "max_queue_time", which are wall-clock times in seconds.
+        * "species", the number of living species, for populations with speciation.
+        """
+        return self.statistics_path
+
+    def get_statistics(self):
+        """
+        Returns the statistics log, as a list with a dictionary for each
+        generation, see "get_statistics_path()".
+        """
+        statistics = []
+        if not self.statistics_path.exists():
+            return statistics
+        with open(self.statistics_path, 'rt') as file:
+            for line in file:
+                try:
+                    statistics.append(json.loads(line))
+                except json.JSONDecodeError:
+                    pass # The last line may be partially written.
+        return statistics
+
+    def save_statistics_csv(self, path):
+        """
+        Write the statistics log to a CSV file, with a row for each generation
+        and a column for each field. Missing values are left empty.
+        """
+        import csv
+        statistics = self.get_statistics()
+        columns = list(dict.fromkeys(key for entry in statistics for key in entry))
+        with open(path, 'wt', newline='') as file:
+            writer = csv.DictWriter(file, columns)
+            writer.writeheader()
+            writer.writerows(statistics)
+
+    def plot_scores(self, output, title="Scores"):
+        """
+        Draw the best and mean score of each generation into an image file.
+        The image format is determined by the file extension: ".svg" or ".png".
+
+        This requires the rust bindings "npc_maker_rs", with the "plot" feature.
+        The rust program "npc_plot" does the same thing from the command line.
+        """
+        import npc_maker_rs
+        if not hasattr(npc_maker_rs, "plot_scores"):
+            raise ImportError("npc_maker_rs was built without the \"plot\" feature")
+        npc_maker_rs.plot_scores([str(self.statistics_path)], str(output), title)
 
     def get_generation(self):
         """
@@ -701,12 +1850,128 @@ class Evolution(API):
         """
         return int(self.ascension_counter / self._population.size)
 
+class Coevolution:
+    """
+    Coordinates the evaluations of several interacting populations,
+    such as predators and prey, or hosts and parasites.
+
+    Individuals are born in cohorts, with the same number of individuals from
+    every population. Each match contains one individual from every population,
+    who are evaluated together. Every individual plays several matches against
+    random opponents from the other populations, and its score is the mean of
+    its scores from all of its matches. After all of an individual's matches
+    are reported, it dies and its evolution service receives it.
+
+    Example:
+
+        coevolution = Coevolution({"predator": predators, "prey": prey}, matches=3)
+        while True:
+            match  = coevolution.next_match()
+            scores = play(match.individuals["predator"], match.individuals["prey"])
+            coevolution.report(match, {"predator": scores[0], "prey": scores[1]})
+    """
+    Match = collections.namedtuple("Match", ("number", "individuals"))
+
+    def __init__(self, services, cohort_size=10, matches=1, random_seed=None):
+        """
+        Argument services is a dictionary of population names and their
+                 evolution API instances.
+
+        Argument cohort_size is the number of individuals from each population
+                 which are born at the same time and play against each other.
+
+        Argument matches is the number of matches which each individual plays.
+
+        Argument random_seed makes the schedule of matches reproducible.
+                 If given then the opponents are drawn from a random number
+                 generator with the given seed. Otherwise they are drawn from
+                 the "random" module.
+        """
+        self.services    = dict(services)
+        self.cohort_size = int(cohort_size)
+        self.matches     = int(matches)
+        self.rng         = random if random_seed is None else random.Random(random_seed)
+        assert self.services and all(isinstance(service, API) for service in self.services.values())
+        assert self.cohort_size >= 1
+        assert self.matches >= 1
+        self._queue   = collections.deque() # Matches which have not been given out yet
+        self._pending = {} # Match number -> Match, for matches which are being played
+        self._results = {} # UUID -> list of the individual's scores
+        self._counter = 0
+
+    def get_outstanding(self):
+        """ Returns the number of matches which were given out and not yet reported. """
+        return len(self._pending)
+
+    def next_match(self):
+        """
+        Returns the next match to evaluate, which has the attributes:
+        "number", a unique identifier, and "individuals", a dictionary of
+        population names and the individuals who play in the match.
+        """
+        if not self._queue:
+            self._schedule()
+        match = self._queue.popleft()
+        self._pending[match.number] = match
+        return match
+
+    def _schedule(self):
+        """ Birth a new cohort and divide it into matches. """
+        cohorts = {}
+        for name, service in self.services.items():
+            cohort = []
+            for _ in range(self.cohort_size):
+                individual = service.birth([])
+                individual.population = name
+                self._results[individual.get_uuid()] = []
+                cohort.append(individual)
+            cohorts[name] = cohort
+        for _ in range(self.matches):
+            for cohort in cohorts.values():
+                self.rng.shuffle(cohort)
+            for index in range(self.cohort_size):
+                individuals = {name: cohort[index] for name, cohort in cohorts.items()}
+                self._queue.append(self.Match(self._counter, individuals))
+                self._counter += 1
+
+    def report(self, match, scores, info=None):
+        """
+        Report the results of a match.
+
+        Argument scores is a dictionary of population names and the scores of
+                 their individuals in the match.
+
+        Argument info is an optional dictionary of population names and
+                 dictionaries to merge into their individuals' info.
+        """
+        match = self._pending.pop(match.number)
+        for name, individual in match.individuals.items():
+            results = self._results[individual.get_uuid()]
+            results.append(scores.get(name))
+            if info is not None and name in info:
+                individual.info.update(info[name])
+            if len(results) < self.matches:
+                continue
+            del self._results[individual.get_uuid()]
+            numbers = [x for x in map(_float_score, results) if x is not None]
+            individual.score = sum(numbers) / len(numbers) if numbers else None
+            individual.name  = None
+            self.services[name].death(individual)
+
 class _Population:
     """
     Manages a population of individuals using regular generations.
     """
-    def __init__(self, path, select, score, size, elites):
+    def __init__(self, path, select, score, size, elites, pretty=False, speciation=None, sharing=None, files=None,
+                 rng=random):
         self.path   = Path(path)
+        self.rng    = rng
+        self.files  = _FileQueue() if files is None else files
+        self.operations = [] # File operations for the caller to perform
+        self.speciation = speciation
+        self.sharing    = sharing
+        self._features  = {} # Path -> fitness sharing feature
+        self.pretty = bool(pretty)
         self.select = select
         self.score  = score
         self.size   = int(size)
@@ -716,20 +1981,29 @@ class _Population:
         assert self.elites >= 0
         self._scan()
 
-    EntryType = collections.namedtuple("Entry", ("score", "ascension", "path"))
+    EntryType = collections.namedtuple("Entry", ("score", "ascension", "path", "species", "evaluations", "uuid"),
+                                       defaults=(None, 1, None))
 
-    def Entry(self, individual) -> EntryType:
-        """ Class Constructor """
+    def Entry(self, individual, score=None) -> EntryType:
+        """ Class Constructor, optionally with a different score function for the individual. """
         return self.EntryType(
+            individual.get_custom_score(self.score if score is None else score),
             individual.get_ascension(),
-            individual.get_custom_score(self.score),
-            individual.get_path())
+            individual.get_path(),
+            individual.get_species(),
+            len(individual.get_scores() or [None]),
+            individual.get_uuid())
 
     def _scan(self):
+        if getattr(self, "_scan_time", None) is not None and (self.files.background or self.files.busy()):
+            return # The directory is being changed, so it can not be watched for outside changes.
         if getattr(self, "_scan_time", -1) == getmtime(self.path):
             return
         self._buffer = []
-        self.data = [self.Entry(individual) for individual in self.scan_dir(self.path)]
+        individuals = list(self.scan_dir(self.path))
+        if self.sharing is not None:
+            self._features = {individual.get_path(): self.sharing.feature(individual) for individual in individuals}
+        self.data = [self.Entry(individual) for individual in individuals]
         self.sort()
         self.rollover()
         self._scan_time = getmtime(self.path)
@@ -745,20 +2019,77 @@ class _Population:
                 individual = Individual.load(path)
                 yield individual
 
-    def death(self, individual):
+    def save(self, individual):
+        """ Submit the individual to be saved into the population directory. """
+        filename, text = individual._serialize(self.pretty)
+        individual.path = self.path.joinpath(filename)
+        if self.sharing is not None:
+            self._features[individual.path] = self.sharing.feature(individual)
+        self.operations.append(self.files.submit("write", individual.path, text))
+
+    def unlink(self, path):
+        """ Submit a member's save file to be deleted. """
+        self._features.pop(path, None)
+        self.operations.append(self.files.submit("unlink", path))
+
+    def take_operations(self):
+        """ Returns the submitted file operations, for the caller to perform. """
+        operations, self.operations = self.operations, []
+        return operations
+
+    def load(self, path):
+        """ Load a member of the population, after any pending writes to it. """
+        return self.files.perform([self.files.submit("read", path)])[0]
+
+    def find(self, ascension):
+        """ Returns the entry of the member with the given ascension number, or None. """
+        return next((entry for entry in self.data if entry.ascension == ascension), None)
+
+    def find_uuid(self, uuid):
+        """ Returns the entry of the member with the given UUID, or None. """
+        return next((entry for entry in self.data if entry.uuid == uuid), None)
+
+    def update(self, individual):
+        """ Save a member of the population after its score changed. """
+        self.save(individual)
+        entry = self.Entry(individual)
+        for index, old in enumerate(self.data):
+            if old.ascension == entry.ascension:
+                self.data[index] = entry
+        self._buffer.clear()
+
+    def death(self, individual, score=None):
         self._scan()
-        individual.save(self.path)
-        self._scan_time = getmtime(self.path)
-        self.data.append(self.Entry(individual))
+        self.save(individual)
+        self.data.append(self.Entry(individual, score))
         self.rollover()
 
     def rollover(self):
         while len(self.data) >= 2 * self.size:
             for _ in range(self.size):
                 individual = self.data.popleft()
-                individual.path.unlink()
+                self.unlink(individual.path)
             self._buffer.clear()
 
+    def mating_pool(self):
+        """ Returns the entries which are eligible to reproduce. """
+        return list(self.data)[:self.size]
+
+    has_generations = True
+
+    def elite_paths(self):
+        """ Returns the paths of the highest scoring members of the mating pool, best first. """
+        pool = self.mating_pool()
+        if not self.has_generations or not self.elites or not pool:
+            return []
+        scores = [entry.score for entry in pool]
+        if isinstance(self.score, (list, tuple)):
+            ranking = npc_maker.selection._nsga2_ranking(scores)
+            order = sorted(range(len(pool)), key=lambda index: (ranking[index][0], -ranking[index][1]))
+        else:
+            order = npc_maker.selection._ranking(npc_maker.selection._clean_scores(scores))
+        return [pool[index].path for index in order[:self.elites]]
+
     def sample(self) -> ['Path', 'Path']:
         self._scan()
         # 
@@ -768,36 +2099,84 @@ class _Population:
         if not self.data:
             return None
         # 
-        scores = [x.score for x in self.data][:self.size]
-        paths  = [x.path  for x in self.data][:self.size]
-        pairs  = self.select.pairs(128, scores)
+        pool   = self.mating_pool()
+        scores = [x.score for x in pool]
+        paths  = [x.path  for x in pool]
+        if self.speciation is not None:
+            species = [x.species for x in pool]
+            indexes, scores = self.speciation.share(species, scores)
+            paths = [paths[index] for index in indexes]
+        if self.sharing is not None:
+            scores = self._share(paths, scores)
+        if self.select is None:
+            pairs = [(self.rng.randrange(len(paths)), self.rng.randrange(len(paths))) for _ in range(128)]
+        else:
+            pairs = self.select.pairs(128, scores)
         self._buffer = [(paths[a], paths[b]) for a,b in pairs]
         return self._buffer.pop()
 
-class _Continuous(_Population):
+    def _share(self, paths, scores):
+        """ Apply fitness sharing, using the features which were found when the members were saved or scanned. """
+        return self.sharing.share([self._features[path] for path in paths], scores)
+
+class _Replacement(_Population):
+    """
+    Manages a population using a replacement policy, see "npc_maker.replacement".
+    """
+    has_generations = False
+    policy = None
+
+    def __init__(self, *args, policy=None, **kwargs):
+        if policy is not None:
+            self.policy = policy
+        super().__init__(*args, **kwargs)
+
+    def sort(self):
+        self.data.sort(key=lambda entry: entry.ascension)
+
+    def death(self, individual, score=None):
+        self._scan()
+        entry   = self.Entry(individual, score)._replace(path=self.path.joinpath(individual._filename() + ".json"))
+        members = list(self.data) + [entry]
+        new     = len(members) - 1
+        evict   = self._evict(members, individual)
+        if new not in evict:
+            self.save(individual)
+        self._discard(members, evict, new)
+
     def rollover(self):
-        while len(self.data) > self.size:
-            individual = self.data.popleft()
-            individual.path.unlink()
+        if len(self.data) > self.size:
+            members = list(self.data)
+            self._discard(members, self._evict(members, None))
+
+    def _evict(self, members, individual):
+        evict = set(self.policy.evict(members, individual, self.size))
+        if not all(isinstance(index, int) and 0 <= index < len(members) for index in evict):
+            raise ValueError(f"replacement policy {self.policy!r} returned an invalid index")
+        return evict
+
+    def _discard(self, members, evict, new=None):
+        for index in evict:
+            if index != new:
+                self.unlink(members[index].path)
+        self.data = [member for index, member in enumerate(members) if index not in evict]
+        if evict:
             self._buffer.clear()
 
-class _Maximizing(_Population):
-    def sort(self):
-        heapq.heapify(self.data)
+class _Continuous(_Replacement):
+    policy = npc_maker.replacement.Oldest()
 
-    def death(self, individual):
-        min_score = self.data[0].score
-        low_score = individual.score <= min_score
-        pop_full  = len(self.data) >= self.size
-        if low_score and pop_full:
-            return
+class _Maximizing(_Replacement):
+    policy = npc_maker.replacement.Worst()
 
-        individual.save(self.path)
-        heapq.heappush(self.Entry(individual))
-        self.rollover()
+class _Frozen(_Population):
+    has_generations = False
+
+    def death(self, individual, score=None):
+        pass
 
     def rollover(self):
-        while len(self.data) > self.size:
-            individual = heapq.heappop()
-            individual.path.unlink()
-            self._buffer.clear()
+        pass
+
+    def mating_pool(self):
+        return list(self.data)
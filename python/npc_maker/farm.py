@@ -0,0 +1,510 @@
+"""
+Networked evaluation workers, for running environments on remote computers.
+
+A farm is a central server which runs the evolutionary algorithms, and workers
+are daemons which run instances of the environment. Workers connect to the farm
+over TCP, and relay the environment's messages to and from the farm. The farm
+and the workers do not need to share a filesystem, but every worker needs its
+own copy of the environment and of the controller programs, at the same paths
+which the experiment configuration uses.
+
+Workers may join and leave the farm at any time. When a worker disconnects
+or stops responding, the individuals who were alive in its environment are
+reassigned to the next worker which asks for a new individual.
+
+Each message is sent as a frame: a 4 byte big-endian length followed by the
+message, which is the same JSON text as the environment sends and receives
+over its standard I/O channels.
+
+Security: anyone who can connect to the farm can join it as a worker, and then
+receive the genomes and report false scores. By default the farm only listens
+on the loopback interface. To accept workers from other computers, listen on a
+public address and give the farm and the workers the same shared secret,
+either with the "secret" argument or with the environment variable
+NPC_MAKER_FARM_SECRET. Workers which do not know the secret are refused.
+The connections are not encrypted and the secret is sent as plain text, so
+only use farms on trusted networks, or connect them with an SSH tunnel or a VPN.
+
+Usage:
+    export NPC_MAKER_FARM_SECRET=...
+    python -m npc_maker run --farm 0.0.0.0:7777 experiment.toml
+    python -m npc_maker worker farm.example.com:7777 path/to/environment.env
+"""
+
+from npc_maker.env import Environment, Specification
+from npc_maker.evo import API
+import collections
+import hmac
+import ipaddress
+import json
+import os
+import queue
+import socket
+import struct
+import subprocess
+import sys
+import threading
+import time
+
+__all__ = (
+    "Farm",
+    "Worker",
+    "send_frame",
+    "recv_frame",
+    "parse_address",
+)
+
+_HEADER = struct.Struct(">I")
+
+MAX_FRAME_SIZE = 64 * 1024 * 1024
+
+SECRET_VARIABLE = "NPC_MAKER_FARM_SECRET"
+
+def _get_secret(secret):
+    """ Returns the shared secret, or None if there is no secret. """
+    if secret is None:
+        secret = os.environ.get(SECRET_VARIABLE)
+    return str(secret) if secret else None
+
+def _is_loopback(host):
+    try:
+        return ipaddress.ip_address(host).is_loopback
+    except ValueError:
+        return host == "localhost"
+
+def parse_address(address, default_host="127.0.0.1"):
+    """ Parse a network address of the form "host:port" or ":port" into a pair of (host, port). """
+    if isinstance(address, tuple):
+        return address
+    host, _, port = str(address).rpartition(":")
+    try:
+        port = int(port)
+    except ValueError:
+        raise ValueError(f"invalid network address \"{address}\", expected \"host:port\"") from None
+    return (host or default_host, port)
+
+def send_frame(sock, message):
+    """ Send one frame. Argument message is either bytes or a JSON-encodable object. """
+    if not isinstance(message, bytes):
+        message = json.dumps(message).encode("utf-8")
+    sock.sendall(_HEADER.pack(len(message)) + message)
+
+def recv_frame(sock):
+    """ Receive one frame. Returns the bytes of the message, or None if the connection closed. """
+    header = _recv_exact(sock, _HEADER.size)
+    if header is None:
+        return None
+    (length,) = _HEADER.unpack(header)
+    if length > MAX_FRAME_SIZE:
+        raise ValueError(f"frame is too large ({length} bytes)")
+    return _recv_exact(sock, length)
+
+def _recv_exact(sock, length):
+    data = bytearray()
+    while len(data) < length:
+        chunk = sock.recv(length - len(data))
+        if not chunk:
+            return None
+        data.extend(chunk)
+    return bytes(data)
+
+class _Connection:
+    """
+    Network connection to a worker, with the same interface as "subprocess.Popen"
+    so that the environment class can use it in place of a local process.
+    """
+    def __init__(self, sock, name):
+        self.name       = name
+        self.returncode = None
+        self.stdin      = _ConnectionInput(self)
+        self.stdout     = _ConnectionOutput(self)
+        self._socket    = sock
+        self._inbox     = queue.SimpleQueue()
+        self._closed    = threading.Event()
+        self._thread    = threading.Thread(target=self._receive, daemon=True)
+        self._thread.start()
+
+    def _receive(self):
+        try:
+            while (message := recv_frame(self._socket)) is not None:
+                self._inbox.put(message + b"\n")
+        except (OSError, ValueError):
+            pass
+        self._closed.set()
+
+    def _send(self, line):
+        if self._closed.is_set():
+            return
+        try:
+            send_frame(self._socket, line)
+        except OSError:
+            self.terminate()
+
+    def poll(self):
+        # Report the disconnection after all of the messages which preceded it.
+        if self.returncode is None and self._closed.is_set() and self._inbox.empty():
+            self.returncode = -1
+        return self.returncode
+
+    def terminate(self):
+        try:
+            self._socket.shutdown(socket.SHUT_RDWR)
+        except OSError:
+            pass
+        self._socket.close()
+        self._closed.set()
+
+    def wait(self, timeout=None):
+        if not self._closed.wait(timeout):
+            self.terminate()
+        self.returncode = -1 if self.returncode is None else self.returncode
+        return self.returncode
+
+class _ConnectionInput:
+    def __init__(self, connection):
+        self._connection = connection
+        self._buffer = b""
+    def write(self, data):
+        self._buffer += data
+    def flush(self):
+        *lines, self._buffer = self._buffer.split(b"\n")
+        for line in lines:
+            self._connection._send(line)
+    def close(self):
+        self.flush()
+
+class _ConnectionOutput:
+    def __init__(self, connection):
+        self._connection = connection
+    def readline(self):
+        try:
+            return self._connection._inbox.get_nowait()
+        except queue.Empty:
+            return b""
+    def close(self):
+        pass
+
+class _WorkerEnvironment(Environment):
+    """ Instance of the environment which is running on a worker. """
+    def __init__(self, connection, *args, **kwargs):
+        self._connection = connection
+        super().__init__(*args, **kwargs)
+
+    def _spawn(self, command, stderr):
+        return self._connection
+
+class _Dispatch(API):
+    """
+    Wrapper for an evolution service, which gives out the reassigned
+    individuals before asking the service for new individuals.
+    """
+    def __init__(self, orphans, service):
+        self.orphans = orphans
+        self.service = service
+
+    def birth(self, parents):
+        if not parents and self.orphans:
+            return self.orphans.popleft()
+        return self.service.birth(parents)
+
+    def death(self, individual):
+        self.service.death(individual)
+
+class Farm:
+    """
+    Central server for networked evaluation workers.
+
+    This has the same interface as the Environment class, except that each
+    worker runs its own instance of the environment.
+    """
+    def __init__(self, services, env_spec, address, mode='headless', settings={},
+                 stderr=sys.stderr, timeout=None, crash_dir=None, develop=None, secret=None):
+        """
+        Start listening for workers.
+
+        Argument services is a dict of evolution API instances, indexed by population name.
+
+        Argument env_spec is the filesystem path of the environment specification.
+                 The workers check that they are running the same environment.
+
+        Argument address is the network address to listen on, as a "host:port" string.
+                 If the host is missing then this only listens on the loopback interface.
+
+        Argument mode and settings are sent to the workers for starting their environments.
+
+        Argument stderr is where to write notices about workers joining and leaving.
+
+        Argument timeout is the number of seconds to wait for a response from a
+                 worker before disconnecting it and reassigning its individuals.
+
+        Argument crash_dir is an optional directory for saving a crash report
+                 whenever a worker disconnects unexpectedly.
+
+        Argument develop is an optional dict of developments, indexed by population name.
+                 The farm develops the individuals before sending them to the workers.
+
+        Argument secret is a shared secret, which workers must know in order to join.
+                 By default this is the environment variable NPC_MAKER_FARM_SECRET.
+                 If there is no secret then every worker which can connect is accepted.
+        """
+        self.env_spec_path = env_spec
+        self.env_spec   = Specification(env_spec)
+        self.mode       = str(mode).strip().lower()
+        self.settings   = {str(key): str(value) for key, value in settings.items()}
+        self.stderr     = stderr
+        self.timeout    = timeout
+        self.crash_dir  = crash_dir
+        self.develop    = develop
+        self._secret    = _get_secret(secret)
+        self.orphans    = {name: collections.deque() for name in services}
+        self.services   = {name: _Dispatch(self.orphans[name], service) for name, service in services.items()}
+        self.workers    = []
+        self.paused     = False
+        self.stopped    = False
+        self._started   = False
+        self._has_quit  = False
+        self._joining   = queue.SimpleQueue()
+        self._listener  = socket.create_server(parse_address(address))
+        self._thread    = threading.Thread(target=self._accept, daemon=True)
+        self._thread.start()
+        host, port = self.get_address()
+        print(f"npc_maker farm: listening on {host}:{port}", file=self.stderr)
+        if self._secret is None and not _is_loopback(host):
+            print(f"npc_maker farm: warning: accepting workers from any computer, set {SECRET_VARIABLE}",
+                  file=self.stderr)
+
+    def get_address(self):
+        """ Returns the pair of (host, port) which this is listening on. """
+        return self._listener.getsockname()[:2]
+
+    def get_env_spec(self):
+        return self.env_spec
+
+    def get_workers(self):
+        """ Returns the names of the connected workers. """
+        return [worker._connection.name for worker in self.workers]
+
+    def get_outstanding(self):
+        """
+        Get all individuals who are currently alive on any of the workers,
+        or who are waiting to be reassigned. Returns a dictionary indexed by individuals names.
+        """
+        outstanding = {}
+        for worker in self.workers:
+            outstanding.update(worker.get_outstanding())
+        for orphans in self.orphans.values():
+            outstanding.update((individual.get_name(), individual) for individual in orphans)
+        return outstanding
+
+    def is_alive(self):
+        """ The farm keeps running while workers come and go, until it quits. """
+        return not self._has_quit
+
+    def _accept(self):
+        while True:
+            try:
+                sock, address = self._listener.accept()
+            except OSError:
+                return
+            threading.Thread(target=self._handshake, args=(sock, address), daemon=True).start()
+
+    def _handshake(self, sock, address):
+        try:
+            sock.settimeout(30)
+            hello = json.loads(recv_frame(sock) or b"null")
+            hello = hello.get("Worker") if isinstance(hello, dict) else None
+            error = None
+            if not isinstance(hello, dict):
+                error = "invalid handshake"
+            elif self._secret is not None and not hmac.compare_digest(
+                    str(hello.get("secret", "")).encode("utf-8"), self._secret.encode("utf-8")):
+                error = "the worker's secret does not match the farm's secret"
+            elif hello.get("environment") != self.env_spec["name"]:
+                error = f"this farm is running the environment \"{self.env_spec['name']}\""
+            if error is not None:
+                send_frame(sock, {"Error": error})
+                sock.close()
+                return
+            send_frame(sock, {"Worker": {"mode": self.mode, "settings": self.settings}})
+            sock.settimeout(None)
+        except (OSError, ValueError, json.JSONDecodeError):
+            sock.close()
+            return
+        name = str(hello.get("name", "")) + "@" + address[0]
+        self._joining.put(_Connection(sock, name))
+
+    def _join(self, connection):
+        worker = _WorkerEnvironment(connection, self.services, self.env_spec_path, self.mode, self.settings,
+                                    stderr=None, timeout=self.timeout, crash_dir=self.crash_dir,
+                                    develop=self.develop)
+        self.workers.append(worker)
+        print(f"npc_maker farm: worker {connection.name} joined", file=self.stderr)
+        if self._started:
+            worker.start()
+        if self.paused:
+            worker.pause()
+        if self.stopped:
+            worker.stop()
+
+    def _lose(self, worker, reason):
+        """ Disconnect from a worker and reassign its individuals. """
+        self.workers.remove(worker)
+        worker._has_quit = True
+        worker._process.terminate()
+        for individual in worker.get_outstanding().values():
+            individual.score = None
+            individual.info  = {}
+            self.orphans[individual.get_population()].append(individual)
+        worker.outstanding = {}
+        print(f"npc_maker farm: worker {worker._connection.name} {reason}", file=self.stderr)
+
+    def poll(self):
+        """
+        Accept new workers and check for messages from the connected workers.
+
+        This function is non-blocking and should be called periodically.
+        """
+        while not self._joining.empty():
+            self._join(self._joining.get())
+        exhausted = False
+        for worker in list(self.workers):
+            try:
+                if worker.is_alive():
+                    worker.poll()
+            except StopIteration:
+                exhausted = True
+            except TimeoutError:
+                self._lose(worker, "timed out")
+                continue
+            if not worker.is_alive():
+                self._lose(worker, "disconnected")
+        if exhausted:
+            raise StopIteration
+
+    def start(self):
+        self._started = True
+        for worker in self.workers:
+            worker.start()
+
+    def stop(self):
+        self.stopped = True
+        for worker in self.workers:
+            worker.stop()
+
+    def pause(self):
+        self.paused = True
+        for worker in self.workers:
+            worker.pause()
+
+    def resume(self):
+        self.paused = False
+        for worker in self.workers:
+            worker.resume()
+
+    def send(self, message):
+        for worker in self.workers:
+            worker.send(message)
+
+    def quit(self):
+        """ Quit the environments on all of the workers, and stop accepting new workers. """
+        self._has_quit = True
+        self._listener.close()
+        for worker in self.workers:
+            worker.quit()
+
+    def wait(self, timeout=None):
+        """ Wait for all of the workers to disconnect, after calling "quit()". """
+        deadline = None if timeout is None else time.monotonic() + timeout
+        for worker in self.workers:
+            remaining = None if deadline is None else max(0, deadline - time.monotonic())
+            worker.wait(remaining)
+
+class Worker:
+    """
+    Daemon which runs an environment on behalf of a farm.
+    """
+    def __init__(self, address, env_spec, stderr=sys.stderr, name=None, secret=None):
+        """
+        Argument address is the farm's network address, as a "host:port" string.
+
+        Argument env_spec is the filesystem path of the environment specification on this computer.
+
+        Argument name identifies this worker in the farm's notices, by default this is the hostname.
+
+        Argument secret is the farm's shared secret, by default this is the
+                 environment variable NPC_MAKER_FARM_SECRET.
+        """
+        self.address    = parse_address(address)
+        self.env_spec   = Specification(env_spec)
+        self.stderr     = stderr
+        self.name       = socket.gethostname() if name is None else str(name)
+        self._secret    = _get_secret(secret)
+
+    def run(self):
+        """
+        Connect to the farm and run one instance of the environment until
+        either the environment exits or the farm disconnects.
+
+        Returns the environment program's exit code.
+        """
+        with socket.create_connection(self.address) as sock:
+            hello = {"name": self.name, "environment": self.env_spec["name"]}
+            if self._secret is not None:
+                hello["secret"] = self._secret
+            send_frame(sock, {"Worker": hello})
+            reply = json.loads(recv_frame(sock) or b"null")
+            if not isinstance(reply, dict) or "Worker" not in reply:
+                error = reply.get("Error") if isinstance(reply, dict) else None
+                raise ConnectionError(error or "farm refused the connection")
+            config = reply["Worker"]
+            command = [self.env_spec["path"], self.env_spec["spec"], config["mode"]]
+            for key, value in config["settings"].items():
+                command.extend((str(key), str(value)))
+            process = subprocess.Popen(command, stdin=subprocess.PIPE, stdout=subprocess.PIPE, stderr=self.stderr)
+            relay = threading.Thread(target=self._relay_input, args=(sock, process), daemon=True)
+            relay.start()
+            try:
+                for line in process.stdout:
+                    send_frame(sock, line.rstrip(b"\r\n"))
+            except OSError:
+                pass
+            process.stdout.close()
+            try:
+                sock.shutdown(socket.SHUT_RDWR)
+            except OSError:
+                pass
+            return process.wait()
+
+    def _relay_input(self, sock, process):
+        """ Copy messages from the farm to the environment. """
+        try:
+            while (message := recv_frame(sock)) is not None:
+                process.stdin.write(message + b"\n")
+                process.stdin.flush()
+        except (OSError, ValueError):
+            pass
+        # The farm disconnected, so ask the environment to shut down.
+        try:
+            process.stdin.write(b'"Quit"\n')
+            process.stdin.close()
+        except OSError:
+            pass
+
+    def serve_forever(self, retry=5.0):
+        """
+        Keep running environments for the farm, and reconnect whenever the connection is lost.
+
+        Argument retry is the number of seconds to wait between attempts to connect.
+        """
+        last_error = None
+        while True:
+            try:
+                exit_code = self.run()
+                print(f"npc_maker worker: environment exited with code {exit_code}", file=self.stderr)
+                last_error = None
+            except (ConnectionError, OSError) as error:
+                # Don't repeat the same error while waiting for the farm to come back.
+                if str(error) != last_error:
+                    print(f"npc_maker worker: {error}", file=self.stderr)
+                last_error = str(error)
+            time.sleep(retry)
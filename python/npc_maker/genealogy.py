@@ -0,0 +1,331 @@
+"""
+Genealogy, for visualizing the lineages of evolution.
+
+Every individual has a permanent UUID and records the UUIDs of its parents,
+see "Individual.get_uuid()" and "Individual.get_parent_uuids()". This module
+walks directories of saved individuals and draws their family tree as a graph,
+where each node is an individual and each edge points from a parent to a child.
+Nodes are annotated with the individual's score, ascension number, and
+generation.
+
+Evolution services delete individuals as they are replaced, so the graph only
+contains the individuals which are still saved, for example in the population
+and leaderboard directories. Parents which were not saved are left out. To
+record the complete genealogy, save every individual with a Recorder filter.
+
+Two formats are supported: DOT for GraphViz, and GEXF for Gephi.
+
+The Lineage class answers questions about the ancestry of individuals, such as
+"what are the ancestors of this individual within three generations?" and
+"what is the most recent common ancestor of these two individuals?". It
+loads the individuals from the directories as they are needed.
+
+Example:
+
+    export_dot("results/xor", "genealogy.dot", population_size=100)
+
+    lineage = Lineage("results/xor")
+    best    = lineage.get(uuid)
+    lineage.ancestors(best, generations=3)
+    lineage.common_ancestor(best, other)
+
+From the command line:
+
+    python -m npc_maker genealogy results/xor --format dot --output genealogy.dot
+    dot -Tsvg genealogy.dot -o genealogy.svg
+"""
+
+from npc_maker.evo import CorruptFileError, Individual, _load_checked
+from pathlib import Path
+from xml.sax.saxutils import quoteattr
+import collections
+import json
+
+__all__ = (
+    "Genealogy",
+    "Lineage",
+    "export_dot",
+    "export_gexf",
+)
+
+class Genealogy:
+    """ Family tree of the individuals saved in some directories. """
+    def __init__(self, *directories, population_size=None):
+        """
+        Argument directories are searched recursively for saved individuals.
+                 Individuals which are saved in several places are only counted once.
+
+        Argument population_size is the number of individuals in each
+                 generation, for numbering the generations. If None then the
+                 generations are not annotated.
+        """
+        self.population_size = None if population_size is None else int(population_size)
+        self.individuals     = {} # UUID -> Individual
+        for directory in directories:
+            directory = Path(directory).expanduser()
+            if not directory.is_dir():
+                raise ValueError(f"directory \"{directory}\" does not exist")
+            for path in sorted(directory.rglob("*.json")):
+                individual = _try_load(path)
+                if individual is not None and individual.get_uuid() is not None:
+                    self.individuals.setdefault(individual.get_uuid(), individual)
+
+    def get_generation(self, individual):
+        """ Returns the generation number of the individual, or None if it is unknown. """
+        ascension = individual.get_ascension()
+        if ascension is None or not self.population_size:
+            return None
+        return ascension // self.population_size
+
+    def nodes(self):
+        """ Returns the individuals, sorted by ascension number. """
+        key = lambda individual: (individual.get_ascension() is None, individual.get_ascension() or 0)
+        return sorted(self.individuals.values(), key=key)
+
+    def edges(self):
+        """ Returns a list of pairs of (parent UUID, child UUID) for the parents which were saved. """
+        edges = []
+        for individual in self.nodes():
+            for parent in individual.get_parent_uuids() or []:
+                if parent in self.individuals:
+                    edges.append((parent, individual.get_uuid()))
+        return edges
+
+    def to_dot(self):
+        """ Returns the genealogy as a GraphViz DOT document. """
+        lines = ["digraph genealogy {", "    node [shape=box];"]
+        for individual in self.nodes():
+            label = [f"#{individual.get_ascension()}"]
+            generation = self.get_generation(individual)
+            if generation is not None:
+                label.append(f"generation {generation}")
+            label.append(f"score {_format_score(individual.get_score())}")
+            attributes = {"label": "\n".join(label), "score": _format_score(individual.get_score())}
+            if generation is not None:
+                attributes["generation"] = str(generation)
+            attributes = ", ".join(f"{key}={json.dumps(value)}" for key, value in attributes.items())
+            lines.append(f"    {json.dumps(individual.get_uuid())} [{attributes}];")
+        for parent, child in self.edges():
+            lines.append(f"    {json.dumps(parent)} -> {json.dumps(child)};")
+        lines.append("}")
+        return "\n".join(lines) + "\n"
+
+    def to_gexf(self):
+        """ Returns the genealogy as a GEXF document. """
+        lines = [
+            '<?xml version="1.0" encoding="UTF-8"?>',
+            '<gexf xmlns="http://gexf.net/1.3" version="1.3">',
+            '  <graph defaultedgetype="directed">',
+            '    <attributes class="node">',
+            '      <attribute id="0" title="score" type="double"/>',
+            '      <attribute id="1" title="ascension" type="integer"/>',
+            '      <attribute id="2" title="generation" type="integer"/>',
+            '      <attribute id="3" title="population" type="string"/>',
+            '    </attributes>',
+            '    <nodes>',
+        ]
+        for individual in self.nodes():
+            values = (_number(individual.get_score()), individual.get_ascension(),
+                      self.get_generation(individual), individual.get_population())
+            lines.append(f'      <node id={quoteattr(individual.get_uuid())} '
+                         f'label={quoteattr("#" + str(individual.get_ascension()))}>')
+            lines.append('        <attvalues>')
+            for index, value in enumerate(values):
+                if value is not None:
+                    lines.append(f'          <attvalue for="{index}" value={quoteattr(str(value))}/>')
+            lines.append('        </attvalues>')
+            lines.append('      </node>')
+        lines.append('    </nodes>')
+        lines.append('    <edges>')
+        for index, (parent, child) in enumerate(self.edges()):
+            lines.append(f'      <edge id="{index}" source={quoteattr(parent)} target={quoteattr(child)}/>')
+        lines.append('    </edges>')
+        lines.append('  </graph>')
+        lines.append('</gexf>')
+        return "\n".join(lines) + "\n"
+
+class Lineage:
+    """
+    Ancestry queries over the individuals saved in some directories.
+
+    The directories are indexed when the first query is made, and individuals
+    are loaded from their files when they are needed. Individuals are given
+    either as Individual objects or as UUID strings. Queries only return the
+    individuals which are saved in the directories.
+    """
+    def __init__(self, *directories):
+        """ Argument directories are searched recursively for saved individuals. """
+        self.directories = [Path(directory).expanduser() for directory in directories]
+        for directory in self.directories:
+            if not directory.is_dir():
+                raise ValueError(f"directory \"{directory}\" does not exist")
+        self._paths    = None # UUID -> Path
+        self._parents  = None # UUID -> list of parent UUIDs
+        self._children = None # UUID -> list of child UUIDs
+        self._cache    = {}   # UUID -> Individual
+
+    def _index(self):
+        if self._paths is not None:
+            return
+        self._paths    = {}
+        self._parents  = {}
+        self._children = collections.defaultdict(list)
+        for directory in self.directories:
+            for path in sorted(directory.rglob("*.json")):
+                try:
+                    data = _load_checked(path)
+                except (CorruptFileError, json.JSONDecodeError):
+                    continue
+                if not isinstance(data, dict) or "genome" not in data or data.get("uuid") is None:
+                    continue
+                uuid = data["uuid"]
+                if uuid in self._paths:
+                    continue
+                self._paths[uuid]   = path
+                self._parents[uuid] = list(data.get("parent_uuids") or [])
+        for uuid, parents in self._parents.items():
+            for parent in parents:
+                self._children[parent].append(uuid)
+
+    @staticmethod
+    def _uuid(individual):
+        return individual if isinstance(individual, str) else individual.get_uuid()
+
+    def __contains__(self, individual):
+        self._index()
+        return self._uuid(individual) in self._paths
+
+    def get(self, uuid):
+        """ Load the individual with the given UUID, or return None if it was not saved. """
+        self._index()
+        uuid = self._uuid(uuid)
+        if uuid not in self._cache:
+            path = self._paths.get(uuid)
+            if path is None:
+                return None
+            self._cache[uuid] = Individual.load(path)
+        return self._cache[uuid]
+
+    def parents(self, individual):
+        """ Returns the saved parents of the individual. """
+        return [self.get(uuid) for uuid in self._parent_uuids(individual) if uuid in self]
+
+    def children(self, individual):
+        """ Returns the saved children of the individual. """
+        self._index()
+        return [self.get(uuid) for uuid in self._children.get(self._uuid(individual), [])]
+
+    def _parent_uuids(self, individual):
+        self._index()
+        uuid = self._uuid(individual)
+        if uuid in self._parents:
+            return self._parents[uuid]
+        if isinstance(individual, Individual):
+            return individual.get_parent_uuids() or []
+        return []
+
+    def _search(self, individual, neighbors, generations):
+        """ Breadth first search, returns a dictionary of UUID -> number of generations away. """
+        self._index()
+        depth    = {self._uuid(individual): 0}
+        frontier = [self._uuid(individual)]
+        for generation in range(1, (generations if generations is not None else len(self._paths)) + 1):
+            next_frontier = []
+            for uuid in frontier:
+                for relative in neighbors(uuid):
+                    if relative not in depth:
+                        depth[relative] = generation
+                        next_frontier.append(relative)
+            frontier = next_frontier
+            if not frontier:
+                break
+        return depth
+
+    def _ancestor_depths(self, individual, generations=None):
+        # The individual itself might not be saved, so its parents are read from the object.
+        parents = lambda uuid: self._parent_uuids(individual if uuid == self._uuid(individual) else uuid)
+        return self._search(individual, parents, generations)
+
+    def _sorted(self, depths):
+        """ Returns the saved individuals, nearest first. """
+        uuids = sorted((uuid for uuid in depths if depths[uuid] > 0 and uuid in self),
+                       key=lambda uuid: (depths[uuid], self._paths[uuid]))
+        return [self.get(uuid) for uuid in uuids]
+
+    def ancestors(self, individual, generations=None):
+        """
+        Returns the saved ancestors of the individual, nearest first.
+
+        Argument generations is the maximum number of generations to go back,
+                 for example one returns the parents and two also returns the
+                 grandparents. If None then there is no limit.
+        """
+        return self._sorted(self._ancestor_depths(individual, generations))
+
+    def descendants(self, individual, generations=None):
+        """
+        Returns the saved descendants of the individual, nearest first.
+
+        Argument generations is the maximum number of generations to go forward.
+                 If None then there is no limit.
+        """
+        return self._sorted(self._search(individual, lambda uuid: self._children.get(uuid, []), generations))
+
+    def common_ancestor(self, a, b):
+        """
+        Returns the most recent common ancestor of two individuals,
+        or None if they have no common ancestor which was saved.
+
+        The most recent common ancestor is the one with the fewest generations
+        to the more distant of the two individuals. An individual which is an
+        ancestor of the other is their most recent common ancestor.
+        """
+        depth_a = self._ancestor_depths(a)
+        depth_b = self._ancestor_depths(b)
+        common  = [uuid for uuid in depth_a.keys() & depth_b.keys() if uuid in self]
+        if not common:
+            return None
+        best = min(common, key=lambda uuid: (max(depth_a[uuid], depth_b[uuid]), depth_a[uuid] + depth_b[uuid],
+                                             str(self._paths[uuid])))
+        return self.get(best)
+
+def _try_load(path):
+    """ Load an individual, or return None if the file is not an individual. """
+    try:
+        return Individual.load(path)
+    except (CorruptFileError, KeyError, TypeError, AttributeError, json.JSONDecodeError):
+        return None
+
+def _number(score):
+    try:
+        return float(score)
+    except (TypeError, ValueError):
+        return None
+
+def _format_score(score):
+    number = _number(score)
+    if number is None:
+        return str(score)
+    return f"{number:.4g}"
+
+def export_dot(directories, path, population_size=None):
+    """
+    Write the genealogy of the individuals saved in the directories to a DOT file.
+
+    Argument directories is a directory or a list of them.
+    """
+    if isinstance(directories, (str, Path)):
+        directories = [directories]
+    with open(path, 'wt') as file:
+        file.write(Genealogy(*directories, population_size=population_size).to_dot())
+
+def export_gexf(directories, path, population_size=None):
+    """
+    Write the genealogy of the individuals saved in the directories to a GEXF file.
+
+    Argument directories is a directory or a list of them.
+    """
+    if isinstance(directories, (str, Path)):
+        directories = [directories]
+    with open(path, 'wt') as file:
+        file.write(Genealogy(*directories, population_size=population_size).to_gexf())
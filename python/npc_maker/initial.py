@@ -0,0 +1,146 @@
+"""
+Initial genome generators, for making the genomes which begin evolution.
+
+Evolution services make new genomes from scratch when there are no parents to
+reproduce, for example before the first generation has died. Each generator
+is a callable object with one method:
+
+    generate() -> genome
+
+Evolution services accept any function which returns a new genome, see the
+argument "seed" of "npc_maker.evo.Evolution".
+
+Experiment configurations select the generator by name, or by a table with the
+generator's name and its parameters. The population's "seed" or "seed_file"
+is the template genome for the generators which need one, for example:
+
+    [populations.xor]
+    seed_file = "seed_genome.json"
+    initial_genome = "constant"
+
+    [populations.xor.initial_genome]
+    algorithm = "template"
+    noise = 0.1
+
+Generator names and parameters:
+
+    constant                                Every initial genome is a copy of the seed.
+    template (noise=0.1, probability=1.0)   Copy of the seed with gaussian noise added to its real numbers.
+                                            Each number is changed with the given probability.
+    random_bytes (length=32)                List of random integers in the range [0, 255].
+                                            This generator does not use the seed.
+
+Random numbers are drawn from the attribute "rng", which is the "random"
+module by default. Evolution services with a random seed replace it with their
+own random number generator, so that their initial genomes are reproducible.
+"""
+
+import copy
+import random
+
+__all__ = (
+    "InitialGenome",
+    "Constant",
+    "Template",
+    "RandomBytes",
+    "ALGORITHMS",
+    "from_config",
+)
+
+class InitialGenome:
+    """
+    Abstract class for initial genome generators.
+    Subclasses must implement "generate()".
+    """
+    rng = random
+
+    def generate(self):
+        """ Abstract method, returns a new JSON-encodable genome. """
+        raise TypeError("abstract method called")
+
+    def __call__(self):
+        return self.generate()
+
+    def __repr__(self):
+        parameters = ", ".join(f"{key}={value!r}" for key, value in vars(self).items() if key != "rng")
+        return f"{type(self).__name__}({parameters})"
+
+class Constant(InitialGenome):
+    """ Every initial genome is a copy of the given genome. """
+    def __init__(self, genome):
+        self.genome = genome
+
+    def generate(self):
+        return copy.deepcopy(self.genome)
+
+class Template(InitialGenome):
+    """
+    Copy the given genome and add gaussian noise to its real numbers.
+    Integers, booleans, and strings are copied unchanged.
+    """
+    def __init__(self, genome, noise=0.1, probability=1.0):
+        self.genome      = genome
+        self.noise       = float(noise)
+        self.probability = float(probability)
+        if not self.noise >= 0.0:
+            raise ValueError("template noise must be non-negative")
+        if not 0.0 <= self.probability <= 1.0:
+            raise ValueError("template probability must be in the range [0, 1]")
+
+    def generate(self):
+        return self._perturb(self.genome)
+
+    def _perturb(self, value):
+        if isinstance(value, float):
+            if self.rng.random() < self.probability:
+                return value + self.rng.gauss(0.0, self.noise)
+            return value
+        elif isinstance(value, dict):
+            return {key: self._perturb(item) for key, item in value.items()}
+        elif isinstance(value, list):
+            return [self._perturb(item) for item in value]
+        else:
+            return value
+
+class RandomBytes(InitialGenome):
+    """ List of random integers in the range [0, 255]. """
+    def __init__(self, length=32):
+        self.length = int(length)
+        if self.length < 0:
+            raise ValueError("random bytes length must be non-negative")
+
+    def generate(self):
+        return list(self.rng.randbytes(self.length))
+
+ALGORITHMS = {
+    "constant":     Constant,
+    "template":     Template,
+    "random_bytes": RandomBytes,
+}
+
+# Generators which take the seed genome as their first argument.
+_USES_SEED = (Constant, Template)
+
+def from_config(config, seed=None):
+    """
+    Make an initial genome generator from its configuration, which is either
+    the name of a generator, or a dictionary with the generator's name under
+    the key "algorithm" and its parameters under the other keys.
+
+    Argument seed is the template genome, for the generators which use one.
+    """
+    if isinstance(config, str):
+        config = {"algorithm": config}
+    parameters = dict(config)
+    name = str(parameters.pop("algorithm", "")).strip().lower()
+    if name not in ALGORITHMS:
+        raise ValueError(f"unrecognized initial genome \"{name}\", expected one of: {', '.join(ALGORITHMS)}")
+    cls = ALGORITHMS[name]
+    if cls in _USES_SEED:
+        if seed is None:
+            raise ValueError(f"the initial genome \"{name}\" requires a seed genome")
+        parameters["genome"] = seed
+    try:
+        return cls(**parameters)
+    except TypeError:
+        raise ValueError(f"invalid parameters for the initial genome \"{name}\": {config}") from None
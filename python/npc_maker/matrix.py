@@ -0,0 +1,209 @@
+"""
+Test every combination of environments and controllers.
+
+The NPC Maker has several implementations of the same programs, for example
+the XOR environment and the NN controller are both written in python and in
+rust. Any implementation of an environment should work with any implementation
+of a compatible controller. This module runs each pair of them through the real
+Environment and Controller classes, evaluates a known solution, and checks its
+score.
+
+Programs which do not exist are skipped, for example rust programs which have
+not been built yet.
+
+Example:
+
+    matrix = Matrix(timeout=60)
+    matrix.add_environment("examples/xor/xor.env", "xor", genome=solution, min_score=15)
+    matrix.add_environment("examples/xor/xor_rust.env", "xor", genome=solution, min_score=15)
+    matrix.add_controller("examples/nn/nn.py")
+    matrix.add_controller("examples/nn/target/release/nn")
+    matrix.check()
+"""
+
+from npc_maker.env import Environment, Specification
+from npc_maker.evo import API
+from pathlib import Path
+import shutil
+import sys
+import time
+
+__all__ = (
+    "Matrix",
+    "Result",
+)
+
+class _Case:
+    """ Environment and the solution which is evaluated in it. """
+    def __init__(self, env_spec, population, genome, min_score, max_score, settings):
+        self.env_spec   = Path(env_spec).expanduser().resolve()
+        self.population = str(population)
+        self.genome     = genome
+        self.min_score  = None if min_score is None else float(min_score)
+        self.max_score  = None if max_score is None else float(max_score)
+        self.settings   = dict(settings)
+
+class Result:
+    """
+    Outcome of evaluating one pair of environment and controller.
+
+    Attribute status is one of: "passed", "failed", or "skipped".
+    Attribute score is the score of the solution, or None if it was not scored.
+    Attribute message explains why the test failed or was skipped.
+    """
+    def __init__(self, env_spec, controller, status, score=None, message="", elapsed=0.0):
+        self.env_spec   = env_spec
+        self.controller = controller
+        self.status     = status
+        self.score      = score
+        self.message    = message
+        self.elapsed    = elapsed
+
+    def passed(self):
+        return self.status == "passed"
+
+    def failed(self):
+        return self.status == "failed"
+
+    def skipped(self):
+        return self.status == "skipped"
+
+    def __str__(self):
+        line = f"{self.status.upper():7} {self.env_spec} with {' '.join(self.controller)}"
+        if self.score is not None:
+            line += f", score {self.score}"
+        if self.message:
+            line += f", {self.message}"
+        return line
+
+class _Solution(API):
+    """ Evolution service which gives the solution to the environment, once. """
+    def __init__(self, controller, genome):
+        self.controller = controller
+        self.genome     = genome
+        self.individual = None
+        self.given      = False
+
+    def birth(self, parents):
+        if self.given:
+            raise StopIteration
+        self.given = True
+        return {"controller": self.controller, "genome": self.genome}
+
+    def death(self, individual):
+        self.individual = individual
+
+def _program_exists(program):
+    path = Path(program).expanduser()
+    return path.exists() or (not path.parent.parts and shutil.which(str(program)) is not None)
+
+class Matrix:
+    """
+    Table of environments and controllers, which are tested in every combination.
+    """
+    def __init__(self, timeout=60, mode="headless", stderr=sys.stderr):
+        """
+        Argument timeout is the number of seconds to wait for each evaluation,
+                 after which it fails.
+
+        Argument mode and stderr are the same as for the Environment class.
+        """
+        self.timeout     = float(timeout)
+        self.mode        = mode
+        self.stderr      = stderr
+        self.cases       = []
+        self.controllers = []
+
+    def add_environment(self, env_spec, population, genome, min_score=None, max_score=None, settings={}):
+        """
+        Argument env_spec is the filesystem path of the environment specification.
+
+        Argument population is the name of the population to evaluate the genome in.
+
+        Argument genome is a known solution, which every controller can run.
+
+        Arguments min_score and max_score are the inclusive range of acceptable scores.
+                  If both are None then any score is acceptable, but the
+                  individual must still be scored.
+
+        Argument settings is a dict of command line arguments for the environment.
+        """
+        self.cases.append(_Case(env_spec, population, genome, min_score, max_score, settings))
+
+    def add_controller(self, command):
+        """
+        Argument command is the controller's program, or a list of the program and its arguments.
+        """
+        if isinstance(command, (str, Path)):
+            command = [command]
+        command = [str(command[0])] + [str(arg) for arg in command[1:]]
+        self.controllers.append(command)
+
+    def run(self):
+        """
+        Evaluate every combination of environment and controller.
+
+        Returns a list of Result objects.
+        """
+        return [self._run_one(case, controller) for case in self.cases for controller in self.controllers]
+
+    def check(self, verbose=True):
+        """
+        Evaluate every combination of environment and controller,
+        and raise an AssertionError if any of them failed.
+
+        Returns a list of Result objects.
+        """
+        results = self.run()
+        if verbose:
+            for result in results:
+                print(result)
+        failures = [str(result) for result in results if result.failed()]
+        if failures:
+            raise AssertionError(f"{len(failures)} of {len(results)} tests failed:\n" + "\n".join(failures))
+        return results
+
+    def _run_one(self, case, controller):
+        start = time.monotonic()
+        def result(status, score=None, message=""):
+            return Result(case.env_spec, controller, status, score, message, time.monotonic() - start)
+        try:
+            env_spec = Specification(case.env_spec)
+        except (OSError, ValueError) as error:
+            return result("failed", message=str(error))
+        if not _program_exists(env_spec["path"]):
+            return result("skipped", message=f"missing environment program \"{env_spec['path']}\"")
+        if not _program_exists(controller[0]):
+            return result("skipped", message=f"missing controller program \"{controller[0]}\"")
+        service = _Solution(controller, case.genome)
+        env = None
+        try:
+            env = Environment({case.population: service}, case.env_spec, self.mode, case.settings,
+                              stderr=self.stderr, timeout=self.timeout)
+            env.start()
+            deadline = start + self.timeout
+            while service.individual is None:
+                if not env.is_alive():
+                    return result("failed", message="environment exited before the evaluation finished")
+                if time.monotonic() > deadline:
+                    return result("failed", message=f"timed out after {self.timeout} seconds")
+                try:
+                    env.poll()
+                except StopIteration:
+                    pass
+                time.sleep(0.01)
+        except Exception as error:
+            return result("failed", message=f"{type(error).__name__}: {error}")
+        finally:
+            if env is not None and env.is_alive():
+                env.quit()
+        score = service.individual.get_score()
+        try:
+            score = float(score)
+        except (TypeError, ValueError):
+            return result("failed", score, "individual was not scored")
+        if case.min_score is not None and not score >= case.min_score:
+            return result("failed", score, f"expected at least {case.min_score}")
+        if case.max_score is not None and not score <= case.max_score:
+            return result("failed", score, f"expected at most {case.max_score}")
+        return result("passed", score)
@@ -0,0 +1,188 @@
+"""
+HTTP endpoint for monitoring and controlling a running experiment.
+
+The monitor serves JSON documents about an experiment from a background
+thread, so that external dashboards and scripts can observe and steer a run
+without touching the filesystem.
+
+| Method | Path | Description |
+| :----- | :--- | :---------- |
+| GET  | `/summary`                  | Progress of the experiment and of each population |
+| GET  | `/leaderboard/<population>` | Best individuals in the population, if its leaderboard is enabled |
+| GET  | `/environment`              | Metrics about the environment instance |
+| GET  | `/metrics`                  | Metrics in the Prometheus text format |
+| POST | `/pause`                    | Pause the environment |
+| POST | `/resume`                   | Resume the environment |
+| POST | `/checkpoint`               | Save the experiment's progress |
+| POST | `/stop`                     | Stop creating new individuals and finish the experiment |
+"""
+
+from http.server import BaseHTTPRequestHandler, ThreadingHTTPServer
+import json
+import threading
+import urllib.parse
+import urllib.request
+
+__all__ = (
+    "Monitor",
+    "prometheus",
+    "push",
+)
+
+def prometheus(experiment):
+    """
+    Format the metrics of an experiment in the Prometheus text exposition format.
+
+    Returns a string.
+    """
+    summary = experiment.summary()
+    lines = []
+    def metric(name, kind, help, samples):
+        lines.append(f"# HELP npc_maker_{name} {help}")
+        lines.append(f"# TYPE npc_maker_{name} {kind}")
+        for labels, value in samples:
+            if value is None:
+                continue
+            labels = ",".join(f'{key}="{_escape(text)}"' for key, text in labels.items())
+            labels = f"{{{labels}}}" if labels else ""
+            lines.append(f"npc_maker_{name}{labels} {float(value)!r}")
+    populations = summary["populations"]
+    metric("births_total", "counter", "Number of individuals born.", [({}, summary["births"])])
+    metric("deaths_total", "counter", "Number of individuals evaluated.", [({}, summary["deaths"])])
+    metric("elapsed_seconds", "counter", "Run time of the experiment.", [({}, summary["elapsed"])])
+    metric("evaluations_per_second", "gauge", "Mean rate of evaluations.", [({}, summary["evaluations_per_second"])])
+    metric("outstanding", "gauge", "Number of individuals currently alive.", [({}, summary["outstanding"])])
+    metric("paused", "gauge", "Whether the environment is paused.", [({}, summary["paused"])])
+    metric("environment_up", "gauge", "Whether the environment process is running.",
+           [({}, experiment.environment is not None and experiment.environment.is_alive())])
+    metric("generation", "gauge", "Number of complete generations.",
+           [({"population": name}, pop["generation"]) for name, pop in populations.items()])
+    metric("population_size", "gauge", "Number of individuals in the mating pool.",
+           [({"population": name}, pop["size"]) for name, pop in populations.items()])
+    metric("best_score", "gauge", "Highest score in the mating pool.",
+           [({"population": name}, pop["best_score"]) for name, pop in populations.items()])
+    metric("mean_score", "gauge", "Mean score of the mating pool.",
+           [({"population": name}, pop["mean_score"]) for name, pop in populations.items()])
+    return "\n".join(lines) + "\n"
+
+def _escape(value):
+    return str(value).replace("\\", "\\\\").replace("\"", "\\\"").replace("\n", "\\n")
+
+def push(experiment, gateway, job="npc_maker", timeout=10):
+    """
+    Send the metrics of an experiment to a Prometheus pushgateway.
+
+    Argument gateway is the URL of the pushgateway, for example "http://localhost:9091".
+
+    Argument job is the name of the job to group the metrics under.
+    """
+    url = f"{gateway.rstrip('/')}/metrics/job/{urllib.parse.quote(job, safe='')}"
+    request = urllib.request.Request(url, data=prometheus(experiment).encode("utf-8"), method="PUT",
+                                     headers={"Content-Type": "text/plain; version=0.0.4"})
+    with urllib.request.urlopen(request, timeout=timeout):
+        pass
+
+class Monitor:
+    """
+    HTTP server for monitoring and controlling an experiment.
+    """
+    def __init__(self, experiment, port=8000, host="127.0.0.1"):
+        """
+        Argument experiment is an instance of "npc_maker.run.Experiment".
+
+        Argument port is the TCP port to listen on. If zero then the operating
+                 system will pick an unused port, see "get_address()".
+
+        Argument host is the network interface to listen on. By default the
+                 monitor is only accessible from the local computer.
+        """
+        self.experiment = experiment
+        Handler = type("Handler", (_Handler,), {"monitor": self})
+        self._server = ThreadingHTTPServer((host, int(port)), Handler)
+        self._thread = threading.Thread(target=self._server.serve_forever, daemon=True)
+        self._thread.start()
+
+    def get_address(self):
+        """ Returns the pair of (host, port) which the server is listening on. """
+        return self._server.server_address[:2]
+
+    def close(self):
+        """ Stop the server. """
+        self._server.shutdown()
+        self._server.server_close()
+
+    def __enter__(self):
+        return self
+
+    def __exit__(self, *exception):
+        self.close()
+
+    def get(self, path):
+        """
+        Handle a GET request.
+        Returns a JSON-encodable object or a string of plain text,
+        or raises KeyError if the path was not found.
+        """
+        parts = [part for part in path.split("/") if part]
+        if parts == ["metrics"]:
+            return prometheus(self.experiment)
+        elif parts == ["summary"]:
+            return self.experiment.summary()
+        elif len(parts) == 2 and parts[0] == "leaderboard":
+            return [{"path": str(path), "score": score}
+                    for path, score in self.experiment.leaderboard(parts[1])]
+        elif parts == ["environment"]:
+            summary = self.experiment.summary()
+            environment = self.experiment.environment
+            return {
+                "alive": environment is not None and environment.is_alive(),
+                "mode": self.experiment.mode,
+                "settings": environment.get_settings() if environment is not None else {},
+                "outstanding": summary["outstanding"],
+                "evaluations_per_second": summary["evaluations_per_second"],
+                "paused": summary["paused"],
+            }
+        raise KeyError(path)
+
+    def post(self, path):
+        """
+        Handle a POST request.
+        Raises KeyError if the path was not found.
+        """
+        parts = [part for part in path.split("/") if part]
+        if len(parts) != 1:
+            raise KeyError(path)
+        try:
+            self.experiment.request(parts[0])
+        except ValueError:
+            raise KeyError(path) from None
+        return {"ok": parts[0]}
+
+class _Handler(BaseHTTPRequestHandler):
+    monitor = None
+
+    def _respond(self, method):
+        try:
+            status, body = 200, method(self.path.split("?")[0])
+        except KeyError:
+            status, body = 404, {"error": f"not found: {self.path}"}
+        except Exception as error:
+            status, body = 500, {"error": str(error)}
+        if isinstance(body, str):
+            data, content_type = body.encode("utf-8"), "text/plain; version=0.0.4"
+        else:
+            data, content_type = json.dumps(body).encode("utf-8"), "application/json"
+        self.send_response(status)
+        self.send_header("Content-Type", content_type)
+        self.send_header("Content-Length", str(len(data)))
+        self.end_headers()
+        self.wfile.write(data)
+
+    def do_GET(self):
+        self._respond(self.monitor.get)
+
+    def do_POST(self):
+        self._respond(self.monitor.post)
+
+    def log_message(self, format, *args):
+        pass
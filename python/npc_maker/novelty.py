@@ -0,0 +1,165 @@
+"""
+Novelty search, for evolving behavioral diversity instead of raw fitness.
+
+Novelty search rewards individuals for behaving differently from those which
+came before them. Each individual's behavior is summarized by a descriptor,
+which is a list of numbers taken from the telemetry that the environment
+reports in the individual's info. The novelty of a behavior is its average
+distance to its k nearest neighbors among the recent behaviors and an archive
+of past behaviors. Novel behaviors are added to the archive, so that evolution
+does not return to them.
+
+The Novelty class wraps an evolution service. When an individual dies it
+measures the individual's novelty and saves it in the individual's info under
+the key "novelty", before passing the individual on to the evolution service.
+Use "novelty" as the evolution service's score, or combine it with the task
+score for multi-objective optimization, for example:
+
+    evolution = Evolution(controller, seed, score=["score", "novelty"])
+    service   = Novelty(evolution, descriptor=["x", "y"], path="results/xor")
+
+Example experiment configuration:
+
+    [populations.xor.novelty]
+    descriptor = ["x", "y"]
+    k = 15
+    threshold = 2.0
+"""
+
+from npc_maker.evo import API
+from pathlib import Path
+import collections
+import heapq
+import itertools
+import json
+import math
+import random
+
+__all__ = (
+    "Novelty",
+)
+
+class Novelty(API):
+    """
+    Wrapper for an evolution service, which measures the novelty of each individual's behavior.
+    """
+    def __init__(self, service, descriptor, k=15, threshold=None, probability=0.02,
+                 archive_size=10000, recent=100, path=None):
+        """
+        Argument service is the underlying evolution API instance.
+
+        Argument descriptor is either a list of keys in the individual's info,
+                 or a function which returns a list of numbers: f(individual) -> [float]
+
+        Argument k is the number of nearest neighbors to measure the distance to.
+
+        Argument threshold is the novelty above which behaviors are added to the archive.
+                 If None then behaviors are added to the archive at random,
+                 with the given probability. The random numbers are drawn
+                 from the service's random number generator, see the argument
+                 "random_seed" of "npc_maker.evo.Evolution".
+
+        Argument archive_size is the maximum number of behaviors in the archive.
+                 Once the archive is full the oldest behaviors are discarded.
+
+        Argument recent is the number of the most recent behaviors which are
+                 compared against in addition to the archive.
+
+        Argument path is an optional directory for saving the archive,
+                 in the file "novelty_archive.ndjson".
+                 Any existing archive in the directory is loaded.
+        """
+        self.service      = service
+        self.descriptor   = descriptor
+        self.k            = int(k)
+        self.threshold    = None if threshold is None else float(threshold)
+        self.probability  = float(probability)
+        self.archive_size = int(archive_size)
+        self.archive      = collections.deque(maxlen=self.archive_size)
+        self.recent       = collections.deque(maxlen=int(recent))
+        self.rng          = getattr(service, "rng", random)
+        assert isinstance(service, API)
+        assert callable(descriptor) or (isinstance(descriptor, (list, tuple)) and len(descriptor) > 0)
+        assert self.k >= 1
+        assert 0.0 <= self.probability <= 1.0
+        self.path = None
+        if path is not None:
+            self.path = Path(path).joinpath("novelty_archive.ndjson")
+            self._load_archive()
+
+    def get_archive(self):
+        """ Returns a list of the behavior descriptors in the archive. """
+        return list(self.archive)
+
+    def _load_archive(self):
+        if not self.path.exists():
+            return
+        with open(self.path, 'rt') as file:
+            for line in file:
+                try:
+                    self.archive.append(tuple(json.loads(line)))
+                except json.JSONDecodeError:
+                    pass # The last line may be partially written.
+
+    def describe(self, individual):
+        """
+        Returns the behavior descriptor of an individual as a tuple of floats,
+        or None if the individual did not report its behavior.
+        """
+        if callable(self.descriptor):
+            values = self.descriptor(individual)
+        else:
+            info = individual.get_info()
+            if not all(key in info for key in self.descriptor):
+                return None
+            values = [info[key] for key in self.descriptor]
+        try:
+            values = tuple(float(value) for value in values)
+        except (TypeError, ValueError):
+            return None
+        if not all(math.isfinite(value) for value in values):
+            return None
+        return values
+
+    def novelty(self, behavior):
+        """ Average distance from the behavior to its k nearest neighbors, or zero if there are no others. """
+        neighbors = heapq.nsmallest(self.k, (math.dist(behavior, other)
+                                             for other in itertools.chain(self.archive, self.recent)
+                                             if len(other) == len(behavior)))
+        if not neighbors:
+            return 0.0
+        return sum(neighbors) / len(neighbors)
+
+    def birth(self, parents):
+        return self.service.birth(parents)
+
+    def death(self, individual):
+        behavior = self.describe(individual)
+        if behavior is None:
+            individual.info["novelty"] = None
+        else:
+            novelty = self.novelty(behavior)
+            individual.info["novelty"] = novelty
+            if self.threshold is not None:
+                archive = novelty > self.threshold or not self.archive
+            else:
+                archive = self.rng.random() < self.probability or not self.archive
+            if archive:
+                self._archive(behavior)
+            self.recent.append(behavior)
+        self.service.death(individual)
+
+    def _archive(self, behavior):
+        full = len(self.archive) == self.archive_size
+        self.archive.append(behavior)
+        if self.path is None:
+            return
+        if full:
+            # Rewrite the file without the discarded behaviors.
+            temp = self.path.with_suffix(".tmp")
+            with open(temp, 'wt') as file:
+                file.writelines(json.dumps(list(item)) + "\n" for item in self.archive)
+            temp.replace(self.path)
+        else:
+            with open(self.path, 'at') as file:
+                file.write(json.dumps(list(behavior)) + "\n")
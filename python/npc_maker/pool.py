@@ -0,0 +1,273 @@
+"""
+Pool of environment instances, for running many evaluations in parallel on this computer.
+
+The pool runs several copies of the environment program and schedules the
+evaluations across them. Each instance asks for new individuals as usual, and
+the scheduler decides when to fulfill each request:
+
+* At most "concurrency" individuals are alive at the same time, across all of
+  the instances. Requests beyond this limit wait in a queue until other
+  individuals die, and then they are fulfilled immediately.
+
+* The scheduler measures how long each instance takes to evaluate an
+  individual, and it gives the free slots to the instances which are expected
+  to finish them the soonest. Slow instances get fewer individuals and fast
+  instances get more.
+
+* Graphical instances which exit shortly after they are launched, before
+  finishing any evaluations, are assumed to have failed to open a display, for
+  example on a cluster node without one. They are relaunched in headless mode,
+  with a warning.
+
+Example experiment configuration:
+
+    [pool]
+    instances = 4
+    concurrency = 16
+"""
+
+from npc_maker.env import Environment, Specification, WireLog
+from npc_maker.evo import API
+import collections
+import os
+import sys
+import time
+
+__all__ = (
+    "EnvironmentPool",
+)
+
+class _Timer(API):
+    """ Wrapper for an evolution service, which measures how long each individual was alive. """
+    def __init__(self, instance, service):
+        self.instance = instance
+        self.service  = service
+
+    def birth(self, parents):
+        return self.service.birth(parents)
+
+    def death(self, individual):
+        self.instance._record_death(individual)
+        self.service.death(individual)
+
+class _Instance(Environment):
+    """ Instance of the environment, whose requests for new individuals go through the pool's scheduler. """
+    def __init__(self, pool, services, *args, **kwargs):
+        self._pool        = pool
+        self._birth_times = {} # Individual id -> birth time
+        self._launch_time = time.monotonic()
+        self.latency      = None
+        services = {name: _Timer(self, service) for name, service in services.items()}
+        super().__init__(services, *args, **kwargs)
+
+    def _request_birth(self, population, parents, request_time=None):
+        if request_time is None:
+            request_time = time.monotonic()
+        self._pool._requests.append((self, population, parents, request_time))
+
+    def _fulfill(self, population, parents, request_time):
+        try:
+            super()._request_birth(population, parents, request_time)
+        finally:
+            self._process.stdin.flush()
+
+    def _birth(self, individual):
+        super()._birth(individual)
+        self._birth_times[id(individual)] = time.monotonic()
+
+    def _record_death(self, individual):
+        birth_time = self._birth_times.pop(id(individual), None)
+        if birth_time is None:
+            return
+        elapsed = time.monotonic() - birth_time
+        if self.latency is None:
+            self.latency = elapsed
+        else:
+            self.latency += self._pool.smoothing * (elapsed - self.latency)
+
+class EnvironmentPool:
+    """
+    Run several instances of the environment on this computer.
+
+    This has the same interface as the Environment class.
+    """
+    def __init__(self, services, env_spec, instances, concurrency=None, mode='headless', settings={},
+                 stderr=sys.stderr, timeout=None, crash_dir=None, develop=None, wire_log=None,
+                 validate_telemetry=False, smoothing=0.2, headless_fallback=5.0):
+        """
+        Argument services, env_spec, mode, settings, stderr, timeout, crash_dir,
+                 develop, wire_log, and validate_telemetry are the same as for
+                 the Environment class.
+                 All of the instances share the wire log.
+
+        Argument instances is the number of copies of the environment program to run.
+
+        Argument concurrency is the maximum number of individuals who may be
+                 alive at the same time, across all of the instances.
+                 By default there is no limit.
+
+        Argument smoothing is the weight of each new measurement in the moving
+                 average of each instance's evaluation time, between 0 and 1.
+
+        Argument headless_fallback is the number of seconds after launching a
+                 graphical instance during which, if it exits before finishing
+                 any evaluations, it is relaunched in headless mode.
+                 If None then graphical instances are never relaunched.
+        """
+        self.env_spec = Specification(env_spec)
+        populations = self.env_spec["populations"]
+        if len(populations) == 1 and isinstance(services, API):
+            services = {populations[0]["name"]: services}
+        self.services    = dict(services)
+        self.concurrency = None if concurrency is None else int(concurrency)
+        self.smoothing   = float(smoothing)
+        self._requests   = collections.deque() # Tuples of (instance, population, parents, request_time)
+        assert int(instances) >= 1
+        assert self.concurrency is None or self.concurrency >= 1
+        assert 0.0 < self.smoothing <= 1.0
+        self.headless_fallback = None if headless_fallback is None else float(headless_fallback)
+        self._started    = False
+        if wire_log is not None and not isinstance(wire_log, WireLog):
+            wire_log = WireLog(wire_log)
+        self._instance_args = (env_spec, settings)
+        self._instance_kwargs = dict(stderr=stderr, timeout=timeout, crash_dir=crash_dir, develop=develop,
+                                     wire_log=wire_log, validate_telemetry=validate_telemetry)
+        self.instances = [self._launch(mode) for _ in range(int(instances))]
+
+    def _launch(self, mode):
+        env_spec, settings = self._instance_args
+        return _Instance(self, self.services, env_spec, mode, settings, **self._instance_kwargs)
+
+    def _graphical_failure(self, instance):
+        """ Did the instance exit because it could not open a display? """
+        return (self.headless_fallback is not None
+                and instance.get_mode() == "graphical"
+                and instance.latency is None
+                and not instance._has_quit
+                and instance._process.poll() is not None
+                and time.monotonic() - instance._launch_time < self.headless_fallback)
+
+    def _headless_fallback(self):
+        """ Relaunch the graphical instances which failed to start, in headless mode. """
+        for index, instance in enumerate(self.instances):
+            if not self._graphical_failure(instance):
+                continue
+            reason = f"exit code {instance._process.returncode}"
+            if os.name == "posix" and not (os.environ.get("DISPLAY") or os.environ.get("WAYLAND_DISPLAY")):
+                reason += ", no display is available"
+            print(f"npc_maker: warning: graphical environment instance {instance.instance_id} exited "
+                  f"immediately ({reason}), relaunching it in headless mode", file=sys.stderr)
+            replacement = self._launch("headless")
+            self.instances[index] = replacement
+            if self._started:
+                replacement.start()
+
+    def get_env_spec(self):
+        return self.env_spec
+
+    def get_outstanding(self):
+        """
+        Get all individuals who are currently alive in any of the instances.
+        Returns a dictionary indexed by individuals names.
+        """
+        outstanding = {}
+        for instance in self.instances:
+            outstanding.update(instance.get_outstanding())
+        return outstanding
+
+    def get_waiting(self):
+        """ Returns the number of requests for new individuals which are waiting for a free slot. """
+        return len(self._requests)
+
+    def get_latencies(self):
+        """
+        Returns the average evaluation time of each instance, in seconds,
+        or None for instances which have not yet finished an evaluation.
+        """
+        return [instance.latency for instance in self.instances]
+
+    def is_alive(self):
+        """ The pool keeps running as long as any of its instances are running. """
+        self._headless_fallback()
+        return any(instance.is_alive() for instance in self.instances)
+
+    def _expected_finish(self, request):
+        """ Sort key for the waiting requests, lower is sooner. """
+        instance = request[0]
+        known = [x.latency for x in self.instances if x.latency is not None]
+        latency = instance.latency
+        if latency is None:
+            latency = sum(known) / len(known) if known else 0.0
+        num_alive = len(instance.get_outstanding())
+        return (latency * (num_alive + 1), num_alive)
+
+    def _schedule(self):
+        """ Fulfill the waiting requests, for as long as there are free slots. """
+        self._requests = collections.deque(request for request in self._requests if request[0].is_alive())
+        num_alive = len(self.get_outstanding())
+        while self._requests and (self.concurrency is None or num_alive < self.concurrency):
+            # The min() function returns the earliest of equal requests.
+            request = min(self._requests, key=self._expected_finish)
+            self._requests.remove(request)
+            request[0]._fulfill(*request[1:])
+            num_alive += 1
+
+    def poll(self):
+        """
+        Check for messages from all of the instances, and schedule their requests for new individuals.
+
+        This function is non-blocking and should be called periodically.
+        """
+        exhausted = False
+        self._headless_fallback()
+        for instance in self.instances:
+            try:
+                instance.poll()
+            except StopIteration:
+                exhausted = True
+        try:
+            self._schedule()
+        except StopIteration:
+            exhausted = True
+        if exhausted:
+            raise StopIteration
+
+    def start(self):
+        self._started = True
+        for instance in self.instances:
+            try:
+                instance.start()
+            except BrokenPipeError:
+                # Graphical instances which failed to open a display are relaunched.
+                if self.headless_fallback is None or instance.get_mode() != "graphical":
+                    raise
+        self._headless_fallback()
+
+    def stop(self):
+        for instance in self.instances:
+            instance.stop()
+
+    def pause(self):
+        for instance in self.instances:
+            instance.pause()
+
+    def resume(self):
+        for instance in self.instances:
+            instance.resume()
+
+    def send(self, message):
+        for instance in self.instances:
+            instance.send(message)
+
+    def quit(self):
+        self._requests.clear()
+        for instance in self.instances:
+            if instance.is_alive():
+                instance.quit()
+
+    def wait(self, timeout=None):
+        """ Wait for all of the instances to exit, after calling "quit()". """
+        deadline = None if timeout is None else time.monotonic() + timeout
+        for instance in self.instances:
+            remaining = None if deadline is None else max(0, deadline - time.monotonic())
+            instance.wait(remaining)
@@ -0,0 +1,97 @@
+"""
+Replacement policies, for choosing which members of a population are evicted.
+
+When an individual dies it is offered to its population. The replacement policy
+receives the members of the population, with the new individual as the last
+member, and returns the indices of the members to evict:
+
+    evict(members, individual, size) -> list of indices
+
+Each member has the attributes: score, ascension, path, species, evaluations,
+and uuid. The scores are the population's custom scores. Evicting the new
+individual rejects it from the population. Argument size is the population
+size. The policy is consulted after every death, and after loading a saved
+population which is larger than its size, in which case the individual is None.
+
+The "continuous" and "maximizing" population types use the policies "Oldest"
+and "Worst" respectively. Evolution services accept any object with an
+"evict()" method as their population type.
+
+Example of a custom policy, which replaces the worst member of the new
+individual's species:
+
+    class WorstInSpecies(ReplacementPolicy):
+        def evict(self, members, individual, size):
+            if len(members) <= size:
+                return []
+            species = [index for index, member in enumerate(members)
+                       if member.species == members[-1].species]
+            return [min(species, key=lambda index: members[index].score)]
+
+Experiment configurations select a custom policy with a "module:attribute"
+string, for example:
+
+    [populations.xor]
+    population_type = "policies:WorstInSpecies"
+"""
+
+import npc_maker.selection
+
+__all__ = (
+    "ReplacementPolicy",
+    "Oldest",
+    "Worst",
+)
+
+class ReplacementPolicy:
+    """ Abstract class for replacement policies. """
+    def evict(self, members, individual, size):
+        """
+        Abstract method, returns a list of the indices of the members to evict.
+        Argument individual is the new individual, or None if there is no new individual.
+        """
+        raise TypeError("abstract method called")
+
+    def __repr__(self):
+        parameters = ", ".join(f"{key}={value!r}" for key, value in vars(self).items())
+        return f"{type(self).__name__}({parameters})"
+
+class Oldest(ReplacementPolicy):
+    """ Evict the oldest members. """
+    def evict(self, members, individual, size):
+        excess = len(members) - size
+        if excess <= 0:
+            return []
+        return sorted(range(len(members)), key=lambda index: members[index].ascension)[:excess]
+
+class Worst(ReplacementPolicy):
+    """
+    Evict the lowest scoring members. New individuals lose ties with the
+    existing members, and otherwise the oldest members lose ties.
+
+    Multi-objective scores evict the members in the worst non-dominated front
+    with the smallest crowding distances, see "npc_maker.selection".
+    """
+    def evict(self, members, individual, size):
+        excess = len(members) - size
+        if excess <= 0:
+            return []
+        scores = [member.score for member in members]
+        if any(isinstance(score, (list, tuple)) for score in scores):
+            return self._evict_pareto(members, scores, excess)
+        scores = npc_maker.selection._clean_scores(scores)
+        newest = None if individual is None else len(members) - 1
+        order  = sorted(range(len(members)),
+                        key=lambda index: (scores[index], index != newest, members[index].ascension))
+        return order[:excess]
+
+    @staticmethod
+    def _evict_pareto(members, scores, excess):
+        evict = []
+        for front in reversed(npc_maker.selection.pareto_fronts(scores)):
+            distance = npc_maker.selection.crowding_distance(scores, front)
+            for index in sorted(front, key=lambda index: (distance[index], -members[index].ascension)):
+                if len(evict) >= excess:
+                    return evict
+                evict.append(index)
+        return evict
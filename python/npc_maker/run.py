@@ -0,0 +1,969 @@
+"""
+Experiment runner, for running evolution experiments from a configuration file.
+
+An experiment configuration is a TOML or JSON file which describes the
+environment, the controller and evolutionary algorithm for each population,
+and the budget for the experiment. The runner evolves the populations in a
+headless environment until the budget is exhausted. Experiments can be stopped
+and resumed at any time, the runner saves its progress in the output directory.
+
+Example configuration file:
+
+    environment = "examples/xor/xor.env"
+    output = "results/xor"
+    settings = {}
+    seed = 42
+    validate_telemetry = true
+
+    [budget]
+    deaths = 10000
+    generations = 50
+    seconds = 3600
+
+    [pool]
+    instances = 4
+    concurrency = 16
+
+    [wire_log]
+    path = "results/xor/wire_log.ndjson"
+    max_bytes = 100000000
+    backups = 5
+
+    [pin]
+    strict = false
+
+    [populations.xor]
+    controller = "examples/nn/nn.py"
+    seed_file = "seed_genome.json"
+    initial_genome = "constant"
+    mutate = "my_genetics:mutate"
+    crossover = "my_genetics:crossover"
+    population_type = "generation"
+    population_size = 100
+    elites = 2
+    leaderboard = 10
+
+Relative paths are relative to the directory containing the configuration file.
+Functions are given as "module:attribute" strings and are imported from the
+configuration file's directory or from the python path.
+
+The configuration is validated when it is loaded, so that mistakes are found
+before the experiment starts instead of hours into it, see "load_config()".
+
+Usage: python -m npc_maker run [--resume] [--graphical] [--farm host:port] experiment.toml
+       python -m npc_maker check experiment.toml
+       python -m npc_maker dry-run experiment.toml
+"""
+
+from npc_maker.env import Environment, Recording, Specification, WireLog, _cast_env_settings
+from npc_maker.evo import API, Evolution, Recorder, _checksum, _load_checked
+from npc_maker.novelty import Novelty
+from npc_maker.sharing import FitnessSharing
+from npc_maker.speciation import Speciation
+from pathlib import Path
+import hashlib
+import importlib
+import npc_maker.initial
+import npc_maker.selection
+import json
+import math
+import os
+import queue
+import random
+import shutil
+import signal
+import sys
+import threading
+import time
+import tomllib
+
+__all__ = (
+    "load_config",
+    "Experiment",
+)
+
+_BUDGET_FIELDS = ("deaths", "generations", "seconds")
+
+_BATCH_FIELDS = (
+    "scheduler",
+    "jobs",
+    "directory",
+    "address",
+    "hostname",
+    "template",
+    "options",
+    "python",
+    "poll_interval",
+)
+
+_POOL_FIELDS = ("instances", "concurrency", "headless_fallback")
+
+_WIRE_LOG_FIELDS = ("path", "max_bytes", "backups")
+
+_PIN_FIELDS = ("strict", "environment", "controllers")
+
+_NOVELTY_FIELDS = ("descriptor", "k", "threshold", "probability", "archive_size", "recent")
+
+_SPECIATION_FIELDS = ("distance", "threshold", "stagnation", "survivors", "sharing")
+
+_SHARING_FIELDS = ("distance", "radius", "alpha", "on")
+
+_POPULATION_FIELDS = (
+    "controller",
+    "seed",
+    "seed_file",
+    "initial_genome",
+    "mutate",
+    "crossover",
+    "allow_mating",
+    "population_type",
+    "population_size",
+    "elites",
+    "select",
+    "score",
+    "novelty",
+    "speciation",
+    "sharing",
+    "leaderboard",
+    "leaderboard_comparator",
+    "checkpoints",
+    "background_writes",
+    "reevaluate",
+    "max_evaluations",
+    "develop",
+    "develop_workers",
+    "pretty",
+)
+
+def load_config(path):
+    """
+    Read and validate an experiment configuration file.
+
+    The file format is determined by the file extension: ".toml" or ".json".
+
+    The configuration is checked thoroughly before returning: the types and
+    values of all of the fields, the existence of every file, the environment
+    specification, and the imported functions. Errors are raised as ValueError
+    with the path to the offending field, for example:
+    "populations.xor.population_size must be a positive integer, found -3"
+
+    Returns the configuration as a dictionary, with all of the file paths made absolute.
+    """
+    path = Path(path).expanduser().resolve()
+    try:
+        if path.suffix.lower() == ".toml":
+            with open(path, 'rb') as file:
+                config = tomllib.load(file)
+        elif path.suffix.lower() == ".json":
+            with open(path, 'rt') as file:
+                config = json.load(file)
+        else:
+            raise ValueError(f"unrecognized experiment configuration file type \"{path.suffix}\"")
+        return _clean_config(config, path.parent)
+    except (ValueError, OSError) as error:
+        raise ValueError(f"{error}, in file: {path}") from None
+
+_MISSING = object()
+
+def _field(table, key, where, types, expected, check=None, default=_MISSING):
+    """
+    Get one field of a table and check its type and value.
+
+    Argument where is the path to the table, for error messages.
+
+    Argument types is a type or a tuple of types which the value must be.
+             Booleans are not accepted as numbers unless bool is in types.
+
+    Argument expected describes the valid values, for error messages.
+
+    Argument check is an optional predicate which the value must satisfy.
+
+    Argument default is returned if the field is missing, otherwise the field is required.
+    """
+    name = f"{where}.{key}" if where else key
+    if key not in table:
+        if default is _MISSING:
+            raise ValueError(f"missing field \"{name}\"")
+        return default
+    value = table[key]
+    types = types if isinstance(types, tuple) else (types,)
+    valid = isinstance(value, types) and not (isinstance(value, bool) and bool not in types)
+    if not valid or (check is not None and not check(value)):
+        raise ValueError(f"{name} must be {expected}, found {json.dumps(value, default=str)}")
+    return value
+
+def _check_fields(table, fields, where):
+    if not isinstance(table, dict):
+        raise ValueError(f"{where or 'experiment'} must be a table, found {json.dumps(table, default=str)}")
+    for key in table:
+        if key not in fields:
+            name = f"{where}.{key}" if where else key
+            raise ValueError(f"unrecognized field \"{name}\"")
+
+def _clean_config(config, directory):
+    def resolve(path):
+        path = Path(path).expanduser()
+        if not path.is_absolute():
+            path = directory.joinpath(path)
+        return path
+
+    def existing_file(table, key, where, default=_MISSING):
+        value = _field(table, key, where, str, "a file path", default=default)
+        if value is default:
+            return default
+        path = resolve(value)
+        if not path.is_file():
+            raise ValueError(f"{where + '.' if where else ''}{key} file not found \"{path}\"")
+        return path
+
+    number      = (int, float)
+    positive    = lambda x: x > 0
+    nonnegative = lambda x: x >= 0
+
+    _check_fields(config, ("environment", "output", "mode", "settings", "timeout", "seed", "budget", "batch",
+                           "pool", "wire_log", "pin", "validate_telemetry", "populations"), "")
+    clean = {"directory": directory}
+    clean["environment"] = existing_file(config, "environment", "")
+    try:
+        env_spec = Specification(clean["environment"])
+    except (ValueError, OSError, AssertionError, KeyError) as error:
+        raise ValueError(f"environment \"{clean['environment']}\" is invalid: {error}") from None
+    clean["output"]  = resolve(_field(config, "output", "", str, "a directory path", default="results"))
+    clean["mode"]    = _field(config, "mode", "", str, "\"graphical\" or \"headless\"",
+                              lambda x: x.strip().lower() in ("graphical", "headless"), "headless").strip().lower()
+    clean["timeout"] = _field(config, "timeout", "", number, "a positive number of seconds", positive, None)
+    clean["seed"]    = _field(config, "seed", "", int, "an integer", default=None)
+    clean["validate_telemetry"] = _field(config, "validate_telemetry", "", bool, "a boolean", default=False)
+
+    # Check the settings against the environment specification.
+    settings = _field(config, "settings", "", dict, "a table", default={})
+    settings_spec = {item["name"]: item for item in env_spec.get("settings", [])}
+    clean["settings"] = {}
+    for key, value in settings.items():
+        if key not in settings_spec:
+            raise ValueError(f"unrecognized environment setting \"settings.{key}\"")
+        _field(settings, key, "settings", (str, int, float, bool), "a string, number, or boolean")
+        value = str(value).lower() if isinstance(value, bool) else str(value)
+        try:
+            _cast_env_settings(env_spec, {key: value})
+        except (ValueError, AssertionError, json.JSONDecodeError) as error:
+            raise ValueError(f"settings.{key} is invalid for the environment: {error}") from None
+        clean["settings"][key] = value
+
+    budget = _field(config, "budget", "", dict, "a table", default={})
+    _check_fields(budget, _BUDGET_FIELDS, "budget")
+    clean["budget"] = {key: _field(budget, key, "budget", number, "a non-negative number", nonnegative)
+                       for key in budget}
+
+    if "batch" in config:
+        batch = _field(config, "batch", "", dict, "a table")
+        _check_fields(batch, _BATCH_FIELDS, "batch")
+        clean_batch = dict(batch)
+        scheduler = _field(batch, "scheduler", "batch", str, "a scheduler name")
+        from npc_maker.batch import get_scheduler
+        try:
+            get_scheduler(scheduler)
+        except ValueError as error:
+            raise ValueError(f"batch.scheduler is invalid: {error}") from None
+        _field(batch, "jobs", "batch", int, "a positive integer", positive)
+        for key in ("address", "hostname", "python"):
+            _field(batch, key, "batch", str, "a string", default=None)
+        _field(batch, "options", "batch", list, "a list of strings",
+               lambda x: all(isinstance(item, str) for item in x), default=None)
+        _field(batch, "poll_interval", "batch", number, "a positive number of seconds", positive, None)
+        directory_field = _field(batch, "directory", "batch", str, "a directory path", default=None)
+        clean_batch["directory"] = None if directory_field is None else resolve(directory_field)
+        if "template" in batch:
+            clean_batch["template"] = existing_file(batch, "template", "batch")
+        clean["batch"] = clean_batch
+
+    if "pool" in config:
+        if "batch" in config:
+            raise ValueError("the fields \"batch\" and \"pool\" can not be used together")
+        pool = _field(config, "pool", "", dict, "a table")
+        _check_fields(pool, _POOL_FIELDS, "pool")
+        clean["pool"] = {
+            "instances":   _field(pool, "instances", "pool", int, "a positive integer", positive),
+            "concurrency": _field(pool, "concurrency", "pool", int, "a positive integer", positive, None),
+            "headless_fallback": _field(pool, "headless_fallback", "pool", number,
+                                        "a non-negative number of seconds", nonnegative, 5.0),
+        }
+
+    if "wire_log" in config:
+        if "batch" in config:
+            raise ValueError("the fields \"batch\" and \"wire_log\" can not be used together")
+        wire_log = _field(config, "wire_log", "", dict, "a table")
+        _check_fields(wire_log, _WIRE_LOG_FIELDS, "wire_log")
+        clean["wire_log"] = {
+            "path":      resolve(_field(wire_log, "path", "wire_log", str, "a file path",
+                                        default=str(clean["output"].joinpath("wire_log.ndjson")))),
+            "max_bytes": _field(wire_log, "max_bytes", "wire_log", int, "a positive integer", positive, 100_000_000),
+            "backups":   _field(wire_log, "backups", "wire_log", int, "a non-negative integer", nonnegative, 5),
+        }
+
+    # Check the populations against the environment specification.
+    populations = _field(config, "populations", "", dict, "a table")
+    env_populations = [pop["name"] for pop in env_spec["populations"]]
+    for name in populations:
+        if name not in env_populations:
+            raise ValueError(f"unrecognized population \"populations.{name}\", "
+                             f"the environment has populations: {', '.join(env_populations)}")
+    for name in env_populations:
+        if name not in populations:
+            raise ValueError(f"missing field \"populations.{name}\", every population in the environment "
+                             "needs a configuration")
+    clean["populations"] = {}
+    for name, pop in populations.items():
+        where = f"populations.{name}"
+        _check_fields(pop, _POPULATION_FIELDS, where)
+        clean_pop = dict(pop)
+        # Find the controller program.
+        controller = _field(pop, "controller", where, (str, list), "a command line",
+                            lambda x: len(x) > 0 and (isinstance(x, str) or all(isinstance(arg, str) for arg in x)))
+        if isinstance(controller, str):
+            controller = [controller]
+        program = resolve(controller[0])
+        if not program.is_file():
+            if os.sep in controller[0] or shutil.which(controller[0]) is None:
+                raise ValueError(f"{where}.controller program not found \"{program}\"")
+            program = controller[0]
+        clean_pop["controller"] = [str(program)] + controller[1:]
+        # Check the seed genome.
+        if "seed" in pop and "seed_file" in pop:
+            raise ValueError(f"{where} requires exactly one of the fields \"seed\" or \"seed_file\"")
+        if "seed" not in pop and "seed_file" not in pop and "initial_genome" not in pop:
+            raise ValueError(f"{where} requires exactly one of the fields \"seed\" or \"seed_file\"")
+        seed = pop.get("seed")
+        if "seed_file" in pop:
+            clean_pop["seed_file"] = existing_file(pop, "seed_file", where)
+            try:
+                with open(clean_pop["seed_file"], 'rt') as file:
+                    seed = json.load(file)
+            except json.JSONDecodeError as error:
+                raise ValueError(f"{where}.seed_file is not valid JSON: {error}") from None
+        initial_genome = _field(pop, "initial_genome", where, (str, dict), "an initial genome generator",
+                                default=None)
+        if isinstance(initial_genome, dict) or (initial_genome is not None and ":" not in initial_genome):
+            try:
+                npc_maker.initial.from_config(initial_genome, seed)
+            except ValueError as error:
+                raise ValueError(f"{where}.initial_genome is invalid: {error}") from None
+        # Check the evolution parameters.
+        _field(pop, "allow_mating", where, bool, "true or false", default=True)
+        population_type = _field(pop, "population_type", where, str,
+               "\"generation\", \"continuous\", \"maximizing\", \"frozen\", or a \"module:attribute\" string",
+               lambda x: x in ("generation", "continuous", "maximizing", "frozen") or ":" in x, "generation")
+        size = _field(pop, "population_size", where, int, "a positive integer", positive, 1000)
+        _field(pop, "elites", where, int, f"a non-negative integer no greater than the population_size ({size})",
+               lambda x: 0 <= x <= size, 0)
+        _field(pop, "leaderboard", where, int, "a non-negative integer", nonnegative, None)
+        _field(pop, "checkpoints", where, int, "a non-negative integer", nonnegative, 0)
+        _field(pop, "background_writes", where, int, "a non-negative integer", nonnegative, 0)
+        _field(pop, "reevaluate", where, (int, float), "a number in the range [0, 1]", lambda x: 0 <= x <= 1, 0.0)
+        _field(pop, "max_evaluations", where, int, "a positive integer", positive, 10)
+        _field(pop, "pretty", where, bool, "true or false", default=False)
+        # Check that all of the functions can be imported.
+        functions = []
+        if ":" in population_type:
+            functions.append((f"{where}.population_type", population_type))
+        for key in ("mutate", "crossover", "leaderboard_comparator"):
+            value = _field(pop, key, where, str, "a \"module:attribute\" string", default=None)
+            if value is not None:
+                functions.append((f"{where}.{key}", value))
+        novelty = _field(pop, "novelty", where, dict, "a table", default=None)
+        if novelty is not None:
+            novelty_where = f"{where}.novelty"
+            _check_fields(novelty, _NOVELTY_FIELDS, novelty_where)
+            descriptor = _field(novelty, "descriptor", novelty_where, (str, list),
+                                "a list of info keys or a \"module:attribute\" string",
+                                lambda x: (isinstance(x, str) and ":" in x)
+                                          or (isinstance(x, list) and len(x) > 0 and all(isinstance(item, str) for item in x)))
+            if isinstance(descriptor, str):
+                functions.append((f"{novelty_where}.descriptor", descriptor))
+            _field(novelty, "k", novelty_where, int, "a positive integer", positive, 15)
+            _field(novelty, "threshold", novelty_where, (int, float), "a non-negative number", nonnegative, None)
+            _field(novelty, "probability", novelty_where, (int, float), "a number in the range [0, 1]",
+                   lambda x: 0 <= x <= 1, 0.02)
+            _field(novelty, "archive_size", novelty_where, int, "a positive integer", positive, 10000)
+            _field(novelty, "recent", novelty_where, int, "a non-negative integer", nonnegative, 100)
+        speciation = _field(pop, "speciation", where, dict, "a table", default=None)
+        if speciation is not None:
+            speciation_where = f"{where}.speciation"
+            _check_fields(speciation, _SPECIATION_FIELDS, speciation_where)
+            distance = _field(speciation, "distance", speciation_where, str, "a \"module:attribute\" string")
+            functions.append((f"{speciation_where}.distance", distance))
+            _field(speciation, "threshold", speciation_where, (int, float), "a non-negative number", nonnegative, 3.0)
+            _field(speciation, "stagnation", speciation_where, int, "a positive integer", positive, 15)
+            _field(speciation, "survivors", speciation_where, int, "a non-negative integer", nonnegative, 2)
+            _field(speciation, "sharing", speciation_where, bool, "true or false", default=True)
+        sharing = _field(pop, "sharing", where, dict, "a table", default=None)
+        if sharing is not None:
+            sharing_where = f"{where}.sharing"
+            _check_fields(sharing, _SHARING_FIELDS, sharing_where)
+            distance = _field(sharing, "distance", sharing_where, str, "a \"module:attribute\" string")
+            functions.append((f"{sharing_where}.distance", distance))
+            _field(sharing, "radius", sharing_where, (int, float), "a positive number", positive, 1.0)
+            _field(sharing, "alpha", sharing_where, (int, float), "a positive number", positive, 1.0)
+            _field(sharing, "on", sharing_where, str, "\"genome\" or \"info\"", lambda x: x in ("genome", "info"),
+                   "genome")
+        score = _field(pop, "score", where, (str, list), "an info key, a \"module:attribute\" string, or a list of them",
+                       lambda x: isinstance(x, str) or (len(x) > 0 and all(isinstance(item, str) for item in x)),
+                       "score" if novelty is None else "novelty")
+        for value in ([score] if isinstance(score, str) else score):
+            if ":" in value:
+                functions.append((f"{where}.score", value))
+        if isinstance(initial_genome, str) and ":" in initial_genome:
+            functions.append((f"{where}.initial_genome", initial_genome))
+        select = _field(pop, "select", where, (str, dict), "a selection algorithm", default=None)
+        if isinstance(select, str) and ":" in select:
+            functions.append((f"{where}.select", select))
+        elif select is not None:
+            try:
+                npc_maker.selection.from_config(select)
+            except ValueError as error:
+                raise ValueError(f"{where}.select is invalid: {error}") from None
+        develop = _field(pop, "develop", where, (str, list), "a \"module:attribute\" string or a list of them",
+                         lambda x: isinstance(x, str) or all(isinstance(item, str) for item in x), [])
+        if isinstance(develop, str):
+            develop = [develop]
+        clean_pop["develop"] = develop
+        functions.extend((f"{where}.develop", value) for value in develop)
+        _field(pop, "develop_workers", where, int, "a non-negative integer", nonnegative, 0)
+        for field, value in functions:
+            try:
+                _import(value, directory)
+            except Exception as error:
+                raise ValueError(f"{field} failed to import \"{value}\": {error}") from None
+        clean["populations"][name] = clean_pop
+
+    pin = _field(config, "pin", "", (bool, dict), "true or a table", default=False)
+    if pin is not False:
+        pin = {} if pin is True else pin
+        _check_fields(pin, _PIN_FIELDS, "pin")
+        sha256 = lambda x: len(x) == 64 and all(char in "0123456789abcdef" for char in x.lower())
+        controllers = _field(pin, "controllers", "pin", dict, "a table", default={})
+        for name in controllers:
+            if name not in clean["populations"]:
+                raise ValueError(f"unrecognized population \"pin.controllers.{name}\"")
+            _field(controllers, name, "pin.controllers", str, "a SHA-256 hash", sha256)
+        clean["pin"] = {
+            "strict":      _field(pin, "strict", "pin", bool, "true or false", default=False),
+            "environment": _field(pin, "environment", "pin", str, "a SHA-256 hash", sha256, None),
+            "controllers": {name: value.lower() for name, value in controllers.items()},
+        }
+        if clean["pin"]["environment"] is not None:
+            clean["pin"]["environment"] = clean["pin"]["environment"].lower()
+    return clean
+
+def _import(name, directory):
+    """ Import an object given as a "module:attribute" string. """
+    if not isinstance(name, str):
+        return name
+    module, _, attribute = name.partition(":")
+    if not attribute:
+        raise ValueError(f"expected \"module:attribute\", found \"{name}\"")
+    if str(directory) not in sys.path:
+        sys.path.insert(0, str(directory))
+    obj = importlib.import_module(module)
+    for part in attribute.split("."):
+        obj = getattr(obj, part)
+    return obj
+
+def _make_initial_genome(pop, directory):
+    """
+    Returns the population's seed genome,
+    or its initial genome generator, see "npc_maker.initial".
+    """
+    if "seed_file" in pop:
+        with open(pop["seed_file"], 'rt') as file:
+            seed = json.load(file)
+    else:
+        seed = pop.get("seed")
+    initial_genome = pop.get("initial_genome")
+    if initial_genome is None:
+        return seed
+    if isinstance(initial_genome, str) and ":" in initial_genome:
+        initial_genome = _import(initial_genome, directory)
+        if isinstance(initial_genome, type):
+            initial_genome = initial_genome()
+        return initial_genome
+    return npc_maker.initial.from_config(initial_genome, seed)
+
+def _make_score(pop, directory):
+    """ Returns the population's custom scoring function, or a list of them for multi-objective optimization. """
+    def make(score):
+        return _import(score, directory) if ":" in score else score
+    score = pop.get("score", "score" if pop.get("novelty") is None else "novelty")
+    if isinstance(score, list):
+        return [make(objective) for objective in score]
+    return make(score)
+
+def _make_sharing(pop, directory):
+    """ Returns the population's fitness sharing, see "npc_maker.sharing", or None. """
+    sharing = pop.get("sharing")
+    if sharing is None:
+        return None
+    return FitnessSharing(_import(sharing["distance"], directory),
+                          radius = sharing.get("radius", 1.0),
+                          alpha  = sharing.get("alpha", 1.0),
+                          on     = sharing.get("on", "genome"))
+
+def _make_developments(config):
+    """ Returns a dict of the populations' developments, see "npc_maker.develop". """
+    from npc_maker.develop import Parallel, Pipeline
+    developments = {}
+    for name, pop in config["populations"].items():
+        if not pop.get("develop"):
+            continue
+        stages = [_import(value, config["directory"]) for value in pop["develop"]]
+        development = Pipeline(*stages)
+        if pop.get("develop_workers", 0) > 0:
+            development = Parallel(development, pop["develop_workers"])
+        developments[name] = development
+    return developments
+
+def _program_files(command):
+    """ Returns the program's file and any of its arguments which are files. """
+    program = Path(command[0])
+    if not program.is_file():
+        program = Path(shutil.which(command[0]) or command[0])
+    return [program] + [Path(arg) for arg in command[1:] if Path(arg).is_file()]
+
+class _Pins:
+    """
+    SHA-256 hashes of the environment and controller programs, which are
+    checked for changes throughout the experiment.
+    """
+    def __init__(self, config, recorded):
+        """
+        Argument recorded is the hashes which were found by a previous run of the experiment.
+        """
+        self.strict   = config["pin"]["strict"]
+        env_spec      = Specification(config["environment"])
+        self.programs = {"environment": [env_spec["path"]]}
+        for name, pop in config["populations"].items():
+            self.programs[f"controllers.{name}"] = pop["controller"]
+        # Hashes in the configuration take priority over the recorded hashes.
+        self.expected = dict(recorded)
+        if config["pin"]["environment"] is not None:
+            self.expected["environment"] = config["pin"]["environment"]
+        for name, value in config["pin"]["controllers"].items():
+            self.expected[f"controllers.{name}"] = value
+        self.current  = {}
+        self._stats   = {}
+
+    def _hash(self, key):
+        """ Hash the program, unless its files are unchanged since they were last hashed. """
+        files = _program_files(self.programs[key])
+        stats = [(path, path.stat().st_mtime_ns, path.stat().st_size) for path in files if path.is_file()]
+        if self._stats.get(key) != stats:
+            hasher = hashlib.sha256()
+            for path, _, _ in stats:
+                hasher.update(path.read_bytes())
+            self._stats[key]  = stats
+            self.current[key] = hasher.hexdigest()
+        return self.current[key]
+
+    def check(self, stderr=sys.stderr):
+        """
+        Compare the programs against their pinned hashes.
+
+        Mismatches raise a RuntimeError in strict mode,
+        otherwise they print a warning and the new hashes are pinned.
+        """
+        for key in self.programs:
+            digest   = self._hash(key)
+            expected = self.expected.setdefault(key, digest)
+            if digest == expected:
+                continue
+            program = " ".join(str(arg) for arg in self.programs[key])
+            message = (f"the {key.replace('.', ' ')} program has changed since it was pinned, "
+                       f"expected hash {expected}, found {digest}: {program}")
+            if self.strict:
+                raise RuntimeError(message)
+            print(f"npc_maker: warning: {message}", file=stderr)
+            self.expected[key] = digest
+
+    def provenance(self, population):
+        """ Returns the hashes of the programs which evaluate the given population. """
+        return {
+            "environment": self.current.get("environment"),
+            "controller":  self.current.get(f"controllers.{population}"),
+        }
+
+class _Budget(API):
+    """
+    Wrapper which counts the deaths of an evolution service,
+    and stops giving out new individuals once the budget is exhausted.
+    """
+    def __init__(self, experiment, service, population):
+        self.experiment = experiment
+        self.service    = service
+        self.population = population
+
+    def birth(self, parents):
+        if self.experiment.is_finished():
+            raise StopIteration
+        child = self.service.birth(parents)
+        self.experiment.births += 1
+        pins = self.experiment._pins
+        if pins is not None:
+            pins.check(self.experiment._stderr)
+            provenance = pins.provenance(self.population)
+            if isinstance(child, dict):
+                child["provenance"] = provenance
+            else:
+                child.provenance = provenance
+        return child
+
+    def death(self, individual):
+        self.service.death(individual)
+        self.experiment.deaths += 1
+
+class Experiment:
+    """
+    Run an evolution experiment, as described by an experiment configuration.
+    """
+    def __init__(self, config, resume=False, mode=None, output=None):
+        """
+        Argument config is either the path to an experiment configuration
+                 file, or an experiment configuration from "load_config()".
+
+        Argument resume allows continuing a previous run of this experiment.
+                 If False and the output directory already contains a run of
+                 this experiment then this raises a FileExistsError.
+
+        Argument mode optionally overrides the configuration's display mode.
+
+        Argument output optionally overrides the configuration's output directory.
+        """
+        if not isinstance(config, dict):
+            config = load_config(config)
+        self.config     = config
+        self.mode       = config["mode"] if mode is None else mode
+        self.output     = Path(config["output"] if output is None else output)
+        self.births     = 0
+        self.deaths     = 0
+        self.elapsed    = 0.0
+        self.paused     = False
+        self.stopped    = False
+        self._requests  = queue.SimpleQueue()
+        self._stderr    = sys.stderr
+        state_path      = self.get_state_path()
+        state           = {}
+        if state_path.exists():
+            if not resume:
+                raise FileExistsError(f"experiment already exists, use resume to continue it: {self.output}")
+            state = _load_checked(state_path)
+            self.births  = int(state.get("births", state["deaths"]))
+            self.deaths  = int(state["deaths"])
+            self.elapsed = float(state["elapsed"])
+        self._pins      = _Pins(config, state.get("pins", {})) if "pin" in config else None
+        self.output.mkdir(parents=True, exist_ok=True)
+        self.evolution  = {}
+        self.recorders  = {}
+        self.services   = {}
+        for name, pop in config["populations"].items():
+            self.evolution[name] = self._make_evolution(name, pop)
+            self.evolution[name].set_settings(config["settings"])
+            service = self.evolution[name]
+            if pop.get("novelty") is not None:
+                service = self._make_novelty(service, name, pop["novelty"])
+            if pop.get("leaderboard"):
+                self.output.joinpath(name).mkdir(exist_ok=True)
+                service = Recorder(service, self.output.joinpath(name),
+                                   leaderboard=pop["leaderboard"], score=_make_score(pop, config["directory"]),
+                                   sharing=_make_sharing(pop, config["directory"]),
+                                   comparator=_import(pop.get("leaderboard_comparator"), config["directory"]),
+                                   pretty=pop.get("pretty", False))
+                self.recorders[name] = service
+            self.services[name] = _Budget(self, service, name)
+        self._start_time = None
+        self.environment = None
+
+    def _make_evolution(self, name, pop):
+        directory = self.config["directory"]
+        seed = _make_initial_genome(pop, directory)
+        def optional_function(key):
+            value = pop.get(key)
+            return None if value is None else _import(value, directory)
+        select = pop.get("select")
+        if isinstance(select, str) and ":" in select:
+            select = _import(select, directory)
+            if isinstance(select, type):
+                select = select()
+        score = _make_score(pop, directory)
+        population_type = pop.get("population_type", "generation")
+        if ":" in population_type:
+            population_type = _import(population_type, directory)
+        speciation = pop.get("speciation")
+        if speciation is not None:
+            speciation = Speciation(_import(speciation["distance"], directory),
+                                    threshold  = speciation.get("threshold", 3.0),
+                                    stagnation = speciation.get("stagnation", 15),
+                                    survivors  = speciation.get("survivors", 2),
+                                    sharing    = speciation.get("sharing", True),
+                                    path       = self.output.joinpath(name))
+        return Evolution(pop["controller"], seed,
+                         mutate          = optional_function("mutate"),
+                         crossover       = optional_function("crossover"),
+                         allow_mating    = pop.get("allow_mating", True),
+                         path            = self.output.joinpath(name),
+                         population_type = population_type,
+                         population_size = pop.get("population_size", 1000),
+                         elites          = pop.get("elites", 0),
+                         select          = select,
+                         score           = score,
+                         speciation      = speciation,
+                         sharing         = _make_sharing(pop, directory),
+                         checkpoints     = pop.get("checkpoints", 0),
+                         background_writes = pop.get("background_writes", 0),
+                         reevaluate      = pop.get("reevaluate", 0.0),
+                         max_evaluations = pop.get("max_evaluations", 10),
+                         pretty          = pop.get("pretty", False))
+
+    def _make_novelty(self, service, name, novelty):
+        descriptor = novelty["descriptor"]
+        if isinstance(descriptor, str):
+            descriptor = _import(descriptor, self.config["directory"])
+        path = self.output.joinpath(name)
+        path.mkdir(exist_ok=True)
+        return Novelty(service, descriptor,
+                       k            = novelty.get("k", 15),
+                       threshold    = novelty.get("threshold"),
+                       probability  = novelty.get("probability", 0.02),
+                       archive_size = novelty.get("archive_size", 10000),
+                       recent       = novelty.get("recent", 100),
+                       path         = path)
+
+    def _handle_signals(self):
+        """ Returns the previous signal handlers. """
+        if threading.current_thread() is not threading.main_thread():
+            return {}
+        def handler(signum, frame):
+            if self.stopped:
+                raise KeyboardInterrupt
+            self.stopped = True
+        return {signum: signal.signal(signum, handler) for signum in (signal.SIGINT, signal.SIGTERM)}
+
+    def get_state_path(self):
+        """ Returns the path of the file which records the progress of this experiment. """
+        return self.output.joinpath("experiment.json")
+
+    def get_elapsed(self):
+        """ Returns the total run time of this experiment, in seconds, including all previous runs. """
+        if self._start_time is None:
+            return self.elapsed
+        return self.elapsed + time.monotonic() - self._start_time
+
+    def is_finished(self):
+        """ Check if the experiment has exhausted any of its budgets, or if it was stopped. """
+        if self.stopped:
+            return True
+        budget = self.config["budget"]
+        if "deaths" in budget and self.deaths >= budget["deaths"]:
+            return True
+        if "seconds" in budget and self.get_elapsed() >= budget["seconds"]:
+            return True
+        if "generations" in budget:
+            return all(evolution.get_generation() >= budget["generations"]
+                       for evolution in self.evolution.values())
+        return False
+
+    def request(self, action):
+        """
+        Control the experiment while it is running.
+        This method is thread safe, the request is carried out by the thread which is running the experiment.
+
+        Argument action is one of: "pause", "resume", "checkpoint", or "stop".
+        """
+        if action not in ("pause", "resume", "checkpoint", "stop"):
+            raise ValueError(f"unrecognized action \"{action}\"")
+        self._requests.put(action)
+
+    def _service_requests(self, env):
+        while not self._requests.empty():
+            action = self._requests.get()
+            if action == "pause" and not self.paused:
+                env.pause()
+                self.paused = True
+            elif action == "resume" and self.paused:
+                env.resume()
+                self.paused = False
+            elif action == "checkpoint":
+                self.save()
+            elif action == "stop":
+                self.stopped = True
+
+    def summary(self):
+        """
+        Returns a JSON-encodable summary of the experiment's progress and of each population.
+        """
+        elapsed = self.get_elapsed()
+        populations = {}
+        for name, evolution in self.evolution.items():
+            scores = []
+            for entry in evolution._population.data:
+                score = entry.score
+                if isinstance(score, tuple):
+                    score = score[0] if score else None
+                try:
+                    score = float(score)
+                except (TypeError, ValueError):
+                    continue
+                if not math.isnan(score):
+                    scores.append(score)
+            populations[name] = {
+                "generation": evolution.get_generation(),
+                "size": len(evolution._population.data),
+                "best_score": max(scores, default=None),
+                "mean_score": sum(scores) / len(scores) if scores else None,
+            }
+        environment = self.environment
+        return {
+            "births": self.births,
+            "deaths": self.deaths,
+            "elapsed": elapsed,
+            "evaluations_per_second": (self.deaths / elapsed) if elapsed > 0 else 0.0,
+            "outstanding": len(environment.get_outstanding()) if environment is not None else 0,
+            "paused": self.paused,
+            "finished": self.is_finished(),
+            "populations": populations,
+        }
+
+    def leaderboard(self, population):
+        """
+        Returns the leaderboard of the given population as a list of pairs of (path, score),
+        sorted with the best individual first.
+        """
+        if population not in self.recorders:
+            raise KeyError(population)
+        return self.recorders[population].get_leaderboard()
+
+    def save(self):
+        """ Record the progress of this experiment. """
+        for evolution in self.evolution.values():
+            evolution.flush()
+        state = {
+            "births":  self.births,
+            "deaths":  self.deaths,
+            "elapsed": self.get_elapsed(),
+            "generations": {name: evolution.get_generation() for name, evolution in self.evolution.items()},
+        }
+        if self._pins is not None:
+            state["pins"] = self._pins.expected
+        state["checksum"] = _checksum(state)
+        path = self.get_state_path()
+        temp = path.with_suffix(".tmp")
+        with open(temp, 'wt') as file:
+            json.dump(state, file, indent=4)
+        os.replace(temp, path)
+
+    def run(self, stderr=sys.stderr, drain_timeout=60, record=None, replay=None, farm=None):
+        """
+        Run the experiment until its budget is exhausted.
+
+        Once the budget is exhausted, the individuals who are still alive are
+        given up to "drain_timeout" seconds to finish their evaluations.
+
+        Argument record is an optional file path for recording the run,
+                 including every message to and from the environment and the
+                 seed of the random number generator.
+
+        Argument replay is an optional file path of a recording to re-drive
+                 the experiment from, instead of running the environment.
+                 Replays should start from the same state as the recording,
+                 so use a new output directory when replaying a new experiment.
+
+        Argument farm is an optional network address to listen on for remote
+                 workers, instead of running the environment on this computer.
+                 See "npc_maker.farm" for how to run the workers.
+
+        When called from the main thread, this handles the signals SIGINT and
+        SIGTERM by stopping the experiment: no more individuals are born, the
+        living individuals finish their evaluations, the progress is saved, and
+        then the environment is shut down. A second signal skips waiting for
+        the living individuals.
+        """
+        if (farm is not None or "batch" in self.config) and record is not None:
+            raise ValueError("farms do not support recording")
+        if "pool" in self.config and farm is None and replay is None and record is not None:
+            raise ValueError("environment pools do not support recording")
+        if farm is not None and replay is not None:
+            raise ValueError("farms do not support replaying")
+        if farm is not None and "wire_log" in self.config:
+            raise ValueError("farms do not support wire logs")
+        if replay is not None:
+            seed = Recording.read(replay)[0].get("seed")
+        elif self.config["seed"] is not None:
+            seed = self.config["seed"]
+        else:
+            seed = random.randrange(2 ** 32)
+        random.seed(seed)
+        if record is not None:
+            record = Recording(record, {"seed": seed, "deaths": self.deaths})
+        self._stderr = stderr
+        if self._pins is not None and replay is None:
+            self._pins.check(stderr)
+        self._start_time = time.monotonic()
+        develop = _make_developments(self.config)
+        wire_log = None
+        if "wire_log" in self.config and replay is None:
+            self.config["wire_log"]["path"].parent.mkdir(parents=True, exist_ok=True)
+            wire_log = WireLog(**self.config["wire_log"])
+        if "batch" in self.config and farm is None and replay is None:
+            from npc_maker.batch import BatchFarm
+            batch = dict(self.config["batch"])
+            batch["directory"] = batch["directory"] or self.output.joinpath("jobs")
+            env = BatchFarm(self.services, self.config["environment"], mode=self.mode,
+                            settings=self.config["settings"], stderr=stderr, timeout=self.config["timeout"],
+                            crash_dir=self.output.joinpath("crashes"), develop=develop, **batch)
+        elif "pool" in self.config and farm is None and replay is None:
+            from npc_maker.pool import EnvironmentPool
+            env = EnvironmentPool(self.services, self.config["environment"], mode=self.mode,
+                                  settings=self.config["settings"], stderr=stderr, timeout=self.config["timeout"],
+                                  crash_dir=self.output.joinpath("crashes"), develop=develop, wire_log=wire_log,
+                                  validate_telemetry=self.config["validate_telemetry"], **self.config["pool"])
+        elif farm is not None:
+            from npc_maker.farm import Farm
+            env = Farm(self.services, self.config["environment"], farm, self.mode, self.config["settings"],
+                       stderr=stderr, timeout=self.config["timeout"], crash_dir=self.output.joinpath("crashes"),
+                       develop=develop)
+        else:
+            env = Environment(self.services, self.config["environment"], self.mode, self.config["settings"],
+                              stderr=stderr, timeout=self.config["timeout"], record=record, replay=replay,
+                              crash_dir=self.output.joinpath("crashes"), develop=develop, wire_log=wire_log,
+                              validate_telemetry=self.config["validate_telemetry"])
+        self.environment = env
+        env.start()
+        drain_start = None
+        last_save = time.monotonic()
+        previous_handlers = self._handle_signals()
+        try:
+            while env.is_alive():
+                self._service_requests(env)
+                try:
+                    env.poll()
+                except StopIteration:
+                    pass
+                if self.is_finished():
+                    if drain_start is None:
+                        drain_start = time.monotonic()
+                    if not env.get_outstanding() or time.monotonic() - drain_start > drain_timeout:
+                        break
+                if time.monotonic() - last_save > 10:
+                    self.save()
+                    last_save = time.monotonic()
+                time.sleep(0.01)
+        finally:
+            env.quit()
+            self.save()
+            env.wait(drain_timeout)
+            if record is not None:
+                record.close()
+            if wire_log is not None:
+                wire_log.close()
+            for signum, handler in previous_handlers.items():
+                signal.signal(signum, handler)
+        if not self.is_finished() and replay is None:
+            raise RuntimeError(f"environment exited unexpectedly with code {env._process.returncode}")
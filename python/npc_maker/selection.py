@@ -0,0 +1,374 @@
+"""
+Mate selection algorithms, for choosing which individuals reproduce.
+
+Each algorithm has two methods:
+
+    select(amount, scores) -> list of indices
+    pairs(amount, scores) -> list of pairs of indices
+
+Scores are numbers where higher is better. Missing or invalid scores, such as
+None or NaN, are treated as the lowest possible score.
+
+These are the same strategies as the `mate_selection` package, which is not
+required. Evolution services accept any object with these methods.
+
+Random numbers are drawn from the attribute "rng", which is the "random"
+module by default. Evolution services with a random seed replace it with their
+own random number generator, so that their mate selections are reproducible.
+
+Experiment configurations select the algorithm by name, or by a table with the
+algorithm's name and its parameters, for example:
+
+    [populations.xor]
+    select = "truncation"
+
+    [populations.xor.select]
+    algorithm = "tournament"
+    size = 4
+
+Algorithm names and parameters:
+
+    random                                  Every individual is equally likely to be selected.
+    truncation (fraction=0.5)               Only the best fraction of the population is selected.
+    percentile (percentile=0.5)             Only individuals above the given percentile are selected.
+    roulette                                Probability is proportional to the score. Negative scores are never selected.
+    softmax (temperature=1.0)               Probability is proportional to exp(score / temperature).
+    tournament (size=2, probability=1.0,    The best of a random group wins with the given probability,
+                sexual=1.0)                 otherwise the next best, and so on. Each pair of mates is
+                                            two separate winners with the "sexual" probability, otherwise
+                                            it is one winner paired with itself for asexual reproduction.
+    ranked_linear (pressure=1.5)            Probability decreases linearly with rank. The best individual
+                                            is selected "pressure" times as often as the average, between 1 and 2.
+    ranked_exponential (median=0.25)        Probability decreases exponentially with rank, so that the
+                                            best "median" fraction of the population gets half of the selections.
+    nsga2 (size=2)                          Multi-objective tournament, see below.
+
+Multi-objective optimization:
+
+The NSGA-II algorithm optimizes several objectives at once, such as the task
+score, energy use, and genome size. Each score is a list of objective values,
+where higher is better. The population is sorted into fronts by non-dominated
+sorting: the first front contains the individuals which no other individual
+beats in every objective, the second front contains those which only the first
+front beats, and so on. Within each front, individuals in sparse regions of the
+objective space have a higher crowding distance than those in dense regions.
+Tournaments are won by the lower front, and ties are won by the higher crowding
+distance. The functions "pareto_fronts()" and "crowding_distance()" are also
+used by the evolution service to choose which individuals survive.
+"""
+
+import math
+import random
+
+__all__ = (
+    "Selection",
+    "Random",
+    "Truncation",
+    "Percentile",
+    "Roulette",
+    "Softmax",
+    "Tournament",
+    "RankedLinear",
+    "RankedExponential",
+    "NSGA2",
+    "pareto_fronts",
+    "crowding_distance",
+    "ALGORITHMS",
+    "from_config",
+)
+
+def _clean_scores(scores):
+    """ Convert the scores to floats, with invalid scores replaced by negative infinity. """
+    clean = []
+    for score in scores:
+        try:
+            score = float(score)
+        except (TypeError, ValueError):
+            score = -math.inf
+        clean.append(-math.inf if math.isnan(score) else score)
+    return clean
+
+def _ranking(scores):
+    """ Returns the indices of the scores, sorted from best to worst. """
+    return sorted(range(len(scores)), key=lambda index: scores[index], reverse=True)
+
+class Selection:
+    """
+    Abstract class for mate selection algorithms.
+    Subclasses must implement either "select()" or "weights()".
+    """
+    rng = random
+
+    def weights(self, scores):
+        """
+        Abstract method, returns the relative probability of selecting each individual.
+        Argument scores is a list of floats, where invalid scores are negative infinity.
+        """
+        raise TypeError("abstract method called")
+
+    def select(self, amount, scores):
+        """ Randomly select individuals, with replacement. Returns a list of indices. """
+        if not scores:
+            return []
+        weights = self.weights(_clean_scores(scores))
+        if sum(weights) <= 0:
+            weights = None
+        return self.rng.choices(range(len(scores)), weights=weights, k=amount)
+
+    def pairs(self, amount, scores):
+        """ Randomly select pairs of individuals for mating. Returns a list of pairs of indices. """
+        indices = self.select(2 * amount, scores)
+        return list(zip(indices[0::2], indices[1::2]))
+
+    def __repr__(self):
+        parameters = ", ".join(f"{key}={value!r}" for key, value in vars(self).items() if key != "rng")
+        return f"{type(self).__name__}({parameters})"
+
+class Random(Selection):
+    """ Every individual is equally likely to be selected. """
+    def weights(self, scores):
+        return [1.0] * len(scores)
+
+class Truncation(Selection):
+    """ Only the best fraction of the population is selected, with equal probability. """
+    def __init__(self, fraction=0.5):
+        self.fraction = float(fraction)
+        if not 0.0 < self.fraction <= 1.0:
+            raise ValueError("truncation fraction must be in the range (0, 1]")
+
+    def weights(self, scores):
+        keep = max(1, round(self.fraction * len(scores)))
+        weights = [0.0] * len(scores)
+        for index in _ranking(scores)[:keep]:
+            weights[index] = 1.0
+        return weights
+
+class Percentile(Truncation):
+    """ Only individuals scoring above the given percentile are selected, with equal probability. """
+    def __init__(self, percentile=0.5):
+        if not 0.0 <= float(percentile) < 1.0:
+            raise ValueError("percentile must be in the range [0, 1)")
+        super().__init__(1.0 - float(percentile))
+
+class Roulette(Selection):
+    """ The probability of selection is proportional to the score. Negative scores are never selected. """
+    def weights(self, scores):
+        return [max(0.0, score) for score in scores]
+
+class Softmax(Selection):
+    """ The probability of selection is proportional to exp(score / temperature). """
+    def __init__(self, temperature=1.0):
+        self.temperature = float(temperature)
+        if not self.temperature > 0.0:
+            raise ValueError("softmax temperature must be positive")
+
+    def weights(self, scores):
+        best = max(scores)
+        if best == -math.inf:
+            return [1.0] * len(scores)
+        return [math.exp((score - best) / self.temperature) for score in scores]
+
+class Tournament(Selection):
+    """
+    Select a random group of individuals, and the best of them wins with the
+    given probability, otherwise the second best wins with the given probability, and so on.
+
+    Argument sexual is the fraction of pairs of mates which are two separate
+             tournament winners. The remaining pairs are one winner paired
+             with itself, which the evolution service reproduces asexually.
+    """
+    def __init__(self, size=2, probability=1.0, sexual=1.0):
+        self.size = int(size)
+        self.probability = float(probability)
+        self.sexual = float(sexual)
+        if self.size < 1:
+            raise ValueError("tournament size must be at least 1")
+        if not 0.0 < self.probability <= 1.0:
+            raise ValueError("tournament probability must be in the range (0, 1]")
+        if not 0.0 <= self.sexual <= 1.0:
+            raise ValueError("tournament sexual ratio must be in the range [0, 1]")
+
+    def pairs(self, amount, scores):
+        winners = iter(self.select(2 * amount, scores))
+        pairs = []
+        for first, second in zip(winners, winners):
+            if self.rng.random() >= self.sexual:
+                second = first
+            pairs.append((first, second))
+        return pairs
+
+    def select(self, amount, scores):
+        if not scores:
+            return []
+        scores = _clean_scores(scores)
+        winners = []
+        for _ in range(amount):
+            group = sorted(self.rng.choices(range(len(scores)), k=self.size), key=lambda index: scores[index],
+                           reverse=True)
+            winner = group[-1]
+            for index in group:
+                if self.rng.random() < self.probability:
+                    winner = index
+                    break
+            winners.append(winner)
+        return winners
+
+class RankedLinear(Selection):
+    """
+    The probability of selection decreases linearly with rank.
+    The best individual is selected "pressure" times as often as the average individual,
+    and the worst individual is selected "2 - pressure" times as often.
+    """
+    def __init__(self, pressure=1.5):
+        self.pressure = float(pressure)
+        if not 1.0 <= self.pressure <= 2.0:
+            raise ValueError("ranked linear pressure must be in the range [1, 2]")
+
+    def weights(self, scores):
+        weights = [0.0] * len(scores)
+        last = max(1, len(scores) - 1)
+        for rank, index in enumerate(_ranking(scores)):
+            weights[index] = self.pressure - 2.0 * (self.pressure - 1.0) * rank / last
+        return weights
+
+class RankedExponential(Selection):
+    """
+    The probability of selection decreases exponentially with rank, so that
+    the best "median" fraction of the population gets half of the selections.
+    """
+    def __init__(self, median=0.25):
+        self.median = float(median)
+        if not 0.0 < self.median <= 1.0:
+            raise ValueError("ranked exponential median must be in the range (0, 1]")
+
+    def weights(self, scores):
+        half_life = max(1e-9, self.median * len(scores))
+        weights = [0.0] * len(scores)
+        for rank, index in enumerate(_ranking(scores)):
+            weights[index] = 0.5 ** (rank / half_life)
+        return weights
+
+def _clean_objectives(scores):
+    """ Convert multi-objective scores to tuples of floats, with invalid values replaced by negative infinity. """
+    clean = []
+    for score in scores:
+        if isinstance(score, (list, tuple)):
+            clean.append(tuple(_clean_scores(score)))
+        else:
+            clean.append(tuple(_clean_scores([score])))
+    width = max((len(score) for score in clean), default=0)
+    return [score + (-math.inf,) * (width - len(score)) for score in clean]
+
+def _dominates(a, b):
+    """ Is score "a" at least as good as "b" in every objective, and better in at least one? """
+    return all(x >= y for x, y in zip(a, b)) and any(x > y for x, y in zip(a, b))
+
+def pareto_fronts(scores):
+    """
+    Sort multi-objective scores into non-dominated fronts.
+
+    Argument scores is a list of lists of objective values, where higher is better.
+
+    Returns a list of fronts, best first, where each front is a list of indices into scores.
+    """
+    scores = _clean_objectives(scores)
+    dominated_by = [0] * len(scores) # Number of scores which dominate each score.
+    dominates    = [[] for _ in scores]
+    for a in range(len(scores)):
+        for b in range(a + 1, len(scores)):
+            if _dominates(scores[a], scores[b]):
+                dominates[a].append(b)
+                dominated_by[b] += 1
+            elif _dominates(scores[b], scores[a]):
+                dominates[b].append(a)
+                dominated_by[a] += 1
+    fronts = []
+    front  = [index for index, count in enumerate(dominated_by) if count == 0]
+    while front:
+        fronts.append(front)
+        next_front = []
+        for a in front:
+            for b in dominates[a]:
+                dominated_by[b] -= 1
+                if dominated_by[b] == 0:
+                    next_front.append(b)
+        front = sorted(next_front)
+    return fronts
+
+def crowding_distance(scores, front):
+    """
+    Measure how isolated each member of a front is in the objective space.
+    The extreme members of each objective have infinite distance.
+
+    Returns a dictionary of distances, indexed by the indices in the front.
+    """
+    scores   = _clean_objectives(scores)
+    distance = {index: 0.0 for index in front}
+    if not front:
+        return distance
+    for objective in range(len(scores[front[0]])):
+        ordered = sorted(front, key=lambda index: scores[index][objective])
+        low     = scores[ordered[0]][objective]
+        high    = scores[ordered[-1]][objective]
+        distance[ordered[0]] = distance[ordered[-1]] = math.inf
+        if not math.isfinite(high - low) or high == low:
+            continue
+        for previous, index, following in zip(ordered, ordered[1:], ordered[2:]):
+            distance[index] += (scores[following][objective] - scores[previous][objective]) / (high - low)
+    return distance
+
+def _nsga2_ranking(scores):
+    """ Returns pairs of (front, crowding distance) for each score, where lower fronts and larger distances are better. """
+    ranking = [None] * len(scores)
+    for rank, front in enumerate(pareto_fronts(scores)):
+        for index, distance in crowding_distance(scores, front).items():
+            ranking[index] = (rank, distance)
+    return ranking
+
+class NSGA2(Selection):
+    """
+    Multi-objective tournament selection from the NSGA-II algorithm.
+    Each score is a list of objective values, where higher is better.
+    The winner of each tournament is the individual in the best non-dominated
+    front, and ties are broken by the larger crowding distance.
+    """
+    def __init__(self, size=2):
+        self.size = int(size)
+        if self.size < 1:
+            raise ValueError("nsga2 tournament size must be at least 1")
+
+    def select(self, amount, scores):
+        if not scores:
+            return []
+        ranking = _nsga2_ranking(scores)
+        key = lambda index: (ranking[index][0], -ranking[index][1])
+        return [min(self.rng.choices(range(len(scores)), k=self.size), key=key) for _ in range(amount)]
+
+ALGORITHMS = {
+    "random":               Random,
+    "truncation":           Truncation,
+    "percentile":           Percentile,
+    "roulette":             Roulette,
+    "softmax":              Softmax,
+    "tournament":           Tournament,
+    "ranked_linear":        RankedLinear,
+    "ranked_exponential":   RankedExponential,
+    "nsga2":                NSGA2,
+}
+
+def from_config(config):
+    """
+    Make a selection algorithm from its configuration, which is either the name
+    of an algorithm, or a dictionary with the algorithm's name under the key
+    "algorithm" and its parameters under the other keys.
+    """
+    if isinstance(config, str):
+        config = {"algorithm": config}
+    parameters = dict(config)
+    name = str(parameters.pop("algorithm", "")).strip().lower()
+    if name not in ALGORITHMS:
+        raise ValueError(f"unrecognized selection algorithm \"{name}\", expected one of: {', '.join(ALGORITHMS)}")
+    try:
+        return ALGORITHMS[name](**parameters)
+    except TypeError:
+        raise ValueError(f"invalid parameters for the selection algorithm \"{name}\": {parameters}") from None
@@ -0,0 +1,105 @@
+"""
+Fitness sharing, for penalizing crowds of similar individuals.
+
+Fitness sharing divides each individual's score by its niche count, which
+measures how many similar individuals there are:
+
+    shared_score = score / sum(sh(distance(individual, other)) for other in population)
+
+    sh(d) = 1 - (d / radius) ** alpha   if d < radius
+    sh(d) = 0                           otherwise
+
+Every individual counts itself, so the niche count is at least one and the
+scores of unique individuals are not changed. Similarity is measured by a user
+supplied distance function, of either the individuals' genomes or their info,
+which contains the telemetry reported by the environment.
+
+The shared scores are used for mate selection and for ranking the leaderboard,
+see the argument "sharing" of "npc_maker.evo.Evolution" and
+"npc_maker.evo.Recorder". The individuals' saved scores are not changed.
+Sharing compares every pair of individuals, which takes quadratic time.
+
+Example experiment configuration:
+
+    [populations.xor.sharing]
+    distance = "genetics:distance"
+    radius = 3.0
+    alpha = 1.0
+    on = "genome"
+"""
+
+import copy
+
+__all__ = (
+    "FitnessSharing",
+)
+
+class FitnessSharing:
+    """ Fitness sharing parameters. """
+    def __init__(self, distance, radius=1.0, alpha=1.0, on="genome"):
+        """
+        Argument distance is a function which measures the difference between
+                 two individuals: f(a, b) -> float
+                 Its arguments are either genomes or info dictionaries.
+
+        Argument radius is the distance at which individuals stop sharing.
+
+        Argument alpha is the shape of the sharing function.
+                 One is linear, less than one is convex, and greater than one is concave.
+
+        Argument on is either "genome" or "info", and selects which data of the
+                 individuals is passed to the distance function.
+        """
+        self.distance = distance
+        self.radius   = float(radius)
+        self.alpha    = float(alpha)
+        self.on       = str(on)
+        assert callable(self.distance)
+        if not self.radius > 0.0:
+            raise ValueError("sharing radius must be positive")
+        if not self.alpha > 0.0:
+            raise ValueError("sharing alpha must be positive")
+        if self.on not in ("genome", "info"):
+            raise ValueError("sharing must be on \"genome\" or \"info\"")
+
+    def __repr__(self):
+        return f"FitnessSharing({self.distance!r}, radius={self.radius}, alpha={self.alpha}, on={self.on!r})"
+
+    def feature(self, individual):
+        """ Returns the data of the individual which is passed to the distance function. """
+        if self.on == "genome":
+            return copy.deepcopy(individual.get_genome())
+        else:
+            return dict(individual.get_info())
+
+    def niche_counts(self, features):
+        """ Returns the niche count of each feature. """
+        counts = [1.0] * len(features)
+        for a in range(len(features)):
+            for b in range(a + 1, len(features)):
+                distance = self.distance(features[a], features[b])
+                if distance < self.radius:
+                    share = 1.0 - (distance / self.radius) ** self.alpha
+                    counts[a] += share
+                    counts[b] += share
+        return counts
+
+    def share(self, features, scores):
+        """
+        Returns the shared scores.
+
+        Argument features is a list of the data returned by "feature()".
+
+        Argument scores is a list of the raw scores. Scores which are not
+                 numbers are returned unchanged, and multi-objective scores are
+                 shared in every objective.
+        """
+        return [_divide(score, count) for score, count in zip(scores, self.niche_counts(features))]
+
+def _divide(score, count):
+    if isinstance(score, tuple):
+        return tuple(_divide(objective, count) for objective in score)
+    try:
+        return float(score) / count
+    except (TypeError, ValueError):
+        return score
@@ -0,0 +1,214 @@
+"""
+Speciation, for protecting new innovations while they are optimized (as in NEAT).
+
+Speciation divides the population into species of similar genomes. Genomes
+are compared by a user supplied compatibility distance function:
+
+    f(genome, genome) -> float
+
+When an individual dies it joins the first species whose representative
+genome is closer than the threshold distance, or else it founds a new species.
+At the start of each generation every species' representative is replaced by
+the genome of its most recent member, and species which had no members in the
+previous generation go extinct.
+
+Individuals compete for mates mostly within their own species:
+
+* Fitness sharing divides each individual's score by the number of members of
+  its species in the mating pool, so that large species do not take over the
+  population. The individual's saved score is not changed.
+
+* Species which have not improved their best score for several generations
+  are stagnant, and their members are not selected as parents. The species
+  with the best scores are never stagnant.
+
+Example experiment configuration:
+
+    [populations.xor.speciation]
+    distance = "genetics:distance"
+    threshold = 3.0
+    stagnation = 15
+    survivors = 2
+"""
+
+from npc_maker.sharing import _divide
+from pathlib import Path
+import json
+import math
+import os
+
+__all__ = (
+    "Species",
+    "Speciation",
+)
+
+class Species:
+    """
+    Record of one species.
+
+    Attribute number is the unique identifier of this species.
+    Attribute representative is the genome which new members are compared to.
+    Attribute best is the highest score which any member has achieved.
+    Attribute improved is the generation when the best score was achieved.
+    Attribute members is the number of members in the current generation.
+    """
+    def __init__(self, number, representative, generation):
+        self.number         = int(number)
+        self.representative = representative
+        self.latest         = representative
+        self.best           = None
+        self.improved       = int(generation)
+        self.members        = 0
+
+    def __repr__(self):
+        return f"Species({self.number}, best={self.best}, improved={self.improved}, members={self.members})"
+
+class Speciation:
+    """
+    Divides a population into species, for the "speciation" argument of "npc_maker.evo.Evolution".
+    """
+    def __init__(self, distance, threshold=3.0, stagnation=15, survivors=2, sharing=True, path=None):
+        """
+        Argument distance is the compatibility distance function: f(genome, genome) -> float
+
+        Argument threshold is the maximum distance between members of the same species.
+
+        Argument stagnation is the number of generations without improvement
+                 after which a species stops reproducing.
+                 If None then species never stagnate.
+
+        Argument survivors is the number of species with the best scores
+                 which are never stagnant.
+
+        Argument sharing enables fitness sharing within each species.
+
+        Argument path is an optional directory for saving the species,
+                 in the file "species.json".
+                 Any existing species in the directory are loaded.
+        """
+        self.distance   = distance
+        self.threshold  = float(threshold)
+        self.stagnation = None if stagnation is None else int(stagnation)
+        self.survivors  = int(survivors)
+        self.sharing    = bool(sharing)
+        self.species    = {} # Species number -> Species
+        self.generation = 0
+        self.counter    = 0
+        assert callable(self.distance)
+        assert self.threshold >= 0.0
+        assert self.stagnation is None or self.stagnation >= 1
+        assert self.survivors >= 0
+        self.path = None
+        if path is not None:
+            self.path = Path(path).joinpath("species.json")
+            if self.path.exists():
+                self._load()
+
+    def get_species(self):
+        """ Returns a list of the living species. """
+        return list(self.species.values())
+
+    def assign(self, individual):
+        """ Returns the number of the species which the individual belongs to. """
+        genome = individual.get_genome()
+        for species in self.species.values():
+            if self.distance(genome, species.representative) < self.threshold:
+                return species.number
+        species = Species(self.counter, genome, self.generation)
+        self.species[species.number] = species
+        self.counter += 1
+        return species.number
+
+    def death(self, individual, score, generation):
+        """
+        Record a dead individual, after its species has been assigned.
+
+        Argument score is the individual's score, or a tuple of objectives.
+
+        Argument generation is the generation which the individual belongs to.
+        """
+        if generation > self.generation:
+            self._advance(generation)
+        species = self.species.get(individual.get_species())
+        if species is None:
+            return
+        species.members += 1
+        species.latest   = individual.get_genome()
+        score = _first_objective(score)
+        if score is not None and (species.best is None or score > species.best):
+            species.best     = score
+            species.improved = self.generation
+        self._save()
+
+    def _advance(self, generation):
+        """ Start a new generation. """
+        for number, species in list(self.species.items()):
+            if species.members == 0:
+                del self.species[number]
+            else:
+                species.representative = species.latest
+                species.members = 0
+        self.generation = int(generation)
+
+    def is_stagnant(self, number):
+        """ Has the species gone too long without improving? """
+        species = self.species.get(number)
+        if species is None or self.stagnation is None:
+            return False
+        if self.generation - species.improved < self.stagnation:
+            return False
+        ranked = sorted(self.species.values(), key=lambda x: -math.inf if x.best is None else x.best, reverse=True)
+        return species not in ranked[:self.survivors]
+
+    def share(self, species, scores):
+        """
+        Apply fitness sharing and stagnation to a mating pool.
+
+        Argument species is a list of the species number of each individual.
+
+        Argument scores is a list of the score of each individual.
+
+        Returns a pair of (indexes, scores) for the individuals which may reproduce.
+        """
+        indexes = [index for index, number in enumerate(species) if not self.is_stagnant(number)]
+        if not indexes:
+            indexes = list(range(len(species)))
+        if not self.sharing:
+            return indexes, [scores[index] for index in indexes]
+        counts = {}
+        for index in indexes:
+            counts[species[index]] = counts.get(species[index], 0) + 1
+        shared = [_divide(scores[index], counts[species[index]]) for index in indexes]
+        return indexes, shared
+
+    def _save(self):
+        if self.path is None:
+            return
+        data = {
+            "generation": self.generation,
+            "counter":    self.counter,
+            "species":    [vars(species) for species in self.species.values()],
+        }
+        temp = self.path.with_suffix(".tmp")
+        with open(temp, 'wt') as file:
+            json.dump(data, file)
+        os.replace(temp, self.path)
+
+    def _load(self):
+        with open(self.path, 'rt') as file:
+            data = json.load(file)
+        self.generation = data["generation"]
+        self.counter    = data["counter"]
+        for fields in data["species"]:
+            species = Species(fields["number"], fields["representative"], fields["improved"])
+            vars(species).update(fields)
+            self.species[species.number] = species
+
+def _first_objective(score):
+    if isinstance(score, (list, tuple)):
+        score = score[0] if score else None
+    try:
+        score = float(score)
+    except (TypeError, ValueError):
+        return None
+    return None if math.isnan(score) else score
@@ -0,0 +1,162 @@
+from npc_maker.batch import PBS, BatchFarm, Scheduler, Slurm, get_scheduler
+from npc_maker.evo import API, Individual
+from pathlib import Path
+import io
+import json
+import npc_maker
+import os
+import subprocess
+import sys
+import time
+
+_ENVIRONMENT = """
+import json, sys
+print(json.dumps({"New": "pop"}), flush=True)
+for line in sys.stdin:
+    message = json.loads(line)
+    if message == "Quit":
+        break
+    if isinstance(message, dict) and "Birth" in message:
+        name = message["Birth"]["name"]
+        print(json.dumps({"Score": str(message["Birth"]["genome"]), "name": name}))
+        print(json.dumps({"Death": name}))
+        print(json.dumps({"New": "pop"}), flush=True)
+"""
+
+class _Counter(API):
+    def __init__(self):
+        self.births = 0
+        self.deaths = []
+    def birth(self, parents):
+        self.births += 1
+        return Individual(genome=self.births, controller=["ctrl"])
+    def death(self, individual):
+        self.deaths.append(individual.get_score())
+
+class _Local(Scheduler):
+    """ Runs the submit scripts on this computer, as if this were a cluster. """
+    directive = "# local"
+    template = """#!/bin/bash
+$options
+cd $directory
+$command > $log 2>&1
+"""
+    def __init__(self):
+        self.processes = {}
+        self.cancelled = []
+    def submit(self, script_path):
+        job_id = str(len(self.processes) + 1)
+        python_path = str(Path(npc_maker.__file__).parent.parent)
+        self.processes[job_id] = subprocess.Popen(["bash", str(script_path)],
+                                                  env=dict(os.environ, PYTHONPATH=python_path))
+        return job_id
+    def status(self, job_ids):
+        return {job_id: ("running" if self.processes[job_id].poll() is None else "finished")
+                for job_id in job_ids}
+    def cancel(self, job_ids):
+        self.cancelled.extend(job_ids)
+        for job_id in job_ids:
+            self.processes[job_id].terminate()
+
+def _write_environment(tmp_path):
+    program = tmp_path.joinpath("environment.py")
+    program.write_text(f"#!{sys.executable}\n" + _ENVIRONMENT)
+    os.chmod(program, 0o755)
+    env_spec = {"name": "test", "path": "environment.py", "populations": [{"name": "pop"}]}
+    tmp_path.joinpath("test.env").write_text(json.dumps(env_spec))
+    return tmp_path.joinpath("test.env")
+
+def _farm(tmp_path, scheduler, service, **kwargs):
+    return BatchFarm({"pop": service}, _write_environment(tmp_path), scheduler, jobs=2,
+                     directory=tmp_path.joinpath("jobs"), address="127.0.0.1:0", hostname="127.0.0.1",
+                     options=["--mem=1G"], poll_interval=0, stderr=io.StringIO(), timeout=30, **kwargs)
+
+def test_run_jobs(tmp_path):
+    scheduler = _Local()
+    service   = _Counter()
+    farm      = _farm(tmp_path, scheduler, service)
+    farm.start()
+    deadline = time.monotonic() + 60
+    while len(service.deaths) < 10:
+        assert time.monotonic() < deadline, farm.stderr.getvalue()
+        farm.poll()
+        time.sleep(0.01)
+    jobs = farm.get_jobs()
+    assert len(jobs) == 2
+    assert len(farm.get_workers()) == 2
+    script = tmp_path.joinpath("jobs", jobs["1"] + ".sh").read_text()
+    assert "# local --mem=1G" in script
+    assert f" worker 127.0.0.1:{farm.get_address()[1]} " in script
+    assert "--once --name npc_maker-test-1" in script
+    farm.quit()
+    farm.wait(30)
+    assert farm.get_jobs() == {}
+    assert sorted(scheduler.cancelled) == ["1", "2"]
+    assert all(process.wait(30) is not None for process in scheduler.processes.values())
+
+def test_failed_jobs(tmp_path):
+    scheduler = _Local()
+    template  = tmp_path.joinpath("broken.sh")
+    template.write_text("echo \"$name is broken\" > $log\nexit 3\n")
+    farm = _farm(tmp_path, scheduler, _Counter(), template=template, max_failures=3)
+    farm.start()
+    deadline = time.monotonic() + 30
+    try:
+        while True:
+            assert time.monotonic() < deadline
+            farm.poll()
+            time.sleep(0.01)
+    except RuntimeError as error:
+        assert "3 jobs in a row failed to join the farm" in str(error)
+    stderr = farm.stderr.getvalue()
+    assert "job 1 (npc_maker-test-1) finished without joining the farm" in stderr
+    assert "npc_maker-test-1 is broken" in stderr
+    farm.quit()
+    farm.wait(10)
+
+def _fake_command(tmp_path, name, script):
+    bin_dir = tmp_path.joinpath("bin")
+    bin_dir.mkdir(exist_ok=True)
+    program = bin_dir.joinpath(name)
+    program.write_text("#!/bin/sh\n" + script)
+    os.chmod(program, 0o755)
+    return str(bin_dir)
+
+def _with_path(bin_dir, function):
+    path = os.environ["PATH"]
+    os.environ["PATH"] = bin_dir + os.pathsep + path
+    try:
+        return function()
+    finally:
+        os.environ["PATH"] = path
+
+def test_slurm(tmp_path):
+    _fake_command(tmp_path, "sbatch", "echo \"$*\" > \"$(dirname \"$0\")/args\"; echo '123;cluster'\n")
+    bin_dir = _fake_command(tmp_path, "squeue", "echo ' 123 RUNNING'; echo '124 PENDING'; echo '125 COMPLETING'\n")
+    slurm = get_scheduler(" SLURM ")
+    assert isinstance(slurm, Slurm)
+    assert _with_path(bin_dir, lambda: slurm.submit(tmp_path.joinpath("job.sh"))) == "123"
+    assert tmp_path.joinpath("bin", "args").read_text().split() == ["--parsable", str(tmp_path.joinpath("job.sh"))]
+    states = _with_path(bin_dir, lambda: slurm.status(["123", "124", "125", "126"]))
+    assert states == {"123": "running", "124": "pending", "125": "running", "126": "finished"}
+    assert slurm.status([]) == {}
+    assert slurm.format_options(["--time=1:00:00", "--mem=4G"]) == "#SBATCH --time=1:00:00\n#SBATCH --mem=4G"
+
+def test_pbs(tmp_path):
+    _fake_command(tmp_path, "qsub", "echo '77.server'\n")
+    bin_dir = _fake_command(tmp_path, "qstat", """case "$2" in
+    1) echo '    job_state = R' ;;
+    2) echo '    job_state = Q' ;;
+    *) echo "qstat: Unknown Job Id $2" >&2; exit 153 ;;
+esac
+""")
+    pbs = get_scheduler("pbs")
+    assert isinstance(pbs, PBS)
+    assert _with_path(bin_dir, lambda: pbs.submit("job.sh")) == "77.server"
+    assert _with_path(bin_dir, lambda: pbs.status(["1", "2", "3"])) == {"1": "running", "2": "pending", "3": "finished"}
+    try:
+        get_scheduler("condor")
+    except ValueError as error:
+        assert "condor" in str(error)
+    else:
+        assert False
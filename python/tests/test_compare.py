@@ -0,0 +1,27 @@
+from npc_maker.compare import compare, format_markdown, genome_similarity
+from npc_maker.evo import Individual
+
+def _save(directory, scores, genomes):
+    for ascension, (score, genome) in enumerate(zip(scores, genomes)):
+        Individual(genome, score=score, ascension=ascension).save(directory)
+
+def test_compare(tmp_path):
+    for name in ("a", "b"):
+        tmp_path.joinpath(name, "population").mkdir(parents=True)
+        tmp_path.joinpath(name, "leaderboard").mkdir()
+    _save(tmp_path / "a" / "population", [1.0, 2.0, 3.0], [[0.0], [1.0], [2.0]])
+    _save(tmp_path / "a" / "leaderboard", [3.0], [[2.0]])
+    _save(tmp_path / "b" / "population", [0.0, 1.0, None], [[0.0], [5.0], [6.0]])
+    _save(tmp_path / "b" / "leaderboard", [1.0], [[5.0]])
+    report = compare(tmp_path / "a", tmp_path / "b")
+    assert report["scores"]["a"]["median"] == 2.0
+    assert report["scores"]["b"]["unscored"] == 1
+    assert report["scores"]["probability_a_wins"] == 5.5 / 6
+    assert report["leaderboard"]["overlap"] == 0
+    assert report["lineages"]["shared_genomes"] == 1
+    assert report["genomes"]["diversity_a"] == 1.0
+    assert "| mean | 2 | 0.5 |" in format_markdown(report)
+
+def test_similarity():
+    assert genome_similarity({"w": [1.0, 2.0]}, {"w": [1.0, 2.0001]}) == 1.0
+    assert genome_similarity({"w": [1.0, 2.0]}, {"w": [1.0, 3.0]}) == 1 / 3
@@ -0,0 +1,49 @@
+from npc_maker.run import load_config
+import json
+import pytest
+
+def write_experiment(tmp_path, population="", extra=""):
+    env_spec = {"name": "test", "path": "test.py", "populations": [{"name": "pop"}],
+                "settings": [{"name": "size", "type": "Integer", "minimum": 1, "maximum": 10, "default": 5}]}
+    tmp_path.joinpath("test.env").write_text(json.dumps(env_spec))
+    tmp_path.joinpath("ctrl.py").write_text("")
+    config = tmp_path.joinpath("experiment.toml")
+    config.write_text(f"""environment = "test.env"
+{extra}
+[populations.pop]
+controller = "ctrl.py"
+seed = [1, 2, 3]
+{population}
+""")
+    return config
+
+def test_valid(tmp_path):
+    config = load_config(write_experiment(tmp_path, "population_size = 10\nelites = 2", "settings = {size = 3}"))
+    assert config["environment"] == tmp_path.joinpath("test.env")
+    assert config["settings"] == {"size": "3"}
+    assert config["populations"]["pop"]["controller"] == [str(tmp_path.joinpath("ctrl.py"))]
+
+@pytest.mark.parametrize("population, extra, error", [
+    ("population_size = -3",        "",                  "populations.pop.population_size must be"),
+    ("population_size = 4\nelites = 5", "",              "populations.pop.elites must be"),
+    ("allow_mating = 1",            "",                  "populations.pop.allow_mating must be"),
+    ("mutate = \"no_such_module:mutate\"", "",           "populations.pop.mutate failed to import"),
+    ("seed_file = \"seed.json\"",   "",                  "exactly one of the fields"),
+    ("select = \"bogus\"",          "",                  "populations.pop.select is invalid"),
+    ("initial_genome = {algorithm = \"template\", noise = -1}", "", "populations.pop.initial_genome is invalid"),
+    ("novelty = {descriptor = []}", "",                  "populations.pop.novelty.descriptor must be"),
+    ("speciation = {threshold = 1.0}", "",               "missing field \"populations.pop.speciation.distance\""),
+    ("sharing = {distance = \"x:y\", on = \"phenome\"}", "", "populations.pop.sharing.on must be"),
+    ("reevaluate = 1.5",            "",                  "populations.pop.reevaluate must be"),
+    ("develop = 5",                 "",                  "populations.pop.develop must be"),
+    ("colour = 1",                  "",                  "unrecognized field \"populations.pop.colour\""),
+    ("",                            "settings = {foo = 1}", "unrecognized environment setting \"settings.foo\""),
+    ("",                            "settings = {size = \"big\"}", "settings.size is invalid"),
+    ("",                            "mode = \"fancy\"",  "mode must be"),
+    ("",                            "[budget]\ndeaths = -1", "budget.deaths must be"),
+    ("",                            "[wire_log]\nbackups = -1", "wire_log.backups must be"),
+    ("",                            "[pin]\nenvironment = \"abc\"", "pin.environment must be a SHA-256 hash"),
+])
+def test_invalid(tmp_path, population, extra, error):
+    with pytest.raises(ValueError, match=error):
+        load_config(write_experiment(tmp_path, population, extra))
@@ -0,0 +1,75 @@
+import contextlib
+import io
+import sys
+import npc_maker.ctrl as ctrl
+
+class Echo(ctrl.API):
+    def __init__(self):
+        self.inputs = {}
+    def new(self, genome):
+        pass
+    def reset(self):
+        pass
+    def set_input(self, gin, value):
+        self.inputs[gin] = value
+    def get_output(self, gin):
+        return self.inputs.get(gin, "0")
+
+def _run(controller, messages):
+    """Feed the messages through the main loop and return its replies."""
+    ctrl._stdin  = io.BufferedReader(io.BytesIO(messages.encode("utf-8")))
+    ctrl._buffer = b""
+    stdout = io.StringIO()
+    with contextlib.redirect_stdout(stdout):
+        ctrl._main_loop(controller)
+    return stdout.getvalue().splitlines()
+
+def test_vectorized():
+    controller = Echo()
+    replies = _run(controller, "M2\n1:0.5\n7:abc\nW7,1,3\nO1\nQ\n")
+    assert controller.inputs == {1: "0.5", 7: "abc"}
+    assert replies == ["7:abc", "1:0.5", "3:0", "1:0.5"]
+
+class Agents(Echo):
+    def __init__(self):
+        self.agents = [{}, {}]
+        self.inputs = self.agents[0]
+    def select(self, agent):
+        self.inputs = self.agents[agent]
+
+def test_select():
+    controller = Agents()
+    replies = _run(controller, "C\nI1:a\nU1\nI1:b\nO1\nU0\nO1\nQ\n")
+    assert replies == ["C:select", "1:b", "1:a"]
+    assert _run(Echo(), "C\nQ\n") == ["C:"]
+    try:
+        _run(Echo(), "U1\nQ\n")
+    except TypeError as error:
+        assert "unsupported" in str(error)
+    else:
+        assert False
+
+_PUBLISHER = """
+import sys
+for line in sys.stdin:
+    if line.startswith("O"):
+        gin = line[1:].strip()
+        print('D{"last_output": "%s"}' % gin)
+        print("%s:%s" % (gin, len(gin)), flush=True)
+    elif line.startswith("H"):
+        print('D{"pinged": "yes"}')
+        print("H:", flush=True)
+"""
+
+def test_epigenome(tmp_path):
+    program = tmp_path / "publisher.py"
+    program.write_text(_PUBLISHER)
+    controller = ctrl.Controller(tmp_path / "env.json", "pop", [sys.executable, program])
+    controller.new("[]")
+    assert controller.get_outputs([3, 10]) == {3: "1", 10: "2"}
+    assert controller.get_epigenome() == {"last_output": "10"}
+    controller.ping()
+    assert controller.get_epigenome() == {"last_output": "10", "pinged": "yes"}
+    controller.new("[]")
+    assert controller.get_epigenome() == {}
+    controller.quit()
@@ -0,0 +1,125 @@
+from npc_maker.develop import Development, DevelopmentError, Function, Parallel, Pipeline, as_development
+from npc_maker.env import Environment
+from npc_maker.evo import API, Individual
+import contextlib
+import io
+import json
+import os
+import subprocess
+import sys
+import threading
+import time
+
+class _Double(Development):
+    def develop(self, genome):
+        return [gene * 2 for gene in genome]
+
+def _reverse(genome):
+    return genome[::-1]
+
+def _fail(genome):
+    raise ValueError("broken gene")
+
+def test_as_development():
+    double = _Double()
+    assert as_development(double) is double
+    assert isinstance(as_development(_Double), _Double)
+    assert isinstance(as_development(_reverse), Function)
+    assert repr(as_development(_reverse)) == "_reverse"
+    try:
+        as_development(42)
+    except TypeError:
+        pass
+    else:
+        assert False
+
+def test_pipeline():
+    pipeline = Pipeline(_Double, _reverse)
+    assert pipeline.develop([1, 2, 3]) == [6, 4, 2]
+    assert pipeline.submit([1, 2]).result() == [4, 2]
+    future = Pipeline(_reverse, _fail).submit([1])
+    error = future.exception()
+    assert isinstance(error, DevelopmentError)
+    assert str(error) == "stage 1 (_fail) failed: broken gene"
+    assert isinstance(error.__cause__, ValueError)
+    try:
+        Development().develop([])
+    except TypeError:
+        pass
+    else:
+        assert False
+
+def test_parallel():
+    # Every genome waits for all of the others, which only works if they develop at the same time.
+    barrier = threading.Barrier(3, timeout=10)
+    def develop(genome):
+        barrier.wait()
+        return genome + 1
+    parallel = Parallel(develop, workers=3)
+    futures = [parallel.submit(genome) for genome in range(3)]
+    assert [future.result(timeout=10) for future in futures] == [1, 2, 3]
+    parallel.close()
+    # Worker processes need picklable developments.
+    parallel = Parallel(sorted, workers=2, processes=True)
+    assert parallel.submit([3, 1, 2]).result(timeout=60) == [1, 2, 3]
+    assert parallel.develop([2, 1]) == [1, 2]
+    parallel.close()
+
+_ENVIRONMENT = """
+import json, sys
+for _ in range(4):
+    print(json.dumps({"New": "pop"}), flush=True)
+for line in sys.stdin:
+    message = json.loads(line)
+    if message == "Quit":
+        break
+    if isinstance(message, dict) and "Birth" in message:
+        name = message["Birth"]["name"]
+        print(json.dumps({"Score": json.dumps(message["Birth"]["genome"]), "name": name}))
+        print(json.dumps({"Death": name}), flush=True)
+"""
+
+class _Counter(API):
+    def __init__(self):
+        self.births = 0
+        self.deaths = []
+    def birth(self, parents):
+        self.births += 1
+        return Individual(genome=self.births, controller=["ctrl"])
+    def death(self, individual):
+        self.deaths.append(individual)
+
+def _odd_only(genome):
+    if genome % 2 == 0:
+        raise ValueError(f"{genome} is even")
+    return [genome]
+
+def test_environment(tmp_path):
+    program = tmp_path.joinpath("environment.py")
+    program.write_text(f"#!{sys.executable}\n" + _ENVIRONMENT)
+    os.chmod(program, 0o755)
+    env_spec = {"name": "test", "path": "environment.py", "populations": [{"name": "pop"}]}
+    tmp_path.joinpath("test.env").write_text(json.dumps(env_spec))
+    service = _Counter()
+    stderr  = io.StringIO()
+    env = Environment({"pop": service}, tmp_path.joinpath("test.env"), mode="headless",
+                      stderr=subprocess.DEVNULL, timeout=30, develop={"pop": Parallel(_odd_only, 2)})
+    with contextlib.redirect_stderr(stderr):
+        env.start()
+        deadline = time.monotonic() + 30
+        while len(service.deaths) < 4:
+            assert time.monotonic() < deadline
+            env.poll()
+            time.sleep(0.001)
+    env.quit()
+    env.wait(30)
+    env.develop["pop"].close()
+    deaths = {individual.get_genome(): individual for individual in service.deaths}
+    # The environment receives the phenomes instead of the genomes.
+    assert deaths[1].get_score() == "[1]"
+    assert deaths[3].get_score() == "[3]"
+    # Individuals who failed to develop die without being born.
+    assert deaths[2].get_score() is None
+    assert deaths[2].get_info()["development_error"] == "2 is even"
+    assert deaths[4].get_birth_date() is None
+    assert stderr.getvalue().count("failed to develop") == 2
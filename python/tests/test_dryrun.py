@@ -0,0 +1,99 @@
+from npc_maker.dryrun import dry_run
+from pathlib import Path
+import json
+import npc_maker
+import os
+import subprocess
+import sys
+
+_ENVIRONMENT = """
+import json, sys
+births = 0
+for _ in range(3):
+    print(json.dumps({"New": "pop"}), flush=True)
+for line in sys.stdin:
+    message = json.loads(line)
+    if message == "Quit":
+        break
+    if isinstance(message, dict) and "Birth" in message:
+        births += 1
+        name = message["Birth"]["name"]
+        print(json.dumps({"Score": "1", "name": name}))
+        print(json.dumps({"Death": name}), flush=True)
+with open("births.txt", "w") as file:
+    file.write(str(births))
+"""
+
+_CONTROLLER = """
+import sys
+sys.path.insert(0, {python_path!r})
+import npc_maker.ctrl as ctrl
+class Stub(ctrl.API):
+    def new(self, genome):
+        pass
+    def reset(self):
+        pass
+    def validate(self, genome):
+        return None if genome == [1, 2, 3] else "expected [1, 2, 3]"
+    def set_input(self, gin, value):
+        if gin in {broken}:
+            raise ValueError("no such input")
+    def get_output(self, gin):
+        if gin in {broken}:
+            raise ValueError("no such output")
+        return "0"
+ctrl.main(Stub())
+"""
+
+def write_experiment(tmp_path, broken=()):
+    python_path = str(Path(npc_maker.__file__).parent.parent)
+    for name, source in [("environment.py", _ENVIRONMENT),
+                         ("ctrl.py", _CONTROLLER.format(python_path=python_path, broken=set(broken) or "()"))]:
+        program = tmp_path.joinpath(name)
+        program.write_text(f"#!{sys.executable}\n" + source)
+        os.chmod(program, 0o755)
+    env_spec = {"name": "test", "path": "environment.py", "populations": [{"name": "pop", "interfaces": [
+        {"gin": 1, "name": "light"},
+        {"gin": 2, "name": "motor"},
+    ]}]}
+    tmp_path.joinpath("test.env").write_text(json.dumps(env_spec))
+    config = tmp_path.joinpath("experiment.toml")
+    config.write_text("""environment = "test.env"
+output = "results"
+
+[populations.pop]
+controller = "ctrl.py"
+seed = [1, 2, 3]
+""")
+    return config
+
+def test_dry_run(tmp_path):
+    config = write_experiment(tmp_path)
+    report = dry_run(config, timeout=10, settle=0.1, stderr=subprocess.DEVNULL)
+    assert report.passed(), str(report)
+    names = [check.name for check in report.checks]
+    assert "population \"pop\" controller handshake" in names
+    assert "population \"pop\" controller accepts GIN 2 (motor)" in names
+    assert "population \"pop\" evaluation" in names
+    assert str(report).endswith(f"{len(names)} of {len(names)} checks passed")
+    # Dry runs give the environment exactly one individual, and they do not save anything.
+    assert tmp_path.joinpath("births.txt").read_text() == "1"
+    assert not tmp_path.joinpath("results").exists()
+
+def test_broken_controller(tmp_path):
+    config = write_experiment(tmp_path, broken=[2])
+    config.write_text(config.read_text().replace("[1, 2, 3]", "[4]"))
+    report = dry_run(config, timeout=10, settle=0.1, stderr=subprocess.DEVNULL)
+    failures = [check.name for check in report.failures()]
+    assert failures == [
+        "population \"pop\" controller accepts the seed genome",
+        "population \"pop\" controller accepts GIN 2 (motor)",
+    ]
+    assert report.failures()[0].message == "expected [1, 2, 3]"
+    assert not tmp_path.joinpath("results").exists()
+
+def test_missing_programs(tmp_path):
+    config = write_experiment(tmp_path)
+    tmp_path.joinpath("environment.py").unlink()
+    report = dry_run(config, timeout=10, settle=0.1, stderr=subprocess.DEVNULL)
+    assert [check.name for check in report.failures()] == ["environment program"]
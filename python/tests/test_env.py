@@ -0,0 +1,75 @@
+from npc_maker.env import Environment, Recording
+from npc_maker.evo import API, Individual
+import json
+import os
+import sys
+import time
+
+_ENVIRONMENT = """
+import json, sys
+births = 0
+print(json.dumps({"New": "pop"}), flush=True)
+for line in sys.stdin:
+    message = json.loads(line)
+    if message == "Quit":
+        break
+    if isinstance(message, dict) and "Birth" in message:
+        name   = message["Birth"]["name"]
+        genome = message["Birth"]["genome"]
+        births += 1
+        print(json.dumps({"Score": str(genome * 2), "name": name}))
+        print(json.dumps({"Death": name}))
+        if births < 5:
+            print(json.dumps({"New": "pop"}))
+        sys.stdout.flush()
+"""
+
+class _Counter(API):
+    def __init__(self):
+        self.births = 0
+        self.deaths = []
+    def birth(self, parents):
+        self.births += 1
+        individual = Individual(genome=self.births, controller=["ctrl"])
+        individual.name = f"individual{self.births}"
+        return individual
+    def death(self, individual):
+        self.deaths.append((individual.get_genome(), individual.get_score()))
+
+def _write_environment(tmp_path):
+    program = tmp_path.joinpath("environment.py")
+    program.write_text(f"#!{sys.executable}\n" + _ENVIRONMENT)
+    os.chmod(program, 0o755)
+    env_spec = {"name": "test", "path": "environment.py", "populations": [{"name": "pop"}]}
+    tmp_path.joinpath("test.env").write_text(json.dumps(env_spec))
+    return tmp_path.joinpath("test.env")
+
+def _run(env_spec, service, **kwargs):
+    env = Environment({"pop": service}, env_spec, mode="headless", **kwargs)
+    env.start()
+    deadline = time.monotonic() + 30
+    while len(service.deaths) < 5 and env.is_alive():
+        assert time.monotonic() < deadline
+        env.poll()
+        time.sleep(0.001)
+    env.quit()
+
+def _messages(path):
+    header, events = Recording.read(path)
+    return [(event["direction"], event["line"]) for event in events]
+
+def test_record_replay(tmp_path):
+    env_spec  = _write_environment(tmp_path)
+    original  = _Counter()
+    recording = Recording(tmp_path.joinpath("original.ndjson"), {"seed": 42})
+    _run(env_spec, original, record=recording)
+    recording.close()
+    assert original.deaths == [(genome, str(genome * 2)) for genome in range(1, 6)]
+    # Replay the recording, and record the replay.
+    replayed  = _Counter()
+    recording = Recording(tmp_path.joinpath("replay.ndjson"))
+    _run(env_spec, replayed, record=recording, replay=tmp_path.joinpath("original.ndjson"))
+    recording.close()
+    assert replayed.deaths == original.deaths
+    assert Recording.read(tmp_path.joinpath("original.ndjson"))[0] == {"seed": 42}
+    assert _messages(tmp_path.joinpath("replay.ndjson")) == _messages(tmp_path.joinpath("original.ndjson"))
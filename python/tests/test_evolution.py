@@ -0,0 +1,312 @@
+from npc_maker.evo import Coevolution, CorruptFileError, Evolution, Individual, Recorder
+from npc_maker.initial import RandomBytes
+from npc_maker.selection import Tournament
+from npc_maker.sharing import FitnessSharing
+from npc_maker.speciation import Speciation
+import collections
+import os
+import pytest
+import random
+import shutil
+import threading
+
+def test_frozen(tmp_path):
+    members = tmp_path.joinpath("population")
+    members.mkdir()
+    for genome in ("a", "b"):
+        Individual(genome, score=1.0, ascension=0).save(members)
+    evolution = Evolution("ctrl", "seed", mutate=lambda genome: genome + "!",
+                          path=tmp_path, population_type="frozen", population_size=2)
+    for _ in range(10):
+        individual = evolution.birth([])
+        assert individual.get_genome() in ("a", "b")
+        individual.score = 5.0
+        evolution.death(individual)
+    # The members are never replaced.
+    assert sorted(Individual.load(path).get_genome() for path in members.iterdir()) == ["a", "b"]
+    assert evolution.get_statistics_path().exists()
+
+def test_elites(tmp_path):
+    counter = iter(range(100))
+    evolution = Evolution("ctrl", lambda: next(counter), path=tmp_path, population_size=4, elites=2)
+    genomes = []
+    for _ in range(12):
+        individual = evolution.birth([])
+        genomes.append(individual.get_genome())
+        individual.score = individual.get_genome()
+        evolution.death(individual)
+    # The first generations are made from scratch.
+    assert genomes[:8] == list(range(8))
+    # The best two of the previous generation are cloned into the next generation.
+    assert genomes[8:10] == [7, 6]
+    assert all(genome in range(4, 8) for genome in genomes[10:])
+
+def test_statistics(tmp_path):
+    evolution = Evolution("ctrl", {"weights": [0.0, 0.0]}, path=tmp_path, population_size=4,
+                          mutate=lambda genome: {"weights": [random.random(), 0.0]})
+    for score in [1, 2, 3, None, 4, 4, 4, 4]:
+        individual = evolution.birth([])
+        individual.score = score
+        evolution.death(individual)
+    statistics = evolution.get_statistics()
+    assert [entry["generation"] for entry in statistics] == [0, 1]
+    assert statistics[0]["best"] == 3 and statistics[0]["mean"] == 2 and statistics[0]["unscored"] == 1
+    assert statistics[1]["evaluations"] == 8 and statistics[1]["stdev"] == 0.0
+    assert 0.0 < statistics[0]["diversity"] < 1.0
+    evolution.save_statistics_csv(tmp_path.joinpath("statistics.csv"))
+    lines = tmp_path.joinpath("statistics.csv").read_text().splitlines()
+    assert len(lines) == 3 and lines[0].startswith("generation,individuals,evaluations")
+
+def test_checkpoints(tmp_path):
+    evolution = Evolution("ctrl", 0, mutate=lambda genome: genome + 1, path=tmp_path,
+                          population_type="continuous", population_size=4, checkpoints=2)
+    for score in range(12):
+        individual = evolution.birth([])
+        individual.score = score
+        evolution.death(individual)
+    assert evolution.get_checkpoints() == [1, 2]
+    population = lambda: sorted(Individual.load(path).get_score() for path in evolution.get_path().iterdir())
+    assert population() == [8, 9, 10, 11]
+    evolution.restore(1)
+    assert population() == [4, 5, 6, 7]
+    assert evolution.ascension_counter == 8
+    assert len(evolution.get_statistics()) == 2
+    # Corrupt checkpoints are detected.
+    tmp_path.joinpath("checkpoints", "2", "checkpoint.json").write_text("{")
+    with pytest.raises(CorruptFileError):
+        evolution.restore(2)
+
+def test_checkpoints_durable(tmp_path):
+    evolution = Evolution("ctrl", 0, path=tmp_path, population_size=2, checkpoints=1)
+    synced = []
+    fsync  = os.fsync
+    os.fsync = lambda fd: synced.append(os.fstat(fd).st_ino) or fsync(fd)
+    try:
+        evolution.death(evolution.birth([]))
+        evolution.checkpoint()
+    finally:
+        os.fsync = fsync
+    assert evolution.get_path().stat().st_ino in synced
+    assert tmp_path.joinpath("checkpoints").stat().st_ino in synced
+    assert tmp_path.joinpath("checkpoints", "0", "checkpoint.json").stat().st_ino in synced
+
+def test_background_writes(tmp_path):
+    evolution = Evolution("ctrl", 0, mutate=lambda genome: genome + 1, path=tmp_path,
+                          population_size=4, background_writes=2)
+    for score in range(20):
+        individual = evolution.birth([])
+        individual.score = score
+        evolution.death(individual)
+    evolution.flush()
+    scores = sorted(Individual.load(path).get_score() for path in evolution.get_path().iterdir())
+    assert scores == [16, 17, 18, 19]
+    # Errors are raised by a later call.
+    individual = evolution.birth([])
+    individual.score = 20
+    shutil.rmtree(evolution.get_path())
+    evolution.death(individual)
+    with pytest.raises(FileNotFoundError):
+        evolution.flush()
+
+@pytest.mark.parametrize("background_writes", [0, 4])
+def test_threads(tmp_path, background_writes):
+    evolution = Evolution("ctrl", 0, mutate=lambda genome: genome + 1, path=tmp_path,
+                          population_type="continuous", population_size=10, background_writes=background_writes)
+    def evaluate():
+        for _ in range(50):
+            individual = evolution.birth([])
+            individual.score = individual.get_genome()
+            evolution.death(individual)
+    threads = [threading.Thread(target=evaluate) for _ in range(4)]
+    for thread in threads:
+        thread.start()
+    for thread in threads:
+        thread.join()
+    evolution.flush()
+    assert evolution.ascension_counter == 200
+    ascensions = sorted(Individual.load(path).get_ascension() for path in evolution.get_path().iterdir())
+    assert ascensions == list(range(190, 200))
+
+def test_random_seed(tmp_path):
+    select = Tournament()
+    seed   = RandomBytes(4)
+    def run(path, random_seed):
+        evolution = Evolution("ctrl", seed, path=path, population_size=5, select=select,
+                              random_seed=random_seed)
+        history = []
+        for _ in range(30):
+            individual = evolution.birth([])
+            individual.score = int(individual.get_uuid()[:8], 16)
+            history.append((individual.get_uuid(), individual.get_parent_uuids(), individual.get_genome()))
+            evolution.death(individual)
+        return history
+    first = run(tmp_path.joinpath("first"), 42)
+    assert first == run(tmp_path.joinpath("second"), 42)
+    assert first != run(tmp_path.joinpath("third"), 43)
+    # The caller's generators keep using the "random" module.
+    assert select.rng is random and seed.rng is random
+
+def test_reevaluate(tmp_path):
+    evolution = Evolution("ctrl", 0, mutate=lambda genome: genome + 1, path=tmp_path,
+                          population_type="continuous", population_size=5, reevaluate=0.5, max_evaluations=3)
+    recorder  = Recorder(evolution, tmp_path, leaderboard=3)
+    noise = random.Random(0)
+    for _ in range(100):
+        individual = recorder.birth([])
+        individual.score = individual.get_genome() + noise.random()
+        individual.name = None
+        recorder.death(individual)
+    members = [Individual.load(path) for path in evolution.get_path().iterdir()]
+    assert len(members) == 5
+    assert all(len(member.get_scores() or [None]) <= 3 for member in members)
+    for member in members:
+        if member.get_scores():
+            assert member.get_score() == pytest.approx(sum(member.get_scores()) / len(member.get_scores()))
+            low, high = member.get_confidence_interval()
+            assert low <= member.get_score() <= high
+    assert evolution.ascension_counter < 100
+    assert sum(entry["reevaluations"] for entry in evolution.get_statistics()) > 0
+    # Re-evaluated members are listed once on the leaderboard.
+    leaderboard = recorder.get_leaderboard()
+    assert len(leaderboard) == 3
+    assert len(set(path for path, score in leaderboard)) == 3
+
+def test_rescore(tmp_path):
+    evolution = Evolution("ctrl", 0, mutate=lambda genome: genome + 1, path=tmp_path,
+                          population_type="maximizing", population_size=4)
+    recorder  = Recorder(evolution, tmp_path, leaderboard=2)
+    evolution.set_settings({"difficulty": 1})
+    for score in range(20):
+        individual = recorder.birth([])
+        individual.score = score
+        individual.name = None
+        recorder.death(individual)
+    assert evolution.get_stale() == []
+    assert evolution.get_stale(generations=2) == []
+    assert len(evolution.get_stale(generations=1)) == 4
+    # Changing the settings makes every member stale.
+    evolution.set_settings({"difficulty": 2})
+    stale = evolution.get_stale()
+    assert [member.get_score() for member in stale] == [16, 17, 18, 19]
+    for member in stale:
+        recorder.rescore(member.get_uuid(), 100 - member.get_score())
+    assert evolution.get_stale() == []
+    # The leaderboard follows the new scores.
+    assert [score for path, score in recorder.get_leaderboard()] == [84, 83]
+    with pytest.raises(KeyError):
+        recorder.rescore("no-such-uuid", 0)
+
+def test_sample_opponents(tmp_path):
+    evolution = Evolution("ctrl", 0, mutate=lambda genome: genome + 1, path=tmp_path,
+                          population_type="continuous", population_size=4)
+    recorder  = Recorder(evolution, tmp_path, leaderboard=3)
+    for score in range(10):
+        individual = recorder.birth([])
+        individual.score = score
+        individual.info["elo"] = 1000 + 400 * score
+        individual.name = None
+        recorder.death(individual)
+    leaders = {Individual.load(path).get_uuid() for path, score in recorder.get_leaderboard()}
+    for weighting in ("uniform", "recency", "elo"):
+        opponents = recorder.sample_opponents(20, weighting)
+        assert len(opponents) == 20
+        assert all(opponent.get_parent_uuids()[0] in leaders for opponent in opponents)
+    # The best rated opponent is chosen about ten times as often as the next.
+    best = Individual.load(recorder.get_leaderboard()[0][0]).get_uuid()
+    opponents = recorder.sample_opponents(100, "elo")
+    assert sum(opponent.get_parent_uuids() == [best] for opponent in opponents) > 70
+    # Opponents do not join the population when they die.
+    for opponent in opponents[:5]:
+        opponent.score = 100
+        opponent.name = None
+        recorder.death(opponent)
+    assert evolution.ascension_counter == 10
+    assert [score for path, score in recorder.get_leaderboard()] == [9, 8, 7]
+
+def test_leaderboard_comparator(tmp_path):
+    def smaller_genome(a, b):
+        if a.get_score() != b.get_score():
+            return a.get_score() - b.get_score()
+        return len(b.get_genome()) - len(a.get_genome())
+    evolution = Evolution("ctrl", [], path=tmp_path, population_type="continuous", population_size=4)
+    recorder  = Recorder(evolution, tmp_path, leaderboard=3, comparator=smaller_genome)
+    for size, score in [(1, 1), (5, 2), (3, 2), (4, 2), (0, 0), (3, 2), (2, 2)]:
+        individual = Individual([0] * size, score=score)
+        individual.name = None
+        recorder.death(individual)
+    leaders = [Individual.load(path) for path, score in recorder.get_leaderboard()]
+    assert [len(leader.get_genome()) for leader in leaders] == [2, 3, 3]
+    # Equal genomes are ordered by ascension.
+    assert [leader.get_ascension() for leader in leaders] == [6, 2, 5]
+    # The leaderboard is the same after it is reloaded.
+    reloaded = Recorder(evolution, tmp_path, leaderboard=3, comparator=smaller_genome)
+    assert reloaded.get_leaderboard() == recorder.get_leaderboard()
+
+def test_leaderboard_ties(tmp_path):
+    evolution = Evolution("ctrl", 0, path=tmp_path, population_type="continuous", population_size=4)
+    recorder  = Recorder(evolution, tmp_path, leaderboard=3)
+    for score in ("nan", "10", "9", "10", "10", "nan"):
+        individual = Individual(0, score=score)
+        individual.name = None
+        recorder.death(individual)
+    # The oldest individuals win ties, and NaN scores are the worst.
+    assert [path.name for path, score in recorder.get_leaderboard()] == ["1.json", "3.json", "4.json"]
+    reloaded = Recorder(evolution, tmp_path, leaderboard=3)
+    assert reloaded.get_leaderboard() == recorder.get_leaderboard()
+
+def test_coevolution(tmp_path):
+    predators = Evolution("ctrl", 0, mutate=lambda genome: genome + 1, path=tmp_path.joinpath("predator"),
+                          population_size=4)
+    prey      = Evolution("ctrl", 0, mutate=lambda genome: genome - 1, path=tmp_path.joinpath("prey"),
+                          population_size=4)
+    coevolution = Coevolution({"predator": predators, "prey": prey}, cohort_size=4, matches=3)
+    opponents = collections.defaultdict(list)
+    for _ in range(12):
+        match = coevolution.next_match()
+        predator, victim = match.individuals["predator"], match.individuals["prey"]
+        assert predator.get_population() == "predator" and victim.get_population() == "prey"
+        opponents[predator.get_uuid()].append(victim.get_genome())
+        coevolution.report(match, {"predator": 1, "prey": 0})
+    assert coevolution.get_outstanding() == 0
+    assert all(len(genomes) == 3 for genomes in opponents.values())
+    assert predators.ascension_counter == 4 and prey.ascension_counter == 4
+    assert sorted(Individual.load(path).get_score() for path in predators.get_path().iterdir()) == [1, 1, 1, 1]
+
+def test_coevolution_seed(tmp_path):
+    def schedule(path):
+        services = {name: Evolution("ctrl", 0, path=path.joinpath(name), population_size=4, random_seed=1)
+                    for name in ("predator", "prey")}
+        coevolution = Coevolution(services, cohort_size=4, matches=3, random_seed=2)
+        matches = [coevolution.next_match() for _ in range(12)]
+        return [(match.individuals["predator"].get_uuid(), match.individuals["prey"].get_uuid())
+                for match in matches]
+    assert schedule(tmp_path.joinpath("a")) == schedule(tmp_path.joinpath("b"))
+
+def test_file_operations_outside_lock(tmp_path):
+    sharing   = FitnessSharing(lambda a, b: abs(a - b), radius=2.0)
+    evolution = Evolution("ctrl", 0, mutate=lambda genome: genome + 1, path=tmp_path, population_size=4,
+                          reevaluate=0.5, sharing=sharing, checkpoints=2)
+    execute = evolution._files._execute
+    def check(operation):
+        assert not evolution._lock._is_owned(), operation
+        return execute(operation)
+    evolution._files._execute = check
+    for _ in range(40):
+        individual = evolution.birth([])
+        individual.score = individual.get_genome()
+        evolution.death(individual)
+    assert len(evolution.get_statistics()) >= 2
+    assert evolution.get_checkpoints()
+
+def test_score_outside_lock(tmp_path):
+    def score(individual):
+        assert not evolution._lock._is_owned()
+        return individual.get_genome() * 10
+    speciation = Speciation(lambda a, b: abs(a - b), threshold=3.0)
+    evolution  = Evolution("ctrl", 0, mutate=lambda genome: genome + 1, path=tmp_path, population_size=4,
+                           score=score, speciation=speciation)
+    for _ in range(12):
+        individual = evolution.birth([])
+        evolution.death(individual)
+    assert [entry["best"] for entry in evolution.get_statistics()] == [10, 10, 20]
+    assert speciation.get_species()[0].best == 20
@@ -0,0 +1,138 @@
+from npc_maker.evo import API, Individual
+from npc_maker.farm import MAX_FRAME_SIZE, Farm, parse_address, recv_frame, send_frame
+import io
+import json
+import pytest
+import queue
+import socket
+import struct
+import threading
+import time
+
+def test_frames():
+    a, b = socket.socketpair()
+    send_frame(a, {"New": "pop"})
+    send_frame(a, b"x" * 100000)
+    assert json.loads(recv_frame(b)) == {"New": "pop"}
+    assert recv_frame(b) == b"x" * 100000
+    a.sendall(struct.pack(">I", MAX_FRAME_SIZE + 1))
+    with pytest.raises(ValueError):
+        recv_frame(b)
+    a.close()
+    assert recv_frame(b) is None
+    b.close()
+    assert parse_address(":7777") == ("127.0.0.1", 7777)
+    assert parse_address("0.0.0.0:7777") == ("0.0.0.0", 7777)
+    with pytest.raises(ValueError):
+        parse_address("farm.example.com")
+
+class _FakeWorker:
+    """ Connects to the farm and plays the part of the environment. """
+    def __init__(self, farm, hello):
+        self.socket = socket.create_connection(farm.get_address())
+        send_frame(self.socket, hello)
+        self.reply  = json.loads(recv_frame(self.socket) or b"null")
+        self.inbox  = queue.SimpleQueue()
+        threading.Thread(target=self._receive, daemon=True).start()
+
+    def _receive(self):
+        try:
+            while (message := recv_frame(self.socket)) is not None:
+                self.inbox.put(json.loads(message))
+        except OSError:
+            pass
+        self.inbox.put(None)
+
+    def receive(self, farm, key):
+        """ Poll the farm until this receives a message of the given type. """
+        deadline = time.monotonic() + 10
+        while time.monotonic() < deadline:
+            farm.poll()
+            try:
+                message = self.inbox.get(timeout=0.01)
+            except queue.Empty:
+                continue
+            if isinstance(message, dict) and key in message:
+                return message[key]
+        raise TimeoutError(key)
+
+    def close(self):
+        self.socket.shutdown(socket.SHUT_RDWR)
+        self.socket.close()
+
+class _Counter(API):
+    def __init__(self):
+        self.births = 0
+        self.deaths = []
+    def birth(self, parents):
+        self.births += 1
+        return Individual(genome=self.births, controller=["ctrl"])
+    def death(self, individual):
+        self.deaths.append(individual)
+
+def _farm(tmp_path, services, **kwargs):
+    env_spec = {"name": "test", "path": "test.py", "populations": [{"name": "pop"}]}
+    tmp_path.joinpath("test.env").write_text(json.dumps(env_spec))
+    return Farm(services, tmp_path.joinpath("test.env"), ":0", stderr=io.StringIO(), **kwargs)
+
+def _wait_for_workers(farm, count):
+    deadline = time.monotonic() + 10
+    while len(farm.get_workers()) != count:
+        assert time.monotonic() < deadline
+        farm.poll()
+        time.sleep(0.01)
+
+def test_handshake(tmp_path):
+    farm = _farm(tmp_path, {"pop": _Counter()}, secret="hunter2")
+    assert farm.get_address()[0] == "127.0.0.1"
+    rejected = [
+        ([1, 2, 3],                                                     "invalid handshake"),
+        ({"Worker": {"environment": "test"}},                           "secret does not match"),
+        ({"Worker": {"environment": "test", "secret": "hunter3"}},      "secret does not match"),
+        ({"Worker": {"environment": "other", "secret": "hunter2"}},     "running the environment \"test\""),
+    ]
+    for hello, error in rejected:
+        worker = _FakeWorker(farm, hello)
+        assert error in worker.reply["Error"]
+        assert worker.inbox.get(timeout=10) is None
+    farm.poll()
+    assert farm.get_workers() == []
+    worker = _FakeWorker(farm, {"Worker": {"name": "w", "environment": "test", "secret": "hunter2"}})
+    assert worker.reply == {"Worker": {"mode": "headless", "settings": {}}}
+    _wait_for_workers(farm, 1)
+    assert farm.get_workers() == ["w@127.0.0.1"]
+    farm.quit()
+
+def test_reassign(tmp_path):
+    service = _Counter()
+    farm    = _farm(tmp_path, {"pop": service})
+    farm.start()
+    first   = _FakeWorker(farm, {"Worker": {"name": "first", "environment": "test"}})
+    _wait_for_workers(farm, 1)
+    send_frame(first.socket, {"New": "pop"})
+    birth = first.receive(farm, "Birth")
+    assert birth["genome"] == 1
+    send_frame(first.socket, {"Score": "0.5", "name": birth["name"]})
+    # The worker dies before the individual does.
+    first.close()
+    _wait_for_workers(farm, 0)
+    assert [individual.get_genome() for individual in farm.get_outstanding().values()] == [1]
+    second  = _FakeWorker(farm, {"Worker": {"name": "second", "environment": "test"}})
+    _wait_for_workers(farm, 1)
+    send_frame(second.socket, {"New": "pop"})
+    reassigned = second.receive(farm, "Birth")
+    assert reassigned["genome"] == 1
+    assert service.births == 1
+    send_frame(second.socket, {"New": "pop"})
+    assert second.receive(farm, "Birth")["genome"] == 2
+    send_frame(second.socket, {"Death": reassigned["name"]})
+    deadline = time.monotonic() + 10
+    while not service.deaths:
+        assert time.monotonic() < deadline
+        farm.poll()
+        time.sleep(0.01)
+    # The score from the dead worker is discarded.
+    assert service.deaths[0].get_genome() == 1
+    assert service.deaths[0].get_score() is None
+    farm.quit()
+    second.close()
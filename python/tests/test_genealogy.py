@@ -0,0 +1,49 @@
+from npc_maker.evo import Individual
+from npc_maker.genealogy import Genealogy
+import xml.etree.ElementTree
+
+def _family(directory):
+    parent = Individual("a", ascension=0, score=1.0, parent_uuids=[])
+    child  = Individual("b", ascension=1, score=2.0, parent_uuids=[parent.get_uuid(), "unsaved"])
+    for individual in (parent, child):
+        individual.name = None
+        individual.save(directory)
+    directory.joinpath("statistics.json").write_text("{}")
+    return parent, child
+
+def test_genealogy(tmp_path):
+    parent, child = _family(tmp_path)
+    genealogy = Genealogy(tmp_path, population_size=1)
+    assert genealogy.edges() == [(parent.get_uuid(), child.get_uuid())]
+    dot = genealogy.to_dot()
+    assert f'"{parent.get_uuid()}" -> "{child.get_uuid()}";' in dot
+    assert 'generation="1"' in dot
+    gexf = xml.etree.ElementTree.fromstring(genealogy.to_gexf())
+    namespace = {"gexf": "http://gexf.net/1.3"}
+    assert len(gexf.findall(".//gexf:node", namespace)) == 2
+    assert len(gexf.findall(".//gexf:edge", namespace)) == 1
+
+def test_lineage(tmp_path):
+    from npc_maker.genealogy import Lineage
+    def save(genome, *parents):
+        individual = Individual(genome, parent_uuids=[parent.get_uuid() for parent in parents])
+        individual.save(tmp_path)
+        return individual
+    root   = save("root")
+    left   = save("left", root)
+    right  = save("right", root)
+    child  = save("child", left)
+    cousin = save("cousin", right, left)
+    other  = save("other")
+    lineage = Lineage(tmp_path)
+    genomes = lambda individuals: sorted(x.get_genome() for x in individuals)
+    assert genomes(lineage.parents(cousin)) == ["left", "right"]
+    assert genomes(lineage.children(left)) == ["child", "cousin"]
+    assert genomes(lineage.ancestors(child)) == ["left", "root"]
+    assert genomes(lineage.ancestors(child, generations=1)) == ["left"]
+    assert genomes(lineage.descendants(root, generations=1)) == ["left", "right"]
+    assert genomes(lineage.descendants(root)) == ["child", "cousin", "left", "right"]
+    assert lineage.common_ancestor(child, cousin).get_genome() == "left"
+    assert lineage.common_ancestor(child, right.get_uuid()).get_genome() == "root"
+    assert lineage.common_ancestor(left, child).get_genome() == "left"
+    assert lineage.common_ancestor(child, other) is None
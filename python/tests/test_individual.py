@@ -9,6 +9,10 @@ def test_name():
 def test_save_load():
     indiv1 = Individual(controller="test_ctrl", genome="test_genome",
         ascension=777,
+        evaluation_time=1.25,
+        queue_time=0.5,
+        species=3,
+        parent_uuids=["a", "b"],
         info={"test": "hello world"},
         foo="bar")
     print(vars(indiv1))
@@ -20,3 +24,35 @@ def test_save_load():
         assert vars(indiv1) == vars(indiv2)
     finally:
         path.unlink()
+
+def test_save_pretty():
+    indiv1 = Individual(controller="test_ctrl", genome={"weights": [1, 2, 3]},
+        ascension=778,
+        info={"b": 1, "a": 2})
+    path = indiv1.save("./", pretty=True)
+    try:
+        text = open(path, "rt").read()
+        print(text)
+        lines = text.splitlines()
+        assert lines[-2] == '    "genome": {"weights": [1, 2, 3]}'
+        assert text.index('"a"') < text.index('"b"')
+        indiv2 = Individual.load(path, controller="test_ctrl")
+        assert vars(indiv1) == vars(indiv2)
+    finally:
+        path.unlink()
+
+def test_corrupt():
+    from npc_maker.evo import CorruptFileError
+    import pytest
+    indiv = Individual(controller="test_ctrl", genome=[1.5, 2.5], ascension=779)
+    path = indiv.save("./")
+    try:
+        text = open(path, "rt").read()
+        open(path, "wt").write(text.replace("1.5", "1.25"))
+        with pytest.raises(CorruptFileError, match="checksum"):
+            Individual.load(path)
+        open(path, "wt").write(text[:len(text) // 2])
+        with pytest.raises(CorruptFileError, match="truncated"):
+            Individual.load(path)
+    finally:
+        path.unlink()
@@ -0,0 +1,22 @@
+from npc_maker.initial import ALGORITHMS, from_config
+import pytest
+
+def test_all_algorithms():
+    seed = {"weights": [1.0, 2.0], "layers": 3}
+    for name in ALGORITHMS:
+        generate = from_config(name, seed)
+        assert generate() is not generate()
+    assert from_config("constant", seed)() == seed
+    assert from_config({"algorithm": "template", "noise": 0.0}, seed)() == seed
+    genome = from_config({"algorithm": "template", "noise": 1.0}, seed)()
+    assert genome["layers"] == 3 and genome["weights"] != seed["weights"]
+    genome = from_config({"algorithm": "random_bytes", "length": 8})()
+    assert len(genome) == 8 and all(0 <= byte <= 255 for byte in genome)
+
+def test_config_errors():
+    with pytest.raises(ValueError):
+        from_config("bogus")
+    with pytest.raises(ValueError, match="requires a seed"):
+        from_config("template")
+    with pytest.raises(ValueError):
+        from_config({"algorithm": "random_bytes", "colour": 3})
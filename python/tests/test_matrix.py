@@ -0,0 +1,80 @@
+from npc_maker.matrix import Matrix
+import json
+import os
+import subprocess
+import sys
+
+_ENVIRONMENT = """
+import json, sys
+print(json.dumps({"New": "pop"}), flush=True)
+for line in sys.stdin:
+    message = json.loads(line)
+    if message == "Quit":
+        break
+    if isinstance(message, dict) and "Birth" in message:
+        name   = message["Birth"]["name"]
+        genome = message["Birth"]["genome"]
+        if genome == "crash":
+            sys.exit(1)
+        if genome != "unscored":
+            print(json.dumps({"Score": str(sum(genome)), "name": name}))
+        print(json.dumps({"Death": name}), flush=True)
+"""
+
+def _write_environment(tmp_path, name="test", program="environment.py"):
+    path = tmp_path.joinpath("environment.py")
+    path.write_text(f"#!{sys.executable}\n" + _ENVIRONMENT)
+    os.chmod(path, 0o755)
+    env_spec = {"name": name, "path": program, "populations": [{"name": "pop"}]}
+    tmp_path.joinpath(name + ".env").write_text(json.dumps(env_spec))
+    return tmp_path.joinpath(name + ".env")
+
+def test_matrix(tmp_path):
+    env_spec = _write_environment(tmp_path)
+    missing  = _write_environment(tmp_path, "missing", "not_built/environment")
+    matrix   = Matrix(timeout=10, stderr=subprocess.DEVNULL)
+    matrix.add_environment(env_spec, "pop", genome=[1, 2, 3], min_score=6, max_score=6)
+    matrix.add_environment(env_spec, "pop", genome=[1, 2], min_score=5)
+    matrix.add_environment(env_spec, "pop", genome=[9], max_score=5)
+    matrix.add_environment(env_spec, "pop", genome="unscored")
+    matrix.add_environment(env_spec, "pop", genome="crash")
+    matrix.add_environment(missing, "pop", genome=[1])
+    matrix.add_environment(tmp_path.joinpath("nowhere.env"), "pop", genome=[1])
+    matrix.add_controller(sys.executable)
+    matrix.add_controller([tmp_path.joinpath("not_built", "nn"), "--fast"])
+    results = matrix.run()
+    assert len(results) == 14
+    # Every environment is tested with every controller.
+    assert [result.controller for result in results[:2]] == [
+        [sys.executable],
+        [str(tmp_path.joinpath("not_built", "nn")), "--fast"],
+    ]
+    outcomes = [(result.status, result.score, result.message) for result in results[::2]]
+    assert outcomes[:5] == [
+        ("passed", 6.0, ""),
+        ("failed", 3.0, "expected at least 5.0"),
+        ("failed", 9.0, "expected at most 5.0"),
+        ("failed", None, "individual was not scored"),
+        ("failed", None, "environment exited before the evaluation finished"),
+    ]
+    assert outcomes[5][0] == "skipped"
+    assert outcomes[5][2].startswith("missing environment program")
+    assert outcomes[6][0] == "failed"
+    assert all(result.skipped() for result in results[1:12:2])
+    assert results[1].message.startswith("missing controller program")
+    assert str(results[0]) == f"PASSED  {env_spec} with {sys.executable}, score 6.0"
+
+def test_check(tmp_path):
+    env_spec = _write_environment(tmp_path)
+    matrix   = Matrix(timeout=10, stderr=subprocess.DEVNULL)
+    matrix.add_environment(env_spec, "pop", genome=[1], min_score=1)
+    matrix.add_controller(sys.executable)
+    [result] = matrix.check(verbose=False)
+    assert result.passed()
+    matrix.add_environment(env_spec, "pop", genome=[0], min_score=1)
+    try:
+        matrix.check(verbose=False)
+    except AssertionError as error:
+        assert str(error).startswith("1 of 2 tests failed:\nFAILED ")
+    else:
+        assert False
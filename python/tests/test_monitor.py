@@ -0,0 +1,116 @@
+from http.server import BaseHTTPRequestHandler, ThreadingHTTPServer
+from npc_maker.monitor import Monitor, prometheus, push
+import json
+import threading
+import urllib.error
+import urllib.request
+
+class _Environment:
+    def is_alive(self):
+        return True
+    def get_settings(self):
+        return {"size": "5"}
+
+class _Experiment:
+    """ Stand-in for "npc_maker.run.Experiment" with a fixed summary. """
+    def __init__(self):
+        self.mode = "headless"
+        self.environment = _Environment()
+        self.requests = []
+    def summary(self):
+        return {
+            "births": 12,
+            "deaths": 10,
+            "elapsed": 5.0,
+            "evaluations_per_second": 2.0,
+            "outstanding": 2,
+            "paused": False,
+            "finished": False,
+            "populations": {
+                "pop": {"generation": 3, "size": 4, "best_score": 7.5, "mean_score": 5.0},
+                "empty \"pop\"": {"generation": 0, "size": 0, "best_score": None, "mean_score": None},
+            },
+        }
+    def leaderboard(self, population):
+        if population != "pop":
+            raise KeyError(population)
+        return [("pop/best.json", 7.5)]
+    def request(self, action):
+        if action not in ("pause", "resume", "checkpoint", "stop"):
+            raise ValueError(action)
+        self.requests.append(action)
+
+def _parse(text):
+    """ Parse the Prometheus text format into a dict of {sample: value}. """
+    samples = {}
+    for line in text.splitlines():
+        if line and not line.startswith("#"):
+            sample, value = line.rsplit(" ", 1)
+            samples[sample] = float(value)
+    return samples
+
+def test_prometheus():
+    text = prometheus(_Experiment())
+    assert text.endswith("\n")
+    samples = _parse(text)
+    assert samples["npc_maker_deaths_total"] == 10
+    assert samples["npc_maker_paused"] == 0
+    assert samples["npc_maker_environment_up"] == 1
+    assert samples['npc_maker_best_score{population="pop"}'] == 7.5
+    assert samples['npc_maker_generation{population="empty \\"pop\\""}'] == 0
+    # Missing scores are omitted.
+    assert 'npc_maker_best_score{population="empty \\"pop\\""}' not in samples
+    assert "# TYPE npc_maker_births_total counter" in text.splitlines()
+
+def _request(monitor, method, path):
+    host, port = monitor.get_address()
+    request = urllib.request.Request(f"http://{host}:{port}{path}", method=method)
+    try:
+        with urllib.request.urlopen(request, timeout=10) as response:
+            return response.status, response.headers["Content-Type"], response.read().decode("utf-8")
+    except urllib.error.HTTPError as error:
+        return error.code, error.headers["Content-Type"], error.read().decode("utf-8")
+
+def test_monitor():
+    experiment = _Experiment()
+    with Monitor(experiment, port=0) as monitor:
+        status, content_type, body = _request(monitor, "GET", "/summary")
+        assert (status, content_type) == (200, "application/json")
+        assert json.loads(body) == json.loads(json.dumps(experiment.summary()))
+        status, content_type, body = _request(monitor, "GET", "/leaderboard/pop")
+        assert json.loads(body) == [{"path": "pop/best.json", "score": 7.5}]
+        status, content_type, body = _request(monitor, "GET", "/environment?verbose")
+        assert json.loads(body)["settings"] == {"size": "5"}
+        status, content_type, body = _request(monitor, "GET", "/metrics")
+        assert content_type.startswith("text/plain")
+        assert _parse(body) == _parse(prometheus(experiment))
+        assert _request(monitor, "GET", "/leaderboard/other")[0] == 404
+        assert _request(monitor, "GET", "/nowhere")[0] == 404
+        status, content_type, body = _request(monitor, "POST", "/stop")
+        assert (status, json.loads(body)) == (200, {"ok": "stop"})
+        assert _request(monitor, "POST", "/explode")[0] == 404
+        assert _request(monitor, "POST", "/stop/now")[0] == 404
+    assert experiment.requests == ["stop"]
+
+def test_push():
+    received = []
+    class Gateway(BaseHTTPRequestHandler):
+        def do_PUT(self):
+            length = int(self.headers["Content-Length"])
+            received.append((self.path, self.rfile.read(length).decode("utf-8")))
+            self.send_response(200)
+            self.end_headers()
+        def log_message(self, format, *args):
+            pass
+    server = ThreadingHTTPServer(("127.0.0.1", 0), Gateway)
+    thread = threading.Thread(target=server.serve_forever, daemon=True)
+    thread.start()
+    try:
+        host, port = server.server_address[:2]
+        push(_Experiment(), f"http://{host}:{port}/", job="my job")
+    finally:
+        server.shutdown()
+        server.server_close()
+    [(path, body)] = received
+    assert path == "/metrics/job/my%20job"
+    assert _parse(body)["npc_maker_births_total"] == 12
@@ -0,0 +1,40 @@
+from npc_maker.evo import API, Individual
+from npc_maker.novelty import Novelty
+import random
+
+class _Sink(API):
+    def __init__(self):
+        self.dead = []
+
+    def birth(self, parents):
+        return {}
+
+    def death(self, individual):
+        self.dead.append(individual)
+
+def test_novelty(tmp_path):
+    sink    = _Sink()
+    novelty = Novelty(sink, ["x", "y"], k=2, threshold=1.0, path=tmp_path)
+    for x, y in [(0, 0), (0, 0.1), (5, 5), (0, 0.2)]:
+        novelty.death(Individual(None, info={"x": x, "y": y}))
+    novelty.death(Individual(None, info={"x": "nan"}))
+    scores = [individual.get_info()["novelty"] for individual in sink.dead]
+    assert scores[0] == 0.0
+    assert abs(scores[1] - 0.1) < 1e-9
+    assert scores[2] > 5.0
+    assert scores[3] < scores[2]
+    assert scores[4] is None
+    assert novelty.get_archive() == [(0.0, 0.0), (5.0, 5.0)]
+    # The archive is reloaded from disk.
+    assert Novelty(sink, ["x", "y"], path=tmp_path).get_archive() == novelty.get_archive()
+
+def test_novelty_rng():
+    # Behaviors are archived at random using the service's random number generator.
+    def archive():
+        sink     = _Sink()
+        sink.rng = random.Random(7)
+        novelty  = Novelty(sink, ["x"], probability=0.5)
+        for x in range(20):
+            novelty.death(Individual(None, info={"x": x}))
+        return novelty.get_archive()
+    assert archive() == archive()
@@ -0,0 +1,99 @@
+from npc_maker.evo import API, Individual
+from npc_maker.pool import EnvironmentPool
+import contextlib
+import io
+import json
+import os
+import subprocess
+import sys
+import time
+
+_ENVIRONMENT = """
+import json, sys, time
+if sys.argv[2] == "graphical":
+    sys.exit("no display")
+for _ in range(2):
+    print(json.dumps({"New": "pop"}), flush=True)
+for line in sys.stdin:
+    message = json.loads(line)
+    if message == "Quit":
+        break
+    if isinstance(message, dict) and "Birth" in message:
+        name = message["Birth"]["name"]
+        time.sleep(0.01)
+        print(json.dumps({"Score": "1", "name": name}))
+        print(json.dumps({"Death": name}))
+        print(json.dumps({"New": "pop"}), flush=True)
+"""
+
+class _Counter(API):
+    def __init__(self):
+        self.births = 0
+        self.deaths = 0
+    def birth(self, parents):
+        self.births += 1
+        return Individual(genome=self.births, controller=["ctrl"])
+    def death(self, individual):
+        self.deaths += 1
+
+def _write_environment(tmp_path):
+    program = tmp_path.joinpath("environment.py")
+    program.write_text(f"#!{sys.executable}\n" + _ENVIRONMENT)
+    os.chmod(program, 0o755)
+    env_spec = {"name": "test", "path": "environment.py", "populations": [{"name": "pop"}]}
+    tmp_path.joinpath("test.env").write_text(json.dumps(env_spec))
+    return tmp_path.joinpath("test.env")
+
+def _run(pool, service, deaths):
+    """ Poll the pool until enough individuals have died, and return the most which were alive at once. """
+    most_alive = 0
+    deadline = time.monotonic() + 60
+    while service.deaths < deaths:
+        assert time.monotonic() < deadline
+        assert pool.is_alive()
+        pool.poll()
+        most_alive = max(most_alive, len(pool.get_outstanding()))
+        time.sleep(0.001)
+    return most_alive
+
+def _shutdown(pool):
+    pool.quit()
+    pool.wait(30)
+    assert not pool.is_alive()
+    assert pool.get_waiting() == 0
+    assert all(instance._process.poll() is not None for instance in pool.instances)
+
+def test_concurrency(tmp_path):
+    service = _Counter()
+    pool = EnvironmentPool(service, _write_environment(tmp_path), instances=3, concurrency=2,
+                           stderr=subprocess.DEVNULL, timeout=30)
+    assert len(pool.instances) == 3
+    pool.start()
+    assert _run(pool, service, 30) == 2
+    # Each instance asks for two individuals, but only two may be alive at once.
+    assert pool.get_waiting() >= 3
+    _shutdown(pool)
+
+def test_reuse(tmp_path):
+    service = _Counter()
+    pool = EnvironmentPool({"pop": service}, _write_environment(tmp_path), instances=2,
+                           stderr=subprocess.DEVNULL, timeout=30)
+    processes = [instance._process for instance in pool.instances]
+    pool.start()
+    assert _run(pool, service, 20) <= 4
+    # The same programs evaluate all of the individuals.
+    assert [instance._process for instance in pool.instances] == processes
+    assert all(latency is not None and latency > 0 for latency in pool.get_latencies())
+    _shutdown(pool)
+
+def test_headless_fallback(tmp_path):
+    service = _Counter()
+    stderr = io.StringIO()
+    with contextlib.redirect_stderr(stderr):
+        pool = EnvironmentPool(service, _write_environment(tmp_path), instances=2, mode="graphical",
+                               stderr=subprocess.DEVNULL, timeout=30)
+        pool.start()
+        _run(pool, service, 4)
+    assert [instance.get_mode() for instance in pool.instances] == ["headless", "headless"]
+    assert stderr.getvalue().count("relaunching it in headless mode") == 2
+    _shutdown(pool)
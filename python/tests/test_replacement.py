@@ -0,0 +1,37 @@
+from npc_maker.evo import Evolution, Individual
+from npc_maker.replacement import Oldest, ReplacementPolicy, Worst
+import collections
+
+Member = collections.namedtuple("Member", ("score", "ascension", "species"))
+
+def test_oldest():
+    members = [Member(5, 3, 0), Member(1, 1, 0), Member(9, 2, 0)]
+    assert Oldest().evict(members, None, 3) == []
+    assert Oldest().evict(members, None, 1) == [1, 2]
+
+def test_worst():
+    members = [Member(5, 0, 0), Member(1, 1, 0), Member(1, 2, 0)]
+    assert Worst().evict(members, None, 2) == [1]
+    # New individuals lose ties.
+    assert Worst().evict(members, "new", 2) == [2]
+    # Multi-objective scores evict the dominated member.
+    members = [Member((1, 1), 0, 0), Member((2, 0), 1, 0), Member((0, 0), 2, 0)]
+    assert Worst().evict(members, "new", 2) == [2]
+
+class WorstInSpecies(ReplacementPolicy):
+    def evict(self, members, individual, size):
+        if len(members) <= size:
+            return []
+        species = [index for index, member in enumerate(members) if member.species == members[-1].species]
+        return [min(species, key=lambda index: members[index].score)]
+
+def test_custom_policy(tmp_path):
+    species = lambda genome: genome % 2
+    evolution = Evolution("ctrl", 0, path=tmp_path, population_type=WorstInSpecies, population_size=4)
+    for genome in range(20):
+        individual = Individual(genome, score=genome)
+        individual.species = species(genome)
+        evolution.death(individual)
+    members = sorted(Individual.load(path).get_genome() for path in evolution.get_path().iterdir())
+    # Each species keeps its two best members.
+    assert members == [16, 17, 18, 19]
@@ -0,0 +1,99 @@
+from npc_maker.__main__ import main
+from npc_maker.run import Experiment
+import contextlib
+import io
+import json
+import os
+import sys
+
+_ENVIRONMENT = """
+import json, sys
+for _ in range(3):
+    print(json.dumps({"New": "pop"}), flush=True)
+for line in sys.stdin:
+    message = json.loads(line)
+    if message == "Quit":
+        break
+    if isinstance(message, dict) and "Birth" in message:
+        name = message["Birth"]["name"]
+        print(json.dumps({"Score": str(sum(message["Birth"]["genome"])), "name": name}))
+        print(json.dumps({"Death": name}))
+        print(json.dumps({"New": "pop"}), flush=True)
+"""
+
+def write_experiment(tmp_path, deaths=10):
+    program = tmp_path.joinpath("environment.py")
+    program.write_text(f"#!{sys.executable}\n" + _ENVIRONMENT)
+    os.chmod(program, 0o755)
+    env_spec = {"name": "test", "path": "environment.py", "populations": [{"name": "pop"}]}
+    tmp_path.joinpath("test.env").write_text(json.dumps(env_spec))
+    tmp_path.joinpath("ctrl.py").write_text("")
+    config = tmp_path.joinpath("experiment.toml")
+    config.write_text(f"""environment = "test.env"
+output = "results"
+seed = 42
+timeout = 30
+
+[budget]
+deaths = {deaths}
+
+[populations.pop]
+controller = "ctrl.py"
+seed = [1, 2, 3]
+population_type = "continuous"
+population_size = 4
+""")
+    return config
+
+def _main(*argv):
+    """ Run the command line tool and return its exit code, stdout, and stderr. """
+    stdout = io.StringIO()
+    stderr = io.StringIO()
+    code = 0
+    with contextlib.redirect_stdout(stdout), contextlib.redirect_stderr(stderr):
+        try:
+            main([str(arg) for arg in argv])
+        except SystemExit as exit:
+            code = exit.code
+    return code, stdout.getvalue(), stderr.getvalue()
+
+def test_arguments(tmp_path):
+    config = write_experiment(tmp_path)
+    code, stdout, stderr = _main("check", config)
+    assert code == 0
+    assert json.loads(stdout)["budget"] == {"deaths": 10}
+    code, stdout, stderr = _main("check", tmp_path.joinpath("missing.toml"))
+    assert code == 1
+    assert stderr.startswith("npc_maker check: ")
+    code, stdout, stderr = _main("run")
+    assert code == 2
+    assert "config" in stderr
+    code, stdout, stderr = _main("run", config, "--monitor", "not-a-port")
+    assert code == 2
+
+def test_run(tmp_path):
+    config = write_experiment(tmp_path)
+    code, stdout, stderr = _main("run", config)
+    assert code == 0, stderr
+    assert stdout.startswith("finished after ")
+    state = json.loads(tmp_path.joinpath("results", "experiment.json").read_text())
+    assert state["deaths"] >= 10
+    assert list(tmp_path.joinpath("results", "pop").iterdir())
+    # The experiment already exists, so it can only be resumed.
+    code, stdout, stderr = _main("run", config)
+    assert code == 1
+    assert "resume" in stderr
+    code, stdout, stderr = _main("run", config, "--resume")
+    assert code == 0, stderr
+    resumed = json.loads(tmp_path.joinpath("results", "experiment.json").read_text())
+    assert resumed["deaths"] == state["deaths"]
+
+def test_output_override(tmp_path):
+    config = write_experiment(tmp_path, deaths=3)
+    experiment = Experiment(config, output=tmp_path.joinpath("other"))
+    with open(tmp_path.joinpath("stderr.txt"), 'wt') as stderr:
+        experiment.run(stderr=stderr)
+    assert experiment.deaths >= 3
+    assert experiment.is_finished()
+    assert tmp_path.joinpath("other", "experiment.json").exists()
+    assert not tmp_path.joinpath("results").exists()
@@ -0,0 +1,49 @@
+from npc_maker.selection import ALGORITHMS, from_config
+import collections
+import pytest
+
+def test_all_algorithms():
+    scores = [1.0, 5.0, None, 3.0, float("nan"), "2.5"]
+    for name in ALGORITHMS:
+        select = from_config(name)
+        indices = select.select(100, scores)
+        assert len(indices) == 100
+        assert all(0 <= index < len(scores) for index in indices)
+        assert len(select.pairs(10, scores)) == 10
+        assert select.select(10, []) == []
+
+def test_pressure():
+    scores = list(range(100))
+    for config in ("truncation", "roulette", "tournament", "ranked_linear", "ranked_exponential",
+                   {"algorithm": "softmax", "temperature": 10}):
+        counts = collections.Counter(from_config(config).select(10000, scores))
+        assert counts[99] > counts[0]
+    assert min(from_config({"algorithm": "truncation", "fraction": 0.1}).select(1000, scores)) >= 90
+
+def test_tournament_sexual():
+    scores = list(range(100))
+    pairs = from_config({"algorithm": "tournament", "sexual": 0.0}).pairs(100, scores)
+    assert all(a == b for a, b in pairs)
+    pairs = from_config({"algorithm": "tournament", "size": 1, "sexual": 0.5}).pairs(1000, scores)
+    assert 400 < sum(a == b for a, b in pairs) < 600
+
+def test_config_errors():
+    with pytest.raises(ValueError):
+        from_config("bogus")
+    with pytest.raises(ValueError):
+        from_config({"algorithm": "tournament", "colour": 3})
+    with pytest.raises(ValueError):
+        from_config({"algorithm": "tournament", "size": 0})
+    with pytest.raises(ValueError):
+        from_config({"algorithm": "tournament", "sexual": 2})
+
+def test_nsga2():
+    from npc_maker.selection import NSGA2, crowding_distance, pareto_fronts
+    scores = [(1, 5), (5, 1), (3, 3), (2, 2), (0, 0), (None, 9)]
+    fronts = pareto_fronts(scores)
+    assert fronts == [[0, 1, 2, 5], [3], [4]]
+    distance = crowding_distance(scores, [0, 1, 2])
+    assert distance[0] == distance[1] == float("inf")
+    assert distance[2] == 2.0
+    counts = collections.Counter(NSGA2(size=3).select(10000, scores))
+    assert counts[2] > counts[3] > counts[4]
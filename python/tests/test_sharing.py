@@ -0,0 +1,27 @@
+from npc_maker.evo import API, Individual, Recorder
+from npc_maker.sharing import FitnessSharing
+
+class _Sink(API):
+    def birth(self, parents):
+        return {}
+
+    def death(self, individual):
+        pass
+
+def test_share():
+    sharing = FitnessSharing(lambda a, b: abs(a - b), radius=2.0)
+    assert sharing.niche_counts([0.0, 1.0, 10.0]) == [1.5, 1.5, 1.0]
+    assert sharing.share([0.0, 1.0, 10.0], [3.0, (3.0, 6.0), None]) == [2.0, (2.0, 4.0), None]
+
+def test_leaderboard(tmp_path):
+    sharing  = FitnessSharing(lambda a, b: abs(a - b), radius=1.0)
+    recorder = Recorder(_Sink(), tmp_path, leaderboard=2, sharing=sharing)
+    for ascension, (genome, score) in enumerate([(0.0, 10.0), (0.0, 9.0), (5.0, 6.0)]):
+        individual = Individual(genome, score=score, ascension=ascension)
+        individual.name = None
+        recorder.death(individual)
+    # The clone of the best individual is displaced by a less crowded individual.
+    leaderboard = recorder.get_leaderboard()
+    assert [score for _, score in leaderboard] == [10.0, 6.0]
+    assert recorder.get_best().get_score() == 10.0
+    assert sorted(path.name for path in tmp_path.joinpath("leaderboard").iterdir()) == ["0.json", "2.json"]
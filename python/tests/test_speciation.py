@@ -0,0 +1,29 @@
+from npc_maker.evo import Individual
+from npc_maker.speciation import Speciation
+
+def test_speciation(tmp_path):
+    speciation = Speciation(lambda a, b: abs(a - b), threshold=1.0, stagnation=2, survivors=1, path=tmp_path)
+    def death(genome, score, generation):
+        individual = Individual(genome)
+        individual.species = speciation.assign(individual)
+        speciation.death(individual, score, generation)
+        return individual.species
+    assert [death(x, 1.0, 0) for x in (0.0, 0.5, 10.0, 10.2, 10.4)] == [0, 0, 1, 1, 1]
+    # Fitness sharing divides the scores by the size of each species.
+    indexes, shared = speciation.share([0, 0, 1, 1, 1], [2.0, 4.0, 3.0, 3.0, None])
+    assert indexes == [0, 1, 2, 3, 4]
+    assert shared == [1.0, 2.0, 1.0, 1.0, None]
+    # Species 0 improves and species 1 stagnates.
+    death(0.2, 5.0, 1)
+    death(10.1, 0.5, 1)
+    death(0.3, 6.0, 2)
+    death(10.1, 0.5, 2)
+    assert speciation.is_stagnant(1) and not speciation.is_stagnant(0)
+    assert speciation.share([0, 1], [1.0, 1.0])[0] == [0]
+    # Species without members go extinct at the start of the next generation.
+    death(0.1, 7.0, 3)
+    death(0.1, 7.0, 4)
+    assert [species.number for species in speciation.get_species()] == [0]
+    # The species are reloaded from disk.
+    reloaded = Speciation(speciation.distance, path=tmp_path)
+    assert vars(reloaded.get_species()[0]) == vars(speciation.get_species()[0])
@@ -0,0 +1,216 @@
+//! Benchmarks for the hot paths of the controller and environment protocols.
+//!
+//! Run with: `cargo bench --bench protocol`
+//!
+//! The controller round trip benchmark runs this same executable as the
+//! controller program, with the environment variable `NPC_MAKER_BENCH_CONTROLLER` set.
+
+use criterion::{criterion_group, BatchSize, BenchmarkId, Criterion, Throughput};
+use npc_maker::ctrl::{self, Controller, Message, MessageReader};
+use npc_maker::messages::{Request, Response};
+use std::hint::black_box;
+use std::io::Write;
+
+const CONTROLLER_VAR: &str = "NPC_MAKER_BENCH_CONTROLLER";
+
+/// Genome sizes to sweep over, in bytes.
+const GENOME_SIZES: [usize; 3] = [100, 10_000, 1_000_000];
+
+/// Make a JSON genotype of approximately the given size in bytes.
+fn genotype(size: usize) -> serde_json::Value {
+    let weights: Vec<f64> = (0..size / 20).map(|i| i as f64 * 0.123456789).collect();
+    serde_json::json!({"weights": weights})
+}
+
+fn message_codec(c: &mut Criterion) {
+    let messages = [
+        Message::SetInput {
+            gin: 42,
+            value: "-1234.5678".to_string(),
+        },
+        Message::GetOutputs {
+            gins: (0..16).collect(),
+        },
+        Message::SetInputs {
+            inputs: (0..16).map(|gin| (gin, "0.5".to_string())).collect(),
+        },
+        Message::Advance { dt: 0.01 },
+    ];
+    let mut group = c.benchmark_group("message");
+    for message in &messages {
+        let mut bytes = Vec::new();
+        message.write(&mut bytes).unwrap();
+        let name = String::from_utf8_lossy(&bytes[..1]).into_owned();
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_with_input(BenchmarkId::new("encode", &name), message, |b, message| {
+            let mut buffer = Vec::with_capacity(bytes.len());
+            b.iter(|| {
+                buffer.clear();
+                black_box(message).write(&mut buffer).unwrap();
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("decode", &name), &bytes, |b, bytes| {
+            b.iter(|| Message::read(&mut black_box(bytes.as_slice())).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("reader", &name), &bytes, |b, bytes| {
+            b.iter(|| {
+                let mut reader = MessageReader::new(black_box(bytes.as_slice()));
+                black_box(reader.read().unwrap());
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Controller which echoes its inputs back as its outputs.
+struct Echo(Vec<String>);
+
+impl ctrl::API for Echo {
+    fn new(&mut self, _genotype: String) {}
+    fn reset(&mut self) {}
+    fn advance(&mut self, _dt: f64) {}
+    fn set_input(&mut self, gin: u64, value: String) {
+        let gin = gin as usize;
+        if gin >= self.0.len() {
+            self.0.resize(gin + 1, String::new());
+        }
+        self.0[gin] = value;
+    }
+    fn get_output(&mut self, gin: u64) -> String {
+        self.0.get(gin as usize).cloned().unwrap_or_default()
+    }
+}
+
+fn controller_round_trip(c: &mut Criterion) {
+    let command = [std::env::current_exe().unwrap().to_string_lossy().into_owned()];
+    let env = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+    let options = ctrl::ControllerOptions::default().env_var(CONTROLLER_VAR, "1");
+    let mut controller = Controller::with_options(env, "pop", &command, &options).unwrap();
+    let mut group = c.benchmark_group("controller");
+    group.bench_function("ping", |b| b.iter(|| controller.ping().unwrap()));
+    group.bench_function("step", |b| {
+        b.iter(|| {
+            controller.set_input(0, "0.5").unwrap();
+            controller.advance(0.01).unwrap();
+            controller.get_outputs(&[0]).unwrap()
+        })
+    });
+    for size in GENOME_SIZES {
+        let genotype = genotype(size).to_string();
+        group.throughput(Throughput::Bytes(genotype.len() as u64));
+        group.bench_with_input(BenchmarkId::new("new_genotype", size), &genotype, |b, genotype| {
+            b.iter(|| {
+                controller.new_genotype(genotype).unwrap();
+                controller.ping().unwrap()
+            })
+        });
+    }
+    group.finish();
+    controller.quit().unwrap();
+}
+
+fn env_json(c: &mut Criterion) {
+    let mut group = c.benchmark_group("env");
+    for size in GENOME_SIZES {
+        let request = Request::Birth {
+            population: "pop".to_string(),
+            individual: 12345,
+            controller: vec!["controller.py".to_string()],
+            controller_options: Box::default(),
+            genotype: genotype(size),
+        };
+        let json = serde_json::to_string(&request).unwrap();
+        group.throughput(Throughput::Bytes(json.len() as u64));
+        group.bench_with_input(BenchmarkId::new("birth_encode", size), &request, |b, request| {
+            b.iter(|| serde_json::to_string(black_box(request)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("birth_decode", size), &json, |b, json| {
+            b.iter(|| serde_json::from_str::<Request>(black_box(json)).unwrap())
+        });
+    }
+    // Responses are small and frequent, measure them as a stream of lines.
+    let responses: Vec<Response> = (0..1000)
+        .map(|individual| serde_json::from_value(serde_json::json!({"Score": 0.5, "individual": individual})))
+        .filter_map(Result::ok)
+        .collect();
+    let lines: String = responses
+        .iter()
+        .map(|response| serde_json::to_string(response).unwrap() + "\n")
+        .collect();
+    group.throughput(Throughput::Elements(responses.len() as u64));
+    group.bench_function("responses_encode", |b| {
+        b.iter(|| {
+            let mut out = Vec::with_capacity(lines.len());
+            for response in &responses {
+                serde_json::to_writer(&mut out, response).unwrap();
+                out.push(b'\n');
+            }
+            out
+        })
+    });
+    group.bench_function("responses_decode", |b| {
+        b.iter(|| {
+            for line in lines.lines() {
+                black_box(serde_json::from_str::<Response>(line).unwrap());
+            }
+        })
+    });
+    group.finish();
+}
+
+/// Saved individuals are JSON files containing the genome and its metadata.
+fn individual_save_load(c: &mut Criterion) {
+    let dir = std::env::temp_dir().join(format!("npc_maker_bench_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("individual.json");
+    let mut group = c.benchmark_group("individual");
+    for size in GENOME_SIZES {
+        let individual = serde_json::json!({
+            "name": "7c858b98-6c2d-47e9-a4c6-45fb81f9bb14",
+            "environment": "xor",
+            "population": "pop",
+            "controller": ["controller.py"],
+            "genome": genotype(size),
+            "parents": 2,
+            "score": "0.75",
+            "info": {},
+        });
+        group.throughput(Throughput::Bytes(serde_json::to_vec(&individual).unwrap().len() as u64));
+        group.bench_with_input(BenchmarkId::new("save", size), &individual, |b, individual| {
+            b.iter(|| {
+                let mut file = std::io::BufWriter::new(std::fs::File::create(&path).unwrap());
+                serde_json::to_writer(&mut file, individual).unwrap();
+                file.flush().unwrap();
+            })
+        });
+        group.bench_function(BenchmarkId::new("load", size), |b| {
+            b.iter_batched(
+                || std::fs::write(&path, serde_json::to_vec(&individual).unwrap()).unwrap(),
+                |()| {
+                    let file = std::io::BufReader::new(std::fs::File::open(&path).unwrap());
+                    serde_json::from_reader::<_, serde_json::Value>(file).unwrap()
+                },
+                BatchSize::PerIteration,
+            )
+        });
+    }
+    group.finish();
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+criterion_group!(
+    benches,
+    message_codec,
+    controller_round_trip,
+    env_json,
+    individual_save_load
+);
+
+fn main() {
+    if std::env::var_os(CONTROLLER_VAR).is_some() {
+        ctrl::main_loop(Echo(Vec::new())).unwrap();
+        return;
+    }
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+}
@@ -0,0 +1,13 @@
+//! First lines of messages, decoded without allocating memory, see `ctrl::codec::decode()`.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use npc_maker::ctrl::codec;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(header) = codec::decode(data) {
+        // Every decoded message can be encoded again.
+        let mut line = String::new();
+        codec::encode(&header, &mut line).unwrap();
+    }
+});
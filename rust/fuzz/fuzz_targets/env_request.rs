@@ -0,0 +1,11 @@
+//! JSON messages which environments receive, see `env_api::poll()`.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use npc_maker::env_api::parse_request;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(line) = std::str::from_utf8(data) {
+        let _ = parse_request(line);
+    }
+});
@@ -0,0 +1,15 @@
+//! Messages which controllers receive from environments, see `ctrl::Message::read()` and `ctrl::MessageReader`.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use npc_maker::ctrl::{Message, MessageReader, ValueEncoding};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = Message::read(&mut &data[..]);
+    for encoding in [ValueEncoding::Text, ValueEncoding::F64, ValueEncoding::F32] {
+        let mut reader = MessageReader::new(data);
+        reader.set_value_encoding(encoding);
+        reader.set_binary_limit(1 << 16);
+        while reader.read().is_ok() {}
+    }
+});
@@ -0,0 +1,123 @@
+#ifndef NPC_MAKER_H
+#define NPC_MAKER_H
+
+/* This file is generated by cbindgen from src/capi.rs, do not edit it by hand. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+// The function succeeded.
+#define NPC_OK 0
+
+// The function failed, see [npc_last_error()].
+#define NPC_ERROR -1
+
+// A message from the environment, as seen by C programs.
+//
+// The pointers in this structure remain valid until the next call to [npc_poll()].
+typedef struct npc_message {
+  // The letter which identifies the type of message, for example 'N' for a new genotype.
+  char message_type;
+  // The gin, the random seed, the agent, or the identifier of a custom message.
+  uint64_t id;
+  // Custom messages without an identifier do not expect a response.
+  bool has_id;
+  // The time step of an advance message, in seconds.
+  double dt;
+  // The text of the message, nul terminated, or the bytes of a binary input.
+  // This is the genotype, the input value, the file path, or the body of a custom message.
+  const char *data;
+  // Number of bytes in the data, not including the nul terminator.
+  size_t len;
+  // Number of gins in the list, for the 'M' and 'W' messages.
+  size_t count;
+  // For 'M' messages, the gins of the inputs, see [npc_input_value()].
+  // For 'W' messages, the gins of the requested outputs.
+  const uint64_t *gins;
+} npc_message;
+
+// Wait for the next message from the environment and write it into the given structure.
+//
+// When the environment closes the input stream this returns a quit message ('Q').
+//
+// # Safety
+//
+// The argument must point to a writable message structure.
+int npc_poll(struct npc_message *message);
+
+// Get the value of one of the inputs of the most recent 'M' message.
+//
+// Returns a nul terminated string, or null if the index is out of bounds.
+// The gin of the input is `message.gins[index]`.
+const char *npc_input_value(size_t index);
+
+// Send an output value to the environment, in reply to an 'O' or 'W' message.
+//
+// # Safety
+//
+// The value must be a nul terminated string.
+int npc_send_output(uint64_t gin, const char *value);
+
+// Send the response to a custom message, which had an identifier.
+//
+// # Safety
+//
+// The body must be a nul terminated string.
+int npc_send_response(char message_type, uint64_t id, const char *body);
+
+// Publish one item of epigenetic data about the current individual.
+//
+// This is typically sent while saving, in reply to an 'S' message, so that
+// the environment records where to find the controller's save file.
+//
+// # Safety
+//
+// The key and value must be nul terminated strings.
+int npc_send_save(const char *key, const char *value);
+
+// Report an unexpected failure to the environment.
+//
+// # Safety
+//
+// The message must be a nul terminated string.
+int npc_send_error(const char *message);
+
+// Reply to a ping message ('H').
+int npc_send_pong(void);
+
+// Reply to a capabilities message ('C') with the optional messages which this controller supports.
+//
+// The names are separated by commas, for example "save,load,Z".
+// Custom message types are named by their type character.
+//
+// # Safety
+//
+// The names must be a nul terminated string.
+int npc_send_capabilities(const char *names);
+
+// Reply to a validate message ('V').
+//
+// The error is null or empty if the genotype is valid,
+// otherwise it describes what is wrong with the genotype.
+//
+// # Safety
+//
+// The error must be null or a nul terminated string.
+int npc_send_validate(const char *error);
+
+// Reply to a value encoding message ('F') with the encoding to use.
+//
+// [npc_poll()] only reads text values, so this accepts only the "text" encoding.
+//
+// # Safety
+//
+// The name must be a nul terminated string.
+int npc_send_encoding(const char *name);
+
+// Get a description of the most recent error, as a nul terminated string.
+//
+// The string remains valid until the next error occurs.
+const char *npc_last_error(void);
+
+#endif  /* NPC_MAKER_H */
@@ -0,0 +1,191 @@
+//! Command line program for testing controllers and environments against the NPC Maker protocols,
+//! see the [conformance] module.
+
+use npc_maker::conformance::{self, ControllerTarget, EnvironmentTarget, Report};
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage:
+    npc_conformance controller [OPTIONS] ENV_SPEC POPULATION -- COMMAND...
+    npc_conformance environment [OPTIONS] ENV_SPEC [-- CONTROLLER...]
+
+Test a controller or environment program against the NPC Maker protocols,
+and print a pass/fail report. Exits with status 1 if any check fails.
+
+Options:
+    --timeout SECONDS       Maximum time to wait for each reply (default 10)
+    --genotype JSON         Genotype for the controller (default {})
+    --json                  Print the report as JSON
+
+Controller options:
+    --inputs GIN,...        Input interfaces to send values to
+    --outputs GIN,...       Output interfaces to request values from
+    --binary GIN,...        Binary input interfaces to send bytes to
+
+Environment options:
+    --set NAME=VALUE        Environment setting, may be repeated
+    --evaluation SECONDS    Maximum time to evaluate one individual (default 60)
+
+The environment is given individuals with the CONTROLLER and the genotype.
+Without a controller, the environment is tested without any individuals.";
+
+struct Args {
+    kind: String,
+    positional: Vec<String>,
+    command: Vec<String>,
+    timeout: Option<f64>,
+    genotype: Option<String>,
+    json: bool,
+    inputs: Vec<u64>,
+    outputs: Vec<u64>,
+    binary: Vec<u64>,
+    settings: Vec<(String, String)>,
+    evaluation: Option<f64>,
+}
+
+fn parse_gins(list: &str) -> Result<Vec<u64>, String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|gin| !gin.is_empty())
+        .map(|gin| gin.parse().map_err(|_| format!("invalid GIN \"{gin}\"")))
+        .collect()
+}
+
+fn parse_seconds(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(seconds) if seconds > 0.0 => Ok(seconds),
+        _ => Err(format!("invalid number of seconds \"{value}\"")),
+    }
+}
+
+fn parse_args(mut argv: impl Iterator<Item = String>) -> Result<Args, String> {
+    let kind = argv.next().ok_or("missing command")?;
+    if kind != "controller" && kind != "environment" {
+        return Err(format!("unrecognized command \"{kind}\""));
+    }
+    let mut args = Args {
+        kind,
+        positional: vec![],
+        command: vec![],
+        timeout: None,
+        genotype: None,
+        json: false,
+        inputs: vec![],
+        outputs: vec![],
+        binary: vec![],
+        settings: vec![],
+        evaluation: None,
+    };
+    while let Some(arg) = argv.next() {
+        let mut value = || argv.next().ok_or(format!("missing value for {arg}"));
+        match arg.as_str() {
+            "--" => {
+                args.command = argv.collect();
+                break;
+            }
+            "--timeout" => args.timeout = Some(parse_seconds(&value()?)?),
+            "--genotype" => args.genotype = Some(value()?),
+            "--json" => args.json = true,
+            "--inputs" => args.inputs = parse_gins(&value()?)?,
+            "--outputs" => args.outputs = parse_gins(&value()?)?,
+            "--binary" => args.binary = parse_gins(&value()?)?,
+            "--evaluation" => args.evaluation = Some(parse_seconds(&value()?)?),
+            "--set" => {
+                let setting = value()?;
+                let (name, value) = setting
+                    .split_once('=')
+                    .ok_or(format!("expected NAME=VALUE, got \"{setting}\""))?;
+                args.settings.push((name.to_string(), value.to_string()));
+            }
+            _ if arg.starts_with("--") => return Err(format!("unrecognized option \"{arg}\"")),
+            _ => args.positional.push(arg),
+        }
+    }
+    Ok(args)
+}
+
+fn run(args: Args) -> Result<Report, String> {
+    if args.kind == "controller" {
+        let [environment, population] = args.positional.as_slice() else {
+            return Err("expected the ENV_SPEC and the POPULATION".to_string());
+        };
+        if args.command.is_empty() {
+            return Err("missing the controller's COMMAND, after \"--\"".to_string());
+        }
+        let mut target = ControllerTarget {
+            environment: environment.into(),
+            population: population.clone(),
+            command: args.command,
+            input_gins: args.inputs,
+            output_gins: args.outputs,
+            binary_gins: args.binary,
+            ..Default::default()
+        };
+        if let Some(genotype) = args.genotype {
+            // Genotypes are sent on a single line, so compact any JSON which spans several lines.
+            target.genotype = match serde_json::from_str::<serde_json::Value>(&genotype) {
+                Ok(json) => json.to_string(),
+                Err(_) if genotype.contains('\n') => return Err("the genotype must be a single line".to_string()),
+                Err(_) => genotype,
+            };
+        }
+        if let Some(timeout) = args.timeout {
+            target.timeout = timeout;
+        }
+        Ok(conformance::check_controller(&target))
+    } else {
+        let [spec] = args.positional.as_slice() else {
+            return Err("expected the ENV_SPEC".to_string());
+        };
+        let mut target = EnvironmentTarget {
+            spec: spec.into(),
+            settings: args.settings,
+            controller: args.command,
+            ..Default::default()
+        };
+        if let Some(genotype) = args.genotype {
+            target.genome = serde_json::from_str(&genotype).map_err(|error| format!("invalid genotype: {error}"))?;
+        }
+        if let Some(timeout) = args.timeout {
+            target.timeout = timeout;
+        }
+        if let Some(evaluation) = args.evaluation {
+            target.evaluation_time = evaluation;
+        }
+        Ok(conformance::check_environment(&target))
+    }
+}
+
+fn main() -> ExitCode {
+    let argv: Vec<String> = std::env::args().skip(1).collect();
+    let mut options = argv.iter().take_while(|arg| *arg != "--");
+    if argv.is_empty() || options.any(|arg| arg == "-h" || arg == "--help") {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+    let args = match parse_args(argv.into_iter()) {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("npc_conformance: {error}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    let json = args.json;
+    let report = match run(args) {
+        Ok(report) => report,
+        Err(error) => {
+            eprintln!("npc_conformance: {error}");
+            return ExitCode::from(2);
+        }
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    } else {
+        print!("{report}");
+    }
+    if report.passed() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
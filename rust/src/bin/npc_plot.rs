@@ -0,0 +1,83 @@
+//! Command line program for plotting the scores from evolution statistics logs,
+//! see the [plot] module.
+
+use npc_maker::plot::{self, ScoreCurve};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage:
+    npc_plot [OPTIONS] STATISTICS... --output IMAGE
+
+Plot the best and mean score of each generation, from the statistics logs of
+one or more populations. The statistics logs are named \"statistics.ndjson\"
+and they are in each population's directory. Directories are also accepted.
+
+Options:
+    --output IMAGE      Image file to write, either \".svg\" or \".png\"
+    --title TITLE       Title of the chart (default \"Scores\")";
+
+struct Args {
+    statistics: Vec<PathBuf>,
+    output: PathBuf,
+    title: String,
+}
+
+fn parse_args(mut argv: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut statistics = vec![];
+    let mut output = None;
+    let mut title = "Scores".to_string();
+    while let Some(arg) = argv.next() {
+        let mut value = || argv.next().ok_or(format!("missing value for {arg}"));
+        match arg.as_str() {
+            "--output" | "-o" => output = Some(PathBuf::from(value()?)),
+            "--title" => title = value()?,
+            _ if arg.starts_with("--") => return Err(format!("unrecognized option \"{arg}\"")),
+            _ => {
+                let path = PathBuf::from(&arg);
+                if path.is_dir() {
+                    statistics.push(path.join("statistics.ndjson"));
+                } else {
+                    statistics.push(path);
+                }
+            }
+        }
+    }
+    if statistics.is_empty() {
+        return Err("missing the STATISTICS logs".to_string());
+    }
+    let output = output.ok_or("missing the --output IMAGE")?;
+    Ok(Args {
+        statistics,
+        output,
+        title,
+    })
+}
+
+fn main() -> ExitCode {
+    let argv: Vec<String> = std::env::args().skip(1).collect();
+    if argv.is_empty() || argv.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+    let args = match parse_args(argv.into_iter()) {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("npc_plot: {error}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    let result = args
+        .statistics
+        .iter()
+        .map(ScoreCurve::load)
+        .collect::<Result<Vec<_>, _>>()
+        .and_then(|curves| plot::plot_scores(&curves, &args.output, &args.title));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("npc_plot: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
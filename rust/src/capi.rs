@@ -0,0 +1,474 @@
+//! C interface, for implementing controllers in C and C++.
+//!
+//! These functions wrap [ctrl::poll()](crate::ctrl::poll) and the functions
+//! for replying to the environment, so that C programs can use the same
+//! message codec as rust programs instead of parsing stdin by hand.
+//! The header file for these functions is "include/npc_maker.h".
+//!
+//! Example:
+//! ```c
+//! npc_message msg;
+//! while (npc_poll(&msg) == NPC_OK && msg.message_type != 'Q') {
+//!     switch (msg.message_type) {
+//!         case 'N': load_genome(msg.data, msg.len); break;
+//!         case 'X': advance(msg.dt); break;
+//!         case 'I': set_input(msg.id, msg.data); break;
+//!         case 'O': npc_send_output(msg.id, get_output(msg.id)); break;
+//!         case 'S': save(msg.data); break;
+//!         case 'H': npc_send_pong(); break;
+//!     }
+//! }
+//! ```
+
+use crate::ctrl::{self, Capability, Message, ValueEncoding};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::io::{self, Write};
+use std::ptr;
+
+/// The function succeeded.
+pub const NPC_OK: c_int = 0;
+/// The function failed, see [npc_last_error()].
+pub const NPC_ERROR: c_int = -1;
+
+/// A message from the environment, as seen by C programs.
+///
+/// The pointers in this structure remain valid until the next call to [npc_poll()].
+#[repr(C)]
+#[derive(Debug)]
+pub struct NpcMessage {
+    /// The letter which identifies the type of message, for example 'N' for a new genotype.
+    pub message_type: c_char,
+    /// The gin, the random seed, the agent, or the identifier of a custom message.
+    pub id: u64,
+    /// Custom messages without an identifier do not expect a response.
+    pub has_id: bool,
+    /// The time step of an advance message, in seconds.
+    pub dt: f64,
+    /// The text of the message, nul terminated, or the bytes of a binary input.
+    /// This is the genotype, the input value, the file path, or the body of a custom message.
+    pub data: *const c_char,
+    /// Number of bytes in the data, not including the nul terminator.
+    pub len: usize,
+    /// Number of gins in the list, for the 'M' and 'W' messages.
+    pub count: usize,
+    /// For 'M' messages, the gins of the inputs, see [npc_input_value()].
+    /// For 'W' messages, the gins of the requested outputs.
+    pub gins: *const u64,
+}
+
+/// Owns the data which the most recent [NpcMessage] points to.
+#[derive(Default)]
+struct Current {
+    data: CString,
+    bytes: Vec<u8>,
+    gins: Vec<u64>,
+    values: Vec<CString>,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Current> = RefCell::default();
+    static LAST_ERROR: RefCell<CString> = RefCell::default();
+}
+
+/// Convert text into a C string, truncating it at the first nul character.
+fn c_string(text: impl Into<Vec<u8>>) -> CString {
+    let mut text = text.into();
+    if let Some(end) = text.iter().position(|&byte| byte == 0) {
+        text.truncate(end);
+    }
+    CString::new(text).unwrap()
+}
+
+fn path_string(path: &std::path::Path) -> CString {
+    c_string(path.to_string_lossy().into_owned())
+}
+
+fn set_error(error: impl ToString) -> c_int {
+    LAST_ERROR.with(|last| *last.borrow_mut() = c_string(error.to_string()));
+    NPC_ERROR
+}
+
+/// Convert a message into its C representation.
+fn convert(message: Message) -> (Current, NpcMessage) {
+    let mut current = Current::default();
+    let mut id = 0;
+    let mut has_id = false;
+    let mut dt = 0.0;
+    let message_type = match message {
+        Message::Environment { environment } => {
+            current.data = path_string(&environment);
+            'E'
+        }
+        Message::Population { population } => {
+            current.data = c_string(population);
+            'P'
+        }
+        Message::ValueEncoding { encoding } => {
+            current.data = c_string(encoding);
+            'F'
+        }
+        Message::Capabilities => 'C',
+        Message::New { genotype } => {
+            current.data = c_string(genotype);
+            'N'
+        }
+        Message::Swap { genotype } => {
+            current.data = c_string(genotype);
+            'K'
+        }
+        Message::Validate { genotype } => {
+            current.data = c_string(genotype);
+            'V'
+        }
+        Message::Seed { seed } => {
+            id = seed;
+            'G'
+        }
+        Message::Select { agent } => {
+            id = agent;
+            'U'
+        }
+        Message::Ping => 'H',
+        Message::Reset => 'R',
+        Message::Advance { dt: time_step } => {
+            dt = time_step;
+            'X'
+        }
+        Message::SetInput { gin, value } => {
+            id = gin;
+            current.data = c_string(value);
+            'I'
+        }
+        Message::SetInputs { inputs } => {
+            for (gin, value) in inputs {
+                current.gins.push(gin);
+                current.values.push(c_string(value));
+            }
+            'M'
+        }
+        Message::SetBinary { gin, bytes } => {
+            id = gin;
+            current.bytes = bytes;
+            'B'
+        }
+        Message::GetOutput { gin } => {
+            id = gin;
+            'O'
+        }
+        Message::GetOutputs { gins } => {
+            current.gins = gins;
+            'W'
+        }
+        Message::Save { path } => {
+            current.data = path_string(&path);
+            'S'
+        }
+        Message::Load { path } => {
+            current.data = path_string(&path);
+            'L'
+        }
+        Message::Quit => 'Q',
+        Message::Custom {
+            message_type,
+            id: message_id,
+            body,
+        } => {
+            has_id = message_id.is_some();
+            id = message_id.unwrap_or(0);
+            current.data = c_string(body);
+            message_type
+        }
+    };
+    let (data, len) = if message_type == 'B' {
+        (current.bytes.as_ptr().cast(), current.bytes.len())
+    } else {
+        (current.data.as_ptr(), current.data.as_bytes().len())
+    };
+    let gins = if current.gins.is_empty() {
+        ptr::null()
+    } else {
+        current.gins.as_ptr()
+    };
+    let message = NpcMessage {
+        message_type: message_type as c_char,
+        id,
+        has_id,
+        dt,
+        data,
+        len,
+        count: current.gins.len(),
+        gins,
+    };
+    (current, message)
+}
+
+/// Convert a C string argument into a rust string.
+///
+/// # Safety
+///
+/// The pointer must be null or point to a nul terminated string.
+unsafe fn read_str<'a>(text: *const c_char) -> Result<&'a str, c_int> {
+    if text.is_null() {
+        return Err(set_error("null string"));
+    }
+    CStr::from_ptr(text).to_str().map_err(set_error)
+}
+
+fn status(result: Result<(), io::Error>) -> c_int {
+    match result {
+        Ok(()) => NPC_OK,
+        Err(error) => set_error(error),
+    }
+}
+
+/// Wait for the next message from the environment and write it into the given structure.
+///
+/// When the environment closes the input stream this returns a quit message ('Q').
+///
+/// # Safety
+///
+/// The argument must point to a writable message structure.
+#[no_mangle]
+pub unsafe extern "C" fn npc_poll(message: *mut NpcMessage) -> c_int {
+    if message.is_null() {
+        return set_error("null message");
+    }
+    let next = match ctrl::poll() {
+        Ok(next) => next,
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => Message::Quit,
+        Err(error) => return set_error(error),
+    };
+    let (current, next) = convert(next);
+    CURRENT.with(|cell| *cell.borrow_mut() = current);
+    message.write(next);
+    NPC_OK
+}
+
+/// Get the value of one of the inputs of the most recent 'M' message.
+///
+/// Returns a nul terminated string, or null if the index is out of bounds.
+/// The gin of the input is `message.gins[index]`.
+#[no_mangle]
+pub extern "C" fn npc_input_value(index: usize) -> *const c_char {
+    CURRENT.with(|cell| match cell.borrow().values.get(index) {
+        Some(value) => value.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// Send an output value to the environment, in reply to an 'O' or 'W' message.
+///
+/// # Safety
+///
+/// The value must be a nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn npc_send_output(gin: u64, value: *const c_char) -> c_int {
+    match read_str(value) {
+        Ok(value) => status(ctrl::send_output(gin, value.replace('\n', " "))),
+        Err(code) => code,
+    }
+}
+
+/// Send the response to a custom message, which had an identifier.
+///
+/// # Safety
+///
+/// The body must be a nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn npc_send_response(message_type: c_char, id: u64, body: *const c_char) -> c_int {
+    match read_str(body) {
+        Ok(body) => status(ctrl::send_response(
+            message_type as u8 as char,
+            id,
+            &body.replace('\n', " "),
+        )),
+        Err(code) => code,
+    }
+}
+
+/// Publish one item of epigenetic data about the current individual.
+///
+/// This is typically sent while saving, in reply to an 'S' message, so that
+/// the environment records where to find the controller's save file.
+///
+/// # Safety
+///
+/// The key and value must be nul terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn npc_send_save(key: *const c_char, value: *const c_char) -> c_int {
+    let (key, value) = match (read_str(key), read_str(value)) {
+        (Ok(key), Ok(value)) => (key, value),
+        (Err(code), _) | (_, Err(code)) => return code,
+    };
+    status(ctrl::send_epigenome(&HashMap::from([(
+        key.to_string(),
+        value.to_string(),
+    )])))
+}
+
+/// Report an unexpected failure to the environment.
+///
+/// # Safety
+///
+/// The message must be a nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn npc_send_error(message: *const c_char) -> c_int {
+    match read_str(message) {
+        Ok(message) => status(ctrl::send_error(message)),
+        Err(code) => code,
+    }
+}
+
+/// Format a reply to one of the standard messages, which carry no identifier.
+fn reply(message_type: char, body: &str) -> String {
+    format!("{message_type}:{}", body.replace('\n', " "))
+}
+
+/// Check a comma separated list of capability names.
+fn capabilities_reply(names: &str) -> Result<String, String> {
+    let mut capabilities = Vec::new();
+    for name in names.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        match Capability::from_name(name) {
+            Some(capability) => capabilities.push(capability.name()),
+            None => return Err(format!("unknown capability {name:?}")),
+        }
+    }
+    Ok(reply('C', &capabilities.join(",")))
+}
+
+fn encoding_reply(name: &str) -> Result<String, String> {
+    match ValueEncoding::from_name(name) {
+        Some(ValueEncoding::Text) => Ok(reply('F', name)),
+        Some(_) => Err(format!("npc_poll() only reads text values, not {name}")),
+        None => Err(format!("unknown value encoding {name:?}")),
+    }
+}
+
+fn send_line(line: Result<String, String>) -> c_int {
+    match line {
+        Ok(line) => {
+            let mut stdout = io::stdout().lock();
+            status(writeln!(stdout, "{line}").and_then(|()| stdout.flush()))
+        }
+        Err(error) => set_error(error),
+    }
+}
+
+/// Reply to a ping message ('H').
+#[no_mangle]
+pub extern "C" fn npc_send_pong() -> c_int {
+    send_line(Ok(reply('H', "")))
+}
+
+/// Reply to a capabilities message ('C') with the optional messages which this controller supports.
+///
+/// The names are separated by commas, for example "save,load,Z".
+/// Custom message types are named by their type character.
+///
+/// # Safety
+///
+/// The names must be a nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn npc_send_capabilities(names: *const c_char) -> c_int {
+    match read_str(names) {
+        Ok(names) => send_line(capabilities_reply(names)),
+        Err(code) => code,
+    }
+}
+
+/// Reply to a validate message ('V').
+///
+/// The error is null or empty if the genotype is valid,
+/// otherwise it describes what is wrong with the genotype.
+///
+/// # Safety
+///
+/// The error must be null or a nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn npc_send_validate(error: *const c_char) -> c_int {
+    if error.is_null() {
+        return send_line(Ok(reply('V', "")));
+    }
+    match read_str(error) {
+        Ok(error) => send_line(Ok(reply('V', error))),
+        Err(code) => code,
+    }
+}
+
+/// Reply to a value encoding message ('F') with the encoding to use.
+///
+/// [npc_poll()] only reads text values, so this accepts only the "text" encoding.
+///
+/// # Safety
+///
+/// The name must be a nul terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn npc_send_encoding(name: *const c_char) -> c_int {
+    match read_str(name) {
+        Ok(name) => send_line(encoding_reply(name)),
+        Err(code) => code,
+    }
+}
+
+/// Get a description of the most recent error, as a nul terminated string.
+///
+/// The string remains valid until the next error occurs.
+#[no_mangle]
+pub extern "C" fn npc_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_messages() {
+        let (current, message) = convert(Message::SetInput {
+            gin: 7,
+            value: "1.5".to_string(),
+        });
+        assert_eq!(message.message_type as u8, b'I');
+        assert_eq!(message.id, 7);
+        assert_eq!(unsafe { CStr::from_ptr(message.data) }.to_str(), Ok("1.5"));
+        assert_eq!(message.len, 3);
+        drop(current);
+
+        let (current, message) = convert(Message::SetInputs {
+            inputs: vec![(3, "a".to_string()), (4, "b".to_string())],
+        });
+        assert_eq!(message.count, 2);
+        assert_eq!(unsafe { *message.gins.add(1) }, 4);
+        assert_eq!(current.values[1].to_str(), Ok("b"));
+
+        let (_current, message) = convert(Message::SetBinary {
+            gin: 2,
+            bytes: vec![0, 1, 2],
+        });
+        assert_eq!(message.len, 3);
+        assert_eq!(unsafe { *message.data.add(2) }, 2);
+
+        let (_current, message) = convert(Message::Custom {
+            message_type: 'Z',
+            id: None,
+            body: "hi".to_string(),
+        });
+        assert_eq!(message.message_type as u8, b'Z');
+        assert!(!message.has_id);
+        assert_eq!(message.gins, ptr::null());
+    }
+
+    #[test]
+    fn format_replies() {
+        assert_eq!(reply('H', ""), "H:");
+        assert_eq!(reply('V', "bad\ngenome"), "V:bad genome");
+        assert_eq!(capabilities_reply("save, load,Z,"), Ok("C:save,load,Z".to_string()));
+        assert_eq!(capabilities_reply(""), Ok("C:".to_string()));
+        assert!(capabilities_reply("save,fly").is_err());
+        assert_eq!(encoding_reply("text"), Ok("F:text".to_string()));
+        assert!(encoding_reply("f64").is_err());
+        assert!(encoding_reply("utf-16").is_err());
+    }
+}
@@ -0,0 +1,838 @@
+//! Protocol conformance tests, for certifying controller and environment programs.
+//!
+//! These tests run a third-party program through every message of its protocol,
+//! including edge cases such as extreme values and empty payloads, and check
+//! that it replies correctly and in time. The results are collected into a [Report].
+//!
+//! ```no_run
+//! # use npc_maker::conformance;
+//! let target = conformance::ControllerTarget {
+//!     environment: "env.env".into(),
+//!     population: "pop".to_string(),
+//!     command: vec!["python".to_string(), "ctrl.py".to_string()],
+//!     input_gins: vec![0, 1],
+//!     output_gins: vec![2],
+//!     ..Default::default()
+//! };
+//! let report = conformance::check_controller(&target);
+//! print!("{report}");
+//! assert!(report.passed());
+//! ```
+//!
+//! The `npc_conformance` program runs these tests from the command line.
+
+use crate::ctrl::{Capability, Controller, ControllerOptions, ValueEncoding};
+use crate::env_spec::EnvironmentSpec;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// Result of a single check.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Pass,
+    /// The program did not conform, with a description of the problem.
+    Fail(String),
+    /// The check was not run, with the reason why not.
+    Skip(String),
+}
+
+/// One step of a conformance test.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Check {
+    pub name: String,
+    pub outcome: Outcome,
+    /// Time taken to run the check, in seconds.
+    pub elapsed: f64,
+}
+
+/// Pass/fail report of a conformance test.
+///
+/// Display the report for a human readable summary, or serialize it to JSON.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    /// Command line invocation of the program under test.
+    pub program: Vec<String>,
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    fn new(program: Vec<String>) -> Self {
+        Self {
+            program,
+            checks: vec![],
+        }
+    }
+
+    /// Did the program pass all of the checks which were run?
+    pub fn passed(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// Iterate over the checks which failed.
+    pub fn failures(&self) -> impl Iterator<Item = &Check> {
+        self.checks
+            .iter()
+            .filter(|check| matches!(check.outcome, Outcome::Fail(_)))
+    }
+
+    /// Run a check and record its outcome. Returns true if it passed.
+    fn run(&mut self, name: &str, check: impl FnOnce() -> Result<(), String>) -> bool {
+        let start = Instant::now();
+        let outcome = match check() {
+            Ok(()) => Outcome::Pass,
+            Err(problem) => Outcome::Fail(problem),
+        };
+        let passed = outcome == Outcome::Pass;
+        self.checks.push(Check {
+            name: name.to_string(),
+            outcome,
+            elapsed: start.elapsed().as_secs_f64(),
+        });
+        passed
+    }
+
+    fn skip(&mut self, name: &str, reason: &str) {
+        self.checks.push(Check {
+            name: name.to_string(),
+            outcome: Outcome::Skip(reason.to_string()),
+            elapsed: 0.0,
+        });
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "conformance test of: {}", self.program.join(" "))?;
+        let (mut passed, mut failed, mut skipped) = (0, 0, 0);
+        for check in &self.checks {
+            match &check.outcome {
+                Outcome::Pass => {
+                    passed += 1;
+                    writeln!(f, "PASS  {} ({:.3}s)", check.name, check.elapsed)?;
+                }
+                Outcome::Fail(problem) => {
+                    failed += 1;
+                    writeln!(f, "FAIL  {}: {problem}", check.name)?;
+                }
+                Outcome::Skip(reason) => {
+                    skipped += 1;
+                    writeln!(f, "SKIP  {}: {reason}", check.name)?;
+                }
+            }
+        }
+        writeln!(f, "{passed} passed, {failed} failed, {skipped} skipped")
+    }
+}
+
+/// Description of a controller program to test, see [check_controller()].
+#[derive(Debug, Clone)]
+pub struct ControllerTarget {
+    /// File path of the environment specification which the controller is given.
+    pub environment: PathBuf,
+
+    /// Name of the population which the controller is given.
+    pub population: String,
+
+    /// Command line invocation of the controller program.
+    pub command: Vec<String>,
+
+    /// A valid genotype for the controller, as a single line of JSON.
+    pub genotype: String,
+
+    /// Interfaces to send input values to.
+    pub input_gins: Vec<u64>,
+
+    /// Interfaces to request output values from.
+    pub output_gins: Vec<u64>,
+
+    /// Interfaces to send binary inputs to, if the controller supports them.
+    pub binary_gins: Vec<u64>,
+
+    /// Maximum number of seconds to wait for each reply.
+    pub timeout: f64,
+
+    /// Additional settings for the controller program.
+    pub options: ControllerOptions,
+}
+
+impl Default for ControllerTarget {
+    fn default() -> Self {
+        Self {
+            environment: PathBuf::new(),
+            population: String::new(),
+            command: vec![],
+            genotype: "{}".to_string(),
+            input_gins: vec![],
+            output_gins: vec![],
+            binary_gins: vec![],
+            timeout: 10.0,
+            options: ControllerOptions::default(),
+        }
+    }
+}
+
+impl ControllerTarget {
+    fn start(&self, options: ControllerOptions) -> Result<Controller, String> {
+        if self.command.is_empty() {
+            return Err("missing controller command".to_string());
+        }
+        let options = options.timeout(self.timeout);
+        Controller::with_options(&self.environment, &self.population, &self.command, &options)
+            .map_err(|error| error.to_string())
+    }
+}
+
+/// Describe an error from a controller, including why it stopped if it is dead.
+fn describe(ctrl: &mut Controller, error: io::Error) -> String {
+    let status = ctrl.status();
+    let error = error.to_string();
+    if status.alive || error.contains(&status.to_string()) {
+        error
+    } else {
+        format!("{error} (controller {status})")
+    }
+}
+
+/// Numeric values which are at the edges of what the text encoding can represent.
+const EDGE_VALUES: [&str; 6] = ["0", "-0.0", "1e308", "-1e-308", "0.1234567890123456789", "-7"];
+
+/// Run a controller program through the controller protocol.
+pub fn check_controller(target: &ControllerTarget) -> Report {
+    let mut report = Report::new(target.command.clone());
+    let mut started = None;
+    report.run("start", || {
+        started = Some(target.start(target.options.clone())?);
+        Ok(())
+    });
+    let Some(mut ctrl) = started else {
+        return report;
+    };
+    let ctrl = &mut ctrl;
+
+    let mut capabilities = None;
+    report.run("capabilities", || {
+        let mut query = target.start(target.options.clone().query_capabilities())?;
+        capabilities = query.capabilities().cloned();
+        let _ = query.quit();
+        Ok(())
+    });
+    let supports = |capability| {
+        capabilities
+            .as_ref()
+            .is_some_and(|capabilities| capabilities.contains(&capability))
+    };
+
+    for encoding in [ValueEncoding::F64, ValueEncoding::F32] {
+        report.run(&format!("value encoding {}", encoding.name()), || {
+            let mut binary = target.start(target.options.clone().value_encoding(encoding))?;
+            let result = (|| {
+                if binary.value_encoding() != ValueEncoding::Text && binary.value_encoding() != encoding {
+                    return Err(io::Error::other("accepted a different encoding than was asked for"));
+                }
+                binary.new_genotype(&target.genotype)?;
+                for gin in &target.input_gins {
+                    binary.set_input_f64(*gin, 0.5)?;
+                }
+                binary.get_outputs(&target.output_gins)?;
+                binary.ping()?;
+                Ok(())
+            })();
+            let result = result.map_err(|error| describe(&mut binary, error));
+            let _ = binary.quit();
+            result
+        });
+    }
+
+    step(&mut report, ctrl, "ping", |_| Ok(()));
+    step(&mut report, ctrl, "seed", |ctrl| ctrl.seed(u64::MAX));
+    step(&mut report, ctrl, "new genotype", |ctrl| {
+        ctrl.new_genotype(&target.genotype)
+    });
+    step(&mut report, ctrl, "comment", |ctrl| {
+        ctrl.comment("conformance test: \u{2603}:H")
+    });
+    step(&mut report, ctrl, "reset", |ctrl| ctrl.reset());
+    step(&mut report, ctrl, "advance", |ctrl| {
+        ctrl.advance(0.01)?;
+        ctrl.advance(0.0)?;
+        ctrl.advance(1e6)
+    });
+
+    if target.input_gins.is_empty() {
+        report.skip("set input", "no input interfaces were given");
+        report.skip("set multiple inputs", "no input interfaces were given");
+    } else {
+        step(&mut report, ctrl, "set input", |ctrl| {
+            for gin in &target.input_gins {
+                for value in EDGE_VALUES {
+                    ctrl.set_input(*gin, value)?;
+                }
+            }
+            Ok(())
+        });
+        step(&mut report, ctrl, "set multiple inputs", |ctrl| {
+            let inputs: Vec<(u64, &str)> = target.input_gins.iter().map(|gin| (*gin, "0.5")).collect();
+            ctrl.set_inputs(&inputs)?;
+            ctrl.set_inputs(&[])
+        });
+    }
+
+    if target.binary_gins.is_empty() {
+        report.skip("set binary input", "no binary interfaces were given");
+    } else if !supports(Capability::SetBinary) {
+        report.skip("set binary input", "the controller does not support binary inputs");
+    } else {
+        step(&mut report, ctrl, "set binary input", |ctrl| {
+            for gin in &target.binary_gins {
+                ctrl.set_binary(*gin, b"")?;
+                ctrl.set_binary(*gin, b"\n\0\xff:\r\nH\n")?;
+                ctrl.set_binary(*gin, &vec![b'\n'; 1 << 20])?;
+            }
+            Ok(())
+        });
+    }
+
+    if target.output_gins.is_empty() {
+        report.skip("get output", "no output interfaces were given");
+        report.skip("get multiple outputs", "no output interfaces were given");
+    } else {
+        report.run("get output", || {
+            for gin in &target.output_gins {
+                ctrl.get_outputs(&[*gin]).map_err(|error| describe(ctrl, error))?;
+            }
+            Ok(())
+        });
+        report.run("get multiple outputs", || {
+            ctrl.enable_vectorized_outputs();
+            let outputs = ctrl
+                .get_outputs(&target.output_gins)
+                .map_err(|error| describe(ctrl, error))?;
+            match target.output_gins.iter().find(|gin| !outputs.contains_key(gin)) {
+                Some(gin) => Err(format!("missing output {gin}")),
+                None => Ok(()),
+            }
+        });
+    }
+
+    if supports(Capability::Validate) {
+        report.run("validate genotype", || {
+            match ctrl.validate_genome(&target.genotype) {
+                Ok(Ok(())) => {}
+                Ok(Err(problem)) => return Err(format!("rejected the genotype: {problem}")),
+                Err(error) => return Err(describe(ctrl, error)),
+            }
+            // Any answer will do, so long as there is one.
+            ctrl.validate_genome("[]")
+                .map(drop)
+                .map_err(|error| describe(ctrl, error))
+        });
+    } else {
+        report.skip("validate genotype", "the controller does not support validation");
+    }
+
+    let directory = temp_dir();
+    let save_path = directory.join("controller.save");
+    if supports(Capability::Save) {
+        report.run("save", || {
+            std::fs::create_dir_all(&directory).map_err(|error| error.to_string())?;
+            ctrl.save(&save_path)
+                .and_then(|()| ctrl.ping().map(drop))
+                .map_err(|error| describe(ctrl, error))?;
+            if !save_path.exists() {
+                return Err(format!("did not write the save file {save_path:?}"));
+            }
+            Ok(())
+        });
+    } else {
+        report.skip("save", "the controller does not support saving");
+    }
+    if !supports(Capability::Load) {
+        report.skip("load", "the controller does not support loading");
+    } else if !save_path.exists() {
+        report.skip("load", "there is no save file to load");
+    } else {
+        step(&mut report, ctrl, "load", |ctrl| ctrl.load(&save_path));
+    }
+    let _ = std::fs::remove_dir_all(&directory);
+
+    report.run("quit", || {
+        ctrl.quit().map_err(|error| describe(ctrl, error))?;
+        let deadline = Instant::now() + Duration::from_secs_f64(target.timeout);
+        while ctrl.is_alive() {
+            if Instant::now() > deadline {
+                return Err(format!("did not exit within {} seconds", target.timeout));
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        Ok(())
+    });
+    report
+}
+
+/// Send messages to the controller and then ping it, to check that it is still responding.
+fn step(
+    report: &mut Report,
+    ctrl: &mut Controller,
+    name: &str,
+    send: impl FnOnce(&mut Controller) -> io::Result<()>,
+) -> bool {
+    report.run(name, || {
+        send(ctrl)
+            .and_then(|()| ctrl.ping().map(drop))
+            .map_err(|error| describe(ctrl, error))
+    })
+}
+
+/// A unique scratch directory for save files.
+fn temp_dir() -> PathBuf {
+    static COUNTER: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+    let count = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    std::env::temp_dir().join(format!("npc_maker_conformance_{}_{count}", std::process::id()))
+}
+
+/// Description of an environment program to test, see [check_environment()].
+#[derive(Debug, Clone)]
+pub struct EnvironmentTarget {
+    /// File path of the environment specification.
+    pub spec: PathBuf,
+
+    /// Settings for the environment, as name-value pairs.
+    pub settings: Vec<(String, String)>,
+
+    /// Command line invocation of the controller for the individuals which
+    /// are given to the environment. If empty then no individuals are given.
+    pub controller: Vec<String>,
+
+    /// Genome of the individuals which are given to the environment.
+    pub genome: Value,
+
+    /// Maximum number of seconds to wait for each reply.
+    pub timeout: f64,
+
+    /// Maximum number of seconds to wait for an individual to be evaluated,
+    /// and for the environment to stop.
+    pub evaluation_time: f64,
+}
+
+impl Default for EnvironmentTarget {
+    fn default() -> Self {
+        Self {
+            spec: PathBuf::new(),
+            settings: vec![],
+            controller: vec![],
+            genome: json!({}),
+            timeout: 10.0,
+            evaluation_time: 60.0,
+        }
+    }
+}
+
+/// Messages which the management program sends, and which the environment acknowledges.
+const CONTROL_MESSAGES: [&str; 6] = ["Start", "Stop", "Pause", "Resume", "Heartbeat", "Quit"];
+
+/// An environment program and the bookkeeping for its individuals.
+struct Session {
+    env_spec: EnvironmentSpec,
+    target: EnvironmentTarget,
+    child: Child,
+    stdin: ChildStdin,
+    lines: mpsc::Receiver<String>,
+    /// Names and populations of the individuals which are alive in the environment.
+    alive: HashMap<String, String>,
+    births: u64,
+    deaths: u64,
+    /// Give new individuals to the environment when it asks for them.
+    give_births: bool,
+    violations: Vec<String>,
+}
+
+impl Session {
+    fn start(env_spec: EnvironmentSpec, target: &EnvironmentTarget) -> Result<Self, String> {
+        // A bare file name would be searched for on the PATH instead of in the spec's directory.
+        let directory = env_spec.spec.parent().filter(|dir| !dir.as_os_str().is_empty());
+        let program = directory.unwrap_or(Path::new(".")).join(&env_spec.path);
+        let mut command = Command::new(&program);
+        command.arg(&env_spec.spec).arg("headless");
+        for (name, value) in &target.settings {
+            command.arg(name).arg(value);
+        }
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit());
+        let mut child = command
+            .spawn()
+            .map_err(|error| format!("could not run {program:?}: {error}"))?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = io::BufReader::new(child.stdout.take().unwrap());
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in stdout.lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self {
+            env_spec,
+            target: target.clone(),
+            child,
+            stdin,
+            lines,
+            alive: HashMap::new(),
+            births: 0,
+            deaths: 0,
+            give_births: true,
+            violations: vec![],
+        })
+    }
+
+    fn send(&mut self, message: &Value) -> Result<(), String> {
+        self.send_line(&message.to_string())
+    }
+
+    fn send_line(&mut self, line: &str) -> Result<(), String> {
+        writeln!(self.stdin, "{line}")
+            .and_then(|()| self.stdin.flush())
+            .map_err(|error| format!("could not send message: {error}"))
+    }
+
+    /// Wait for a message which matches the predicate, while handling all other messages.
+    fn expect(&mut self, timeout: f64, mut predicate: impl FnMut(&Value) -> bool) -> Result<Value, String> {
+        let deadline = Instant::now() + Duration::from_secs_f64(timeout);
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let line = match self.lines.recv_timeout(remaining) {
+                Ok(line) => line,
+                Err(mpsc::RecvTimeoutError::Timeout) => return Err(format!("no reply within {timeout} seconds")),
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    let status = match self.child.wait() {
+                        Ok(status) => status.to_string(),
+                        Err(error) => error.to_string(),
+                    };
+                    return Err(format!("environment closed its stdout ({status})"));
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let message = match serde_json::from_str::<Value>(&line) {
+                Ok(message) => message,
+                Err(error) => {
+                    self.violations.push(format!("malformed JSON {line:?}: {error}"));
+                    continue;
+                }
+            };
+            if let Err(problem) = self.handle(&message) {
+                self.violations.push(format!("{problem}, in message {line}"));
+            }
+            if predicate(&message) {
+                return Ok(message);
+            }
+        }
+    }
+
+    /// Wait for the environment to acknowledge the given request.
+    fn expect_ack(&mut self, request: &Value, timeout: f64) -> Result<(), String> {
+        self.expect(timeout, |message| message.get("Ack") == Some(request))
+            .map(drop)
+    }
+
+    fn is_alive(&self, name: Option<&Value>) -> Result<String, String> {
+        match name.and_then(Value::as_str) {
+            Some(name) if self.alive.contains_key(name) => Ok(name.to_string()),
+            Some(name) => Err(format!("unknown individual \"{name}\"")),
+            None => Err("missing the individual's name".to_string()),
+        }
+    }
+
+    /// Check a message from the environment and update the bookkeeping.
+    fn handle(&mut self, message: &Value) -> Result<(), String> {
+        let Some(object) = message.as_object() else {
+            return Err("expected a JSON object".to_string());
+        };
+        if let Some(request) = object.get("Ack") {
+            let known = match request {
+                Value::String(request) => CONTROL_MESSAGES.contains(&request.as_str()),
+                Value::Object(request) => {
+                    request.len() == 1 && ["Save", "Load", "Message"].iter().any(|key| request.contains_key(*key))
+                }
+                _ => false,
+            };
+            return if known {
+                Ok(())
+            } else {
+                Err("acknowledged an unknown request".to_string())
+            };
+        }
+        if let Some(population) = object.get("New") {
+            let population = match population {
+                Value::Null if self.env_spec.populations.len() == 1 => self.env_spec.populations[0].name.clone(),
+                Value::String(name) if self.env_spec.populations.iter().any(|pop| &pop.name == name) => name.clone(),
+                _ => return Err(format!("unknown population {population}")),
+            };
+            return self.birth(&population, &[]);
+        }
+        if let Some(parents) = object.get("Mate") {
+            let parents = parents.as_array().ok_or("expected a list of parents")?;
+            if parents.is_empty() {
+                return Err("mating requires at least one parent".to_string());
+            }
+            let parents = parents
+                .iter()
+                .map(|name| self.is_alive(Some(name)))
+                .collect::<Result<Vec<_>, _>>()?;
+            let population = self.alive[&parents[0]].clone();
+            if parents.iter().any(|name| self.alive[name] != population) {
+                return Err("parents are from different populations".to_string());
+            }
+            return self.birth(&population, &parents);
+        }
+        if let Some(score) = object.get("Score") {
+            self.is_alive(object.get("name"))?;
+            // Numbers are also accepted as strings.
+            return match score {
+                Value::Number(_) => Ok(()),
+                Value::String(score) if score.trim().parse::<f64>().is_ok() => Ok(()),
+                _ => Err("score is not a number".to_string()),
+            };
+        }
+        if let Some(info) = object.get("Info") {
+            let name = self.is_alive(object.get("name"))?;
+            let Some(info) = info.as_object().filter(|info| info.values().all(Value::is_string)) else {
+                return Err("info is not an object of strings".to_string());
+            };
+            let population = &self.alive[&name];
+            let pop_spec = self.env_spec.populations.iter().find(|pop| &pop.name == population);
+            return match pop_spec {
+                Some(pop_spec) => pop_spec.validate_info(
+                    info.iter()
+                        .map(|(key, value)| (key.as_str(), value.as_str().unwrap_or_default())),
+                ),
+                None => Ok(()),
+            };
+        }
+        if let Some(name) = object.get("Death") {
+            let name = self.is_alive(Some(name))?;
+            self.alive.remove(&name);
+            self.deaths += 1;
+            return Ok(());
+        }
+        Err("unknown message type".to_string())
+    }
+
+    fn birth(&mut self, population: &str, parents: &[String]) -> Result<(), String> {
+        if !self.give_births || self.target.controller.is_empty() {
+            return Ok(());
+        }
+        self.births += 1;
+        let name = format!("conformance-{}", self.births);
+        let birth = json!({"Birth": {
+            "environment": self.env_spec.name,
+            "population": population,
+            "name": name,
+            "controller": self.target.controller,
+            "genome": self.target.genome,
+            "parents": parents,
+        }});
+        self.alive.insert(name, population.to_string());
+        // Failures to send are reported by the next check.
+        let _ = self.send(&birth);
+        Ok(())
+    }
+
+    /// Wait for the environment program to exit.
+    fn wait_exit(&mut self, timeout: f64) -> Result<(), String> {
+        let deadline = Instant::now() + Duration::from_secs_f64(timeout);
+        loop {
+            match self.child.try_wait() {
+                Ok(Some(_)) => return Ok(()),
+                Ok(None) if Instant::now() > deadline => {
+                    return Err(format!("did not exit within {timeout} seconds"));
+                }
+                Ok(None) => std::thread::sleep(Duration::from_millis(10)),
+                Err(error) => return Err(error.to_string()),
+            }
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.kill();
+        }
+        let _ = self.child.wait();
+    }
+}
+
+/// Send a control message to the environment and wait for its acknowledgement.
+fn control(report: &mut Report, env: &mut Session, name: &str, request: Value, timeout: f64) -> bool {
+    report.run(name, || {
+        env.send(&request)?;
+        env.expect_ack(&request, timeout)
+    })
+}
+
+/// Run an environment program through the environment protocol.
+///
+/// The environment is run in headless mode.
+pub fn check_environment(target: &EnvironmentTarget) -> Report {
+    let mut report = Report::new(vec![target.spec.to_string_lossy().into_owned()]);
+    let mut env_spec = None;
+    report.run("environment specification", || {
+        env_spec = Some(EnvironmentSpec::new(&target.spec).map_err(|error| error.to_string())?);
+        Ok(())
+    });
+    let Some(env_spec) = env_spec else {
+        return report;
+    };
+    let mut session = None;
+    report.run("start", || {
+        session = Some(Session::start(env_spec, target)?);
+        Ok(())
+    });
+    let Some(mut session) = session else {
+        return report;
+    };
+    let env = &mut session;
+    let timeout = target.timeout;
+
+    control(&mut report, env, "heartbeat", json!("Heartbeat"), timeout);
+    control(&mut report, env, "start running", json!("Start"), timeout);
+
+    if target.controller.is_empty() {
+        report.skip("new individual", "no controller was given for the individuals");
+        report.skip("evaluation", "no controller was given for the individuals");
+    } else {
+        report.run("new individual", || {
+            if env.births == 0 {
+                env.expect(timeout, |message| {
+                    message.get("New").is_some() || message.get("Mate").is_some()
+                })?;
+            }
+            Ok(())
+        });
+        report.run("evaluation", || {
+            if env.births > 0 && env.deaths == 0 {
+                env.expect(target.evaluation_time, |message| message.get("Death").is_some())?;
+            }
+            Ok(())
+        });
+    }
+
+    control(&mut report, env, "pause", json!("Pause"), timeout);
+    control(&mut report, env, "resume", json!("Resume"), timeout);
+
+    let directory = temp_dir();
+    let save_path = directory.join("environment.save").to_string_lossy().into_owned();
+    report.run("save", || {
+        std::fs::create_dir_all(&directory).map_err(|error| error.to_string())?;
+        let request = json!({"Save": save_path});
+        env.send(&request)?;
+        env.expect_ack(&request, timeout)?;
+        if !Path::new(&save_path).exists() {
+            return Err(format!("did not write the save file \"{save_path}\""));
+        }
+        Ok(())
+    });
+    if Path::new(&save_path).exists() {
+        control(&mut report, env, "load", json!({"Load": save_path}), timeout);
+    } else {
+        report.skip("load", "there is no save file to load");
+    }
+    let _ = std::fs::remove_dir_all(&directory);
+
+    control(
+        &mut report,
+        env,
+        "user defined message",
+        json!({"Message": {"conformance": [1, "\u{2603}", null]}}),
+        timeout,
+    );
+
+    report.run("recover from malformed messages", || {
+        env.send_line("this is not JSON")?;
+        env.send_line("{\"Unknown\":[]}")?;
+        env.send_line("")?;
+        env.send(&json!("Heartbeat"))?;
+        env.expect_ack(&json!("Heartbeat"), timeout)
+    });
+
+    report.run("stop", || {
+        env.give_births = false;
+        env.send(&json!("Stop"))?;
+        env.expect_ack(&json!("Stop"), target.evaluation_time)
+    });
+
+    report.run("quit", || {
+        env.send(&json!("Quit"))?;
+        env.wait_exit(timeout)
+    });
+
+    report.run("message format", || match env.violations.as_slice() {
+        [] => Ok(()),
+        [problem] => Err(problem.clone()),
+        [problem, rest @ ..] => Err(format!("{problem}, and {} more problems", rest.len())),
+    });
+    report
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn controller() {
+        let env = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+        let script = r#"while read -r line; do case $line in
+            C) echo "C:save,load";;
+            F*) echo "F:text";;
+            H) echo "H:";;
+            O*) echo "${line#O}:0";;
+            W*) for gin in $(echo "${line#W}" | tr , ' '); do echo "$gin:0"; done;;
+            M*) n=${line#M}; while [ "$n" -gt 0 ]; do read -r input; n=$((n - 1)); done;;
+            S*) echo saved > "${line#S}";;
+            Q) exit;;
+        esac; done"#;
+        let mut target = ControllerTarget {
+            environment: env.into(),
+            population: "pop".to_string(),
+            command: vec!["/bin/sh".to_string(), "-c".to_string(), script.to_string()],
+            input_gins: vec![0, 1],
+            output_gins: vec![2, 3],
+            binary_gins: vec![4],
+            timeout: 5.0,
+            ..Default::default()
+        };
+        let report = check_controller(&target);
+        assert!(report.passed(), "{report}");
+        let outcome = |report: &Report, name: &str| {
+            report
+                .checks
+                .iter()
+                .find(|check| check.name == name)
+                .map(|check| check.outcome.clone())
+        };
+        assert_eq!(outcome(&report, "load"), Some(Outcome::Pass));
+        assert!(matches!(outcome(&report, "set binary input"), Some(Outcome::Skip(_))));
+
+        // Never answers pings.
+        target.command[2] = r#"while read -r line; do case $line in C) echo "C:";; Q) exit;; esac; done"#.to_string();
+        target.timeout = 0.2;
+        let report = check_controller(&target);
+        assert!(!report.passed());
+        assert_eq!(outcome(&report, "capabilities"), Some(Outcome::Pass));
+        assert!(matches!(outcome(&report, "ping"), Some(Outcome::Fail(_))));
+        assert_eq!(outcome(&report, "quit"), Some(Outcome::Pass));
+        assert!(report.to_string().contains("FAIL  ping: "));
+    }
+}
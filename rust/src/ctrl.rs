@@ -7,11 +7,30 @@
 //! messages (see [eprintln!()]).
 //! By default, controllers inherit stderr from the environment.
 
+use crate::env_spec::{EnvironmentSpec, InterfaceSpec, PopulationSpec};
+use codec::DecodeError;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::io::{self, BufRead, BufReader, BufWriter, Write};
-use std::path::{Path, PathBuf};
-use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::io::{self, BufRead, Read, Write};
+use std::path::PathBuf;
+#[cfg(feature = "spawn")]
+use std::{
+    collections::{HashSet, VecDeque},
+    io::BufWriter,
+    path::Path,
+    process::{Child, ChildStderr, Command, Stdio},
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+};
+#[cfg(any(feature = "spawn", feature = "ctrl"))]
+use std::{
+    io::BufReader,
+    time::{Duration, Instant},
+};
 
+#[cfg(feature = "spawn")]
 fn _clean_path(path: impl AsRef<Path>) -> Result<PathBuf, io::Error> {
     let path = path.as_ref();
     // Expand home directory.
@@ -24,6 +43,9 @@ fn _clean_path(path: impl AsRef<Path>) -> Result<PathBuf, io::Error> {
             }
             let path = path.canonicalize()?;
             Ok(path)
+        } else {
+            let path = path.canonicalize()?;
+            Ok(path)
         }
     } else {
         let path = path.canonicalize()?;
@@ -31,19 +53,1036 @@ fn _clean_path(path: impl AsRef<Path>) -> Result<PathBuf, io::Error> {
     }
 }
 
+/// Lookup table for a population's interfaces, for using symbolic names instead of GINs.
+///
+/// Index by name to get the GIN, for example: `interfaces["left_wheel"]`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Interfaces {
+    by_name: HashMap<String, u64>,
+    by_gin: HashMap<u64, InterfaceSpec>,
+}
+
+impl Interfaces {
+    /// Argument population is a name and a key into the environment spec's "populations" table.
+    pub fn new(env_spec: &EnvironmentSpec, population: &str) -> Result<Self, String> {
+        let Some(pop_spec) = env_spec.populations.iter().find(|pop_spec| pop_spec.name == population) else {
+            return Err(format!(
+                "no such population \"{population}\", in file: {:?}",
+                env_spec.spec
+            ));
+        };
+        Self::from_population(pop_spec)
+    }
+
+    pub fn from_population(pop_spec: &PopulationSpec) -> Result<Self, String> {
+        let mut this = Self::default();
+        for interface in &pop_spec.interfaces {
+            if this.by_name.insert(interface.name.clone(), interface.gin).is_some() {
+                return Err(format!("duplicate interface name \"{}\"", interface.name));
+            }
+            if this.by_gin.insert(interface.gin, interface.clone()).is_some() {
+                return Err(format!("duplicate interface gin {}", interface.gin));
+            }
+        }
+        Ok(this)
+    }
+
+    /// Get the GIN of the named interface.
+    pub fn gin(&self, name: &str) -> Option<u64> {
+        self.by_name.get(name).copied()
+    }
+
+    /// Get the name of an interface.
+    pub fn name(&self, gin: u64) -> Option<&str> {
+        self.by_gin.get(&gin).map(|interface| interface.name.as_str())
+    }
+
+    /// Get the full specification of an interface.
+    pub fn get(&self, gin: u64) -> Option<&InterfaceSpec> {
+        self.by_gin.get(&gin)
+    }
+
+    pub fn contains(&self, gin: u64) -> bool {
+        self.by_gin.contains_key(&gin)
+    }
+
+    /// Check that all of the given GINs are interfaces in this population.
+    pub fn validate(&self, gins: &[u64]) -> Result<(), String> {
+        for gin in gins {
+            if !self.contains(*gin) {
+                return Err(format!("no such interface gin {gin}"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the named interface uses the given unit of measurement.
+    ///
+    /// Units with different names but the same size are accepted, for example "m" and "meters".
+    /// Returns the GIN of the interface.
+    pub fn check_unit(&self, name: &str, unit: &str) -> Result<u64, String> {
+        let gin = self.gin(name).ok_or_else(|| format!("no such interface \"{name}\""))?;
+        let expected = crate::units::Unit::parse(unit)?;
+        let Some(actual) = &self.by_gin[&gin].unit else {
+            return Err(format!("interface \"{name}\" has no unit, expected {}", expected.name));
+        };
+        if crate::units::Unit::parse(actual)? != expected {
+            return Err(format!(
+                "interface \"{name}\" is in {actual}, expected {}",
+                expected.name
+            ));
+        }
+        Ok(gin)
+    }
+
+    /// Get the GINs of several named interfaces.
+    pub fn resolve(&self, names: &[&str]) -> Result<Vec<u64>, String> {
+        names
+            .iter()
+            .map(|name| self.gin(name).ok_or_else(|| format!("no such interface \"{name}\"")))
+            .collect()
+    }
+}
+
+impl std::ops::Index<&str> for Interfaces {
+    type Output = u64;
+
+    /// Panics if there is no interface with the given name.
+    fn index(&self, name: &str) -> &u64 {
+        self.by_name
+            .get(name)
+            .unwrap_or_else(|| panic!("no such interface \"{name}\""))
+    }
+}
+
+/// Writer which counts the number of bytes that pass through it,
+/// and the messages which have not yet been flushed.
+#[cfg(feature = "spawn")]
+#[derive(Debug)]
+struct ByteCounter<W: Write> {
+    inner: W,
+    count: u64,
+    unflushed: usize,
+    oldest_unflushed: Option<Instant>,
+}
+
+#[cfg(feature = "spawn")]
+impl<W: Write> Write for ByteCounter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let num_bytes = self.inner.write(buf)?;
+        self.count += num_bytes as u64;
+        Ok(num_bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.unflushed = 0;
+        self.oldest_unflushed = None;
+        self.inner.flush()
+    }
+}
+
+/// When to flush messages which do not need a response.
+///
+/// Messages are buffered until either limit is reached. Each limit is checked
+/// when a message is sent, so a message can wait longer than the time limit
+/// if nothing else is sent after it. Use an explicit flush to send it sooner.
+///
+/// Messages which wait for a response, such as requests for outputs, are
+/// always sent immediately along with everything that was buffered before them.
+///
+/// See [ControllerOptions::flush_policy] and [crate::env_api::set_flush_policy()].
+#[cfg(feature = "spawn")]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct FlushPolicy {
+    /// Flush once this many messages are waiting.
+    #[serde(default)]
+    pub messages: Option<usize>,
+
+    /// Flush once the oldest waiting message has waited this many seconds.
+    #[serde(default)]
+    pub seconds: Option<f64>,
+}
+
+#[cfg(feature = "spawn")]
+impl FlushPolicy {
+    /// Flush every message as soon as it is sent.
+    pub const IMMEDIATE: Self = Self {
+        messages: Some(1),
+        seconds: None,
+    };
+
+    /// Only flush when a response is needed, or when the buffer is full.
+    pub const ON_DEMAND: Self = Self {
+        messages: None,
+        seconds: None,
+    };
+
+    /// Flush once this many messages are waiting.
+    pub fn messages(mut self, num_messages: usize) -> Self {
+        self.messages = Some(num_messages.max(1));
+        self
+    }
+
+    /// Flush once the oldest waiting message has waited this many seconds.
+    pub fn seconds(mut self, seconds: f64) -> Self {
+        self.seconds = Some(seconds);
+        self
+    }
+
+    /// Check if the waiting messages should be flushed.
+    pub fn is_due(&self, num_messages: usize, oldest: Option<Instant>) -> bool {
+        if num_messages == 0 {
+            return false;
+        }
+        let too_many = self.messages.is_some_and(|limit| num_messages >= limit);
+        let too_old = self
+            .seconds
+            .zip(oldest)
+            .is_some_and(|(limit, oldest)| oldest.elapsed().as_secs_f64() >= limit);
+        too_many || too_old
+    }
+}
+
+/// Performance statistics for communicating with a controller.
+///
+/// See [Controller::enable_profiling()] and [Controller::get_profile()].
+#[cfg(feature = "spawn")]
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Profile {
+    /// Statistics for each type of message, indexed by the message's type character.
+    pub messages: HashMap<char, MessageProfile>,
+}
+
+/// Performance statistics for one type of message.
+#[cfg(feature = "spawn")]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct MessageProfile {
+    /// Number of messages sent to the controller.
+    pub count: u64,
+
+    /// Number of bytes sent to the controller, including binary payloads.
+    pub bytes_sent: u64,
+
+    /// Number of bytes received from the controller in reply.
+    pub bytes_received: u64,
+
+    /// Number of round-trip latency measurements.  
+    /// Each call to get_outputs() or request() is one round-trip.  
+    /// Messages which do not have a reply, such as save, are only counted as sent.  
+    pub round_trips: u64,
+
+    /// Sum of all round-trip latencies.
+    pub total_latency: Duration,
+
+    /// Longest round-trip latency.
+    pub max_latency: Duration,
+}
+
+#[cfg(feature = "spawn")]
+impl MessageProfile {
+    /// Average round-trip latency, or None if there are no measurements.
+    pub fn mean_latency(&self) -> Option<Duration> {
+        if self.round_trips == 0 {
+            None
+        } else {
+            Some(self.total_latency / self.round_trips as u32)
+        }
+    }
+}
+
+/// Optional settings for launching a controller program.
+///
+/// These are applied in addition to the controller's command line, for
+/// example to set the `PYTHONPATH` or to select a GPU.
+///
+/// ```no_run
+/// # use npc_maker::ctrl::{Controller, ControllerOptions};
+/// let options = ControllerOptions::default()
+///     .env_var("CUDA_VISIBLE_DEVICES", "1")
+///     .working_dir("models/");
+/// let command = ["python".to_string(), "ctrl.py".to_string()];
+/// let ctrl = Controller::with_options("env.json", "pop", &command, &options);
+/// ```
+#[cfg(feature = "spawn")]
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ControllerOptions {
+    /// Environment variables to set for the controller program,
+    /// in addition to the variables which it inherits from this process.
+    #[serde(default)]
+    pub env_vars: HashMap<String, String>,
+
+    /// Working directory for the controller program.
+    /// By default, controllers inherit the working directory of this process.
+    #[serde(default)]
+    pub working_dir: Option<PathBuf>,
+
+    /// Number of lines of the controller's stderr to keep for diagnostics,
+    /// see [Controller::status()]. If zero then stderr is not captured.
+    /// Captured lines are still forwarded to this process's stderr.
+    #[serde(default)]
+    pub stderr_lines: usize,
+
+    /// Record every message sent to and received from the controller to an NDJSON file.
+    /// See the [trace] module for the file format.
+    #[serde(default)]
+    pub trace: Option<PathBuf>,
+
+    /// Restrict the controller program to run on these CPUs, identified by their index.
+    /// If empty then the operating system may schedule the controller on any CPU.
+    ///
+    /// Pinning an environment and its controllers to nearby CPUs reduces the
+    /// latency of communicating between them. This is only supported on linux,
+    /// and it does not apply to sockets or WebAssembly controllers.
+    #[serde(default)]
+    pub cpu_affinity: Vec<usize>,
+
+    /// Maximum number of bytes of virtual memory for the controller program.
+    /// If the controller dies after exceeding this limit then the environment
+    /// receives a [ControllerOutOfMemory] error, which it should record on the
+    /// individual with [crate::env_api::report_out_of_memory()].
+    /// By default there is no limit.
+    ///
+    /// Programs which fail to allocate memory are only recognized if they say
+    /// so on stderr, so use this together with the stderr_lines option.
+    ///
+    /// This is only supported on unix, and it does not apply to sockets or
+    /// WebAssembly controllers.
+    #[serde(default)]
+    pub memory_limit: Option<u64>,
+
+    /// Send and receive numeric values in binary, instead of as decimal strings.
+    ///
+    /// The controller is asked to use this encoding when it starts. If it
+    /// declines then the values are sent as text, see [Controller::value_encoding()].
+    #[serde(default)]
+    pub value_encoding: ValueEncoding,
+
+    /// Maximum number of seconds to wait for the controller to respond.
+    /// If the controller takes longer then the environment receives a
+    /// [ControllerTimeout] error. By default there is no time limit.
+    /// WebAssembly controllers which time out are interrupted, since they can
+    /// not be killed. This is only supported on unix.
+    #[serde(default)]
+    pub timeout: Option<f64>,
+
+    /// Ping the controller if it has not sent anything for this many seconds,
+    /// to detect hung controllers. Use this together with the timeout option.
+    /// See [Controller::ping()].
+    #[serde(default)]
+    pub heartbeat: Option<f64>,
+
+    /// Ask the controller which optional operations it implements, when it starts.
+    /// See [Controller::supports()].
+    #[serde(default)]
+    pub query_capabilities: bool,
+
+    /// When to flush messages which do not need a response, such as inputs.
+    /// By default they are only flushed when the environment waits for a
+    /// response from the controller, see [Controller::flush()].
+    #[serde(default)]
+    pub flush_policy: FlushPolicy,
+}
+
+/// Format of the values of inputs and outputs, see [ControllerOptions::value_encoding].
+///
+/// The binary encodings avoid the cost of formatting and parsing decimal
+/// numbers, and they never lose precision. They only apply to the "Set Input"
+/// and "Send Output" messages.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueEncoding {
+    /// Values are UTF-8 strings.
+    #[default]
+    Text,
+    /// Values are 64-bit little-endian floating point numbers.
+    F64,
+    /// Values are 32-bit little-endian floating point numbers.
+    F32,
+}
+
+impl ValueEncoding {
+    /// Name of this encoding in the protocol.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Text => "text",
+            Self::F64 => "f64",
+            Self::F32 => "f32",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::Text, Self::F64, Self::F32]
+            .into_iter()
+            .find(|encoding| encoding.name() == name)
+    }
+
+    /// Number of bytes in each value, or zero for text.
+    pub fn size(&self) -> usize {
+        match self {
+            Self::Text => 0,
+            Self::F64 => 8,
+            Self::F32 => 4,
+        }
+    }
+
+    /// Write a value followed by a newline. Binary encodings only.
+    #[cfg(any(feature = "spawn", feature = "ctrl"))]
+    fn write_value(&self, writer: &mut impl Write, value: f64) -> Result<(), io::Error> {
+        match self {
+            Self::Text => unreachable!(),
+            Self::F64 => writer.write_all(&value.to_le_bytes())?,
+            Self::F32 => writer.write_all(&(value as f32).to_le_bytes())?,
+        }
+        writer.write_all(b"\n")
+    }
+
+    /// Read a value and its trailing newline. Binary encodings only.
+    fn read_value(&self, reader: &mut impl Read) -> Result<f64, io::Error> {
+        let mut bytes = [0; 9];
+        let bytes = &mut bytes[..self.size() + 1];
+        reader.read_exact(bytes)?;
+        let (newline, value) = bytes.split_last().unwrap();
+        if *newline != b'\n' {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "binary value is missing its newline",
+            ));
+        }
+        Ok(match self {
+            Self::Text => unreachable!(),
+            Self::F64 => f64::from_le_bytes(value.try_into().unwrap()),
+            Self::F32 => f32::from_le_bytes(value.try_into().unwrap()) as f64,
+        })
+    }
+}
+
+/// Maximum length of the text before the colon of a binary value message, which is just a message type and a GIN.
+const MAX_VALUE_HEADER: usize = 64;
+
+/// Read the start of a binary value message, up to and including its colon.
+/// Returns the text before the colon.
+fn read_value_header<'a>(reader: &mut impl BufRead, buffer: &'a mut Vec<u8>) -> Result<Cow<'a, str>, io::Error> {
+    buffer.clear();
+    let num_bytes = reader
+        .by_ref()
+        .take(MAX_VALUE_HEADER as u64 + 1)
+        .read_until(b':', buffer)?;
+    if num_bytes > MAX_VALUE_HEADER {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            MessageTooLarge {
+                num_bytes,
+                limit: MAX_VALUE_HEADER,
+            },
+        ));
+    }
+    if num_bytes == 0 || buffer.pop() != Some(b':') {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "input stream closed"));
+    }
+    Ok(String::from_utf8_lossy(buffer))
+}
+
+/// Read one line of text, including its trailing newline.
+///
+/// Lines which are longer than the limit are rejected with [MessageTooLarge], instead of allocating memory for them.
+/// Returns the number of bytes read, which is zero at the end of the stream.
+pub(crate) fn read_line_limited(
+    reader: &mut impl BufRead,
+    line: &mut String,
+    limit: usize,
+) -> Result<usize, io::Error> {
+    let num_bytes = reader.by_ref().take(limit as u64 + 1).read_line(line)?;
+    if num_bytes > limit {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            MessageTooLarge { num_bytes, limit },
+        ));
+    }
+    Ok(num_bytes)
+}
+
+#[cfg(feature = "spawn")]
+impl ControllerOptions {
+    /// Set an environment variable for the controller program.
+    pub fn env_var(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env_vars.insert(key.into(), value.into());
+        self
+    }
+
+    /// Set the working directory for the controller program.
+    pub fn working_dir(mut self, path: impl Into<PathBuf>) -> Self {
+        self.working_dir = Some(path.into());
+        self
+    }
+
+    /// Keep the last few lines that the controller writes to stderr.
+    pub fn stderr_lines(mut self, num_lines: usize) -> Self {
+        self.stderr_lines = num_lines;
+        self
+    }
+
+    /// Record the controller's messages to the given file.
+    pub fn trace(mut self, path: impl Into<PathBuf>) -> Self {
+        self.trace = Some(path.into());
+        self
+    }
+
+    /// Ask the controller to send and receive numeric values in binary.
+    pub fn value_encoding(mut self, encoding: ValueEncoding) -> Self {
+        self.value_encoding = encoding;
+        self
+    }
+
+    /// Limit how long to wait for the controller to respond, in seconds.
+    pub fn timeout(mut self, seconds: f64) -> Self {
+        self.timeout = Some(seconds);
+        self
+    }
+
+    /// Ping the controller after this many seconds without hearing from it.
+    pub fn heartbeat(mut self, seconds: f64) -> Self {
+        self.heartbeat = Some(seconds);
+        self
+    }
+
+    /// Pin the controller program to the given CPUs.
+    pub fn cpu_affinity(mut self, cpus: impl IntoIterator<Item = usize>) -> Self {
+        self.cpu_affinity = cpus.into_iter().collect();
+        self
+    }
+
+    /// Limit the controller program's virtual memory, in bytes.
+    pub fn memory_limit(mut self, bytes: u64) -> Self {
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    /// Ask the controller which optional operations it implements.
+    pub fn query_capabilities(mut self) -> Self {
+        self.query_capabilities = true;
+        self
+    }
+
+    /// Decide when to flush messages which do not need a response.
+    pub fn flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.flush_policy = policy;
+        self
+    }
+
+    /// Check if all of the options are unset.
+    pub fn is_empty(&self) -> bool {
+        self.env_vars.is_empty()
+            && self.working_dir.is_none()
+            && self.stderr_lines == 0
+            && self.trace.is_none()
+            && self.cpu_affinity.is_empty()
+            && self.memory_limit.is_none()
+            && self.value_encoding == ValueEncoding::Text
+            && self.timeout.is_none()
+            && self.heartbeat.is_none()
+            && !self.query_capabilities
+            && self.flush_policy == FlushPolicy::ON_DEMAND
+    }
+}
+
+/// Pin the command's process to the given CPUs, before it starts running.
+#[cfg(feature = "spawn")]
+#[cfg(target_os = "linux")]
+fn set_cpu_affinity(cmd: &mut Command, cpus: &[usize]) -> Result<(), io::Error> {
+    use std::os::unix::process::CommandExt;
+    let mut cpu_set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    for &cpu in cpus {
+        if cpu >= libc::CPU_SETSIZE as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid cpu {cpu}"),
+            ));
+        }
+        unsafe { libc::CPU_SET(cpu, &mut cpu_set) };
+    }
+    // Only async-signal-safe functions may be called between fork and exec.
+    unsafe {
+        cmd.pre_exec(move || {
+            if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &cpu_set) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    Ok(())
+}
+
+#[cfg(feature = "spawn")]
+#[cfg(not(target_os = "linux"))]
+fn set_cpu_affinity(_cmd: &mut Command, _cpus: &[usize]) -> Result<(), io::Error> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "controller cpu affinity is only supported on linux",
+    ))
+}
+
+/// Limit the command's process to the given number of bytes of virtual memory.
+#[cfg(feature = "spawn")]
+#[cfg(unix)]
+fn set_memory_limit(cmd: &mut Command, bytes: u64) {
+    use std::os::unix::process::CommandExt;
+    let limit = libc::rlimit {
+        rlim_cur: bytes as libc::rlim_t,
+        rlim_max: bytes as libc::rlim_t,
+    };
+    // Only async-signal-safe functions may be called between fork and exec.
+    unsafe {
+        cmd.pre_exec(move || {
+            if libc::setrlimit(libc::RLIMIT_AS, &limit) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Messages which programs print when the memory limit refuses an allocation, in lower case.
+#[cfg(feature = "spawn")]
+const ALLOCATION_FAILURES: [&str; 6] = [
+    "memoryerror",
+    "out of memory",
+    "memory exhausted",
+    "memory allocation",
+    "cannot allocate memory",
+    "bad_alloc",
+];
+
+/// Check if a controller which was running under a memory limit died from running out of memory.
+///
+/// The kernel's out-of-memory killer sends SIGKILL. Programs which are refused
+/// an allocation by the memory limit fail in different ways, so they only count
+/// if they say so on stderr, see [ControllerOptions::stderr_lines].
+/// Other crashes are reported as [ControllerDead].
+#[cfg(feature = "spawn")]
+fn ran_out_of_memory(status: &ControllerStatus) -> bool {
+    #[cfg(unix)]
+    if status.signal == Some(libc::SIGKILL) {
+        return true;
+    }
+    status.stderr.iter().any(|line| {
+        let line = line.to_lowercase();
+        ALLOCATION_FAILURES.iter().any(|message| line.contains(message))
+    })
+}
+
+/// Optional operations which a controller may implement, see [Controller::supports()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    Save,
+    Load,
+    SetBinary,
+    Validate,
+    /// Hosting many agents, see [Multiplexer].
+    Select,
+    /// Replacing the genotype while preserving the internal state, see [SwapMode::PreserveState].
+    Swap,
+    /// Custom message type.
+    Custom(char),
+}
+
+impl Capability {
+    /// Name of this capability in the protocol. Custom message types are named by their type character.
+    pub fn name(&self) -> Cow<'static, str> {
+        match self {
+            Self::Save => "save".into(),
+            Self::Load => "load".into(),
+            Self::SetBinary => "binary".into(),
+            Self::Validate => "validate".into(),
+            Self::Select => "select".into(),
+            Self::Swap => "swap".into(),
+            Self::Custom(message_type) => message_type.to_string().into(),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let capability = match name {
+            "save" => Self::Save,
+            "load" => Self::Load,
+            "binary" => Self::SetBinary,
+            "validate" => Self::Validate,
+            "select" => Self::Select,
+            "swap" => Self::Swap,
+            _ => {
+                let mut chars = name.chars();
+                let message_type = chars.next()?;
+                if chars.next().is_some() || check_custom_type(message_type).is_err() {
+                    return None;
+                }
+                Self::Custom(message_type)
+            }
+        };
+        Some(capability)
+    }
+}
+
+/// What happens to a controller's internal state when it receives a new genotype,
+/// see [Controller::swap_genotype()].
+#[cfg(feature = "spawn")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SwapMode {
+    /// Discard the current model, including everything that it has learned,
+    /// and initialize a new one. This is the same as [Controller::new_genotype()].
+    #[default]
+    Reinitialize,
+    /// Rebuild the model from the new genotype while carrying over its
+    /// internal state, such as learned weights and activations, wherever the
+    /// old and new models are compatible. This is useful for Lamarckian
+    /// evolution and for plastic networks.
+    PreserveState,
+}
+
+/// Error for an operation which the controller does not implement, see [Controller::supports()].
+///
+/// Controller methods return this inside of an [io::Error] with the kind
+/// [io::ErrorKind::Unsupported], without sending anything to the controller.
+/// Use [UnsupportedOperation::from_io()] to retrieve it.
+#[cfg(feature = "spawn")]
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+#[error("controller does not support {:?}", capability.name())]
+pub struct UnsupportedOperation {
+    pub capability: Capability,
+}
+
+#[cfg(feature = "spawn")]
+impl UnsupportedOperation {
+    /// Check if an I/O error was caused by an operation which the controller does not implement.
+    pub fn from_io(error: &io::Error) -> Option<&UnsupportedOperation> {
+        error.get_ref()?.downcast_ref()
+    }
+}
+
+/// Report on whether a controller is still running, see [Controller::status()].
+#[cfg(feature = "spawn")]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ControllerStatus {
+    /// Is the controller still running?
+    pub alive: bool,
+
+    /// Exit code, if the controller program exited on its own.
+    pub exit_code: Option<i32>,
+
+    /// Signal number, if the controller program was terminated by a signal.
+    pub signal: Option<i32>,
+
+    /// Most recent lines which the controller wrote to stderr,
+    /// if enabled by [ControllerOptions::stderr_lines].
+    pub stderr: Vec<String>,
+
+    /// Did the controller stop because it exceeded its [ControllerOptions::memory_limit]?
+    pub out_of_memory: bool,
+}
+
+#[cfg(feature = "spawn")]
+impl std::fmt::Display for ControllerStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.alive {
+            write!(f, "running")?;
+        } else if self.out_of_memory {
+            write!(f, "ran out of memory")?;
+        } else if let Some(code) = self.exit_code {
+            write!(f, "exited with code {code}")?;
+        } else if let Some(signal) = self.signal {
+            write!(f, "terminated by signal {signal}")?;
+        } else {
+            write!(f, "stopped")?;
+        }
+        for line in &self.stderr {
+            write!(f, "\n{line}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Error for communicating with a controller which is no longer running.
+///
+/// Controller methods return this inside of an [io::Error] with the kind
+/// [io::ErrorKind::BrokenPipe], instead of generic I/O errors.
+/// Use [ControllerDead::from_io()] to retrieve it.
+#[cfg(feature = "spawn")]
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+#[error("controller {status}")]
+pub struct ControllerDead {
+    pub status: ControllerStatus,
+}
+
+#[cfg(feature = "spawn")]
+impl ControllerDead {
+    /// Check if an I/O error was caused by the controller dying.
+    pub fn from_io(error: &io::Error) -> Option<&ControllerDead> {
+        error.get_ref()?.downcast_ref()
+    }
+}
+
+/// Error for a controller which stopped after exceeding its [ControllerOptions::memory_limit].
+///
+/// Controller methods return this inside of an [io::Error] with the kind
+/// [io::ErrorKind::OutOfMemory], instead of a [ControllerDead] error.
+/// Use [ControllerOutOfMemory::from_io()] to retrieve it.
+#[cfg(feature = "spawn")]
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+#[error("controller exceeded its memory limit of {limit} bytes")]
+pub struct ControllerOutOfMemory {
+    pub limit: u64,
+    pub status: ControllerStatus,
+}
+
+#[cfg(feature = "spawn")]
+impl ControllerOutOfMemory {
+    /// Check if an I/O error was caused by the controller running out of memory.
+    pub fn from_io(error: &io::Error) -> Option<&ControllerOutOfMemory> {
+        error.get_ref()?.downcast_ref()
+    }
+}
+
+/// Error for a controller which reported an unexpected failure, such as a panic.
+///
+/// Controller methods return this inside of an [io::Error] with the kind
+/// [io::ErrorKind::Other]. The controller may still be running, see [API::panicked()].
+/// Use [ControllerPanic::from_io()] to retrieve it.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+#[error("controller panicked: {message}")]
+pub struct ControllerPanic {
+    pub message: String,
+}
+
+impl ControllerPanic {
+    /// Check if an I/O error was caused by the controller panicking.
+    pub fn from_io(error: &io::Error) -> Option<&ControllerPanic> {
+        error.get_ref()?.downcast_ref()
+    }
+}
+
+/// Error for a controller which did not respond in time, see [ControllerOptions::timeout].
+///
+/// Controller methods return this inside of an [io::Error] with the kind
+/// [io::ErrorKind::TimedOut]. The controller may still be running.
+/// Use [ControllerTimeout::from_io()] to retrieve it.
+#[cfg(feature = "spawn")]
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+#[error("controller did not respond within {timeout:?}")]
+pub struct ControllerTimeout {
+    pub timeout: Duration,
+}
+
+#[cfg(feature = "spawn")]
+impl ControllerTimeout {
+    /// Check if an I/O error was caused by the controller not responding in time.
+    pub fn from_io(error: &io::Error) -> Option<&ControllerTimeout> {
+        error.get_ref()?.downcast_ref()
+    }
+}
+
+/// Forward a controller's stderr to this process's stderr, keeping the last few lines.
+#[cfg(feature = "spawn")]
+fn capture_stderr(stderr: ChildStderr, num_lines: usize) -> (Arc<Mutex<VecDeque<String>>>, JoinHandle<()>) {
+    let tail = Arc::new(Mutex::new(VecDeque::with_capacity(num_lines)));
+    let thread_tail = Arc::clone(&tail);
+    let thread = std::thread::spawn(move || {
+        for line in BufReader::new(stderr).lines() {
+            let Ok(line) = line else { break };
+            eprintln!("{line}");
+            let mut tail = thread_tail.lock().unwrap();
+            if tail.len() == num_lines {
+                tail.pop_front();
+            }
+            tail.push_back(line);
+        }
+    });
+    (tail, thread)
+}
+
+/// Everything needed to restart a controller from its save file.
+///
+/// See [Controller::checkpoint()] and [Controller::restore()].
+#[cfg(feature = "spawn")]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SavedController {
+    pub environment: PathBuf,
+    pub population: String,
+    pub command: Vec<String>,
+    #[serde(default, skip_serializing_if = "ControllerOptions::is_empty")]
+    pub options: ControllerOptions,
+    /// File containing the internal state of the control system.
+    pub path: PathBuf,
+}
+
+#[cfg(feature = "spawn")]
+mod adapter;
+#[cfg(feature = "spawn")]
+pub mod bench;
+pub mod codec;
+#[cfg(feature = "spawn")]
+mod group;
+#[cfg(any(feature = "spawn", feature = "ctrl"))]
+mod mux;
+#[cfg(feature = "spawn")]
+mod socket;
+#[cfg(any(feature = "spawn", feature = "ctrl"))]
+pub mod trace;
+#[cfg(all(feature = "wasm", unix))]
+mod wasm;
+
+#[cfg(feature = "spawn")]
+pub use adapter::{BinaryInput, Input, Interface, Output};
+#[cfg(feature = "spawn")]
+pub use group::ControllerGroup;
+#[cfg(feature = "spawn")]
+pub use mux::ControllerMux;
+#[cfg(feature = "ctrl")]
+pub use mux::Multiplexer;
+#[cfg(feature = "ctrl")]
+pub use trace::Traced;
+
+/// The program which is executing a controller.
+#[cfg(feature = "spawn")]
+#[derive(Debug)]
+enum Process {
+    /// Separate computer process.
+    Child(Child),
+
+    /// WebAssembly module running in a thread of this process.
+    #[cfg(all(feature = "wasm", unix))]
+    Wasm(wasm::Guest),
+
+    /// Connection to a controller server.
+    Socket(socket::Address),
+}
+
+/// Outputs which were requested but not yet retrieved, see [Controller::request_outputs()].
+#[cfg(feature = "spawn")]
+#[must_use]
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct OutputHandle(u64);
+
+/// Error for messages from a controller which do not follow the protocol.
+///
+/// Controller methods return this inside of an [io::Error] with the kind
+/// [io::ErrorKind::InvalidData]. Use [ProtocolError::from_io()] to retrieve it.
+#[cfg(feature = "spawn")]
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum ProtocolError {
+    #[error("malformed message {0:?}")]
+    Malformed(String),
+
+    #[error("unknown message type {0:?}")]
+    UnknownType(String),
+
+    #[error("unexpected output for gin {0}")]
+    UnexpectedOutput(u64),
+}
+
+#[cfg(feature = "spawn")]
+impl ProtocolError {
+    /// Check if an I/O error was caused by a protocol violation.
+    pub fn from_io(error: &io::Error) -> Option<&ProtocolError> {
+        error.get_ref()?.downcast_ref()
+    }
+}
+
+#[cfg(feature = "spawn")]
+impl From<ProtocolError> for io::Error {
+    fn from(error: ProtocolError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+}
+
+/// Messages sent from controllers to environments, except for epigenome updates.
+#[cfg(feature = "spawn")]
+enum Frame {
+    Output {
+        gin: u64,
+        value: String,
+    },
+    /// Always a custom message. Replies to validate messages are custom messages of type "V".
+    Message(Message),
+}
+
+#[cfg(feature = "spawn")]
+impl Frame {
+    fn parse(line: &str) -> Result<Self, ProtocolError> {
+        let malformed = || ProtocolError::Malformed(line.to_string());
+        let first = line.chars().next().ok_or_else(malformed)?;
+        if first.is_ascii_digit() {
+            let (gin, value) = line.split_once(':').ok_or_else(malformed)?;
+            let gin = gin.parse().map_err(|_| malformed())?;
+            Ok(Self::Output {
+                gin,
+                value: value.to_string(),
+            })
+        } else if first.is_ascii_uppercase() {
+            let (id, body) = line[1..].split_once(':').ok_or_else(malformed)?;
+            let id = if id.is_empty() {
+                None
+            } else {
+                Some(id.parse().map_err(|_| malformed())?)
+            };
+            Ok(Self::Message(Message::Custom {
+                message_type: first,
+                id,
+                body: body.to_string(),
+            }))
+        } else {
+            Err(ProtocolError::UnknownType(line.to_string()))
+        }
+    }
+}
+
+/// Output request which the controller has not yet replied to.
+#[cfg(feature = "spawn")]
+struct PendingOutputs {
+    handle: u64,
+    gins: Vec<u64>,
+    message_type: char,
+    start_time: Instant,
+}
+
 /// An instance of a control system.
 ///
 /// This structure provides methods for using controllers.
-#[derive(Debug)]
+#[cfg(feature = "spawn")]
 pub struct Controller {
     env: PathBuf,
     pop: String,
     cmd: Vec<String>,
-    ctrl: Child,
-    stdin: BufWriter<ChildStdin>,
-    stdout: BufReader<ChildStdout>,
+    opts: ControllerOptions,
+    ctrl: Process,
+    stdin: ByteCounter<BufWriter<Box<dyn Write + Send>>>,
+    stdout: BufReader<Box<dyn Read + Send>>,
+    next_id: u64,
+    profile: Option<Profile>,
+    /// Most recent genotype and seed, only kept for controllers which can reconnect.
+    genotype: Option<String>,
+    seed: Option<u64>,
+    epigenome: HashMap<String, String>,
+    has_quit: bool,
+    vectorized: bool,
+    encoding: ValueEncoding,
+    /// Optional operations which the controller implements, if it was asked.
+    capabilities: Option<HashSet<Capability>>,
+    next_handle: u64,
+    pending: VecDeque<PendingOutputs>,
+    received: HashMap<u64, HashMap<u64, String>>,
+    /// Output requests which were outstanding when the controller reconnected.
+    lost: HashSet<u64>,
+    messages: VecDeque<Message>,
+    stderr: Option<Arc<Mutex<VecDeque<String>>>>,
+    stderr_thread: Option<JoinHandle<()>>,
+    /// File descriptor of the controller's stdout, for waiting with a timeout.
+    stdout_fd: Option<i32>,
+    /// When the controller last sent anything.
+    last_contact: Instant,
+    /// Final status, once the controller is known to have stopped.
+    dead: Option<ControllerStatus>,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
 }
 
+#[cfg(feature = "spawn")]
 impl Controller {
     /// Argument environment is the file path of the current environment specification file.
     ///
@@ -51,82 +1090,702 @@ impl Controller {
     ///
     /// Argument command is the command line invocation for the controller program.  
     /// The first string in the list is the program, the remaining strings are its command line arguments.  
+    ///
+    /// Programs with the file extension ".wasm" are executed with `Controller::new_wasm()`,
+    /// if this crate was compiled with the "wasm" feature. This is only supported on unix.
+    ///
+    /// Programs which are socket addresses are connected to with [Controller::new_socket()].
     pub fn new(environment: impl AsRef<Path>, population: &str, command: &[String]) -> Result<Self, io::Error> {
+        Self::with_options(environment, population, command, &ControllerOptions::default())
+    }
+
+    /// Same as [Controller::new()] but with additional settings for the controller program.
+    ///
+    /// Socket connections ignore the options for starting the controller program,
+    /// such as env_vars, working_dir, stderr_lines, cpu_affinity, and memory_limit,
+    /// since the controller server is already running. The options for the
+    /// message protocol still apply: trace, value_encoding, query_capabilities,
+    /// timeout, heartbeat, and flush_policy.
+    pub fn with_options(
+        environment: impl AsRef<Path>,
+        population: &str,
+        command: &[String],
+        options: &ControllerOptions,
+    ) -> Result<Self, io::Error> {
+        if socket::Address::parse(&command[0]).is_some() {
+            return Self::connect_socket(environment, population, command, options);
+        }
         // Clean the arguments.
         let env = _clean_path(environment)?;
-        let pop = population.to_string();
         let prog = _clean_path(&command[0])?;
-        let env_str = env.to_str().unwrap();
-        debug_assert!(!env_str.contains("\n"));
-        debug_assert!(!pop.contains("\n"));
+
+        #[cfg(all(feature = "wasm", unix))]
+        if prog.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("wasm")) {
+            return Self::spawn_wasm(env, population, command, options);
+        }
 
         // Setup and run the controller command in a subprocess.
         let mut cmd = Command::new(&prog);
         cmd.args(&command[1..]);
+        cmd.envs(&options.env_vars);
+        if let Some(working_dir) = &options.working_dir {
+            cmd.current_dir(_clean_path(working_dir)?);
+        }
         cmd.stdin(Stdio::piped());
         cmd.stdout(Stdio::piped());
-        cmd.stderr(Stdio::inherit());
+        if options.stderr_lines > 0 {
+            cmd.stderr(Stdio::piped());
+        } else {
+            cmd.stderr(Stdio::inherit());
+        }
+        if !options.cpu_affinity.is_empty() {
+            set_cpu_affinity(&mut cmd, &options.cpu_affinity)?;
+        }
+        if let Some(bytes) = options.memory_limit {
+            #[cfg(unix)]
+            set_memory_limit(&mut cmd, bytes);
+            #[cfg(not(unix))]
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("controller memory limit of {bytes} bytes is only supported on unix"),
+            ));
+        }
         let mut ctrl = cmd.spawn()?;
-        let mut stdin = BufWriter::new(ctrl.stdin.take().unwrap());
-        let stdout = BufReader::new(ctrl.stdout.take().unwrap());
+        let stdin = Box::new(ctrl.stdin.take().unwrap());
+        let stdout = ctrl.stdout.take().unwrap();
+        #[cfg(unix)]
+        let stdout_fd = Some(std::os::fd::AsRawFd::as_raw_fd(&stdout));
+        #[cfg(not(unix))]
+        let stdout_fd = None;
+        let stderr = ctrl.stderr.take();
+        let mut ctrl = Self::connect(
+            env,
+            population,
+            command,
+            options,
+            Process::Child(ctrl),
+            stdin,
+            Box::new(stdout),
+        )?;
+        if let Some(stderr) = stderr {
+            let (tail, thread) = capture_stderr(stderr, options.stderr_lines);
+            ctrl.stderr = Some(tail);
+            ctrl.stderr_thread = Some(thread);
+        }
+        ctrl.stdout_fd = stdout_fd;
+        ctrl.handshake()?;
+        Ok(ctrl)
+    }
+
+    /// Send the initial messages to a newly started controller.
+    ///
+    /// Callers must then call [Controller::handshake()], after setting the
+    /// stdout_fd so that the handshake is subject to the timeout.
+    fn connect(
+        env: PathBuf,
+        population: &str,
+        command: &[String],
+        options: &ControllerOptions,
+        ctrl: Process,
+        stdin: Box<dyn Write + Send>,
+        stdout: Box<dyn Read + Send>,
+    ) -> Result<Self, io::Error> {
+        let (stdin, stdout) = match &options.trace {
+            Some(path) => trace::wrap(path, stdin, stdout)?,
+            None => (stdin, stdout),
+        };
+        let pop = population.to_string();
+        let env_str = env.to_str().unwrap();
+        debug_assert!(!env_str.contains("\n"));
+        debug_assert!(!pop.contains("\n"));
+        let mut stdin = ByteCounter {
+            inner: BufWriter::new(stdin),
+            count: 0,
+            unflushed: 0,
+            oldest_unflushed: None,
+        };
+        let stdout = BufReader::new(stdout);
 
         //
         writeln!(stdin, "E{}", env_str)?;
         writeln!(stdin, "P{pop}")?;
 
-        Ok(Self {
+        #[cfg(feature = "tracing")]
+        let span = {
+            static INSTANCES: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+            let instance = INSTANCES.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            let program = command.first().map(String::as_str).unwrap_or_default();
+            tracing::debug_span!(
+                "controller",
+                population = %pop,
+                instance,
+                program,
+                individual = tracing::field::Empty
+            )
+        };
+        let ctrl = Self {
             env,
             pop,
             cmd: command.to_vec(),
+            opts: options.clone(),
             ctrl,
             stdin,
             stdout,
-        })
+            next_id: 0,
+            profile: None,
+            genotype: None,
+            seed: None,
+            epigenome: HashMap::new(),
+            has_quit: false,
+            vectorized: false,
+            encoding: ValueEncoding::Text,
+            capabilities: None,
+            next_handle: 0,
+            pending: VecDeque::new(),
+            received: HashMap::new(),
+            lost: HashSet::new(),
+            messages: VecDeque::new(),
+            stderr: None,
+            stderr_thread: None,
+            stdout_fd: None,
+            last_contact: Instant::now(),
+            dead: None,
+            #[cfg(feature = "tracing")]
+            span,
+        };
+        Ok(ctrl)
+    }
+
+    /// Negotiate the optional features of the protocol, as requested by the options.
+    fn handshake(&mut self) -> Result<(), io::Error> {
+        if self.opts.value_encoding != ValueEncoding::Text {
+            self.negotiate_encoding(self.opts.value_encoding)?;
+        }
+        if self.opts.query_capabilities {
+            self.query_capabilities()?;
+        }
+        Ok(())
+    }
+
+    /// Ask the controller which optional operations it implements and wait for its answer.
+    fn query_capabilities(&mut self) -> Result<(), io::Error> {
+        writeln!(self.stdin, "C")?;
+        self.stdin.flush().map_err(|err| self.closed(err))?;
+        loop {
+            match self.read_frame()?.0 {
+                Frame::Output { gin, .. } => return Err(ProtocolError::UnexpectedOutput(gin).into()),
+                Frame::Message(Message::Custom {
+                    message_type: 'C',
+                    id: None,
+                    body,
+                }) => {
+                    let names = body.split(',').map(str::trim).filter(|name| !name.is_empty());
+                    // Ignore unknown capabilities, for forward compatibility.
+                    self.capabilities = Some(names.filter_map(Capability::from_name).collect());
+                    return Ok(());
+                }
+                Frame::Message(message) => self.messages.push_back(message),
+            }
+        }
+    }
+
+    /// Check if the controller implements an optional operation.
+    ///
+    /// Controllers are only asked about their capabilities if the
+    /// [ControllerOptions::query_capabilities] option is set, otherwise all
+    /// operations are assumed to be supported.
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.capabilities
+            .as_ref()
+            .map_or(true, |capabilities| capabilities.contains(&capability))
+    }
+
+    /// Get the optional operations which the controller implements, or None if it was not asked.
+    pub fn capabilities(&self) -> Option<&HashSet<Capability>> {
+        self.capabilities.as_ref()
+    }
+
+    fn require(&self, capability: Capability) -> Result<(), io::Error> {
+        if self.supports(capability) {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                UnsupportedOperation { capability },
+            ))
+        }
+    }
+
+    /// Ask the controller to use the given value encoding and wait for its answer.
+    fn negotiate_encoding(&mut self, encoding: ValueEncoding) -> Result<(), io::Error> {
+        writeln!(self.stdin, "F{}", encoding.name())?;
+        self.stdin.flush().map_err(|err| self.closed(err))?;
+        loop {
+            match self.read_frame()?.0 {
+                Frame::Output { gin, .. } => return Err(ProtocolError::UnexpectedOutput(gin).into()),
+                Frame::Message(Message::Custom {
+                    message_type: 'F',
+                    id: None,
+                    body,
+                }) => {
+                    if ValueEncoding::from_name(&body) == Some(encoding) {
+                        self.encoding = encoding;
+                    }
+                    return Ok(());
+                }
+                Frame::Message(message) => self.messages.push_back(message),
+            }
+        }
+    }
+
+    /// Get the encoding which the controller agreed to use for numeric values.
+    pub fn value_encoding(&self) -> ValueEncoding {
+        self.encoding
+    }
+
+    /// Start recording performance statistics about the messages sent to this controller.
+    pub fn enable_profiling(&mut self) {
+        if self.profile.is_none() {
+            self.profile = Some(Profile::default());
+        }
+    }
+
+    /// Get the performance statistics, or None if profiling is not enabled.
+    pub fn get_profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    fn record_sent(&mut self, message_type: char, start_count: u64) -> Result<(), io::Error> {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(parent: &self.span, %message_type, bytes = self.stdin.count - start_count, "sent message");
+        if let Some(profile) = &mut self.profile {
+            let stats = profile.messages.entry(message_type).or_default();
+            stats.count += 1;
+            stats.bytes_sent += self.stdin.count - start_count;
+        }
+        self.stdin.unflushed += 1;
+        self.stdin.oldest_unflushed.get_or_insert_with(Instant::now);
+        if self
+            .opts
+            .flush_policy
+            .is_due(self.stdin.unflushed, self.stdin.oldest_unflushed)
+        {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Send all of the buffered messages to the controller now,
+    /// instead of waiting for the flush policy.
+    pub fn flush(&mut self) -> Result<(), io::Error> {
+        self.stdin.flush().map_err(|err| self.closed(err))
+    }
+
+    fn record_round_trip(&mut self, message_type: char, bytes_received: usize, start_time: Instant) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            parent: &self.span,
+            %message_type,
+            bytes_received,
+            latency_us = start_time.elapsed().as_micros() as u64,
+            "round trip"
+        );
+        if let Some(profile) = &mut self.profile {
+            let latency = start_time.elapsed();
+            let stats = profile.messages.entry(message_type).or_default();
+            stats.bytes_received += bytes_received as u64;
+            stats.round_trips += 1;
+            stats.total_latency += latency;
+            stats.max_latency = stats.max_latency.max(latency);
+        }
+    }
+
+    /// Label this controller's tracing span with the individual which it is currently controlling.
+    ///
+    /// This does nothing unless the crate is compiled with the "tracing" feature.
+    pub fn set_individual(&mut self, individual: u64) {
+        #[cfg(feature = "tracing")]
+        self.span.record("individual", individual);
+        #[cfg(not(feature = "tracing"))]
+        let _ = individual;
+    }
+
+    /// Get this controller's tracing span, for nesting the environment's own spans inside of it.
+    #[cfg(feature = "tracing")]
+    pub fn span(&self) -> &tracing::Span {
+        &self.span
     }
 
     pub fn get_environment(&self) -> &Path {
-        return &self.env;
+        &self.env
     }
 
     pub fn get_population(&self) -> &str {
-        return &self.pop;
+        &self.pop
     }
 
     pub fn get_command(&self) -> &[String] {
-        return &self.cmd;
+        &self.cmd
+    }
+
+    /// Check if the controller is still running.
+    pub fn is_alive(&mut self) -> bool {
+        self.status().alive
+    }
+
+    /// Check if the controller is still running, and if not then find out why.
+    ///
+    /// Controllers which are connected over a socket are assumed to be alive
+    /// until their connection is closed.
+    pub fn status(&mut self) -> ControllerStatus {
+        let mut status = self.dead.clone().unwrap_or_default();
+        match &mut self.ctrl {
+            _ if self.dead.is_some() => {}
+            Process::Child(child) => match child.try_wait() {
+                Ok(None) => status.alive = true,
+                Ok(Some(exit)) => {
+                    status.exit_code = exit.code();
+                    #[cfg(unix)]
+                    {
+                        status.signal = std::os::unix::process::ExitStatusExt::signal(&exit);
+                    }
+                }
+                Err(_) => {}
+            },
+            #[cfg(all(feature = "wasm", unix))]
+            Process::Wasm(guest) => guest.status(&mut status),
+            Process::Socket(_) => status.alive = true,
+        }
+        if let Some(tail) = &self.stderr {
+            status.stderr = tail.lock().unwrap().iter().cloned().collect();
+        }
+        status
+    }
+
+    /// Fail fast if the controller is already known to be dead.
+    /// Also pings the controller if it is due for a heartbeat.
+    fn check_alive(&mut self) -> Result<(), io::Error> {
+        #[cfg(all(feature = "wasm", unix))]
+        if let Process::Wasm(guest) = &self.ctrl {
+            if self.dead.is_none() && guest.failed() {
+                self.dead = Some(self.status());
+            }
+        }
+        self.check_dead()?;
+        if let Some(interval) = self.opts.heartbeat {
+            if self.last_contact.elapsed().as_secs_f64() >= interval {
+                self.ping()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_dead(&self) -> Result<(), io::Error> {
+        match &self.dead {
+            None => Ok(()),
+            Some(status) if status.out_of_memory => Err(io::Error::new(
+                io::ErrorKind::OutOfMemory,
+                ControllerOutOfMemory {
+                    limit: self.opts.memory_limit.unwrap_or_default(),
+                    status: status.clone(),
+                },
+            )),
+            Some(status) => Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                ControllerDead { status: status.clone() },
+            )),
+        }
+    }
+
+    /// Replace errors caused by the controller stopping with a [ControllerDead] error.
+    fn closed(&mut self, error: io::Error) -> io::Error {
+        use io::ErrorKind::*;
+        if !matches!(
+            error.kind(),
+            BrokenPipe | UnexpectedEof | ConnectionReset | ConnectionAborted
+        ) {
+            return error;
+        }
+        let mut status = self.status();
+        if let Process::Child(child) = &mut self.ctrl {
+            // The pipes can close slightly before the process finishes exiting.
+            if status.alive && child.wait().is_ok() {
+                status = self.status();
+            }
+        }
+        // Wait briefly for the rest of stderr, which may explain why the controller died.
+        if let Some(thread) = &self.stderr_thread {
+            let deadline = Instant::now() + Duration::from_millis(100);
+            while !thread.is_finished() && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            status.stderr = self.status().stderr;
+        }
+        status.alive = false;
+        status.out_of_memory = self.opts.memory_limit.is_some() && ran_out_of_memory(&status);
+        #[cfg(feature = "tracing")]
+        tracing::warn!(parent: &self.span, %status, "controller died");
+        self.dead = Some(status);
+        self.check_dead().unwrap_err()
+    }
+
+    /// Get the epigenetic data which the controller has published about the current individual.
+    ///
+    /// Controllers may publish epigenetic data at any time, but it is only
+    /// received while waiting for outputs or responses from the controller.
+    /// Environments should forward this data to the NPC Maker, see
+    /// [crate::env_api::report_info()].
+    pub fn get_epigenome(&self) -> &HashMap<String, String> {
+        &self.epigenome
+    }
+
+    fn receive_epigenome(&mut self, data: &str) -> Result<(), io::Error> {
+        let data: HashMap<String, String> = serde_json::from_str(data)?;
+        self.epigenome.extend(data);
+        Ok(())
     }
 
     /// Initialize the control system with a new genotype.  
     /// This discards the currently loaded model.  
     pub fn new_genotype(&mut self, genotype: &str) -> Result<(), io::Error> {
+        self.check_alive()?;
         debug_assert!(!genotype.contains("\n"));
+        self.epigenome.clear();
+        if let Process::Socket(_) = self.ctrl {
+            self.genotype = Some(genotype.to_string());
+        }
+        let start = self.stdin.count;
         writeln!(self.stdin, "N{genotype}")?;
+        self.record_sent('N', start)?;
+        Ok(())
+    }
+
+    /// Replace the genotype, possibly in the middle of an episode.
+    ///
+    /// The controller must advertise the [Capability::Swap] in order to preserve
+    /// its state. Controllers which do not implement it fall back to reinitializing.
+    pub fn swap_genotype(&mut self, genotype: &str, mode: SwapMode) -> Result<(), io::Error> {
+        if mode == SwapMode::Reinitialize {
+            return self.new_genotype(genotype);
+        }
+        self.require(Capability::Swap)?;
+        self.check_alive()?;
+        debug_assert!(!genotype.contains("\n"));
+        self.epigenome.clear();
+        if let Process::Socket(_) = self.ctrl {
+            self.genotype = Some(genotype.to_string());
+        }
+        let start = self.stdin.count;
+        writeln!(self.stdin, "K{genotype}")?;
+        self.record_sent('K', start)?;
+        Ok(())
+    }
+
+    /// Check that the controller is responding, and measure the round trip time.
+    ///
+    /// The controller replies after it has finished with all of the previous
+    /// messages. Use [ControllerOptions::timeout] to detect controllers which
+    /// have stopped responding. This method blocks on IO.
+    pub fn ping(&mut self) -> Result<Duration, io::Error> {
+        self.check_dead()?;
+        let start_time = Instant::now();
+        let start = self.stdin.count;
+        writeln!(self.stdin, "H")?;
+        self.stdin.flush().map_err(|err| self.closed(err))?;
+        self.record_sent('H', start)?;
+        // The controller replies to all previous output requests first.
+        while !self.pending.is_empty() {
+            self.receive_outputs()?;
+        }
+        let mut bytes_received = 0;
+        loop {
+            let (frame, num_bytes) = self.read_frame()?;
+            bytes_received += num_bytes;
+            match frame {
+                Frame::Output { gin, .. } => return Err(ProtocolError::UnexpectedOutput(gin).into()),
+                Frame::Message(Message::Custom {
+                    message_type: 'H',
+                    id: None,
+                    ..
+                }) => break,
+                Frame::Message(message) => self.messages.push_back(message),
+            }
+        }
+        self.record_round_trip('H', bytes_received, start_time);
+        Ok(start_time.elapsed())
+    }
+
+    /// Wait for the controller to send something, up to the time limit.
+    fn wait_readable(&mut self) -> Result<(), io::Error> {
+        #[cfg(unix)]
+        if let (Some(timeout), Some(fd)) = (self.opts.timeout, self.stdout_fd) {
+            if !self.stdout.buffer().is_empty() {
+                return Ok(());
+            }
+            let mut pollfd = libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let millis = (timeout * 1000.0).ceil().clamp(0.0, i32::MAX as f64) as i32;
+            loop {
+                let ready = unsafe { libc::poll(&mut pollfd, 1, millis) };
+                if ready > 0 {
+                    return Ok(());
+                } else if ready == 0 {
+                    #[cfg(feature = "wasm")]
+                    if let Process::Wasm(guest) = &self.ctrl {
+                        guest.interrupt();
+                    }
+                    let timeout = Duration::from_secs_f64(timeout.max(0.0));
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, ControllerTimeout { timeout }));
+                }
+                let error = io::Error::last_os_error();
+                if error.kind() != io::ErrorKind::Interrupted {
+                    return Err(error);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check if the controller can load a genotype, without loading it.
+    ///
+    /// Returns the controller's error message if the genotype is invalid.
+    /// This is much cheaper than evaluating the genotype, so it can be used
+    /// to screen new offspring for malformed genotypes. This method blocks on IO.
+    pub fn validate_genome(&mut self, genotype: &str) -> Result<Result<(), String>, io::Error> {
+        self.require(Capability::Validate)?;
+        self.check_alive()?;
+        debug_assert!(!genotype.contains("\n"));
+        let start_time = Instant::now();
+        let start = self.stdin.count;
+        writeln!(self.stdin, "V{genotype}")?;
+        self.stdin.flush().map_err(|err| self.closed(err))?;
+        self.record_sent('V', start)?;
+        // The controller replies to all previous output requests first.
+        while !self.pending.is_empty() {
+            self.receive_outputs()?;
+        }
+        let mut bytes_received = 0;
+        let error = loop {
+            let (frame, num_bytes) = self.read_frame()?;
+            bytes_received += num_bytes;
+            match frame {
+                Frame::Output { gin, .. } => return Err(ProtocolError::UnexpectedOutput(gin).into()),
+                Frame::Message(Message::Custom {
+                    message_type: 'V',
+                    id: None,
+                    body,
+                }) => break body,
+                Frame::Message(message) => self.messages.push_back(message),
+            }
+        };
+        self.record_round_trip('V', bytes_received, start_time);
+        if error.is_empty() {
+            Ok(Ok(()))
+        } else {
+            Ok(Err(error))
+        }
+    }
+
+    /// Seed the controller's random number generator, for reproducible evaluations.  
+    /// Call this before [Controller::new_genotype()].  
+    pub fn seed(&mut self, seed: u64) -> Result<(), io::Error> {
+        self.check_alive()?;
+        if let Process::Socket(_) = self.ctrl {
+            self.seed = Some(seed);
+        }
+        let start = self.stdin.count;
+        writeln!(self.stdin, "G{seed}")?;
+        self.record_sent('G', start)?;
         Ok(())
     }
 
     /// Reset the control system to its initial state.
     pub fn reset(&mut self) -> Result<(), io::Error> {
+        self.check_alive()?;
+        let start = self.stdin.count;
         writeln!(self.stdin, "R")?;
+        self.record_sent('R', start)?;
         Ok(())
     }
 
     /// Advance the control system's internal state.
     pub fn advance(&mut self, dt: f64) -> Result<(), io::Error> {
+        self.check_alive()?;
+        let start = self.stdin.count;
         writeln!(self.stdin, "X{dt}")?;
+        self.record_sent('X', start)?;
         Ok(())
     }
 
     /// Write a single value to a GIN in the controller.
+    ///
+    /// If the controller uses a binary value encoding then the value must be a number.
     pub fn set_input(&mut self, gin: u64, value: &str) -> Result<(), io::Error> {
+        if self.encoding != ValueEncoding::Text {
+            let Ok(value) = value.trim().parse() else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("input {value:?} is not a number"),
+                ));
+            };
+            return self.set_input_f64(gin, value);
+        }
+        self.check_alive()?;
         debug_assert!(!value.contains("\n"));
+        let start = self.stdin.count;
         writeln!(self.stdin, "I{gin}:{value}")?;
+        self.record_sent('I', start)?;
+        Ok(())
+    }
+
+    /// Write a single number to a GIN in the controller, using the negotiated value encoding.
+    pub fn set_input_f64(&mut self, gin: u64, value: f64) -> Result<(), io::Error> {
+        self.check_alive()?;
+        let start = self.stdin.count;
+        if self.encoding == ValueEncoding::Text {
+            writeln!(self.stdin, "I{gin}:{value}")?;
+        } else {
+            write!(self.stdin, "I{gin}:")?;
+            self.encoding.write_value(&mut self.stdin, value)?;
+        }
+        self.record_sent('I', start)?;
         Ok(())
     }
 
+    /// Write many values to GINs in the controller, using a single message.
+    ///
+    /// These values are always sent as text, regardless of the value encoding.
+    pub fn set_inputs(&mut self, inputs: &[(u64, &str)]) -> Result<(), io::Error> {
+        self.check_alive()?;
+        let start = self.stdin.count;
+        writeln!(self.stdin, "M{}", inputs.len())?;
+        for (gin, value) in inputs {
+            debug_assert!(!value.contains("\n"));
+            writeln!(self.stdin, "{gin}:{value}")?;
+        }
+        self.record_sent('M', start)?;
+        Ok(())
+    }
+
+    /// Request all outputs using a single message, instead of one message per GIN.
+    ///
+    /// This changes the behavior of the get_outputs() method. The controller
+    /// program must support the "Get Outputs" message.
+    pub fn enable_vectorized_outputs(&mut self) {
+        self.vectorized = true;
+    }
+
     /// Write an array of bytes to a GIN in the controller.
     pub fn set_binary(&mut self, gin: u64, value: &[u8]) -> Result<(), io::Error> {
+        self.require(Capability::SetBinary)?;
+        self.check_alive()?;
+        let start = self.stdin.count;
         writeln!(self.stdin, "B{gin}:{}", value.len())?;
         self.stdin.write_all(value)?;
+        self.record_sent('B', start)?;
         Ok(())
     }
 
@@ -134,49 +1793,322 @@ impl Controller {
     ///
     /// This method blocks on IO.
     pub fn get_outputs(&mut self, gin_list: &[u64]) -> Result<HashMap<u64, String>, io::Error> {
-        // Request the outputs.
-        for gin in gin_list {
-            writeln!(self.stdin, "O{gin}")?;
+        let handle = self.request_outputs(gin_list)?;
+        self.wait_outputs(handle)
+    }
+
+    /// Request a list of outputs without waiting for the controller to respond.
+    ///
+    /// This allows the environment to do other work while the controller is
+    /// computing its outputs, or to request outputs for several agents or
+    /// time steps at once. Use the returned handle to retrieve the outputs
+    /// with [Controller::wait_outputs()].
+    pub fn request_outputs(&mut self, gin_list: &[u64]) -> Result<OutputHandle, io::Error> {
+        self.check_alive()?;
+        let start_time = Instant::now();
+        if self.vectorized {
+            let start = self.stdin.count;
+            write!(self.stdin, "W")?;
+            for (index, gin) in gin_list.iter().enumerate() {
+                if index > 0 {
+                    write!(self.stdin, ",")?;
+                }
+                write!(self.stdin, "{gin}")?;
+            }
+            writeln!(self.stdin)?;
+            self.record_sent('W', start)?;
+        } else {
+            for gin in gin_list {
+                let start = self.stdin.count;
+                writeln!(self.stdin, "O{gin}")?;
+                self.record_sent('O', start)?;
+            }
         }
-        self.stdin.flush()?;
-        // Wait for the controller to respond.
+        self.stdin.flush().map_err(|err| self.closed(err))?;
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.pending.push_back(PendingOutputs {
+            handle,
+            gins: gin_list.to_vec(),
+            message_type: if self.vectorized { 'W' } else { 'O' },
+            start_time,
+        });
+        Ok(OutputHandle(handle))
+    }
+
+    /// Retrieve the outputs which were previously requested.
+    ///
+    /// Requests which were outstanding when the controller reconnected
+    /// fail with a [ControllerDead] error, see [Controller::reconnect()].
+    ///
+    /// This method blocks on IO.
+    pub fn wait_outputs(&mut self, handle: OutputHandle) -> Result<HashMap<u64, String>, io::Error> {
+        self.check_alive()?;
+        if self.lost.remove(&handle.0) {
+            let status = ControllerStatus {
+                stderr: vec!["connection closed by reconnect() before the outputs were received".to_string()],
+                ..Default::default()
+            };
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe, ControllerDead { status }));
+        }
+        loop {
+            if let Some(outputs) = self.received.remove(&handle.0) {
+                return Ok(outputs);
+            }
+            if self.pending.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "unknown output handle"));
+            }
+            self.receive_outputs()?;
+        }
+    }
+
+    /// Read the replies to the oldest outstanding output request.
+    fn receive_outputs(&mut self) -> Result<(), io::Error> {
+        let Some(pending) = self.pending.pop_front() else {
+            return Ok(());
+        };
         let mut outputs = HashMap::<u64, String>::new();
-        let mut message = String::new();
-        while outputs.len() < gin_list.len() {
-            message.clear();
-            self.stdout.read_line(&mut message)?;
-            message.pop(); // Discard the trailing newline.
-            let mut parts = message.splitn(2, ":");
-            let gin = parts.next().unwrap();
-            let value = parts.next().unwrap();
-            let gin = gin.parse().unwrap();
-            outputs.insert(gin, value.to_string());
+        let mut bytes_received = 0;
+        while outputs.len() < pending.gins.len() {
+            let (frame, num_bytes) = self.read_frame()?;
+            bytes_received += num_bytes;
+            match frame {
+                Frame::Output { gin, value } => {
+                    if !pending.gins.contains(&gin) {
+                        return Err(ProtocolError::UnexpectedOutput(gin).into());
+                    }
+                    outputs.insert(gin, value);
+                }
+                Frame::Message(message) => self.messages.push_back(message),
+            }
+        }
+        self.record_round_trip(pending.message_type, bytes_received, pending.start_time);
+        self.received.insert(pending.handle, outputs);
+        Ok(())
+    }
+
+    /// Save the current state of the control system to file.
+    pub fn save(&mut self, path: impl AsRef<Path>) -> Result<(), io::Error> {
+        self.require(Capability::Save)?;
+        self.check_alive()?;
+        let path = path.as_ref().to_str().unwrap();
+        let start = self.stdin.count;
+        writeln!(self.stdin, "S{path}")?;
+        self.stdin.flush().map_err(|err| self.closed(err))?;
+        self.record_sent('S', start)?;
+        Ok(())
+    }
+    ///  Load the state of the control system from file.
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<(), io::Error> {
+        self.require(Capability::Load)?;
+        self.check_alive()?;
+        let path = path.as_ref().to_str().unwrap();
+        let start = self.stdin.count;
+        writeln!(self.stdin, "L{path}")?;
+        self.record_sent('L', start)?;
+        Ok(())
+    }
+
+    /// Save the current state of the control system to file,
+    /// and return a description of how to restore it.
+    ///
+    /// The controller writes its save file asynchronously.
+    pub fn checkpoint(&mut self, path: impl AsRef<Path>) -> Result<SavedController, io::Error> {
+        let path = path.as_ref();
+        self.save(path)?;
+        Ok(SavedController {
+            environment: self.env.clone(),
+            population: self.pop.clone(),
+            command: self.cmd.clone(),
+            options: self.opts.clone(),
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Start a new controller process and load a previously saved state into it.
+    pub fn restore(saved: &SavedController) -> Result<Self, io::Error> {
+        let mut ctrl = Self::with_options(&saved.environment, &saved.population, &saved.command, &saved.options)?;
+        ctrl.load(&saved.path)?;
+        Ok(ctrl)
+    }
+
+    /// Send a custom message to the controller using a new message type.
+    ///
+    /// Argument message_type is a single capital letter, which is not already
+    /// in use by the protocol.
+    pub fn message(&mut self, message_type: char, body: &str) -> Result<(), io::Error> {
+        self.check_alive()?;
+        debug_assert!(!body.contains("\n"));
+        check_custom_type(message_type)?;
+        self.require(Capability::Custom(message_type))?;
+        let start = self.stdin.count;
+        writeln!(self.stdin, "{message_type}:{body}")?;
+        self.record_sent(message_type, start)?;
+        Ok(())
+    }
+
+    /// Send a typed custom message to the controller, without waiting for a response.
+    pub fn send<M: CustomMessage>(&mut self, message: &M) -> Result<(), io::Error> {
+        let body = serde_json::to_string(message)?;
+        self.message(M::TYPE, &body)
+    }
+
+    /// Send a typed custom message to the controller and wait for its response.
+    ///
+    /// This method blocks on IO.
+    pub fn request<M: CustomMessage>(&mut self, message: &M) -> Result<M::Response, io::Error> {
+        self.check_alive()?;
+        check_custom_type(M::TYPE)?;
+        self.require(Capability::Custom(M::TYPE))?;
+        let id = self.next_id;
+        self.next_id += 1;
+        let body = serde_json::to_string(message)?;
+        let start_time = Instant::now();
+        let start = self.stdin.count;
+        writeln!(self.stdin, "{}{id}:{body}", M::TYPE)?;
+        self.stdin.flush().map_err(|err| self.closed(err))?;
+        self.record_sent(M::TYPE, start)?;
+        // The controller replies to all previous output requests first.
+        while !self.pending.is_empty() {
+            self.receive_outputs()?;
+        }
+        // Wait for the controller to respond.
+        let mut bytes_received = 0;
+        let is_reply = |message: &Message| {
+            matches!(message, Message::Custom { message_type, id: Some(reply_id), .. }
+                if *message_type == M::TYPE && *reply_id == id)
+        };
+        let reply = if let Some(index) = self.messages.iter().position(is_reply) {
+            self.messages.remove(index).unwrap()
+        } else {
+            loop {
+                let (frame, num_bytes) = self.read_frame()?;
+                bytes_received += num_bytes;
+                match frame {
+                    Frame::Output { gin, .. } => return Err(ProtocolError::UnexpectedOutput(gin).into()),
+                    Frame::Message(message) if is_reply(&message) => break message,
+                    Frame::Message(message) => self.messages.push_back(message),
+                }
+            }
+        };
+        let Message::Custom { body, .. } = reply else {
+            unreachable!()
+        };
+        let response = serde_json::from_str(&body)?;
+        self.record_round_trip(M::TYPE, bytes_received, start_time);
+        Ok(response)
+    }
+
+    /// Get the custom messages which the controller sent without being asked,
+    /// and replies to requests which are no longer waiting.
+    ///
+    /// Messages are only received while waiting for outputs or responses from the controller.
+    pub fn drain_messages(&mut self) -> impl Iterator<Item = Message> + '_ {
+        self.messages.drain(..)
+    }
+
+    /// Read the next frame from the controller's stdout. Epigenome frames are
+    /// handled here and are not returned. Also returns the number of bytes read.
+    fn read_frame(&mut self) -> Result<(Frame, usize), io::Error> {
+        let mut line = String::new();
+        let mut bytes_received = 0;
+        loop {
+            self.wait_readable()?;
+            self.last_contact = Instant::now();
+            if self.encoding != ValueEncoding::Text {
+                let first = self.stdout.fill_buf().map(|buffer| buffer.first().copied());
+                if first
+                    .map_err(|err| self.closed(err))?
+                    .is_some_and(|byte| byte.is_ascii_digit())
+                {
+                    return self
+                        .read_binary_output()
+                        .map(|(frame, num_bytes)| (frame, bytes_received + num_bytes));
+                }
+            }
+            line.clear();
+            let num_bytes = read_line_limited(&mut self.stdout, &mut line, DEFAULT_BINARY_LIMIT);
+            let num_bytes = num_bytes.map_err(|err| self.closed(err))?;
+            if num_bytes == 0 {
+                return Err(self.closed(io::ErrorKind::UnexpectedEof.into()));
+            }
+            bytes_received += num_bytes;
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(data) = line.strip_prefix('D') {
+                self.receive_epigenome(data)?;
+                continue;
+            }
+            if let Some(message) = line.strip_prefix("E:") {
+                let message = message.to_string();
+                return Err(io::Error::other(ControllerPanic { message }));
+            }
+            return Ok((Frame::parse(line)?, bytes_received));
         }
-        Ok(outputs)
     }
 
-    /// Save the current state of the control system to file.
-    pub fn save(&mut self, path: impl AsRef<Path>) -> Result<(), io::Error> {
-        let path = path.as_ref().to_str().unwrap();
-        writeln!(self.stdin, "S{path}")?;
-        self.stdin.flush()?;
-        Ok(())
+    /// Read an output which is in a binary value encoding.
+    fn read_binary_output(&mut self) -> Result<(Frame, usize), io::Error> {
+        let mut buffer = Vec::new();
+        let header = read_value_header(&mut self.stdout, &mut buffer);
+        let header = header.map_err(|err| self.closed(err))?;
+        let Ok(gin) = header.trim().parse() else {
+            return Err(ProtocolError::Malformed(header.into_owned()).into());
+        };
+        let value = self
+            .encoding
+            .read_value(&mut self.stdout)
+            .map_err(|err| self.closed(err))?;
+        let num_bytes = buffer.len() + 1 + self.encoding.size() + 1;
+        let value = value.to_string();
+        Ok((Frame::Output { gin, value }, num_bytes))
     }
-    ///  Load the state of the control system from file.
-    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<(), io::Error> {
-        let path = path.as_ref().to_str().unwrap();
-        writeln!(self.stdin, "L{path}")?;
+
+    /// Write a comment into the message stream, which the controller ignores.
+    ///
+    /// Comments are useful for annotating protocol traces.
+    pub fn comment(&mut self, text: &str) -> Result<(), io::Error> {
+        self.check_alive()?;
+        debug_assert!(!text.contains("\n"));
+        let start = self.stdin.count;
+        writeln!(self.stdin, "#{text}")?;
+        self.record_sent('#', start)?;
         Ok(())
     }
 
     /// Stop running the controller process.
+    ///
+    /// The controller is sent an explicit quit message so that it can finish
+    /// any outstanding work before exiting. This is called automatically when
+    /// the controller is dropped. Calling it more than once has no effect.
     pub fn quit(&mut self) -> Result<(), io::Error> {
+        if self.has_quit || self.dead.is_some() {
+            return Ok(());
+        }
+        self.has_quit = true;
+        let start = self.stdin.count;
         writeln!(self.stdin, "Q")?;
+        self.record_sent('Q', start)?;
         self.stdin.flush()?;
         Ok(())
     }
 }
 
+#[cfg(feature = "spawn")]
+impl std::fmt::Debug for Controller {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Controller")
+            .field("env", &self.env)
+            .field("pop", &self.pop)
+            .field("cmd", &self.cmd)
+            .field("ctrl", &self.ctrl)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "spawn")]
 impl Drop for Controller {
     fn drop(&mut self) {
         let _ = self.quit();
@@ -188,107 +2120,573 @@ impl Drop for Controller {
 /// These messages are transmitted over the controller stdin channel.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Message {
-    Environment { environment: PathBuf },
-    Population { population: String },
-    New { genotype: String },
+    Environment {
+        environment: PathBuf,
+    },
+    Population {
+        population: String,
+    },
+    /// Ask the controller to use a binary value encoding, see [ValueEncoding].
+    /// The controller replies with `F:<encoding>`, naming the encoding which it will use.
+    ValueEncoding {
+        encoding: String,
+    },
+    /// Ask which optional operations the controller implements, see [Capability].
+    /// The controller replies with `C:<capabilities>`, a comma separated list of their names.
+    Capabilities,
+    New {
+        genotype: String,
+    },
+    /// Replace the genotype while preserving the internal state, see [SwapMode::PreserveState].
+    Swap {
+        genotype: String,
+    },
+    /// Check the genotype without loading it. The controller replies with `V:<error>`.
+    Validate {
+        genotype: String,
+    },
+    Seed {
+        seed: u64,
+    },
+    /// Direct all subsequent messages to the given agent, see [Multiplexer].
+    Select {
+        agent: u64,
+    },
+    /// Check that the controller is responding. The controller replies with "H:".
+    Ping,
     Reset,
-    Advance { dt: f64 },
-    SetInput { gin: u64, value: String },
-    SetBinary { gin: u64, bytes: Vec<u8> },
-    GetOutput { gin: u64 },
-    Save { path: PathBuf },
-    Load { path: PathBuf },
+    Advance {
+        dt: f64,
+    },
+    SetInput {
+        gin: u64,
+        value: String,
+    },
+    SetInputs {
+        inputs: Vec<(u64, String)>,
+    },
+    SetBinary {
+        gin: u64,
+        bytes: Vec<u8>,
+    },
+    GetOutput {
+        gin: u64,
+    },
+    GetOutputs {
+        gins: Vec<u64>,
+    },
+    Save {
+        path: PathBuf,
+    },
+    Load {
+        path: PathBuf,
+    },
     Quit,
+    /// User defined message type. Messages with an `id` expect a response.
+    Custom {
+        message_type: char,
+        id: Option<u64>,
+        body: String,
+    },
 }
 
 impl Message {
     /// Format this message and write it to the given stream.
     pub fn write(&self, writer: &mut impl Write) -> Result<(), io::Error> {
+        let mut out = IoWriter { writer, error: None };
+        let mut result = codec::encode(&self.header(), &mut out);
+        if let Self::SetInputs { inputs } = self {
+            for (gin, value) in inputs {
+                result = result.and_then(|_| codec::encode_input(*gin, value, &mut out));
+            }
+        }
+        if result.is_err() {
+            return Err(out.error.unwrap_or_else(|| io::Error::other("formatting error")));
+        }
+        if let Self::SetBinary { bytes, .. } = self {
+            writer.write_all(bytes.as_slice())?;
+        }
+        Ok(())
+    }
+
+    /// Get the first line of this message.
+    fn header(&self) -> codec::Header<'_> {
+        use codec::Header;
         match self {
-            Self::Environment { environment } => write!(writer, "E{}\n", environment.to_str().unwrap())?,
+            Self::Environment { environment } => Header::Environment {
+                environment: environment.to_str().unwrap(),
+            },
+            Self::Population { population } => Header::Population { population },
+            Self::ValueEncoding { encoding } => Header::ValueEncoding { encoding },
+            Self::Capabilities => Header::Capabilities,
+            Self::New { genotype } => Header::New { genotype },
+            Self::Swap { genotype } => Header::Swap { genotype },
+            Self::Validate { genotype } => Header::Validate { genotype },
+            Self::Seed { seed } => Header::Seed { seed: *seed },
+            Self::Select { agent } => Header::Select { agent: *agent },
+            Self::Ping => Header::Ping,
+            Self::Reset => Header::Reset,
+            Self::Advance { dt } => Header::Advance { dt: *dt },
+            Self::SetInput { gin, value } => Header::SetInput { gin: *gin, value },
+            Self::SetInputs { inputs } => Header::SetInputs {
+                num_inputs: inputs.len(),
+            },
+            Self::SetBinary { gin, bytes } => Header::SetBinary {
+                gin: *gin,
+                num_bytes: bytes.len(),
+            },
+            Self::GetOutput { gin } => Header::GetOutput { gin: *gin },
+            Self::GetOutputs { gins } => Header::GetOutputs {
+                gins: codec::Gins::new(gins),
+            },
+            Self::Save { path } => Header::Save {
+                path: path.to_str().unwrap(),
+            },
+            Self::Load { path } => Header::Load {
+                path: path.to_str().unwrap(),
+            },
+            Self::Quit => Header::Quit,
+            Self::Custom { message_type, id, body } => Header::Custom {
+                message_type: *message_type,
+                id: *id,
+                body,
+            },
+        }
+    }
+
+    /// Parse the next message from the given input stream. Blocking.
+    ///
+    /// Malformed messages are reported as a [MessageError].
+    pub fn read(reader: &mut impl BufRead) -> Result<Message, io::Error> {
+        let mut line = String::new();
+        read_line(reader, &mut line, DEFAULT_BINARY_LIMIT)?;
+        Self::parse(&line, reader)
+    }
+
+    /// Parse a message from its first line. Multi-line messages read the rest of their data from the given stream.
+    fn parse(line: &str, reader: &mut impl BufRead) -> Result<Message, io::Error> {
+        let header = codec::decode_str(line).map_err(|error| MessageError::new(line, error))?;
+        Self::from_header(header, reader, DEFAULT_BINARY_LIMIT)
+    }
+
+    fn from_header(
+        header: codec::Header,
+        reader: &mut impl BufRead,
+        binary_limit: usize,
+    ) -> Result<Message, io::Error> {
+        use codec::Header;
+        let message = match header {
+            Header::Environment { environment } => Self::Environment {
+                environment: environment.into(),
+            },
+            Header::Population { population } => Self::Population {
+                population: population.to_string(),
+            },
+            Header::ValueEncoding { encoding } => Self::ValueEncoding {
+                encoding: encoding.to_string(),
+            },
+            Header::Capabilities => Self::Capabilities,
+            Header::New { genotype } => Self::New {
+                genotype: genotype.to_string(),
+            },
+            Header::Swap { genotype } => Self::Swap {
+                genotype: genotype.to_string(),
+            },
+            Header::Validate { genotype } => Self::Validate {
+                genotype: genotype.to_string(),
+            },
+            Header::Seed { seed } => Self::Seed { seed },
+            Header::Select { agent } => Self::Select { agent },
+            Header::Ping => Self::Ping,
+            Header::Reset => Self::Reset,
+            Header::Advance { dt } => Self::Advance { dt },
+            Header::SetInput { gin, value } => Self::SetInput {
+                gin,
+                value: value.to_string(),
+            },
+            Header::SetInputs { num_inputs } => {
+                // Don't trust the length prefix for the initial allocation.
+                let mut inputs = Vec::with_capacity(num_inputs.min(1024));
+                let mut input = String::new();
+                for _ in 0..num_inputs {
+                    input.clear();
+                    if read_line_limited(reader, &mut input, binary_limit)? == 0 {
+                        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "input stream closed"));
+                    }
+                    if input.ends_with('\n') {
+                        input.pop();
+                    }
+                    let (gin, value) = codec::decode_input(&input).map_err(|error| MessageError::new(&input, error))?;
+                    inputs.push((gin, value.to_string()));
+                }
+                Self::SetInputs { inputs }
+            }
+            Header::SetBinary { gin, num_bytes } => Self::SetBinary {
+                gin,
+                bytes: read_binary(reader, num_bytes, binary_limit)?,
+            },
+            Header::GetOutput { gin } => Self::GetOutput { gin },
+            Header::GetOutputs { gins } => Self::GetOutputs {
+                gins: gins.iter().collect(),
+            },
+            Header::Save { path } => Self::Save { path: path.into() },
+            Header::Load { path } => Self::Load { path: path.into() },
+            Header::Quit => Self::Quit,
+            Header::Custom { message_type, id, body } => Self::Custom {
+                message_type,
+                id,
+                body: body.to_string(),
+            },
+        };
+        Ok(message)
+    }
+}
+
+/// Default value of [MessageReader::set_binary_limit()], in bytes.
+///
+/// This also limits the length of each line of text.
+pub const DEFAULT_BINARY_LIMIT: usize = 1 << 30;
+
+/// Error for a message which is larger than the reader allows, see [MessageReader::set_binary_limit()].
+///
+/// This is returned inside of an [io::Error] with the kind [io::ErrorKind::InvalidData].
+/// The data is not read, so the stream can not be used after this error.
+/// Use [MessageTooLarge::from_io()] to retrieve it.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+#[error("message of {num_bytes} bytes exceeds the limit of {limit} bytes")]
+pub struct MessageTooLarge {
+    pub num_bytes: usize,
+    pub limit: usize,
+}
 
-            Self::Population { population } => write!(writer, "P{population}\n")?,
+impl MessageTooLarge {
+    /// Check if an I/O error was caused by a message which is too large.
+    pub fn from_io(error: &io::Error) -> Option<&MessageTooLarge> {
+        error.get_ref()?.downcast_ref()
+    }
+}
 
-            Self::New { genotype } => write!(writer, "N{genotype}\n")?,
+/// Read the data of a binary message, given its length prefix.
+///
+/// The data is read in chunks, so that the memory is allocated as the data
+/// arrives instead of trusting the length prefix up front.
+pub fn read_binary(reader: &mut impl Read, num_bytes: usize, limit: usize) -> Result<Vec<u8>, io::Error> {
+    let mut bytes = Vec::new();
+    read_binary_chunks(reader, num_bytes, limit, |chunk| {
+        bytes.extend_from_slice(chunk);
+        Ok(())
+    })?;
+    Ok(bytes)
+}
 
-            Self::Reset => write!(writer, "R\n")?,
+/// Stream the data of a binary message through a callback, one chunk at a time, without holding all of it in memory.
+///
+/// Argument limit is the maximum number of bytes to accept, anything larger is rejected with [MessageTooLarge].
+pub fn read_binary_chunks(
+    reader: &mut impl Read,
+    num_bytes: usize,
+    limit: usize,
+    mut callback: impl FnMut(&[u8]) -> Result<(), io::Error>,
+) -> Result<(), io::Error> {
+    if num_bytes > limit {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            MessageTooLarge { num_bytes, limit },
+        ));
+    }
+    let mut chunk = [0; 8192];
+    let mut remaining = num_bytes;
+    while remaining > 0 {
+        let chunk = &mut chunk[..remaining.min(8192)];
+        reader.read_exact(chunk)?;
+        callback(chunk)?;
+        remaining -= chunk.len();
+    }
+    Ok(())
+}
 
-            Self::Advance { dt } => write!(writer, "X{dt}\n")?,
+/// Adapter for writing formatted text into a byte stream.
+struct IoWriter<'a, W: Write> {
+    writer: &'a mut W,
+    error: Option<io::Error>,
+}
 
-            Self::SetInput { gin, value } => write!(writer, "I{gin}:{value}\n")?,
+impl<W: Write> std::fmt::Write for IoWriter<'_, W> {
+    fn write_str(&mut self, text: &str) -> std::fmt::Result {
+        self.writer.write_all(text.as_bytes()).map_err(|error| {
+            self.error = Some(error);
+            std::fmt::Error
+        })
+    }
+}
 
-            Self::SetBinary { gin, bytes } => write!(writer, "B{gin}:{}\n", bytes.len())?,
+impl std::error::Error for DecodeError {}
 
-            Self::GetOutput { gin } => write!(writer, "O{gin}\n")?,
+/// Error for receiving a malformed message, see [Message::read()].
+///
+/// This is returned inside of an [io::Error] with the kind [io::ErrorKind::InvalidData],
+/// or [io::ErrorKind::Unsupported] for unknown message types.
+/// Use [MessageError::from_io()] to retrieve it.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+#[error("{error}: {line:?}")]
+pub struct MessageError {
+    /// The offending line, without its trailing newline.
+    pub line: String,
+    pub error: DecodeError,
+}
 
-            Self::Save { path } => write!(writer, "S{}\n", path.to_str().unwrap())?,
+impl MessageError {
+    fn new(line: &str, error: DecodeError) -> Self {
+        Self {
+            line: line.to_string(),
+            error,
+        }
+    }
 
-            Self::Load { path } => write!(writer, "L{}\n", path.to_str().unwrap())?,
+    /// Check if an I/O error was caused by a malformed message.
+    pub fn from_io(error: &io::Error) -> Option<&MessageError> {
+        error.get_ref()?.downcast_ref()
+    }
+}
 
-            Self::Quit => write!(writer, "Q\n")?,
+impl From<MessageError> for io::Error {
+    fn from(error: MessageError) -> Self {
+        let kind = match error.error {
+            DecodeError::UnknownType(_) => io::ErrorKind::Unsupported,
+            _ => io::ErrorKind::InvalidData,
         };
-        if let Self::SetBinary { bytes, .. } = self {
-            writer.write_all(bytes.as_slice())?;
+        io::Error::new(kind, error)
+    }
+}
+
+/// What to do with malformed messages, see [API::malformed_message()].
+#[cfg(feature = "ctrl")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Stop the main loop and return the error.
+    Abort,
+    /// Ignore the message.
+    Skip,
+    /// Write the error to stderr and ignore the message.
+    Report,
+}
+
+/// Error for an input value which is not in the expected format, see [API::parse_f64()].
+#[cfg(feature = "ctrl")]
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+#[error("invalid value {value:?} for input gin {gin}, expected {expected}")]
+pub struct InputError {
+    pub gin: u64,
+    pub value: String,
+    /// Description of the expected format.
+    pub expected: &'static str,
+}
+
+#[cfg(feature = "ctrl")]
+impl InputError {
+    fn new(gin: u64, value: &str, expected: &'static str) -> Self {
+        Self {
+            gin,
+            value: value.to_string(),
+            expected,
         }
-        Ok(())
     }
+}
 
-    /// Parse the next message from the given input stream. Blocking.
-    pub fn read(reader: &mut impl BufRead) -> Result<Message, io::Error> {
-        let mut line = String::new();
-        while line.is_empty() {
-            reader.read_line(&mut line)?;
-            line.pop(); // Remove the trailing newline.
+/// Read the next line which is not empty and not a comment.
+fn read_line(reader: &mut impl BufRead, line: &mut String, limit: usize) -> Result<(), io::Error> {
+    loop {
+        line.clear();
+        if read_line_limited(reader, line, limit)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "input stream closed"));
+        }
+        if line.ends_with('\n') {
+            line.pop();
+        }
+        if !line.is_empty() && !line.starts_with('#') {
+            return Ok(());
+        }
+    }
+}
+
+/// Borrowed view of a message, see [MessageReader].
+///
+/// The most frequent messages are decoded without allocating any memory.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MessageRef<'a> {
+    Advance {
+        dt: f64,
+    },
+    SetInput {
+        gin: u64,
+        value: &'a str,
+    },
+    /// Set input message in a binary value encoding.
+    SetInputF64 {
+        gin: u64,
+        value: f64,
+    },
+    GetOutput {
+        gin: u64,
+    },
+    /// All other types of messages.
+    Other(Message),
+}
+
+/// Reads messages from a stream, reusing its internal buffers between messages.
+#[derive(Debug)]
+pub struct MessageReader<R: BufRead> {
+    reader: R,
+    line: String,
+    encoding: ValueEncoding,
+    buffer: Vec<u8>,
+    binary_limit: usize,
+}
+
+impl<R: BufRead> MessageReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: String::new(),
+            encoding: ValueEncoding::Text,
+            buffer: Vec::new(),
+            binary_limit: DEFAULT_BINARY_LIMIT,
+        }
+    }
+
+    /// Set the maximum size of binary messages and of lines of text, in bytes.
+    /// Larger messages are rejected with a [MessageTooLarge] error, instead of allocating memory for them.
+    pub fn set_binary_limit(&mut self, limit: usize) {
+        self.binary_limit = limit;
+    }
+
+    /// Get the encoding of the input values.
+    pub fn value_encoding(&self) -> ValueEncoding {
+        self.encoding
+    }
+
+    /// Change the encoding of the input values, after negotiating it with the environment.
+    pub fn set_value_encoding(&mut self, encoding: ValueEncoding) {
+        self.encoding = encoding;
+    }
+
+    /// Parse the next message from the stream. Blocking.
+    pub fn read(&mut self) -> Result<MessageRef<'_>, io::Error> {
+        if self.encoding != ValueEncoding::Text && self.reader.fill_buf()?.first() == Some(&b'I') {
+            let header = read_value_header(&mut self.reader, &mut self.buffer)?;
+            let Some(gin) = header.strip_prefix('I').and_then(|gin| gin.trim().parse().ok()) else {
+                return Err(MessageError::new(&header, DecodeError::Malformed('I')).into());
+            };
+            let value = self.encoding.read_value(&mut self.reader)?;
+            return Ok(MessageRef::SetInputF64 { gin, value });
         }
-        let Some((msg_type, msg_body)) = line.split_at_checked(1) else {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "error message"));
+        read_line(&mut self.reader, &mut self.line, self.binary_limit)?;
+        let header = codec::decode_str(&self.line).map_err(|error| MessageError::new(&self.line, error))?;
+        let message = match header {
+            codec::Header::Advance { dt } => MessageRef::Advance { dt },
+            codec::Header::SetInput { gin, value } => MessageRef::SetInput { gin, value },
+            codec::Header::GetOutput { gin } => MessageRef::GetOutput { gin },
+            header => MessageRef::Other(Message::from_header(header, &mut self.reader, self.binary_limit)?),
         };
-        let msg_data = match msg_type {
-            "E" => Self::Environment {
-                environment: msg_body.into(),
-            },
-            "P" => Self::Population {
-                population: msg_body.to_string(),
-            },
-            "N" => Self::New {
-                genotype: msg_body.to_string(),
-            },
-            "R" => Self::Reset,
-            "I" => {
-                let Some((gin, value)) = msg_body.split_once(":") else {
-                    return Err(io::Error::new(io::ErrorKind::InvalidData, "error message"));
-                };
-                Self::SetInput {
-                    gin: gin.trim().parse::<u64>().unwrap(),
-                    value: value.to_string(),
-                }
-            }
-            "B" => {
-                let Some((gin, num_bytes)) = msg_body.split_once(":") else {
-                    return Err(io::Error::new(io::ErrorKind::InvalidData, "error message"));
-                };
-                let num_bytes = num_bytes.trim().parse::<usize>().unwrap();
-                let mut bytes = vec![0; num_bytes];
-                reader.read_exact(&mut bytes).unwrap();
-                Self::SetBinary {
-                    gin: gin.trim().parse::<u64>().unwrap(),
-                    bytes,
-                }
-            }
-            "X" => Self::Advance {
-                dt: msg_body.parse::<f64>().unwrap(),
-            },
-            "O" => Self::GetOutput {
-                gin: msg_body.parse::<u64>().unwrap(),
-            },
-            "S" => Self::Save { path: msg_body.into() },
-            "L" => Self::Load { path: msg_body.into() },
-            "Q" => Self::Quit,
-            _ => {
-                return Err(io::Error::new(io::ErrorKind::Unsupported, "error message"));
-            }
+        Ok(message)
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+fn check_custom_type(message_type: char) -> Result<(), io::Error> {
+    if message_type.is_ascii_uppercase() && !codec::RESERVED_TYPES.contains(message_type) {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid custom message type {message_type:?}"),
+        ))
+    }
+}
+
+/// Interface for custom message types, for extending the controller protocol.
+///
+/// The message payloads are encoded as JSON.
+pub trait CustomMessage: Serialize + DeserializeOwned {
+    /// Single capital letter which identifies this type of message.  
+    /// It must not be already in use by the protocol.  
+    const TYPE: char;
+
+    /// Type of the controller's reply to this message. Use `()` if there is no reply.
+    type Response: Serialize + DeserializeOwned;
+}
+
+#[cfg(feature = "ctrl")]
+type Handler<C> = Box<dyn FnMut(&mut C, &str) -> Result<String, io::Error>>;
+
+/// Collection of handlers for custom message types, for implementing controllers.
+///
+/// See [API::custom_messages()].
+#[cfg(feature = "ctrl")]
+pub struct Registry<C> {
+    handlers: HashMap<char, Handler<C>>,
+}
+
+#[cfg(feature = "ctrl")]
+impl<C> Default for Registry<C> {
+    fn default() -> Self {
+        Self {
+            handlers: HashMap::new(),
+        }
+    }
+}
+
+#[cfg(feature = "ctrl")]
+impl<C> Registry<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a handler function for a custom message type.
+    ///
+    /// Panics if the message type is invalid or already registered.
+    pub fn register<M: CustomMessage>(
+        &mut self,
+        mut handler: impl FnMut(&mut C, M) -> M::Response + 'static,
+    ) -> &mut Self {
+        check_custom_type(M::TYPE).unwrap();
+        assert!(
+            !self.handlers.contains_key(&M::TYPE),
+            "duplicate custom message type {:?}",
+            M::TYPE
+        );
+        self.handlers.insert(
+            M::TYPE,
+            Box::new(move |controller, body| {
+                let message: M = serde_json::from_str(body)?;
+                let response = handler(controller, message);
+                Ok(serde_json::to_string(&response)?)
+            }),
+        );
+        self
+    }
+
+    /// Check if there is a handler for the given message type.
+    pub fn contains(&self, message_type: char) -> bool {
+        self.handlers.contains_key(&message_type)
+    }
+
+    /// Decode a custom message and call its handler. Returns the encoded response.
+    pub fn dispatch(&mut self, controller: &mut C, message_type: char, body: &str) -> Result<String, io::Error> {
+        let Some(handler) = self.handlers.get_mut(&message_type) else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("unregistered custom message type {message_type:?}"),
+            ));
         };
-        Ok(msg_data)
+        handler(controller, body)
     }
 }
 
@@ -296,41 +2694,273 @@ impl Message {
 ///
 /// Controllers should implement this trait. Call "npc_maker::ctrl::main_loop()"
 /// with an instance of the implementation to run it as a controller program.
-pub trait Controller {
+#[cfg(feature = "ctrl")]
+pub trait API {
+    #[allow(clippy::new_ret_no_self, clippy::wrong_self_convention)]
     fn new(&mut self, genotype: String);
 
+    /// Replace the genotype while preserving the internal state, see [SwapMode::PreserveState].
+    ///
+    /// The controller should keep whatever state is compatible with the new
+    /// genotype and initialize the rest. Advertise [Capability::Swap] when
+    /// implementing this. By default this discards the current model, the same as new().
+    fn swap(&mut self, genotype: String) {
+        self.new(genotype)
+    }
+
+    /// Check if a genotype is valid, without loading it.
+    ///
+    /// Returns a description of the problem if the genotype is invalid.
+    /// By default all genotypes are accepted.
+    fn validate(&mut self, _genotype: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Seed the controller's random number generator.
+    ///
+    /// This is called before new() for evaluations which should be reproducible.
+    /// Controllers which are not stochastic can ignore it.
+    fn seed(&mut self, _seed: u64) {}
+
     fn reset(&mut self);
 
     fn advance(&mut self, dt: f64);
 
     fn set_input(&mut self, gin: u64, value: String);
 
-    fn set_binary(&mut self, gin: u64, bytes: Vec<u8>) {
+    fn set_binary(&mut self, _gin: u64, _bytes: Vec<u8>) {
         panic!("unsupported operation: set_binary")
     }
 
     fn get_output(&mut self, gin: u64) -> String;
 
-    fn save(&mut self, path: PathBuf) {
+    /// Receive a number, if the environment uses a binary value encoding.
+    fn set_input_f64(&mut self, gin: u64, value: f64) {
+        self.set_input(gin, value.to_string())
+    }
+
+    /// Send a number, if the environment uses a binary value encoding.
+    fn get_output_f64(&mut self, gin: u64) -> f64 {
+        self.get_output(gin).trim().parse().unwrap_or(f64::NAN)
+    }
+
+    /// Parse an input value as a number. Surrounding whitespace is ignored.
+    fn parse_f64(&self, gin: u64, value: &str) -> Result<f64, InputError> {
+        value
+            .trim()
+            .parse()
+            .map_err(|_| InputError::new(gin, value, "a number"))
+    }
+
+    /// Parse an input value as an integer. Surrounding whitespace is ignored.
+    fn parse_i64(&self, gin: u64, value: &str) -> Result<i64, InputError> {
+        value
+            .trim()
+            .parse()
+            .map_err(|_| InputError::new(gin, value, "an integer"))
+    }
+
+    /// Parse an input value as a boolean, either "true", "false", "1", or "0".
+    fn parse_bool(&self, gin: u64, value: &str) -> Result<bool, InputError> {
+        match value.trim() {
+            "true" | "1" => Ok(true),
+            "false" | "0" => Ok(false),
+            _ => Err(InputError::new(gin, value, "a boolean")),
+        }
+    }
+
+    /// Parse an input value as JSON.
+    fn parse_json<T: DeserializeOwned>(&self, gin: u64, value: &str) -> Result<T, InputError>
+    where
+        Self: Sized,
+    {
+        serde_json::from_str(value).map_err(|_| InputError::new(gin, value, "JSON"))
+    }
+
+    /// Format a number for get_output(). This is the inverse of parse_f64().
+    fn format_f64(&self, value: f64) -> String {
+        value.to_string()
+    }
+
+    /// Format an integer for get_output(). This is the inverse of parse_i64().
+    fn format_i64(&self, value: i64) -> String {
+        value.to_string()
+    }
+
+    /// Format a boolean for get_output(). This is the inverse of parse_bool().
+    fn format_bool(&self, value: bool) -> String {
+        value.to_string()
+    }
+
+    /// Format a value as JSON for get_output(). This is the inverse of parse_json().
+    ///
+    /// Returns an error for values which JSON can not represent, such as maps with non-string keys.
+    fn format_json<T: Serialize>(&self, value: &T) -> Result<String, serde_json::Error>
+    where
+        Self: Sized,
+    {
+        serde_json::to_string(value)
+    }
+
+    fn save(&mut self, _path: PathBuf) {
         panic!("unsupported operation: save")
     }
 
-    fn load(&mut self, path: PathBuf) {
+    fn load(&mut self, _path: PathBuf) {
         panic!("unsupported operation: load")
     }
 
+    /// Handle a custom message which does not have a registered handler.
+    ///
+    /// Returns the response, which is discarded unless the sender requested it.
+    /// Errors are handled the same as malformed messages, see [API::malformed_message()].
+    /// By default all custom messages are rejected as [DecodeError::UnknownType].
+    fn message(&mut self, message_type: char, _body: String) -> Result<String, DecodeError> {
+        Err(DecodeError::UnknownType(message_type))
+    }
+
+    /// Direct all subsequent messages to the given agent.
+    /// Only controllers which host many agents need to implement this, see [Multiplexer].
+    fn select(&mut self, agent: u64) {
+        panic!("unsupported operation: select {agent}")
+    }
+
+    /// Handlers for typed custom messages. These take priority over the "message" method.
+    fn custom_messages() -> Registry<Self>
+    where
+        Self: Sized,
+    {
+        Registry::new()
+    }
+
+    /// Decide what to do after receiving a malformed message.
+    ///
+    /// This includes custom messages which are not supported or whose handler
+    /// failed to decode them. If the environment is waiting for a response
+    /// then it receives an error instead. By default the main loop stops and
+    /// returns the error.
+    fn malformed_message(&mut self, _error: &MessageError) -> ErrorPolicy {
+        ErrorPolicy::Abort
+    }
+
+    /// Optional operations which this controller implements, see [Controller::supports()].
+    ///
+    /// The message types in [API::custom_messages()] are included automatically.
+    /// By default none of the optional operations are advertised.
+    fn capabilities(&mut self) -> Vec<Capability> {
+        vec![]
+    }
+
+    /// Decide what to do after one of this controller's methods panics.
+    ///
+    /// The environment is always notified of the panic, so that it does not
+    /// wait forever for a reply. By default the main loop stops and returns an error.
+    fn panicked(&mut self, _message: &str) -> ErrorPolicy {
+        ErrorPolicy::Abort
+    }
+
+    /// Do background work while waiting for messages, see [main_loop_nonblocking()].
+    ///
+    /// Argument dt is the time since the previous call to idle, measured in seconds.
+    ///
+    /// By default this sleeps for one millisecond, to avoid spinning on the CPU.
+    fn idle(&mut self, _dt: f64) {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
     fn quit(&mut self) {}
 }
 
-/// Wait for the next message from the environment, for implementing controllers.
-pub fn poll() -> Result<Message, io::Error> {
-    Message::read(&mut io::stdin().lock())
+/// Switch a file descriptor between blocking and non-blocking I/O.
+#[cfg(all(unix, any(feature = "env", feature = "ctrl")))]
+pub(crate) fn change_blocking_fd(fd: std::os::unix::io::RawFd, blocking: bool) -> Result<(), io::Error> {
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let error = libc::fcntl(
+            fd,
+            libc::F_SETFL,
+            if blocking {
+                flags & !libc::O_NONBLOCK
+            } else {
+                flags | libc::O_NONBLOCK
+            },
+        );
+        if error < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Wait for the next message from the environment, for implementing controllers.
+#[cfg(feature = "ctrl")]
+pub fn poll() -> Result<Message, io::Error> {
+    Message::read(&mut io::stdin().lock())
+}
+
+/// Send an output value to the environment, for implementing controllers.
+#[cfg(feature = "ctrl")]
+pub fn send_output(gin: u64, value: String) -> Result<(), io::Error> {
+    debug_assert!(!value.contains("\n"));
+    println!("{gin}:{value}");
+    io::stdout().flush()?;
+    Ok(())
+}
+
+/// Get an output from the controller and send it in the given encoding.
+#[cfg(feature = "ctrl")]
+fn send_output_value<C: API>(controller: &mut C, gin: u64, encoding: ValueEncoding) -> Result<(), io::Error> {
+    if encoding == ValueEncoding::Text {
+        return send_output(gin, controller.get_output(gin));
+    }
+    let value = controller.get_output_f64(gin);
+    let mut stdout = io::stdout().lock();
+    write!(stdout, "{gin}:")?;
+    encoding.write_value(&mut stdout, value)?;
+    stdout.flush()
+}
+
+/// Send the response to a custom message to the environment, for implementing controllers.
+#[cfg(feature = "ctrl")]
+pub fn send_response(message_type: char, id: u64, body: &str) -> Result<(), io::Error> {
+    debug_assert!(!body.contains("\n"));
+    println!("{message_type}{id}:{body}");
+    io::stdout().flush()?;
+    Ok(())
+}
+
+/// Report an unexpected failure to the environment, for implementing controllers.
+///
+/// The environment receives a [ControllerPanic] error instead of any outstanding replies.
+#[cfg(feature = "ctrl")]
+pub fn send_error(message: &str) -> Result<(), io::Error> {
+    println!("E:{}", message.replace('\n', " "));
+    io::stdout().flush()?;
+    Ok(())
+}
+
+/// Write a comment to the environment, which it ignores, for implementing controllers.
+///
+/// Comments are useful as human readable markers while debugging.
+#[cfg(feature = "ctrl")]
+pub fn send_comment(text: &str) -> Result<(), io::Error> {
+    debug_assert!(!text.contains("\n"));
+    println!("#{text}");
+    io::stdout().flush()?;
+    Ok(())
 }
 
-/// Send an output value to the environment, for implementing controllers.
-pub fn send_output(gin: u64, value: String) -> Result<(), io::Error> {
-    debug_assert!(!value.contains("\n"));
-    println!("{gin}:{value}");
+/// Publish epigenetic data about the current individual, for implementing controllers.
+///
+/// Argument epigenome is a mapping of string key-value pairs, which are merged
+/// into the individual's existing epigenetic data.
+#[cfg(feature = "ctrl")]
+pub fn send_epigenome(epigenome: &HashMap<String, String>) -> Result<(), io::Error> {
+    let data = serde_json::to_string(epigenome)?;
+    println!("D{data}");
     io::stdout().flush()?;
     Ok(())
 }
@@ -339,58 +2969,263 @@ pub fn send_output(gin: u64, value: String) -> Result<(), io::Error> {
 ///
 /// This method handles communications between the controller (this program) and
 /// the environment. It reads and parses messages from stdin, interfaces with
-/// your implementation of the Controller trait, and writes messages to stdout.
+/// your implementation of the API trait, and writes messages to stdout.
 ///
 /// This method never returns!
-pub fn main_loop(mut controller: impl Controller) -> Result<(), io::Error> {
+#[cfg(feature = "ctrl")]
+pub fn main_loop<C: API>(mut controller: C) -> Result<(), io::Error> {
+    let mut registry = C::custom_messages();
+    let mut reader = MessageReader::new(io::stdin().lock());
+    while handle_message_contained(&mut controller, &mut registry, &mut reader)? {}
+    Ok(())
+}
+
+/// Start the main program loop, without blocking while waiting for messages.
+///
+/// This is the same as [main_loop()] except that it calls [API::idle()]
+/// whenever there are no messages waiting to be processed, so that controllers
+/// can do background work such as rendering or learning.
+#[cfg(feature = "ctrl")]
+#[cfg(unix)]
+pub fn main_loop_nonblocking<C: API>(mut controller: C) -> Result<(), io::Error> {
+    use std::os::fd::AsRawFd;
+    let mut registry = C::custom_messages();
+    let mut reader = MessageReader::new(BufReader::new(io::stdin()));
+    let stdin_fd = io::stdin().as_raw_fd();
+    let mut last_idle = Instant::now();
     loop {
-        let message = poll()?;
-        eprintln!("CTRL-STDIN: {message:?}");
-        match message {
-            Message::Environment { .. } => {
-                todo!()
-            }
-            Message::Population { .. } => {
-                todo!()
-            }
-            Message::New { genotype } => {
-                controller.new(genotype);
-            }
-            Message::Reset => {
-                controller.reset();
+        // Check for new data without blocking. Messages are read with
+        // blocking I/O, since the rest of the message is surely on its way.
+        if reader.get_mut().buffer().is_empty() {
+            change_blocking_fd(stdin_fd, false)?;
+            let ready = reader.get_mut().fill_buf().map(|_| ());
+            change_blocking_fd(stdin_fd, true)?;
+            match ready {
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => {
+                    let now = Instant::now();
+                    controller.idle((now - last_idle).as_secs_f64());
+                    last_idle = now;
+                    continue;
+                }
+                Err(error) => return Err(error),
+                Ok(()) => {}
             }
-            Message::Advance { dt } => {
-                controller.advance(dt);
+        }
+        if !handle_message_contained(&mut controller, &mut registry, &mut reader)? {
+            return Ok(());
+        }
+    }
+}
+
+/// Same as [handle_message()] except that panics are reported to the environment.
+#[cfg(feature = "ctrl")]
+fn handle_message_contained<C: API, R: BufRead>(
+    controller: &mut C,
+    registry: &mut Registry<C>,
+    reader: &mut MessageReader<R>,
+) -> Result<bool, io::Error> {
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        handle_message(controller, registry, reader)
+    }));
+    let payload = match result {
+        Ok(result) => return result,
+        Err(payload) => payload,
+    };
+    let message = if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.as_str()
+    } else {
+        "unknown panic"
+    };
+    send_error(message)?;
+    match controller.panicked(message) {
+        ErrorPolicy::Abort => Err(io::Error::other(ControllerPanic {
+            message: message.to_string(),
+        })),
+        ErrorPolicy::Skip => Ok(true),
+        ErrorPolicy::Report => {
+            eprintln!("continuing after panic, {message}");
+            Ok(true)
+        }
+    }
+}
+
+/// Read and act upon one message. Returns false after the quit message.
+#[cfg(feature = "ctrl")]
+fn handle_message<C: API, R: BufRead>(
+    controller: &mut C,
+    registry: &mut Registry<C>,
+    reader: &mut MessageReader<R>,
+) -> Result<bool, io::Error> {
+    let encoding = reader.value_encoding();
+    // Closing stdin is equivalent to sending the quit message.
+    let message = match reader.read() {
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => Message::Quit,
+        Err(error) => {
+            if MessageError::from_io(&error).is_none() {
+                return Err(error);
             }
-            Message::SetInput { gin, value } => {
+            return skip_malformed(controller, error);
+        }
+        // Fast path for the most frequent messages.
+        Ok(MessageRef::Advance { dt }) => {
+            controller.advance(dt);
+            return Ok(true);
+        }
+        Ok(MessageRef::SetInput { gin, value }) => {
+            controller.set_input(gin, value.to_string());
+            return Ok(true);
+        }
+        Ok(MessageRef::SetInputF64 { gin, value }) => {
+            controller.set_input_f64(gin, value);
+            return Ok(true);
+        }
+        Ok(MessageRef::GetOutput { gin }) => {
+            send_output_value(controller, gin, encoding)?;
+            return Ok(true);
+        }
+        Ok(MessageRef::Other(message)) => message,
+    };
+    match message {
+        // These messages are informational, controllers do not need to reply to them.
+        Message::Environment { .. } | Message::Population { .. } => {}
+        Message::ValueEncoding { encoding } => {
+            // All encodings are supported, using the default methods of the API trait.
+            let encoding = ValueEncoding::from_name(&encoding).unwrap_or_default();
+            println!("F:{}", encoding.name());
+            io::stdout().flush()?;
+            reader.set_value_encoding(encoding);
+        }
+        Message::Capabilities => {
+            let mut capabilities = controller.capabilities();
+            capabilities.extend(
+                registry
+                    .handlers
+                    .keys()
+                    .map(|&message_type| Capability::Custom(message_type)),
+            );
+            let names: Vec<_> = capabilities.iter().map(Capability::name).collect();
+            println!("C:{}", names.join(","));
+            io::stdout().flush()?;
+        }
+        Message::New { genotype } => {
+            controller.new(genotype);
+        }
+        Message::Swap { genotype } => {
+            controller.swap(genotype);
+        }
+        Message::Validate { genotype } => {
+            let error = match controller.validate(&genotype) {
+                Ok(()) => String::new(),
+                Err(error) if error.is_empty() => "invalid genotype".to_string(),
+                Err(error) => error.replace('\n', " "),
+            };
+            println!("V:{error}");
+            io::stdout().flush()?;
+        }
+        Message::Seed { seed } => {
+            controller.seed(seed);
+        }
+        Message::Select { agent } => {
+            controller.select(agent);
+        }
+        Message::Ping => {
+            println!("H:");
+            io::stdout().flush()?;
+        }
+        Message::Reset => {
+            controller.reset();
+        }
+        Message::Advance { dt } => {
+            controller.advance(dt);
+        }
+        Message::SetInput { gin, value } => {
+            controller.set_input(gin, value);
+        }
+        Message::SetInputs { inputs } => {
+            for (gin, value) in inputs {
                 controller.set_input(gin, value);
             }
-            Message::SetBinary { gin, bytes } => {
-                controller.set_binary(gin, bytes);
-            }
-            Message::GetOutput { gin } => {
-                let output = controller.get_output(gin);
-                send_output(gin, output)?;
-            }
-            Message::Save { path } => {
-                controller.save(path);
-            }
-            Message::Load { path } => {
-                controller.load(path);
+        }
+        Message::SetBinary { gin, bytes } => {
+            controller.set_binary(gin, bytes);
+        }
+        Message::GetOutput { gin } => {
+            send_output_value(controller, gin, encoding)?;
+        }
+        Message::GetOutputs { gins } => {
+            for gin in gins {
+                send_output_value(controller, gin, encoding)?;
             }
-            Message::Quit => {
-                controller.quit();
-                break;
+        }
+        Message::Save { path } => {
+            controller.save(path);
+        }
+        Message::Load { path } => {
+            controller.load(path);
+        }
+        Message::Quit => {
+            controller.quit();
+            return Ok(false);
+        }
+        Message::Custom { message_type, id, body } => {
+            let response = if registry.contains(message_type) {
+                registry
+                    .dispatch(controller, message_type, &body)
+                    .map_err(|_| DecodeError::Malformed(message_type))
+            } else {
+                controller.message(message_type, body.clone())
+            };
+            match (response, id) {
+                (Ok(response), Some(id)) => send_response(message_type, id, &response)?,
+                (Ok(_), None) => {}
+                (Err(error), _) => {
+                    let line = match id {
+                        Some(id) => format!("{message_type}{id}:{body}"),
+                        None => format!("{message_type}:{body}"),
+                    };
+                    let error = MessageError::new(&line, error);
+                    // Don't leave the environment waiting for a response which will never come.
+                    if id.is_some() {
+                        send_error(&error.to_string())?;
+                    }
+                    return skip_malformed(controller, error.into());
+                }
             }
         }
     }
-    Ok(())
+    Ok(true)
+}
+
+/// Apply the controller's [ErrorPolicy] to an error which contains a [MessageError].
+#[cfg(feature = "ctrl")]
+fn skip_malformed<C: API>(controller: &mut C, error: io::Error) -> Result<bool, io::Error> {
+    let message_error = MessageError::from_io(&error).expect("expected a MessageError");
+    match controller.malformed_message(message_error) {
+        ErrorPolicy::Abort => return Err(error),
+        ErrorPolicy::Skip => {}
+        ErrorPolicy::Report => eprintln!("skipping malformed message, {message_error}"),
+    }
+    Ok(true)
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "spawn", feature = "ctrl"))]
 mod tests {
     use super::*;
 
+    /// Run a shell script as the controller program.
+    fn script_controller(script: &str) -> Controller {
+        script_controller_with(script, &ControllerOptions::default()).unwrap()
+    }
+
+    /// Same as [script_controller()] but with additional settings for the controller program.
+    fn script_controller_with(script: &str, options: &ControllerOptions) -> Result<Controller, io::Error> {
+        let command = ["/bin/sh".to_string(), "-c".to_string(), script.to_string()];
+        let env = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+        Controller::with_options(env, "pop", &command, options)
+    }
+
     #[test]
     fn message_roundtrip() {
         let test_messages = [
@@ -423,6 +3258,23 @@ mod tests {
                 genotype: "] } ){([\\n\" ".to_string(),
             },
             //
+            Message::Validate {
+                genotype: "{\"weights\": [1, 2]}".to_string(),
+            },
+            Message::Swap {
+                genotype: "{\"weights\": [3]}".to_string(),
+            },
+            Message::ValueEncoding {
+                encoding: "f64".to_string(),
+            },
+            Message::Ping,
+            Message::Capabilities,
+            Message::Seed { seed: 0 },
+            Message::Seed { seed: u64::MAX },
+            //
+            Message::Select { agent: 0 },
+            Message::Select { agent: 500 },
+            //
             Message::Reset,
             //
             Message::Advance { dt: 0.123 },
@@ -511,6 +3363,16 @@ mod tests {
             Message::GetOutput { gin: 100 },
             Message::GetOutput { gin: u64::MAX },
             //
+            Message::SetInputs { inputs: vec![] },
+            Message::SetInputs {
+                inputs: vec![(1, "1.5".to_string()), (2, "".to_string()), (3, ": ,=".to_string())],
+            },
+            Message::GetOutputs { gins: vec![] },
+            Message::GetOutputs { gins: vec![7] },
+            Message::GetOutputs {
+                gins: vec![7, 0, u64::MAX],
+            },
+            //
             Message::Save {
                 path: PathBuf::from("/tmp/my_save_file,"),
             },
@@ -520,6 +3382,17 @@ mod tests {
             },
             //
             Message::Quit,
+            //
+            Message::Custom {
+                message_type: 'Z',
+                id: None,
+                body: "".to_string(),
+            },
+            Message::Custom {
+                message_type: 'A',
+                id: Some(7),
+                body: r#"{"x": 1, "y": ":"}"#.to_string(),
+            },
         ];
 
         for original in test_messages {
@@ -529,4 +3402,1036 @@ mod tests {
             assert_eq!(original, returned);
         }
     }
+
+    #[test]
+    fn custom_registry() {
+        #[derive(Serialize, serde::Deserialize)]
+        struct Double(i64);
+        impl CustomMessage for Double {
+            const TYPE: char = 'T';
+            type Response = i64;
+        }
+        struct Counter(i64);
+        let mut registry = Registry::<Counter>::new();
+        registry.register(|counter: &mut Counter, msg: Double| {
+            counter.0 += 1;
+            msg.0 * 2
+        });
+        let mut counter = Counter(0);
+        assert!(registry.contains('T'));
+        assert!(!registry.contains('F'));
+        assert_eq!(registry.dispatch(&mut counter, 'T', "21").unwrap(), "42");
+        assert_eq!(counter.0, 1);
+        assert!(registry.dispatch(&mut counter, 'T', "\"x\"").is_err());
+        assert!(registry.dispatch(&mut counter, 'F', "21").is_err());
+        assert!(check_custom_type('Q').is_err());
+        assert!(check_custom_type('D').is_err());
+        assert!(check_custom_type('z').is_err());
+    }
+
+    #[test]
+    fn handle_messages() {
+        #[derive(Default)]
+        struct Recorder(Vec<String>);
+        impl API for Recorder {
+            fn new(&mut self, genotype: String) {
+                self.0.push(format!("new {genotype}"));
+            }
+            fn seed(&mut self, seed: u64) {
+                self.0.push(format!("seed {seed}"));
+            }
+            fn reset(&mut self) {
+                self.0.push("reset".to_string());
+            }
+            fn advance(&mut self, dt: f64) {
+                self.0.push(format!("advance {dt}"));
+            }
+            fn set_input(&mut self, gin: u64, value: String) {
+                self.0.push(format!("input {gin} {value}"));
+            }
+            fn get_output(&mut self, _gin: u64) -> String {
+                unreachable!()
+            }
+        }
+        let mut controller = Recorder::default();
+        let mut registry = Recorder::custom_messages();
+        let mut reader = MessageReader::new(b"G5\nN{}\nI3:x\nX0.5\nR\nQ\n".as_slice());
+        while handle_message(&mut controller, &mut registry, &mut reader).unwrap() {}
+        assert_eq!(controller.0, ["seed 5", "new {}", "input 3 x", "advance 0.5", "reset"]);
+        // End of input is the same as quitting.
+        assert!(!handle_message(&mut controller, &mut registry, &mut reader).unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn validate_genome() {
+        // Accepts genotypes which are JSON objects.
+        let script = r#"while read line; do case $line in V{*) echo "V:";; V*) echo "V:not an object";; O*) echo "${line#O}:1";; Q) exit;; esac; done"#;
+        let mut ctrl = script_controller(script);
+        assert_eq!(ctrl.validate_genome("{}").unwrap(), Ok(()));
+        let handle = ctrl.request_outputs(&[4]).unwrap();
+        assert_eq!(ctrl.validate_genome("[]").unwrap(), Err("not an object".to_string()));
+        assert_eq!(ctrl.wait_outputs(handle).unwrap()[&4], "1");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn value_encoding() {
+        #[derive(Default)]
+        struct Inputs(Vec<(u64, String)>);
+        impl API for Inputs {
+            fn new(&mut self, _genotype: String) {}
+            fn reset(&mut self) {}
+            fn advance(&mut self, _dt: f64) {}
+            fn set_input(&mut self, gin: u64, value: String) {
+                self.0.push((gin, value));
+            }
+            fn get_output(&mut self, _gin: u64) -> String {
+                unreachable!()
+            }
+        }
+        let mut data = b"I1:0.25\nFf32\nI3:".to_vec();
+        data.extend_from_slice(&0.5_f32.to_le_bytes());
+        data.extend_from_slice(b"\nM1\n4:x\nQ\n");
+        let mut controller = Inputs::default();
+        let mut registry = Inputs::custom_messages();
+        let mut reader = MessageReader::new(data.as_slice());
+        while handle_message(&mut controller, &mut registry, &mut reader).unwrap() {}
+        assert_eq!(reader.value_encoding(), ValueEncoding::F32);
+        assert_eq!(
+            controller.0,
+            [(1, "0.25".to_string()), (3, "0.5".to_string()), (4, "x".to_string())]
+        );
+        // Accepts the f64 encoding and replies to every output request with 0.5
+        let script = r#"while read line; do case $line in Ff64) echo "F:f64";; F*) echo "F:text";; O*) printf '%s:\000\000\000\000\000\000\340?\n' "${line#O}";; Q) exit;; esac; done"#;
+        let options = ControllerOptions::default().value_encoding(ValueEncoding::F64);
+        let mut ctrl = script_controller_with(script, &options).unwrap();
+        assert_eq!(ctrl.value_encoding(), ValueEncoding::F64);
+        assert!(ctrl.set_input(1, "x").is_err());
+        let outputs = ctrl.get_outputs(&[2, 10]).unwrap();
+        assert_eq!((outputs[&2].as_str(), outputs[&10].as_str()), ("0.5", "0.5"));
+        let options = ControllerOptions::default().value_encoding(ValueEncoding::F32);
+        let ctrl = script_controller_with(script, &options).unwrap();
+        assert_eq!(ctrl.value_encoding(), ValueEncoding::Text);
+    }
+
+    #[test]
+    fn malformed_messages() {
+        struct Lenient(ErrorPolicy, Vec<f64>);
+        impl API for Lenient {
+            fn new(&mut self, _genotype: String) {}
+            fn reset(&mut self) {}
+            fn advance(&mut self, dt: f64) {
+                self.1.push(dt);
+            }
+            fn set_input(&mut self, _gin: u64, _value: String) {}
+            fn get_output(&mut self, _gin: u64) -> String {
+                unreachable!()
+            }
+            fn malformed_message(&mut self, error: &MessageError) -> ErrorPolicy {
+                assert_eq!(error.line, "Xfast");
+                assert_eq!(error.error, DecodeError::Malformed('X'));
+                self.0
+            }
+        }
+        let data = b"X1\nXfast\nX2\nQ\n";
+        for policy in [ErrorPolicy::Skip, ErrorPolicy::Report] {
+            let mut controller = Lenient(policy, vec![]);
+            let mut registry = Lenient::custom_messages();
+            let mut reader = MessageReader::new(data.as_slice());
+            while handle_message(&mut controller, &mut registry, &mut reader).unwrap() {}
+            assert_eq!(controller.1, [1.0, 2.0]);
+        }
+        let mut controller = Lenient(ErrorPolicy::Abort, vec![]);
+        let mut registry = Lenient::custom_messages();
+        let mut reader = MessageReader::new(data.as_slice());
+        assert!(handle_message(&mut controller, &mut registry, &mut reader).unwrap());
+        let error = handle_message(&mut controller, &mut registry, &mut reader).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(MessageError::from_io(&error).unwrap().line, "Xfast");
+        let error = Message::read(&mut b"M1\n7\n".as_slice()).unwrap_err();
+        assert_eq!(
+            MessageError::from_io(&error).unwrap().error,
+            DecodeError::Malformed('M')
+        );
+        let error = Message::read(&mut b"z:1\n".as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn custom_message_errors() {
+        #[derive(Serialize, serde::Deserialize)]
+        struct Double(i64);
+        impl CustomMessage for Double {
+            const TYPE: char = 'T';
+            type Response = i64;
+        }
+        struct Strict(ErrorPolicy, Vec<MessageError>);
+        impl API for Strict {
+            fn new(&mut self, _genotype: String) {}
+            fn reset(&mut self) {}
+            fn advance(&mut self, _dt: f64) {}
+            fn set_input(&mut self, _gin: u64, _value: String) {}
+            fn get_output(&mut self, _gin: u64) -> String {
+                unreachable!()
+            }
+            fn custom_messages() -> Registry<Self> {
+                let mut registry = Registry::new();
+                registry.register(|_: &mut Self, msg: Double| msg.0 * 2);
+                registry
+            }
+            fn malformed_message(&mut self, error: &MessageError) -> ErrorPolicy {
+                self.1.push(error.clone());
+                self.0
+            }
+        }
+        let data = b"T1:\"x\"\nT2:21\nZ3:hi\nZ:hi\nQ\n";
+        let mut controller = Strict(ErrorPolicy::Skip, vec![]);
+        let mut registry = Strict::custom_messages();
+        let mut reader = MessageReader::new(data.as_slice());
+        while handle_message(&mut controller, &mut registry, &mut reader).unwrap() {}
+        assert_eq!(
+            controller.1,
+            [
+                MessageError::new("T1:\"x\"", DecodeError::Malformed('T')),
+                MessageError::new("Z3:hi", DecodeError::UnknownType('Z')),
+                MessageError::new("Z:hi", DecodeError::UnknownType('Z')),
+            ]
+        );
+        let mut controller = Strict(ErrorPolicy::Abort, vec![]);
+        let mut reader = MessageReader::new(data.as_slice());
+        let error = handle_message(&mut controller, &mut registry, &mut reader).unwrap_err();
+        assert_eq!(MessageError::from_io(&error).unwrap().line, "T1:\"x\"");
+        // Custom messages without the colon are rejected, since their ID can not be told apart from their body.
+        let error = Message::read(&mut b"Zhello\n".as_slice()).unwrap_err();
+        assert_eq!(
+            MessageError::from_io(&error).unwrap().error,
+            DecodeError::Malformed('Z')
+        );
+    }
+
+    #[test]
+    fn panic_containment() {
+        struct Fragile(ErrorPolicy, Vec<f64>);
+        impl API for Fragile {
+            fn new(&mut self, _genotype: String) {}
+            fn reset(&mut self) {}
+            fn advance(&mut self, dt: f64) {
+                assert!(dt >= 0.0, "negative dt");
+                self.1.push(dt);
+            }
+            fn set_input(&mut self, _gin: u64, _value: String) {}
+            fn get_output(&mut self, _gin: u64) -> String {
+                unreachable!()
+            }
+            fn panicked(&mut self, message: &str) -> ErrorPolicy {
+                assert_eq!(message, "negative dt");
+                self.0
+            }
+        }
+        let data = b"X1\nX-1\nX2\nQ\n";
+        let mut controller = Fragile(ErrorPolicy::Skip, vec![]);
+        let mut registry = Fragile::custom_messages();
+        let mut reader = MessageReader::new(data.as_slice());
+        while handle_message_contained(&mut controller, &mut registry, &mut reader).unwrap() {}
+        assert_eq!(controller.1, [1.0, 2.0]);
+        let mut controller = Fragile(ErrorPolicy::Abort, vec![]);
+        let mut reader = MessageReader::new(data.as_slice());
+        assert!(handle_message_contained(&mut controller, &mut registry, &mut reader).unwrap());
+        let error = handle_message_contained(&mut controller, &mut registry, &mut reader).unwrap_err();
+        assert_eq!(ControllerPanic::from_io(&error).unwrap().message, "negative dt");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn flush_policy() {
+        let policy = FlushPolicy::ON_DEMAND.messages(3);
+        assert!(!policy.is_due(0, None));
+        assert!(!policy.is_due(2, Some(Instant::now())));
+        assert!(policy.is_due(3, Some(Instant::now())));
+        let policy = FlushPolicy::ON_DEMAND.seconds(0.0);
+        assert!(policy.is_due(1, Some(Instant::now())));
+        assert!(!FlushPolicy::ON_DEMAND.is_due(1000, Some(Instant::now() - Duration::from_secs(60))));
+        // Counts the advance messages which the controller has received.
+        let script =
+            r#"n=0; while read line; do case $line in X*) n=$((n+1));; O*) echo "${line#O}:$n";; Q) exit;; esac; done"#;
+        let options = ControllerOptions::default().flush_policy(FlushPolicy::ON_DEMAND.messages(2));
+        let mut ctrl = script_controller_with(script, &options).unwrap();
+        assert_eq!(ctrl.stdin.unflushed, 0);
+        ctrl.advance(0.1).unwrap();
+        assert_eq!(ctrl.stdin.unflushed, 1);
+        ctrl.advance(0.1).unwrap();
+        assert_eq!(ctrl.stdin.unflushed, 0);
+        ctrl.advance(0.1).unwrap();
+        ctrl.flush().unwrap();
+        assert_eq!(ctrl.stdin.unflushed, 0);
+        assert_eq!(ctrl.get_outputs(&[1]).unwrap()[&1], "3");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn controller_panic() {
+        let script = r#"while read line; do case $line in O*) echo "E:boom";; Q) exit;; esac; done"#;
+        let mut ctrl = script_controller(script);
+        let error = ctrl.get_outputs(&[1]).unwrap_err();
+        assert_eq!(ControllerPanic::from_io(&error).unwrap().message, "boom");
+        assert!(ctrl.is_alive());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn capabilities() {
+        let script = r#"while read line; do case $line in C) echo "C:save,Z,teleport";; O*) echo "${line#O}:1";; Q) exit;; esac; done"#;
+        let ctrl = script_controller(script);
+        assert!(ctrl.capabilities().is_none());
+        assert!(ctrl.supports(Capability::Load));
+        let options = ControllerOptions::default().query_capabilities();
+        let mut ctrl = script_controller_with(script, &options).unwrap();
+        assert_eq!(
+            ctrl.capabilities().unwrap(),
+            &HashSet::from([Capability::Save, Capability::Custom('Z')])
+        );
+        ctrl.save("/dev/null").unwrap();
+        ctrl.message('Z', "").unwrap();
+        let error = ctrl.load("/dev/null").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+        assert_eq!(
+            UnsupportedOperation::from_io(&error).unwrap().capability,
+            Capability::Load
+        );
+        assert!(ctrl.set_binary(1, b"x").is_err());
+        assert!(ctrl.swap_genotype("{}", SwapMode::PreserveState).is_err());
+        ctrl.swap_genotype("{}", SwapMode::Reinitialize).unwrap();
+        assert!(ctrl.message('Y', "").is_err());
+        assert_eq!(ctrl.get_outputs(&[1]).unwrap()[&1], "1");
+        for capability in [
+            Capability::Save,
+            Capability::SetBinary,
+            Capability::Select,
+            Capability::Custom('Z'),
+        ] {
+            assert_eq!(Capability::from_name(&capability.name()), Some(capability));
+        }
+        assert_eq!(Capability::from_name("E"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn profile_counters() {
+        let script = r#"while read line; do case $line in O*) echo "${line#O}:1";; Q) exit;; esac; done"#;
+        let mut ctrl = script_controller(script);
+        ctrl.enable_profiling();
+        ctrl.set_input(1, "0.5").unwrap();
+        ctrl.comment("hello").unwrap();
+        ctrl.save("/dev/null").unwrap();
+        ctrl.get_outputs(&[7]).unwrap();
+        let profile = ctrl.get_profile().unwrap();
+        assert_eq!(profile.messages[&'I'].bytes_sent, "I1:0.5\n".len() as u64);
+        assert_eq!(profile.messages[&'#'].count, 1);
+        assert_eq!(profile.messages[&'#'].bytes_sent, "#hello\n".len() as u64);
+        let save = profile.messages[&'S'];
+        assert_eq!((save.count, save.bytes_sent), (1, "S/dev/null\n".len() as u64));
+        assert_eq!(save.round_trips, 0);
+        assert_eq!(save.mean_latency(), None);
+        let output = profile.messages[&'O'];
+        assert_eq!((output.count, output.round_trips), (1, 1));
+        assert_eq!(output.bytes_received, "7:1\n".len() as u64);
+    }
+
+    #[test]
+    fn genotype_swap() {
+        #[derive(Default)]
+        struct Plastic {
+            genotype: String,
+            learned: f64,
+        }
+        impl API for Plastic {
+            fn new(&mut self, genotype: String) {
+                self.genotype = genotype;
+                self.learned = 0.0;
+            }
+            fn swap(&mut self, genotype: String) {
+                self.genotype = genotype;
+            }
+            fn reset(&mut self) {}
+            fn advance(&mut self, dt: f64) {
+                self.learned += dt;
+            }
+            fn set_input(&mut self, _gin: u64, _value: String) {}
+            fn get_output(&mut self, _gin: u64) -> String {
+                unreachable!()
+            }
+        }
+        let mut controller = Plastic::default();
+        let mut registry = Plastic::custom_messages();
+        let mut reader = MessageReader::new(b"Na\nX1\nKb\nX2\nQ\n".as_slice());
+        while handle_message(&mut controller, &mut registry, &mut reader).unwrap() {}
+        assert_eq!((controller.genotype.as_str(), controller.learned), ("b", 3.0));
+        let mut reader = MessageReader::new(b"Nc\nQ\n".as_slice());
+        while handle_message(&mut controller, &mut registry, &mut reader).unwrap() {}
+        assert_eq!((controller.genotype.as_str(), controller.learned), ("c", 0.0));
+    }
+
+    #[test]
+    fn input_parsing() {
+        struct Ctrl;
+        impl API for Ctrl {
+            fn new(&mut self, _genotype: String) {}
+            fn reset(&mut self) {}
+            fn advance(&mut self, _dt: f64) {}
+            fn set_input(&mut self, _gin: u64, _value: String) {}
+            fn get_output(&mut self, _gin: u64) -> String {
+                unreachable!()
+            }
+        }
+        let ctrl = Ctrl;
+        assert_eq!(ctrl.parse_f64(1, " 0.25\r"), Ok(0.25));
+        assert_eq!(ctrl.parse_i64(1, "-7"), Ok(-7));
+        assert_eq!(ctrl.parse_bool(1, "1"), Ok(true));
+        assert_eq!(ctrl.parse_bool(1, "false"), Ok(false));
+        assert_eq!(ctrl.parse_json::<Vec<u8>>(1, "[1, 2]"), Ok(vec![1, 2]));
+        let error = ctrl.parse_i64(3, "0.5").unwrap_err();
+        assert_eq!(error, InputError::new(3, "0.5", "an integer"));
+        assert_eq!(
+            error.to_string(),
+            r#"invalid value "0.5" for input gin 3, expected an integer"#
+        );
+        assert!(ctrl.parse_bool(1, "yes").is_err());
+        assert!(ctrl.parse_json::<Vec<u8>>(1, "[").is_err());
+        for value in [0.1, -3.0, 1e300] {
+            assert_eq!(ctrl.parse_f64(1, &ctrl.format_f64(value)), Ok(value));
+        }
+        assert_eq!(ctrl.parse_i64(1, &ctrl.format_i64(i64::MIN)), Ok(i64::MIN));
+        assert_eq!(ctrl.parse_bool(1, &ctrl.format_bool(true)), Ok(true));
+        assert_eq!(ctrl.format_json(&[1, 2]).unwrap(), "[1,2]");
+        assert!(ctrl.format_json(&HashMap::from([((1, 2), 3)])).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn heartbeat() {
+        // Counts the pings and never replies to output requests.
+        let script = r#"n=0; while read line; do case $line in H) n=$((n+1)); echo "H:";; O9) ;; O*) echo "${line#O}:$n";; Q) exit;; esac; done"#;
+        let options = ControllerOptions::default().timeout(0.2);
+        let mut ctrl = script_controller_with(script, &options).unwrap();
+        assert!(ctrl.ping().unwrap() < Duration::from_millis(200));
+        let error = ctrl.get_outputs(&[9]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+        assert!(ControllerTimeout::from_io(&error).is_some());
+        // Ping before every message.
+        let options = options.heartbeat(0.0);
+        let mut ctrl = script_controller_with(script, &options).unwrap();
+        ctrl.advance(1.0).unwrap();
+        assert_eq!(ctrl.get_outputs(&[1]).unwrap()[&1], "2");
+        // The timeout also applies to the handshake when the controller starts.
+        let options = ControllerOptions::default().timeout(0.2).query_capabilities();
+        let error = script_controller_with(script, &options).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn bench() {
+        let script = r#"while read line; do case $line in H) echo "H:";; O*) echo "${line#O}:1";; W*) for gin in $(echo ${line#W} | tr , ' '); do echo "$gin:1"; done;; M*) for i in $(seq ${line#M}); do read input; done;; Q) exit;; esac; done"#;
+        let mut workload = bench::Workload {
+            genotypes: bench::Workload::genome_size_sweep(&[10, 1000]),
+            ticks: 20,
+            input_gins: vec![1, 2],
+            output_gins: vec![3, 4],
+            ..Default::default()
+        };
+        assert_eq!(workload.genotypes[1].len(), 1000);
+        for mix in [bench::MessageMix::Individual, bench::MessageMix::Batched] {
+            workload.mix = mix;
+            let mut ctrl = script_controller(script);
+            let report = bench::run(&mut ctrl, &workload).unwrap();
+            assert_eq!(report.samples.len(), 2);
+            for sample in &report.samples {
+                assert_eq!(sample.tick_times.len(), 20);
+                assert!(sample.percentile(0.5) <= sample.percentile(1.0));
+                assert!(sample.throughput() > 0.0);
+                let advance = sample.profile.messages[&'X'];
+                assert_eq!(advance.count, 20);
+            }
+            assert_eq!(report.to_string().lines().count(), 3);
+        }
+    }
+
+    #[test]
+    fn multiplexer() {
+        #[derive(Default)]
+        struct Sum(f64);
+        impl API for Sum {
+            fn new(&mut self, _genotype: String) {}
+            fn reset(&mut self) {
+                self.0 = 0.0;
+            }
+            fn advance(&mut self, dt: f64) {
+                self.0 += dt;
+            }
+            fn set_input(&mut self, _gin: u64, _value: String) {}
+            fn get_output(&mut self, _gin: u64) -> String {
+                self.0.to_string()
+            }
+        }
+        let mut mux = Multiplexer::new(Sum::default);
+        let mut registry = Multiplexer::<Sum>::custom_messages();
+        let mut reader = MessageReader::new(b"X1\nU7\nX2\nX3\nU0\nX4\nU9\nR\nQ\n".as_slice());
+        while handle_message(&mut mux, &mut registry, &mut reader).unwrap() {}
+        assert_eq!(mux.agents().len(), 3);
+        assert_eq!(mux.agents()[&0].0, 5.0);
+        assert_eq!(mux.agents()[&7].0, 5.0);
+        assert_eq!(mux.agents()[&9].0, 0.0);
+    }
+
+    #[test]
+    fn socket_transport() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("tcp://{}", listener.local_addr().unwrap());
+        // Echo server, replies to every output request with the GIN.
+        let server = std::thread::spawn(move || {
+            for _connection in 0..2 {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut writer = stream;
+                let mut line = String::new();
+                loop {
+                    line.clear();
+                    // The old connection closes with unread outputs when the client reconnects.
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 || line.trim() == "Q" {
+                        break;
+                    }
+                    if let Some(gin) = line.trim().strip_prefix('O') {
+                        let reply = format!("D{{\"gin\":\"{gin}\"}}\n{gin}:{gin}\n");
+                        if writer.write_all(reply.as_bytes()).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+        let mut ctrl = Controller::new(".", "pop", &[address]).unwrap();
+        ctrl.new_genotype("[]").unwrap();
+        assert_eq!(ctrl.get_outputs(&[3]).unwrap()[&3], "3");
+        assert_eq!(ctrl.get_epigenome()["gin"], "3");
+        let received = ctrl.request_outputs(&[5]).unwrap();
+        ctrl.receive_outputs().unwrap();
+        let lost = ctrl.request_outputs(&[7]).unwrap();
+        ctrl.reconnect().unwrap();
+        assert_eq!(ctrl.get_outputs(&[4]).unwrap()[&4], "4");
+        assert_eq!(ctrl.wait_outputs(received).unwrap()[&5], "5");
+        let error = ctrl.wait_outputs(lost).unwrap_err();
+        assert!(ControllerDead::from_io(&error).is_some());
+        drop(ctrl);
+        server.join().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reconnect_retry() {
+        use std::os::unix::net::UnixListener;
+        let path = std::env::temp_dir().join(format!("npc_maker_reconnect_{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        // Answer the capabilities query and record every other message, or hang up right away.
+        let serve = |path: &Path, answer: bool| {
+            let _ = std::fs::remove_file(path);
+            let listener = UnixListener::bind(path).unwrap();
+            std::thread::spawn(move || {
+                let (stream, _) = listener.accept().unwrap();
+                let mut received = vec![];
+                if answer {
+                    let mut writer = stream.try_clone().unwrap();
+                    for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                        if line == "C" {
+                            writer.write_all(b"C:\n").unwrap();
+                        }
+                        received.push(line);
+                    }
+                }
+                received
+            })
+        };
+        let options = ControllerOptions::default().query_capabilities();
+        let server = serve(&path, true);
+        let address = format!("unix://{}", path.display());
+        let mut ctrl = Controller::with_options(".", "pop", &[address], &options).unwrap();
+        ctrl.seed(5).unwrap();
+        ctrl.new_genotype("[1]").unwrap();
+        ctrl.flush().unwrap();
+        // The handshake fails, but the controller still remembers the individual.
+        let server2 = serve(&path, false);
+        assert!(ctrl.reconnect().is_err());
+        server2.join().unwrap();
+        let server3 = serve(&path, true);
+        ctrl.reconnect().unwrap();
+        drop(ctrl);
+        let received = server3.join().unwrap();
+        assert!(received.contains(&"G5".to_string()));
+        assert!(received.contains(&"N[1]".to_string()));
+        assert_eq!(received.last().map(String::as_str), Some("Q"));
+        server.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn read_closed_stream() {
+        let error = Message::read(&mut b"".as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        let mut stream = b"R\n".as_slice();
+        assert_eq!(Message::read(&mut stream).unwrap(), Message::Reset);
+        let mut stream = b"# comment\n\n#\nR\n".as_slice();
+        assert_eq!(Message::read(&mut stream).unwrap(), Message::Reset);
+        assert!(Message::read(&mut stream).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pipelined_outputs() {
+        // Replies to every output request with the GIN.
+        let script = r#"while read line; do case $line in O*) echo "${line#O}:${line#O}";; Q) exit;; esac; done"#;
+        let mut ctrl = script_controller(script);
+        let first = ctrl.request_outputs(&[1, 2]).unwrap();
+        let second = ctrl.request_outputs(&[3]).unwrap();
+        assert_eq!(ctrl.wait_outputs(second).unwrap()[&3], "3");
+        assert_eq!(ctrl.get_outputs(&[4]).unwrap()[&4], "4");
+        let first = ctrl.wait_outputs(first).unwrap();
+        assert_eq!((first[&1].as_str(), first[&2].as_str()), ("1", "2"));
+    }
+
+    #[test]
+    fn parse_frames() {
+        assert!(matches!(Frame::parse("7:a:b"), Ok(Frame::Output { gin: 7, value }) if value == "a:b"));
+        assert!(matches!(
+            Frame::parse("T:hi"),
+            Ok(Frame::Message(Message::Custom {
+                message_type: 'T',
+                id: None,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            Frame::parse("T12:{}"),
+            Ok(Frame::Message(Message::Custom { id: Some(12), .. }))
+        ));
+        assert!(matches!(Frame::parse("7"), Err(ProtocolError::Malformed(_))));
+        assert!(matches!(Frame::parse("1.5:1"), Err(ProtocolError::Malformed(_))));
+        assert!(matches!(Frame::parse("T1x:1"), Err(ProtocolError::Malformed(_))));
+        assert!(matches!(Frame::parse("hello"), Err(ProtocolError::UnknownType(_))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unsolicited_frames() {
+        let script = r##"while read line; do case $line in O1) echo "T:note"; echo "#comment"; echo "1:x";; O*) echo "9:y";; esac; done"##;
+        let mut ctrl = script_controller(script);
+        assert_eq!(ctrl.get_outputs(&[1]).unwrap()[&1], "x");
+        let messages: Vec<Message> = ctrl.drain_messages().collect();
+        assert_eq!(
+            messages,
+            [Message::Custom {
+                message_type: 'T',
+                id: None,
+                body: "note".to_string()
+            }]
+        );
+        let error = ctrl.get_outputs(&[2]).unwrap_err();
+        assert_eq!(
+            ProtocolError::from_io(&error),
+            Some(&ProtocolError::UnexpectedOutput(9))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn tracing() {
+        let path = std::env::temp_dir().join(format!("npc_maker_trace_{}.ndjson", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let script = r#"while read line; do case $line in O*) echo "${line#O}:${line#O}";; Q) exit;; esac; done"#;
+        let options = ControllerOptions::default().trace(&path);
+        let mut ctrl = script_controller_with(script, &options).unwrap();
+        ctrl.set_binary(5, b"\n\n").unwrap();
+        assert_eq!(ctrl.get_outputs(&[1]).unwrap()[&1], "1");
+        drop(ctrl);
+        let trace = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let frames: Vec<(String, String)> = trace
+            .lines()
+            .map(|line| {
+                let record: serde_json::Value = serde_json::from_str(line).unwrap();
+                (
+                    record["from"].as_str().unwrap().to_string(),
+                    record["frame"].as_str().unwrap().to_string(),
+                )
+            })
+            .collect();
+        let frames: Vec<(&str, &str)> = frames
+            .iter()
+            .map(|(from, frame)| (from.as_str(), frame.as_str()))
+            .collect();
+        assert_eq!(
+            frames[1..],
+            [
+                ("env", "Ppop"),
+                ("env", "B5:2"),
+                ("env", "O1"),
+                ("ctrl", "1:1"),
+                ("env", "Q")
+            ]
+        );
+    }
+
+    /// Read the pairs of (from, frame) in a trace file, and then delete it.
+    fn take_trace(path: &Path) -> Vec<(String, String)> {
+        let trace = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        trace
+            .lines()
+            .map(|line| {
+                let record: serde_json::Value = serde_json::from_str(line).unwrap();
+                (
+                    record["from"].as_str().unwrap().to_string(),
+                    record["frame"].as_str().unwrap().to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn trace_replay() {
+        let recorded = std::env::temp_dir().join(format!("npc_maker_recorded_{}.ndjson", std::process::id()));
+        let replayed = std::env::temp_dir().join(format!("npc_maker_replayed_{}.ndjson", std::process::id()));
+        let _ = std::fs::remove_file(&recorded);
+        let _ = std::fs::remove_file(&replayed);
+        // Record a session from the environment's side.
+        let script = r#"while read line; do case $line in O*) echo "${line#O}:${line#O}";; Q) exit;; esac; done"#;
+        let options = ControllerOptions::default().trace(&recorded);
+        let mut ctrl = script_controller_with(script, &options).unwrap();
+        ctrl.seed(7).unwrap();
+        ctrl.new_genotype(r#"{"w": [1, 2]}"#).unwrap();
+        ctrl.set_input(1, "0.5").unwrap();
+        ctrl.advance(0.25).unwrap();
+        assert_eq!(ctrl.get_outputs(&[3]).unwrap()[&3], "3");
+        ctrl.reset().unwrap();
+        drop(ctrl);
+        let session = take_trace(&recorded);
+        // Replay the environment's side of the session into a controller which records its own side.
+        struct Echo;
+        impl API for Echo {
+            fn new(&mut self, _genotype: String) {}
+            fn reset(&mut self) {}
+            fn advance(&mut self, _dt: f64) {}
+            fn set_input(&mut self, _gin: u64, _value: String) {}
+            fn get_output(&mut self, gin: u64) -> String {
+                gin.to_string()
+            }
+        }
+        let input: String = session
+            .iter()
+            .filter(|(from, _)| from == "env")
+            .map(|(_, frame)| format!("{frame}\n"))
+            .collect();
+        let mut controller = Traced::new(Echo, &replayed).unwrap();
+        let mut registry = Traced::<Echo>::custom_messages();
+        let mut reader = MessageReader::new(input.as_bytes());
+        while handle_message(&mut controller, &mut registry, &mut reader).unwrap() {}
+        drop(controller);
+        // The controller does not see the environment and population messages.
+        assert!(session[0].1.starts_with('E'));
+        assert_eq!(session[1].1, "Ppop");
+        assert_eq!(take_trace(&replayed), session[2..]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn controller_dead() {
+        let script = "read a; echo oops >&2; exit 3";
+        let options = ControllerOptions::default().stderr_lines(5);
+        let mut ctrl = script_controller_with(script, &options).unwrap();
+        let error = ctrl.get_outputs(&[1]).unwrap_err();
+        let dead = ControllerDead::from_io(&error).unwrap();
+        assert_eq!(dead.status.exit_code, Some(3));
+        assert!(!ctrl.is_alive());
+        assert!(ControllerDead::from_io(&ctrl.reset().unwrap_err()).is_some());
+        // Wait for the stderr capture thread to catch up.
+        for _ in 0..100 {
+            if !ctrl.status().stderr.is_empty() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(ctrl.status().stderr, ["oops"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn controller_options() {
+        let script = r#"while read line; do case $line in O*) echo "1:$NPC_TEST:$(pwd)";; Q) exit;; esac; done"#;
+        let options = ControllerOptions::default()
+            .env_var("NPC_TEST", "hello")
+            .working_dir("/");
+        let mut ctrl = script_controller_with(script, &options).unwrap();
+        assert_eq!(ctrl.get_outputs(&[1]).unwrap()[&1], "hello:/");
+        let saved = ctrl.checkpoint("/dev/null").unwrap();
+        assert_eq!(saved.options, options);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn cpu_affinity() {
+        let script = r#"while read line; do case $line in O*) echo "1:$(grep Cpus_allowed_list /proc/self/status | cut -f2)";; Q) exit;; esac; done"#;
+        let options = ControllerOptions::default().cpu_affinity([0]);
+        let mut ctrl = script_controller_with(script, &options).unwrap();
+        assert_eq!(ctrl.get_outputs(&[1]).unwrap()[&1], "0");
+        let options = ControllerOptions::default().cpu_affinity([usize::MAX]);
+        assert!(script_controller_with(script, &options).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn memory_limit() {
+        // The dd program reports that it failed to allocate a huge buffer.
+        let script = r#"read line; dd if=/dev/zero of=/dev/null bs=100M count=1 || exit 1; echo "1:ok""#;
+        let options = ControllerOptions::default().memory_limit(50_000_000).stderr_lines(5);
+        let mut ctrl = script_controller_with(script, &options).unwrap();
+        let error = ctrl.get_outputs(&[1]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::OutOfMemory);
+        let oom = ControllerOutOfMemory::from_io(&error).unwrap();
+        assert_eq!(oom.limit, 50_000_000);
+        assert!(oom.status.out_of_memory);
+        assert!(ControllerOutOfMemory::from_io(&ctrl.reset().unwrap_err()).is_some());
+        // Other crashes are not mistaken for running out of memory.
+        let script = "read line; kill -SEGV $$";
+        let mut ctrl = script_controller_with(script, &options).unwrap();
+        let error = ctrl.get_outputs(&[1]).unwrap_err();
+        assert!(!ControllerDead::from_io(&error).unwrap().status.out_of_memory);
+    }
+
+    #[test]
+    fn interfaces() {
+        let env_spec: EnvironmentSpec = serde_json::from_str(
+            r#"{
+                "name": "test",
+                "path": "test.exe",
+                "populations": [{
+                    "name": "robot",
+                    "interfaces": [
+                        {"gin": 1, "name": "left_wheel", "unit": "rad"},
+                        {"gin": 2, "name": "right_wheel"},
+                        {"gin": 7, "name": "camera"}
+                    ]
+                }]
+            }"#,
+        )
+        .unwrap();
+        let interfaces = Interfaces::new(&env_spec, "robot").unwrap();
+        assert_eq!(interfaces.check_unit("left_wheel", "radians"), Ok(1));
+        assert!(interfaces.check_unit("left_wheel", "degrees").is_err());
+        assert!(interfaces.check_unit("right_wheel", "radians").is_err());
+        assert_eq!(interfaces["left_wheel"], 1);
+        assert_eq!(interfaces.gin("camera"), Some(7));
+        assert_eq!(interfaces.gin("nose"), None);
+        assert_eq!(interfaces.name(2), Some("right_wheel"));
+        assert_eq!(interfaces.resolve(&["camera", "left_wheel"]).unwrap(), [7, 1]);
+        assert!(interfaces.resolve(&["camera", "nose"]).is_err());
+        assert!(interfaces.validate(&[1, 2, 7]).is_ok());
+        assert!(interfaces.validate(&[1, 3]).is_err());
+        assert!(Interfaces::new(&env_spec, "zebra").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn controller_group() {
+        let env = std::env::temp_dir().join(format!("npc_maker_group_{}.json", std::process::id()));
+        std::fs::write(
+            &env,
+            r#"{
+                "name": "test",
+                "path": "test.exe",
+                "populations": [{
+                    "name": "robot",
+                    "interfaces": [
+                        {"gin": 1, "name": "left_wheel"},
+                        {"gin": 2, "name": "right_wheel"},
+                        {"gin": 7, "name": "camera"}
+                    ],
+                    "modules": [
+                        {"name": "vision", "interfaces": ["camera"]},
+                        {"name": "policy", "interfaces": ["left_wheel", "right_wheel"]}
+                    ]
+                }]
+            }"#,
+        )
+        .unwrap();
+        // Replies to every output request with its genotype.
+        let script =
+            r#"while read line; do case $line in N*) g=${line#N};; O*) echo "${line#O}:$g";; Q) exit;; esac; done"#;
+        let command = vec!["/bin/sh".to_string(), "-c".to_string(), script.to_string()];
+        let commands = HashMap::from([("vision".to_string(), command.clone()), ("policy".to_string(), command)]);
+        let mut group = ControllerGroup::new(&env, "robot", &commands, &ControllerOptions::default()).unwrap();
+        assert!(ControllerGroup::new(&env, "robot", &HashMap::new(), &ControllerOptions::default()).is_err());
+        std::fs::remove_file(&env).unwrap();
+        assert_eq!(group.module_names().collect::<Vec<_>>(), ["vision", "policy"]);
+        group.new_genotype(r#"{"vision": "eyes", "policy": {"w": 1}}"#).unwrap();
+        group.set_input(1, "0.5").unwrap();
+        group.advance(0.1).unwrap();
+        let outputs = group.get_outputs(&[1, 7]).unwrap();
+        assert_eq!(outputs[&1], r#"{"w":1}"#);
+        assert_eq!(outputs[&7], "eyes");
+        assert!(group.set_input(3, "0.5").is_err());
+        assert!(group.new_genotype(r#"{"vision": "eyes"}"#).is_err());
+        assert!(group.module("policy").is_some());
+    }
+
+    crate::interface_adapter! {
+        struct Robot {
+            left_wheel: Input<f64>,
+            camera: BinaryInput,
+            right_wheel: Output<u64>,
+        }
+    }
+
+    crate::interface_adapter! {
+        struct Robot2 {
+            nose: Input<f64>,
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn interface_adapter() {
+        let mut interfaces = Interfaces::default();
+        interfaces.by_name.insert("left_wheel".to_string(), 1);
+        interfaces.by_name.insert("right_wheel".to_string(), 2);
+        interfaces.by_name.insert("camera".to_string(), 7);
+        let robot = Robot::new(&interfaces).unwrap();
+        assert_eq!(
+            (robot.left_wheel.gin(), robot.camera.gin(), robot.right_wheel.gin()),
+            (1, 7, 2)
+        );
+        assert!(Robot2::new(&interfaces).is_err());
+        // Replies to every output request with the GIN.
+        let script = r#"while read line; do case $line in O*) echo "${line#O}:${line#O}";; Q) exit;; esac; done"#;
+        let mut ctrl = script_controller(script);
+        robot.left_wheel.set(&mut ctrl, 0.5).unwrap();
+        robot.camera.set(&mut ctrl, b"rgb\n").unwrap();
+        assert_eq!(robot.right_wheel.get(&mut ctrl).unwrap(), 2);
+        assert!(robot.right_wheel.parse("x").is_err());
+    }
+
+    #[test]
+    fn codec() {
+        use codec::{DecodeError, Gins, Header};
+        assert_eq!(codec::decode(b"X0.25\r\n"), Ok(Header::Advance { dt: 0.25 }));
+        assert_eq!(codec::decode(b"I 3:x:y"), Ok(Header::SetInput { gin: 3, value: "x:y" }));
+        assert_eq!(codec::decode(b"B5:2\n"), Ok(Header::SetBinary { gin: 5, num_bytes: 2 }));
+        assert_eq!(
+            codec::decode(b"W1, 2,3"),
+            Ok(Header::GetOutputs {
+                gins: Gins::new(&[1, 2, 3])
+            })
+        );
+        assert_eq!(codec::decode(b"Xfast"), Err(DecodeError::Malformed('X')));
+        assert_eq!(codec::decode(b"W1,x"), Err(DecodeError::Malformed('W')));
+        assert_eq!(codec::decode(b"D{}"), Err(DecodeError::UnknownType('D')));
+        assert_eq!(codec::decode(b"z:"), Err(DecodeError::UnknownType('z')));
+        assert_eq!(codec::decode(b"\xFF"), Err(DecodeError::Utf8));
+        assert_eq!(codec::decode(b"\n"), Err(DecodeError::Empty));
+        assert_eq!(codec::decode_input("7:abc"), Ok((7, "abc")));
+        let mut text = String::new();
+        let header = Header::Custom {
+            message_type: 'T',
+            id: Some(4),
+            body: "{}",
+        };
+        codec::encode(&header, &mut text).unwrap();
+        codec::encode_output(2, "0.5", &mut text).unwrap();
+        assert_eq!(text, "T4:{}\n2:0.5\n");
+        assert_eq!(codec::decode(text.lines().next().unwrap().as_bytes()), Ok(header));
+        // Malformed messages are errors, not panics.
+        let error = Message::read(&mut b"O-1\n".as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn message_reader() {
+        let mut data = vec![];
+        let messages = [
+            Message::Advance { dt: 0.5 },
+            Message::SetInput {
+                gin: 3,
+                value: "x:y".to_string(),
+            },
+            Message::GetOutput { gin: 4 },
+            Message::Reset,
+            Message::SetBinary {
+                gin: 5,
+                bytes: b"\n\n".to_vec(),
+            },
+            Message::SetInput {
+                gin: 6,
+                value: "".to_string(),
+            },
+        ];
+        for msg in &messages {
+            msg.write(&mut data).unwrap();
+        }
+        let mut reader = MessageReader::new(data.as_slice());
+        assert_eq!(reader.read().unwrap(), MessageRef::Advance { dt: 0.5 });
+        assert_eq!(reader.read().unwrap(), MessageRef::SetInput { gin: 3, value: "x:y" });
+        assert_eq!(reader.read().unwrap(), MessageRef::GetOutput { gin: 4 });
+        assert_eq!(reader.read().unwrap(), MessageRef::Other(Message::Reset));
+        assert_eq!(reader.read().unwrap(), MessageRef::Other(messages[4].clone()));
+        assert_eq!(reader.read().unwrap(), MessageRef::SetInput { gin: 6, value: "" });
+        assert!(reader.read().is_err());
+    }
+
+    #[test]
+    fn binary_limit() {
+        let mut reader = MessageReader::new(
+            b"B1:18446744073709551615
+"
+            .as_slice(),
+        );
+        let error = reader.read().unwrap_err();
+        let too_large = MessageTooLarge::from_io(&error).unwrap();
+        assert_eq!(too_large.limit, DEFAULT_BINARY_LIMIT);
+        assert!(matches!(crate::Error::from(error), crate::Error::Protocol(_)));
+
+        let mut reader = MessageReader::new(b"B1:5\nabcdeB2:6\nabcdef".as_slice());
+        reader.set_binary_limit(5);
+        let expected = Message::SetBinary {
+            gin: 1,
+            bytes: b"abcde".to_vec(),
+        };
+        assert_eq!(reader.read().unwrap(), MessageRef::Other(expected));
+        assert!(MessageTooLarge::from_io(&reader.read().unwrap_err()).is_some());
+
+        // Truncated data.
+        let error = Message::read(&mut b"B1:100000\nabc".as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        // Lines of text are limited too.
+        let mut reader = MessageReader::new(b"N0123456789\n".as_slice());
+        reader.set_binary_limit(5);
+        assert!(MessageTooLarge::from_io(&reader.read().unwrap_err()).is_some());
+        let mut reader = MessageReader::new(b"M1\n1:0123456789\n".as_slice());
+        reader.set_binary_limit(5);
+        assert!(MessageTooLarge::from_io(&reader.read().unwrap_err()).is_some());
+        let mut reader =
+            MessageReader::new(b"IIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIIII".as_slice());
+        reader.set_value_encoding(ValueEncoding::F64);
+        assert!(MessageTooLarge::from_io(&reader.read().unwrap_err()).is_some());
+
+        // The last line of the stream does not need a trailing newline.
+        assert_eq!(Message::read(&mut b"G7".as_slice()).unwrap(), Message::Seed { seed: 7 });
+
+        let data = vec![7; 20_000];
+        let mut chunks = Vec::new();
+        read_binary_chunks(&mut data.as_slice(), data.len(), data.len(), |chunk| {
+            chunks.push(chunk.len());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(chunks, [8192, 8192, 3616]);
+    }
 }
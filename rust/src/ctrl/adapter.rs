@@ -0,0 +1,187 @@
+//! Typed handles for a population's interfaces, for using controllers without juggling GINs.
+//!
+//! Use the [interface_adapter!](crate::interface_adapter) macro to declare a
+//! structure with one handle per interface. The interfaces are looked up by
+//! name when the structure is made, so typos and missing interfaces are
+//! reported up front instead of while the environment is running.
+
+use super::{Controller, Interfaces};
+use std::fmt::Display;
+use std::io;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+/// Handle for one of a population's interfaces.
+pub trait Interface: Sized {
+    /// Find the named interface.
+    fn resolve(interfaces: &Interfaces, name: &str) -> Result<Self, String>;
+
+    fn gin(&self) -> u64;
+}
+
+fn lookup(interfaces: &Interfaces, name: &str) -> Result<u64, String> {
+    interfaces
+        .gin(name)
+        .ok_or_else(|| format!("no such interface \"{name}\""))
+}
+
+/// Implement the standard traits without placing any bounds on the value type.
+macro_rules! impl_handle_traits {
+    ($handle:ident) => {
+        impl<T> Clone for $handle<T> {
+            fn clone(&self) -> Self {
+                *self
+            }
+        }
+
+        impl<T> Copy for $handle<T> {}
+
+        impl<T> PartialEq for $handle<T> {
+            fn eq(&self, other: &Self) -> bool {
+                self.gin == other.gin
+            }
+        }
+
+        impl<T> Eq for $handle<T> {}
+
+        impl<T> std::fmt::Debug for $handle<T> {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.debug_struct(stringify!($handle))
+                    .field("gin", &self.gin)
+                    .finish()
+            }
+        }
+    };
+}
+
+/// Interface for sending values to the controller.
+pub struct Input<T> {
+    gin: u64,
+    value_type: PhantomData<fn(T)>,
+}
+
+impl<T> Interface for Input<T> {
+    fn resolve(interfaces: &Interfaces, name: &str) -> Result<Self, String> {
+        Ok(Self {
+            gin: lookup(interfaces, name)?,
+            value_type: PhantomData,
+        })
+    }
+
+    fn gin(&self) -> u64 {
+        self.gin
+    }
+}
+
+impl<T: Display> Input<T> {
+    pub fn set(&self, controller: &mut Controller, value: T) -> Result<(), io::Error> {
+        controller.set_input(self.gin, &value.to_string())
+    }
+}
+
+/// Interface for sending arrays of bytes to the controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BinaryInput {
+    gin: u64,
+}
+
+impl Interface for BinaryInput {
+    fn resolve(interfaces: &Interfaces, name: &str) -> Result<Self, String> {
+        Ok(Self {
+            gin: lookup(interfaces, name)?,
+        })
+    }
+
+    fn gin(&self) -> u64 {
+        self.gin
+    }
+}
+
+impl BinaryInput {
+    pub fn set(&self, controller: &mut Controller, bytes: &[u8]) -> Result<(), io::Error> {
+        controller.set_binary(self.gin, bytes)
+    }
+}
+
+/// Interface for receiving values from the controller.
+pub struct Output<T> {
+    gin: u64,
+    value_type: PhantomData<fn() -> T>,
+}
+
+impl_handle_traits!(Input);
+impl_handle_traits!(Output);
+
+impl<T> Interface for Output<T> {
+    fn resolve(interfaces: &Interfaces, name: &str) -> Result<Self, String> {
+        Ok(Self {
+            gin: lookup(interfaces, name)?,
+            value_type: PhantomData,
+        })
+    }
+
+    fn gin(&self) -> u64 {
+        self.gin
+    }
+}
+
+impl<T: FromStr> Output<T>
+where
+    T::Err: Display,
+{
+    /// Retrieve the output from the controller. This method blocks on IO.
+    pub fn get(&self, controller: &mut Controller) -> Result<T, io::Error> {
+        let mut outputs = controller.get_outputs(&[self.gin])?;
+        let value = outputs.remove(&self.gin).unwrap_or_default();
+        self.parse(&value)
+    }
+
+    /// Parse a value which was retrieved by [Controller::get_outputs()].
+    pub fn parse(&self, value: &str) -> Result<T, io::Error> {
+        value.trim().parse().map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid output {value:?} for gin {}, {err}", self.gin),
+            )
+        })
+    }
+}
+
+/// Declare a structure with a typed handle for each of a population's interfaces.
+///
+/// The field names are the names of the interfaces, as written in the
+/// environment specification. The field types are [Input], [BinaryInput], or
+/// [Output]. The structure gets a constructor `new(&Interfaces) -> Result<Self, String>`
+/// which fails if any of the interfaces are missing.
+///
+/// ```
+/// npc_maker::interface_adapter! {
+///     /// Body of a wheeled robot.
+///     pub struct Robot {
+///         left_wheel: Input<f64>,
+///         right_wheel: Input<f64>,
+///         camera: BinaryInput,
+///         bumper: Output<bool>,
+///     }
+/// }
+/// // let robot = Robot::new(&interfaces)?;
+/// // robot.left_wheel.set(&mut controller, 0.5)?;
+/// ```
+#[macro_export]
+macro_rules! interface_adapter {
+    ($(#[$meta:meta])* $vis:vis struct $name:ident { $($field:ident : $kind:ident $(<$value:ty>)?),* $(,)? }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        $vis struct $name {
+            $(pub $field: $crate::ctrl::$kind $(<$value>)?,)*
+        }
+
+        impl $name {
+            pub fn new(interfaces: &$crate::ctrl::Interfaces) -> Result<Self, String> {
+                Ok(Self {
+                    $($field: $crate::ctrl::Interface::resolve(interfaces, stringify!($field))?,)*
+                })
+            }
+        }
+    };
+}
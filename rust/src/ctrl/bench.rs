@@ -0,0 +1,208 @@
+//! Benchmarks for controllers, using synthetic workloads.
+//!
+//! The benchmark drives a controller through a number of simulated time steps,
+//! each of which sets the inputs, advances the controller, and retrieves the
+//! outputs. It works with any controller and any transport, so the results can
+//! be compared between controller implementations, versions, or transports.
+//!
+//! ```no_run
+//! # use npc_maker::ctrl::{bench, Controller};
+//! let command = ["python".to_string(), "ctrl.py".to_string()];
+//! let mut controller = Controller::new("env.json", "pop", &command)?;
+//! let workload = bench::Workload {
+//!     genotypes: bench::Workload::genome_size_sweep(&[100, 10_000, 1_000_000]),
+//!     input_gins: vec![0, 1, 2],
+//!     output_gins: vec![3],
+//!     ..Default::default()
+//! };
+//! println!("{}", bench::run(&mut controller, &workload)?);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use super::{Controller, Profile};
+use std::io;
+use std::time::{Duration, Instant};
+
+/// How the messages of each time step are sent.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MessageMix {
+    /// One message per input and output.
+    #[default]
+    Individual,
+    /// One message for all of the inputs and one message for all of the outputs.
+    /// The controller must support the "Set Multiple Inputs" and "Get Multiple Outputs" messages.
+    Batched,
+}
+
+/// Description of the work to give to the controller.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Workload {
+    /// Each genotype is loaded in turn and benchmarked separately.
+    pub genotypes: Vec<String>,
+
+    /// Number of time steps to run for each genotype.
+    pub ticks: usize,
+
+    /// Time period to advance by each time step, in seconds.
+    pub dt: f64,
+
+    /// Inputs to set each time step.
+    pub input_gins: Vec<u64>,
+
+    /// Value to send to every input.
+    pub input_value: String,
+
+    /// Outputs to retrieve each time step.
+    pub output_gins: Vec<u64>,
+
+    pub mix: MessageMix,
+}
+
+impl Default for Workload {
+    fn default() -> Self {
+        Self {
+            genotypes: vec!["{}".to_string()],
+            ticks: 1000,
+            dt: 0.01,
+            input_gins: vec![],
+            input_value: "0.5".to_string(),
+            output_gins: vec![],
+            mix: MessageMix::default(),
+        }
+    }
+}
+
+impl Workload {
+    /// Make synthetic genotypes of the given sizes, in bytes.
+    ///
+    /// Each genotype is a JSON object with a single string of padding.
+    pub fn genome_size_sweep(sizes: &[usize]) -> Vec<String> {
+        let overhead = r#"{"padding":""}"#.len();
+        sizes
+            .iter()
+            .map(|&size| format!(r#"{{"padding":"{}"}}"#, "x".repeat(size.saturating_sub(overhead))))
+            .collect()
+    }
+}
+
+/// Benchmark results for one genotype.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    /// Length of the genotype, in bytes.
+    pub genome_size: usize,
+
+    /// Time taken to send the genotype and for the controller to acknowledge it.
+    pub load_time: Duration,
+
+    /// Total time taken for all of the time steps.
+    pub elapsed: Duration,
+
+    /// Time taken for each time step, sorted from fastest to slowest.
+    pub tick_times: Vec<Duration>,
+
+    /// Statistics for each type of message.
+    pub profile: Profile,
+}
+
+impl Sample {
+    /// Number of time steps per second.
+    pub fn throughput(&self) -> f64 {
+        self.tick_times.len() as f64 / self.elapsed.as_secs_f64()
+    }
+
+    /// Get a percentile of the time steps' durations, for example 0.5 is the median.
+    pub fn percentile(&self, fraction: f64) -> Duration {
+        if self.tick_times.is_empty() {
+            return Duration::ZERO;
+        }
+        let index = (fraction.clamp(0.0, 1.0) * (self.tick_times.len() - 1) as f64).round();
+        self.tick_times[index as usize]
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.tick_times.is_empty() {
+            return Duration::ZERO;
+        }
+        self.tick_times.iter().sum::<Duration>() / self.tick_times.len() as u32
+    }
+}
+
+/// Benchmark results for all of the genotypes in a workload.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub samples: Vec<Sample>,
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let micros = |time: Duration| time.as_secs_f64() * 1e6;
+        writeln!(
+            f,
+            "{:>12} {:>12} {:>12} {:>10} {:>10} {:>10} {:>10}",
+            "genome bytes", "load (us)", "ticks/sec", "mean (us)", "p50 (us)", "p99 (us)", "max (us)"
+        )?;
+        for sample in &self.samples {
+            writeln!(
+                f,
+                "{:>12} {:>12.1} {:>12.1} {:>10.1} {:>10.1} {:>10.1} {:>10.1}",
+                sample.genome_size,
+                micros(sample.load_time),
+                sample.throughput(),
+                micros(sample.mean()),
+                micros(sample.percentile(0.5)),
+                micros(sample.percentile(0.99)),
+                micros(sample.percentile(1.0)),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Run the workload on the controller.
+///
+/// This replaces the controller's profile with a new one for each genotype.
+pub fn run(controller: &mut Controller, workload: &Workload) -> Result<Report, io::Error> {
+    if workload.mix == MessageMix::Batched {
+        controller.enable_vectorized_outputs();
+    }
+    let inputs: Vec<(u64, &str)> = workload
+        .input_gins
+        .iter()
+        .map(|&gin| (gin, workload.input_value.as_str()))
+        .collect();
+    let mut samples = Vec::with_capacity(workload.genotypes.len());
+    for genotype in &workload.genotypes {
+        let start_time = Instant::now();
+        controller.new_genotype(genotype)?;
+        controller.ping()?;
+        let load_time = start_time.elapsed();
+        controller.profile = Some(Profile::default());
+
+        let mut tick_times = Vec::with_capacity(workload.ticks);
+        let start_time = Instant::now();
+        for _ in 0..workload.ticks {
+            let tick_start = Instant::now();
+            match workload.mix {
+                MessageMix::Individual => {
+                    for (gin, value) in &inputs {
+                        controller.set_input(*gin, value)?;
+                    }
+                }
+                MessageMix::Batched => controller.set_inputs(&inputs)?,
+            }
+            controller.advance(workload.dt)?;
+            controller.get_outputs(&workload.output_gins)?;
+            tick_times.push(tick_start.elapsed());
+        }
+        let elapsed = start_time.elapsed();
+        tick_times.sort();
+        samples.push(Sample {
+            genome_size: genotype.len(),
+            load_time,
+            elapsed,
+            tick_times,
+            profile: controller.profile.take().unwrap_or_default(),
+        });
+    }
+    Ok(Report { samples })
+}
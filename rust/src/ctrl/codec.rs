@@ -0,0 +1,298 @@
+//! Encoder and decoder for the controller protocol, which does not allocate memory.
+//!
+//! This module works on one line at a time. The caller is responsible for
+//! splitting the input stream into lines, and for reading the rest of the
+//! multi-line messages ("M" and "B").
+//!
+//! The npc_maker crate requires the standard library, and so it can not be
+//! used on microcontrollers as is. However this file only uses the `core`
+//! library and it does not depend on the rest of the crate, so it can be
+//! copied into a `no_std` program which speaks the protocol, for example over a UART.
+//!
+//! Desktop programs should use [Message](super::Message) and [MessageReader](super::MessageReader) instead.
+
+use core::fmt::{self, Write};
+use core::str::FromStr;
+
+/// Message types which are reserved for the standard controller protocol.
+/// This includes the messages sent from controllers to environments ("D").
+pub const RESERVED_TYPES: &str = "EPFCNKVGUHRXIMBOWSLQD";
+
+/// Reasons why a line is not a valid message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The line is empty.
+    Empty,
+    /// The line is not valid UTF-8.
+    Utf8,
+    /// The message type is not a capital letter, or it is reserved for messages sent to environments.
+    UnknownType(char),
+    /// The body of the message is not in the expected format for its type.
+    Malformed(char),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "empty message"),
+            Self::Utf8 => write!(f, "message is not valid UTF-8"),
+            Self::UnknownType(message_type) => write!(f, "unknown message type {message_type:?}"),
+            Self::Malformed(message_type) => write!(f, "malformed message of type {message_type:?}"),
+        }
+    }
+}
+
+/// List of GINs in a "W" message.
+#[derive(Debug, Clone, Copy)]
+pub struct Gins<'a>(GinList<'a>);
+
+#[derive(Debug, Clone, Copy)]
+enum GinList<'a> {
+    /// Comma separated list, which has already been checked for errors.
+    Text(&'a str),
+    Slice(&'a [u64]),
+}
+
+impl<'a> Gins<'a> {
+    pub fn new(gins: &'a [u64]) -> Self {
+        Self(GinList::Slice(gins))
+    }
+
+    fn parse(text: &'a str) -> Result<Self, DecodeError> {
+        for gin in text.split(',').filter(|gin| !gin.trim().is_empty()) {
+            number::<u64>(gin, 'W')?;
+        }
+        Ok(Self(GinList::Text(text)))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u64> + 'a {
+        let (text, slice) = match self.0 {
+            GinList::Text(text) => (text, &[][..]),
+            GinList::Slice(slice) => ("", slice),
+        };
+        let text = text.split(',').filter_map(|gin| gin.trim().parse().ok());
+        text.chain(slice.iter().copied())
+    }
+}
+
+impl PartialEq for Gins<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl fmt::Display for Gins<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, gin) in self.iter().enumerate() {
+            if index > 0 {
+                f.write_char(',')?;
+            }
+            write!(f, "{gin}")?;
+        }
+        Ok(())
+    }
+}
+
+/// First line of a message sent from an environment to a controller.
+///
+/// The body of the message is borrowed from the line. See [Message](super::Message)
+/// for the meaning of each message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Header<'a> {
+    Environment {
+        environment: &'a str,
+    },
+    Population {
+        population: &'a str,
+    },
+    ValueEncoding {
+        encoding: &'a str,
+    },
+    Capabilities,
+    New {
+        genotype: &'a str,
+    },
+    Swap {
+        genotype: &'a str,
+    },
+    Validate {
+        genotype: &'a str,
+    },
+    Seed {
+        seed: u64,
+    },
+    Select {
+        agent: u64,
+    },
+    Ping,
+    Reset,
+    Advance {
+        dt: f64,
+    },
+    SetInput {
+        gin: u64,
+        value: &'a str,
+    },
+    /// Followed by one line per input, see [decode_input()].
+    SetInputs {
+        num_inputs: usize,
+    },
+    /// Followed by the binary data.
+    SetBinary {
+        gin: u64,
+        num_bytes: usize,
+    },
+    GetOutput {
+        gin: u64,
+    },
+    GetOutputs {
+        gins: Gins<'a>,
+    },
+    Save {
+        path: &'a str,
+    },
+    Load {
+        path: &'a str,
+    },
+    Quit,
+    Custom {
+        message_type: char,
+        id: Option<u64>,
+        body: &'a str,
+    },
+}
+
+fn number<T: FromStr>(text: &str, message_type: char) -> Result<T, DecodeError> {
+    text.trim().parse().map_err(|_| DecodeError::Malformed(message_type))
+}
+
+fn strip_newline(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// Decode the first line of a message. The trailing newline is optional.
+pub fn decode(line: &[u8]) -> Result<Header<'_>, DecodeError> {
+    let line = core::str::from_utf8(strip_newline(line)).map_err(|_| DecodeError::Utf8)?;
+    decode_str(line)
+}
+
+/// Decode the first line of a message, without the trailing newline.
+pub fn decode_str(line: &str) -> Result<Header<'_>, DecodeError> {
+    let message_type = line.chars().next().ok_or(DecodeError::Empty)?;
+    let body = &line[message_type.len_utf8()..];
+    let malformed = DecodeError::Malformed(message_type);
+    let header = match message_type {
+        'E' => Header::Environment { environment: body },
+        'P' => Header::Population { population: body },
+        'F' => Header::ValueEncoding { encoding: body },
+        'C' => Header::Capabilities,
+        'N' => Header::New { genotype: body },
+        'K' => Header::Swap { genotype: body },
+        'V' => Header::Validate { genotype: body },
+        'G' => Header::Seed {
+            seed: number(body, message_type)?,
+        },
+        'U' => Header::Select {
+            agent: number(body, message_type)?,
+        },
+        'H' => Header::Ping,
+        'R' => Header::Reset,
+        'X' => Header::Advance {
+            dt: number(body, message_type)?,
+        },
+        'I' => {
+            let (gin, value) = body.split_once(':').ok_or(malformed)?;
+            Header::SetInput {
+                gin: number(gin, message_type)?,
+                value,
+            }
+        }
+        'M' => Header::SetInputs {
+            num_inputs: number(body, message_type)?,
+        },
+        'B' => {
+            let (gin, num_bytes) = body.split_once(':').ok_or(malformed)?;
+            Header::SetBinary {
+                gin: number(gin, message_type)?,
+                num_bytes: number(num_bytes, message_type)?,
+            }
+        }
+        'O' => Header::GetOutput {
+            gin: number(body, message_type)?,
+        },
+        'W' => Header::GetOutputs {
+            gins: Gins::parse(body)?,
+        },
+        'S' => Header::Save { path: body },
+        'L' => Header::Load { path: body },
+        'Q' => Header::Quit,
+        _ if message_type.is_ascii_uppercase() && !RESERVED_TYPES.contains(message_type) => {
+            let (id, body) = body.split_once(':').ok_or(malformed)?;
+            let id = if id.is_empty() {
+                None
+            } else {
+                Some(number(id, message_type)?)
+            };
+            Header::Custom { message_type, id, body }
+        }
+        _ => return Err(DecodeError::UnknownType(message_type)),
+    };
+    Ok(header)
+}
+
+/// Decode one of the lines which follow a "M" message, without the trailing newline.
+pub fn decode_input(line: &str) -> Result<(u64, &str), DecodeError> {
+    let (gin, value) = line.split_once(':').ok_or(DecodeError::Malformed('M'))?;
+    Ok((number(gin, 'M')?, value))
+}
+
+/// Encode the first line of a message, including the trailing newline.
+pub fn encode(header: &Header, out: &mut impl Write) -> fmt::Result {
+    match header {
+        Header::Environment { environment } => writeln!(out, "E{environment}"),
+        Header::Population { population } => writeln!(out, "P{population}"),
+        Header::ValueEncoding { encoding } => writeln!(out, "F{encoding}"),
+        Header::Capabilities => writeln!(out, "C"),
+        Header::New { genotype } => writeln!(out, "N{genotype}"),
+        Header::Swap { genotype } => writeln!(out, "K{genotype}"),
+        Header::Validate { genotype } => writeln!(out, "V{genotype}"),
+        Header::Seed { seed } => writeln!(out, "G{seed}"),
+        Header::Select { agent } => writeln!(out, "U{agent}"),
+        Header::Ping => writeln!(out, "H"),
+        Header::Reset => writeln!(out, "R"),
+        Header::Advance { dt } => writeln!(out, "X{dt}"),
+        Header::SetInput { gin, value } => writeln!(out, "I{gin}:{value}"),
+        Header::SetInputs { num_inputs } => writeln!(out, "M{num_inputs}"),
+        Header::SetBinary { gin, num_bytes } => writeln!(out, "B{gin}:{num_bytes}"),
+        Header::GetOutput { gin } => writeln!(out, "O{gin}"),
+        Header::GetOutputs { gins } => writeln!(out, "W{gins}"),
+        Header::Save { path } => writeln!(out, "S{path}"),
+        Header::Load { path } => writeln!(out, "L{path}"),
+        Header::Quit => writeln!(out, "Q"),
+        Header::Custom { message_type, id, body } => match id {
+            Some(id) => writeln!(out, "{message_type}{id}:{body}"),
+            None => writeln!(out, "{message_type}:{body}"),
+        },
+    }
+}
+
+/// Encode one of the lines which follow a "M" message, including the trailing newline.
+pub fn encode_input(gin: u64, value: &str, out: &mut impl Write) -> fmt::Result {
+    writeln!(out, "{gin}:{value}")
+}
+
+/// Encode an output value, for sending from the controller to the environment.
+pub fn encode_output(gin: u64, value: &str, out: &mut impl Write) -> fmt::Result {
+    writeln!(out, "{gin}:{value}")
+}
+
+/// Encode an error report, for sending from the controller to the environment.
+pub fn encode_error(message: &str, out: &mut impl Write) -> fmt::Result {
+    writeln!(out, "E:{message}")
+}
+
+/// Encode the response to a custom message, for sending from the controller to the environment.
+pub fn encode_response(message_type: char, id: u64, body: &str, out: &mut impl Write) -> fmt::Result {
+    writeln!(out, "{message_type}{id}:{body}")
+}
@@ -0,0 +1,195 @@
+//! Control systems which are made of several controller programs.
+//!
+//! A population's specification may split its control system into modules,
+//! for example a vision module and a policy module. Each module is a separate
+//! controller program, and each of the population's interfaces is connected to
+//! at most one module. See [ModuleSpec](crate::env_spec::ModuleSpec).
+
+use super::{Controller, ControllerOptions, Interfaces};
+use crate::env_spec::EnvironmentSpec;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// One controller for each module of a population's control system.
+///
+/// Inputs and outputs are routed to the module which owns their interface.
+/// Messages which apply to the whole control system, such as advance and
+/// reset, are sent to every module.
+#[derive(Debug)]
+pub struct ControllerGroup {
+    modules: Vec<(String, Controller)>,
+    /// Index into modules, for each interface.
+    routes: HashMap<u64, usize>,
+}
+
+impl ControllerGroup {
+    /// Start one controller program for each module of the population.
+    ///
+    /// Argument commands contains the command line invocation for each module, keyed by the module's name.
+    pub fn new(
+        environment: impl AsRef<Path>,
+        population: &str,
+        commands: &HashMap<String, Vec<String>>,
+        options: &ControllerOptions,
+    ) -> Result<Self, io::Error> {
+        let environment = environment.as_ref();
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+        let env_spec =
+            EnvironmentSpec::new(environment).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let Some(pop_spec) = env_spec.populations.iter().find(|pop_spec| pop_spec.name == population) else {
+            return Err(invalid(format!(
+                "no such population \"{population}\", in file: {environment:?}"
+            )));
+        };
+        if pop_spec.modules.is_empty() {
+            return Err(invalid(format!("population \"{population}\" has no modules")));
+        }
+        let interfaces = Interfaces::from_population(pop_spec).map_err(invalid)?;
+        let mut modules = Vec::with_capacity(pop_spec.modules.len());
+        let mut routes = HashMap::new();
+        for (index, module) in pop_spec.modules.iter().enumerate() {
+            let Some(command) = commands.get(&module.name) else {
+                return Err(invalid(format!("missing command for module \"{}\"", module.name)));
+            };
+            for name in &module.interfaces {
+                let gin = interfaces
+                    .gin(name)
+                    .ok_or_else(|| invalid(format!("no such interface \"{name}\"")))?;
+                routes.insert(gin, index);
+            }
+            let controller = Controller::with_options(environment, population, command, options)?;
+            modules.push((module.name.clone(), controller));
+        }
+        Ok(Self { modules, routes })
+    }
+
+    /// Get the names of the modules, in the order they appear in the specification.
+    pub fn module_names(&self) -> impl Iterator<Item = &str> {
+        self.modules.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Get the controller for the named module.
+    pub fn module(&mut self, name: &str) -> Option<&mut Controller> {
+        self.modules
+            .iter_mut()
+            .find(|(module, _)| module == name)
+            .map(|(_, controller)| controller)
+    }
+
+    /// Get the controller which owns the given interface.
+    pub fn route(&mut self, gin: u64) -> Result<&mut Controller, io::Error> {
+        let Some(&index) = self.routes.get(&gin) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("interface gin {gin} is not connected to any module"),
+            ));
+        };
+        Ok(&mut self.modules[index].1)
+    }
+
+    /// Check if every module's controller is still running.
+    pub fn is_alive(&mut self) -> bool {
+        self.modules.iter_mut().all(|(_, controller)| controller.is_alive())
+    }
+
+    /// Initialize every module with a new genotype.
+    ///
+    /// Argument genotype is a JSON object with one genotype per module, keyed
+    /// by the module's name. Genotypes which are JSON strings are sent as is,
+    /// other JSON values are sent in their serialized form.
+    pub fn new_genotype(&mut self, genotype: &str) -> Result<(), io::Error> {
+        let mut genotypes: HashMap<String, serde_json::Value> = serde_json::from_str(genotype)?;
+        for (name, controller) in &mut self.modules {
+            let Some(genotype) = genotypes.remove(name.as_str()) else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("genotype is missing module \"{name}\""),
+                ));
+            };
+            match genotype {
+                serde_json::Value::String(genotype) => controller.new_genotype(&genotype)?,
+                genotype => controller.new_genotype(&genotype.to_string())?,
+            }
+        }
+        Ok(())
+    }
+
+    pub fn reset(&mut self) -> Result<(), io::Error> {
+        self.modules
+            .iter_mut()
+            .try_for_each(|(_, controller)| controller.reset())
+    }
+
+    /// Advance every module's internal state by the given time period.
+    pub fn advance(&mut self, dt: f64) -> Result<(), io::Error> {
+        self.modules
+            .iter_mut()
+            .try_for_each(|(_, controller)| controller.advance(dt))
+    }
+
+    pub fn set_input(&mut self, gin: u64, value: &str) -> Result<(), io::Error> {
+        self.route(gin)?.set_input(gin, value)
+    }
+
+    pub fn set_binary(&mut self, gin: u64, value: &[u8]) -> Result<(), io::Error> {
+        self.route(gin)?.set_binary(gin, value)
+    }
+
+    /// Retrieve outputs from all of the modules which own them.
+    ///
+    /// The requests are sent to every module before waiting for any of the
+    /// replies, so that the modules compute their outputs concurrently.
+    /// This method blocks on IO.
+    pub fn get_outputs(&mut self, gin_list: &[u64]) -> Result<HashMap<u64, String>, io::Error> {
+        let mut requests = vec![Vec::new(); self.modules.len()];
+        for &gin in gin_list {
+            self.route(gin)?;
+            requests[self.routes[&gin]].push(gin);
+        }
+        let mut handles = Vec::new();
+        for (index, gins) in requests.iter().enumerate() {
+            if !gins.is_empty() {
+                handles.push((index, self.modules[index].1.request_outputs(gins)?));
+            }
+        }
+        let mut outputs = HashMap::with_capacity(gin_list.len());
+        for (index, handle) in handles {
+            outputs.extend(self.modules[index].1.wait_outputs(handle)?);
+        }
+        Ok(outputs)
+    }
+
+    /// Save every module to its own file, named by appending the module's name to the path.
+    ///
+    /// Example: saving to "brain.sav" makes the files "brain.sav.vision" and "brain.sav.policy".
+    pub fn save(&mut self, path: impl AsRef<Path>) -> Result<(), io::Error> {
+        let path = path.as_ref();
+        for (name, controller) in &mut self.modules {
+            controller.save(module_path(path, name))?;
+        }
+        Ok(())
+    }
+
+    /// Load every module from the files made by [ControllerGroup::save()].
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<(), io::Error> {
+        let path = path.as_ref();
+        for (name, controller) in &mut self.modules {
+            controller.load(module_path(path, name))?;
+        }
+        Ok(())
+    }
+
+    pub fn quit(&mut self) -> Result<(), io::Error> {
+        self.modules
+            .iter_mut()
+            .try_for_each(|(_, controller)| controller.quit())
+    }
+}
+
+fn module_path(path: &Path, module: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(module);
+    path.into()
+}
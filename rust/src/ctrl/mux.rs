@@ -0,0 +1,207 @@
+//! Controllers which host many agents in a single program.
+//!
+//! Environments with many simple agents can not afford a separate program for
+//! each of them. Instead, one controller program can host many independent
+//! agents, each with its own genotype and internal state. The environment
+//! selects an agent by its ID and all subsequent messages are directed to it,
+//! until another agent is selected. Agent zero is selected at startup, so
+//! controllers which host a single agent do not need to use this feature.
+
+#[cfg(feature = "spawn")]
+use super::Controller;
+#[cfg(feature = "ctrl")]
+use super::{codec::DecodeError, Capability, ErrorPolicy, MessageError, Registry, API};
+#[cfg(feature = "ctrl")]
+use std::collections::HashMap;
+#[cfg(feature = "spawn")]
+use std::io::{self, Write};
+#[cfg(feature = "ctrl")]
+use std::path::PathBuf;
+
+#[cfg(feature = "spawn")]
+impl Controller {
+    /// Direct all subsequent messages to the given agent.
+    ///
+    /// The controller program must support hosting many agents, see [Multiplexer].
+    pub fn select(&mut self, agent: u64) -> Result<(), io::Error> {
+        self.check_alive()?;
+        let start = self.stdin.count;
+        writeln!(self.stdin, "U{agent}")?;
+        self.record_sent('U', start)?;
+        Ok(())
+    }
+}
+
+/// Client for a controller program which hosts many agents.
+///
+/// This keeps track of the currently selected agent and only sends the select
+/// message when switching between agents.
+#[cfg(feature = "spawn")]
+#[derive(Debug)]
+pub struct ControllerMux {
+    controller: Controller,
+    current: u64,
+}
+
+#[cfg(feature = "spawn")]
+impl ControllerMux {
+    pub fn new(controller: Controller) -> Self {
+        Self { controller, current: 0 }
+    }
+
+    /// Get the controller, with the given agent selected.
+    ///
+    /// Example: `mux.agent(7)?.set_input(gin, "1.0")?;`
+    pub fn agent(&mut self, agent: u64) -> Result<&mut Controller, io::Error> {
+        if agent != self.current {
+            self.controller.select(agent)?;
+            self.current = agent;
+        }
+        Ok(&mut self.controller)
+    }
+
+    /// Get the currently selected agent.
+    pub fn current(&self) -> u64 {
+        self.current
+    }
+
+    /// Get the controller without changing which agent is selected.
+    pub fn controller(&mut self) -> &mut Controller {
+        &mut self.controller
+    }
+
+    pub fn into_inner(self) -> Controller {
+        self.controller
+    }
+}
+
+/// Host many agents in a single controller program.
+///
+/// Each agent is an independent instance of the controller implementation.
+/// Agents are created on demand, the first time that they are selected.
+///
+/// Example: `main_loop(Multiplexer::new(|| MyController::default()))`
+#[cfg(feature = "ctrl")]
+pub struct Multiplexer<C> {
+    factory: Box<dyn FnMut() -> C>,
+    agents: HashMap<u64, C>,
+    current: u64,
+}
+
+#[cfg(feature = "ctrl")]
+impl<C> Multiplexer<C> {
+    /// Argument factory makes a new instance of the controller for each agent.
+    pub fn new(factory: impl FnMut() -> C + 'static) -> Self {
+        Self {
+            factory: Box::new(factory),
+            agents: HashMap::new(),
+            current: 0,
+        }
+    }
+
+    /// Get the currently selected agent.
+    pub fn agent(&mut self) -> &mut C {
+        self.agents.entry(self.current).or_insert_with(&mut self.factory)
+    }
+
+    /// Get all of the agents which have been created, indexed by their ID.
+    pub fn agents(&mut self) -> &mut HashMap<u64, C> {
+        &mut self.agents
+    }
+}
+
+#[cfg(feature = "ctrl")]
+impl<C: API + 'static> API for Multiplexer<C> {
+    fn new(&mut self, genotype: String) {
+        self.agent().new(genotype)
+    }
+
+    fn swap(&mut self, genotype: String) {
+        self.agent().swap(genotype)
+    }
+
+    fn validate(&mut self, genotype: &str) -> Result<(), String> {
+        self.agent().validate(genotype)
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.agent().seed(seed)
+    }
+
+    fn select(&mut self, agent: u64) {
+        self.current = agent;
+    }
+
+    fn reset(&mut self) {
+        self.agent().reset()
+    }
+
+    fn advance(&mut self, dt: f64) {
+        self.agent().advance(dt)
+    }
+
+    fn set_input(&mut self, gin: u64, value: String) {
+        self.agent().set_input(gin, value)
+    }
+
+    fn set_binary(&mut self, gin: u64, bytes: Vec<u8>) {
+        self.agent().set_binary(gin, bytes)
+    }
+
+    fn get_output(&mut self, gin: u64) -> String {
+        self.agent().get_output(gin)
+    }
+
+    fn set_input_f64(&mut self, gin: u64, value: f64) {
+        self.agent().set_input_f64(gin, value)
+    }
+
+    fn get_output_f64(&mut self, gin: u64) -> f64 {
+        self.agent().get_output_f64(gin)
+    }
+
+    fn save(&mut self, path: PathBuf) {
+        self.agent().save(path)
+    }
+
+    fn load(&mut self, path: PathBuf) {
+        self.agent().load(path)
+    }
+
+    fn message(&mut self, message_type: char, body: String) -> Result<String, DecodeError> {
+        self.agent().message(message_type, body)
+    }
+
+    fn malformed_message(&mut self, error: &MessageError) -> ErrorPolicy {
+        self.agent().malformed_message(error)
+    }
+
+    fn panicked(&mut self, message: &str) -> ErrorPolicy {
+        self.agent().panicked(message)
+    }
+
+    fn capabilities(&mut self) -> Vec<Capability> {
+        let mut capabilities = self.agent().capabilities();
+        capabilities.push(Capability::Select);
+        capabilities.push(Capability::Swap);
+        capabilities
+    }
+
+    /// Custom messages are handled by the currently selected agent.
+    fn custom_messages() -> Registry<Self> {
+        let mut registry = Registry::new();
+        for (message_type, mut handler) in C::custom_messages().handlers {
+            registry.handlers.insert(
+                message_type,
+                Box::new(move |mux: &mut Self, body: &str| handler(mux.agent(), body)) as super::Handler<Self>,
+            );
+        }
+        registry
+    }
+
+    fn quit(&mut self) {
+        for agent in self.agents.values_mut() {
+            agent.quit();
+        }
+    }
+}
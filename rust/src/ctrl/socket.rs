@@ -0,0 +1,157 @@
+//! Controllers which are served over a network socket.
+//!
+//! Instead of starting a new program, the controller connects to a server which
+//! is already running. This allows a single long-lived program to serve many
+//! environments. Each connection is an independent controller which uses the
+//! standard message protocol.
+//!
+//! Socket addresses are given in place of the controller's program:
+//! * `tcp://hostname:port`
+//! * `unix:///path/to/socket`
+//!
+//! Broken connections are not restored automatically, because the server loses
+//! the controller's state. Methods fail with a [ControllerDead](super::ControllerDead)
+//! error and the caller decides whether to call [Controller::reconnect()].
+
+use super::{_clean_path, Controller, ControllerOptions, Process};
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+/// Network address of a controller server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Address {
+    Tcp(String),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+/// Also returns the file descriptor for reading, on unix.
+type Streams = (Box<dyn Write + Send>, Box<dyn Read + Send>, Option<i32>);
+
+impl Address {
+    /// Returns None if the program is not a socket address.
+    pub(super) fn parse(program: &str) -> Option<Self> {
+        if let Some(address) = program.strip_prefix("tcp://") {
+            return Some(Self::Tcp(address.to_string()));
+        }
+        #[cfg(unix)]
+        if let Some(path) = program.strip_prefix("unix://") {
+            return Some(Self::Unix(path.into()));
+        }
+        None
+    }
+
+    fn open(&self) -> Result<Streams, io::Error> {
+        match self {
+            Self::Tcp(address) => {
+                let stream = TcpStream::connect(address)?;
+                stream.set_nodelay(true)?;
+                #[cfg(unix)]
+                let fd = Some(std::os::fd::AsRawFd::as_raw_fd(&stream));
+                #[cfg(not(unix))]
+                let fd = None;
+                Ok((Box::new(stream.try_clone()?), Box::new(stream), fd))
+            }
+            #[cfg(unix)]
+            Self::Unix(path) => {
+                let stream = UnixStream::connect(path)?;
+                let fd = Some(std::os::fd::AsRawFd::as_raw_fd(&stream));
+                Ok((Box::new(stream.try_clone()?), Box::new(stream), fd))
+            }
+        }
+    }
+}
+
+impl Controller {
+    /// Connect to a controller server.
+    ///
+    /// Argument environment is the file path of the current environment specification file.
+    ///
+    /// Argument population is a name and a key into the environment spec's "populations" table.
+    ///
+    /// Argument command is the controller's command line invocation, where the
+    /// program is a socket address. The remaining command line arguments are ignored.
+    pub fn new_socket(environment: impl AsRef<Path>, population: &str, command: &[String]) -> Result<Self, io::Error> {
+        Self::connect_socket(environment, population, command, &ControllerOptions::default())
+    }
+
+    pub(super) fn connect_socket(
+        environment: impl AsRef<Path>,
+        population: &str,
+        command: &[String],
+        options: &ControllerOptions,
+    ) -> Result<Self, io::Error> {
+        let env = _clean_path(environment)?;
+        let Some(address) = Address::parse(&command[0]) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid socket address {:?}", command[0]),
+            ));
+        };
+        let (stdin, stdout, stdout_fd) = address.open()?;
+        let mut ctrl = Self::connect(
+            env,
+            population,
+            command,
+            options,
+            Process::Socket(address),
+            stdin,
+            stdout,
+        )?;
+        ctrl.stdout_fd = stdout_fd;
+        ctrl.handshake()?;
+        Ok(ctrl)
+    }
+
+    /// Re-establish the connection to a controller server.
+    ///
+    /// The new connection is initialized with the most recent seed and genotype, and
+    /// so its internal state is reset. This does nothing for controllers which
+    /// are not connected over a socket.
+    ///
+    /// Outputs which were requested but not yet received are lost, and waiting
+    /// for them returns a [ControllerDead](super::ControllerDead) error.
+    /// Outputs which were already received can still be retrieved.
+    pub fn reconnect(&mut self) -> Result<(), io::Error> {
+        let Process::Socket(address) = &self.ctrl else {
+            return Ok(());
+        };
+        let (stdin, stdout, stdout_fd) = address.open()?;
+        // Keep the seed and genotype until the new connection succeeds, so that a
+        // failed attempt to reconnect can be retried.
+        let genotype = self.genotype.clone();
+        let seed = self.seed;
+        let mut ctrl = Self::connect(
+            self.env.clone(),
+            &self.pop,
+            &self.cmd,
+            &self.opts,
+            Process::Socket(address.clone()),
+            stdin,
+            stdout,
+        )?;
+        ctrl.stdout_fd = stdout_fd;
+        ctrl.handshake()?;
+        std::mem::swap(self, &mut ctrl);
+        // Close the old connection without telling the server to quit.
+        ctrl.has_quit = true;
+        // Keep the existing statistics and message counters.
+        self.next_id = ctrl.next_id;
+        self.profile = ctrl.profile.take();
+        // Keep the output handles, so that they are never reused.
+        self.next_handle = ctrl.next_handle;
+        self.received = std::mem::take(&mut ctrl.received);
+        self.lost = std::mem::take(&mut ctrl.lost);
+        self.lost.extend(ctrl.pending.drain(..).map(|pending| pending.handle));
+        if let Some(seed) = seed {
+            self.seed(seed)?;
+        }
+        if let Some(genotype) = genotype {
+            self.new_genotype(&genotype)?;
+        }
+        Ok(())
+    }
+}
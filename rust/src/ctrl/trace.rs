@@ -0,0 +1,326 @@
+//! Protocol traces, for debugging the communications between environments and controllers.
+//!
+//! Traces are NDJSON files with one record per message, in the order that they
+//! were sent or received. Each record has the following fields:
+//! * `time`: seconds since the unix epoch, so that the traces from both sides can be compared.
+//! * `from`: either `"env"` or `"ctrl"`.
+//! * `frame`: the message, without its trailing newline.
+//!
+//! Binary payloads are not recorded. Records are appended to existing trace files.
+//! Values in a binary encoding (see [ValueEncoding](super::ValueEncoding)) are
+//! not decoded, so traces are most useful with the default text encoding.
+
+#[cfg(feature = "ctrl")]
+use super::{codec::DecodeError, Capability, ErrorPolicy, MessageError, Registry, API};
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+#[cfg(feature = "ctrl")]
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(feature = "spawn")]
+use std::{
+    io::Read,
+    sync::{Arc, Mutex},
+};
+
+#[derive(Serialize)]
+struct Record<'a> {
+    time: f64,
+    from: &'a str,
+    frame: &'a str,
+}
+
+/// Trace file writer.
+#[derive(Debug)]
+struct Tracer {
+    file: BufWriter<File>,
+}
+
+impl Tracer {
+    fn open(path: &Path) -> Result<Self, io::Error> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: BufWriter::new(file),
+        })
+    }
+
+    fn record(&mut self, from: &str, frame: &str) {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let record = Record { time, from, frame };
+        // Tracing is a diagnostic aid, it should never interfere with the controller.
+        let _ = serde_json::to_writer(&mut self.file, &record);
+        let _ = writeln!(self.file);
+        let _ = self.file.flush();
+    }
+}
+
+/// Splits a stream of bytes into lines and records them.
+#[cfg(feature = "spawn")]
+#[derive(Debug)]
+struct LineRecorder {
+    tracer: Arc<Mutex<Tracer>>,
+    from: &'static str,
+    line: Vec<u8>,
+    /// Number of bytes of binary payload remaining, which are not recorded.
+    skip: usize,
+}
+
+#[cfg(feature = "spawn")]
+impl LineRecorder {
+    fn observe(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            if self.skip > 0 {
+                let num_bytes = self.skip.min(data.len());
+                self.skip -= num_bytes;
+                data = &data[num_bytes..];
+                continue;
+            }
+            let Some(end) = data.iter().position(|&byte| byte == b'\n') else {
+                self.line.extend_from_slice(data);
+                return;
+            };
+            self.line.extend_from_slice(&data[..end]);
+            data = &data[end + 1..];
+            let line = String::from_utf8_lossy(&self.line).into_owned();
+            self.line.clear();
+            self.tracer.lock().unwrap().record(self.from, &line);
+            // Binary messages are followed by their payload: "B[GIN]:[NUM_BYTES]".
+            if let Some((_, num_bytes)) = line.strip_prefix('B').and_then(|body| body.split_once(':')) {
+                self.skip = num_bytes.parse().unwrap_or(0);
+            }
+        }
+    }
+}
+
+/// Records the messages sent to a controller.
+#[cfg(feature = "spawn")]
+struct TraceWriter {
+    inner: Box<dyn Write + Send>,
+    recorder: LineRecorder,
+}
+
+#[cfg(feature = "spawn")]
+impl Write for TraceWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let num_bytes = self.inner.write(buf)?;
+        self.recorder.observe(&buf[..num_bytes]);
+        Ok(num_bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Records the messages received from a controller.
+#[cfg(feature = "spawn")]
+struct TraceReader {
+    inner: Box<dyn Read + Send>,
+    recorder: LineRecorder,
+}
+
+#[cfg(feature = "spawn")]
+impl Read for TraceReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let num_bytes = self.inner.read(buf)?;
+        self.recorder.observe(&buf[..num_bytes]);
+        Ok(num_bytes)
+    }
+}
+
+#[cfg(feature = "spawn")]
+type Streams = (Box<dyn Write + Send>, Box<dyn Read + Send>);
+
+/// Record all of the messages passing through a controller's stdin and stdout.
+#[cfg(feature = "spawn")]
+pub(super) fn wrap(
+    path: &Path,
+    stdin: Box<dyn Write + Send>,
+    stdout: Box<dyn Read + Send>,
+) -> Result<Streams, io::Error> {
+    let tracer = Arc::new(Mutex::new(Tracer::open(path)?));
+    let recorder = |from| LineRecorder {
+        tracer: Arc::clone(&tracer),
+        from,
+        line: Vec::new(),
+        skip: 0,
+    };
+    let stdin = TraceWriter {
+        inner: stdin,
+        recorder: recorder("env"),
+    };
+    let stdout = TraceReader {
+        inner: stdout,
+        recorder: recorder("ctrl"),
+    };
+    Ok((Box::new(stdin), Box::new(stdout)))
+}
+
+/// Record every message that a controller implementation handles.
+///
+/// This is the controller side counterpart to [ControllerOptions::trace](super::ControllerOptions::trace).
+/// Messages which the controller writes directly to stdout, such as
+/// epigenome updates, are not recorded.
+///
+/// Example: `main_loop(Traced::new(MyController::default(), "ctrl_trace.ndjson")?)`
+#[cfg(feature = "ctrl")]
+pub struct Traced<C> {
+    inner: C,
+    tracer: Tracer,
+}
+
+#[cfg(feature = "ctrl")]
+impl<C> Traced<C> {
+    pub fn new(controller: C, path: impl Into<PathBuf>) -> Result<Self, io::Error> {
+        Ok(Self {
+            inner: controller,
+            tracer: Tracer::open(&path.into())?,
+        })
+    }
+
+    pub fn get_ref(&self) -> &C {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+#[cfg(feature = "ctrl")]
+impl<C: API + 'static> API for Traced<C> {
+    fn new(&mut self, genotype: String) {
+        self.tracer.record("env", &format!("N{genotype}"));
+        self.inner.new(genotype)
+    }
+
+    fn swap(&mut self, genotype: String) {
+        self.tracer.record("env", &format!("K{genotype}"));
+        self.inner.swap(genotype)
+    }
+
+    fn validate(&mut self, genotype: &str) -> Result<(), String> {
+        self.tracer.record("env", &format!("V{genotype}"));
+        let result = self.inner.validate(genotype);
+        self.tracer.record(
+            "ctrl",
+            &format!("V:{}", result.as_ref().err().map_or("", String::as_str)),
+        );
+        result
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.tracer.record("env", &format!("G{seed}"));
+        self.inner.seed(seed)
+    }
+
+    fn select(&mut self, agent: u64) {
+        self.tracer.record("env", &format!("U{agent}"));
+        self.inner.select(agent)
+    }
+
+    fn reset(&mut self) {
+        self.tracer.record("env", "R");
+        self.inner.reset()
+    }
+
+    fn advance(&mut self, dt: f64) {
+        self.tracer.record("env", &format!("X{dt}"));
+        self.inner.advance(dt)
+    }
+
+    fn set_input(&mut self, gin: u64, value: String) {
+        self.tracer.record("env", &format!("I{gin}:{value}"));
+        self.inner.set_input(gin, value)
+    }
+
+    fn set_binary(&mut self, gin: u64, bytes: Vec<u8>) {
+        self.tracer.record("env", &format!("B{gin}:{}", bytes.len()));
+        self.inner.set_binary(gin, bytes)
+    }
+
+    fn get_output(&mut self, gin: u64) -> String {
+        self.tracer.record("env", &format!("O{gin}"));
+        let output = self.inner.get_output(gin);
+        self.tracer.record("ctrl", &format!("{gin}:{output}"));
+        output
+    }
+
+    fn set_input_f64(&mut self, gin: u64, value: f64) {
+        self.tracer.record("env", &format!("I{gin}:{value}"));
+        self.inner.set_input_f64(gin, value)
+    }
+
+    fn get_output_f64(&mut self, gin: u64) -> f64 {
+        self.tracer.record("env", &format!("O{gin}"));
+        let output = self.inner.get_output_f64(gin);
+        self.tracer.record("ctrl", &format!("{gin}:{output}"));
+        output
+    }
+
+    fn save(&mut self, path: PathBuf) {
+        self.tracer.record("env", &format!("S{}", path.display()));
+        self.inner.save(path)
+    }
+
+    fn load(&mut self, path: PathBuf) {
+        self.tracer.record("env", &format!("L{}", path.display()));
+        self.inner.load(path)
+    }
+
+    fn message(&mut self, message_type: char, body: String) -> Result<String, DecodeError> {
+        self.tracer.record("env", &format!("{message_type}:{body}"));
+        let response = self.inner.message(message_type, body)?;
+        self.tracer.record("ctrl", &format!("{message_type}:{response}"));
+        Ok(response)
+    }
+
+    /// Custom messages are recorded without their ID.
+    fn custom_messages() -> Registry<Self> {
+        let mut registry = Registry::new();
+        for (message_type, mut handler) in C::custom_messages().handlers {
+            registry.handlers.insert(
+                message_type,
+                Box::new(move |traced: &mut Self, body: &str| {
+                    traced.tracer.record("env", &format!("{message_type}:{body}"));
+                    let response = handler(&mut traced.inner, body)?;
+                    traced.tracer.record("ctrl", &format!("{message_type}:{response}"));
+                    Ok(response)
+                }) as super::Handler<Self>,
+            );
+        }
+        registry
+    }
+
+    fn malformed_message(&mut self, error: &MessageError) -> ErrorPolicy {
+        self.tracer.record("env", &error.line);
+        self.inner.malformed_message(error)
+    }
+
+    fn capabilities(&mut self) -> Vec<Capability> {
+        self.inner.capabilities()
+    }
+
+    fn panicked(&mut self, message: &str) -> ErrorPolicy {
+        self.tracer.record("ctrl", &format!("E:{message}"));
+        self.inner.panicked(message)
+    }
+
+    fn idle(&mut self, dt: f64) {
+        self.inner.idle(dt)
+    }
+
+    fn quit(&mut self) {
+        self.tracer.record("env", "Q");
+        self.inner.quit()
+    }
+}
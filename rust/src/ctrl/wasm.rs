@@ -0,0 +1,255 @@
+//! WebAssembly controllers, which execute inside of this process.
+//!
+//! Controller programs compiled to WebAssembly (targeting WASI preview 1) run
+//! in a sandbox and communicate using the same message protocol as any other
+//! controller. The sandbox has no access to the filesystem or the network, and
+//! it only sees the environment variables given in its [ControllerOptions].
+//! It inherits stderr for diagnostic messages.
+//!
+//! The sandbox can not be killed like a separate process, so a program which
+//! does not respond within the [ControllerOptions::timeout] is interrupted.
+//! This is only supported on unix.
+
+use super::{_clean_path, Controller, ControllerOptions, ControllerStatus, Process};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+use wasi_common::sync::WasiCtxBuilder;
+use wasi_common::{I32Exit, WasiCtx};
+use wasmtime::{Config, Engine, Linker, Module, Store, UpdateDeadline};
+
+/// How often running programs check whether they have been interrupted.
+const EPOCH_INTERVAL: Duration = Duration::from_millis(10);
+
+/// All controllers share one compilation engine.
+fn engine() -> &'static Engine {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    ENGINE.get_or_init(|| {
+        let mut config = Config::new();
+        config.epoch_interruption(true);
+        let engine = Engine::new(&config).expect("failed to configure the WebAssembly engine");
+        let ticker = engine.clone();
+        std::thread::Builder::new()
+            .name("wasm epoch".to_string())
+            .spawn(move || loop {
+                std::thread::sleep(EPOCH_INTERVAL);
+                ticker.increment_epoch();
+            })
+            .expect("failed to start the WebAssembly epoch thread");
+        engine
+    })
+}
+
+/// Compiled modules are cached so that new controllers start quickly.
+/// Files which are modified are compiled again.
+fn load_module(path: &Path) -> Result<Module, io::Error> {
+    static MODULES: Mutex<Option<HashMap<PathBuf, (SystemTime, Module)>>> = Mutex::new(None);
+    let modified = std::fs::metadata(path)?.modified()?;
+    let mut modules = MODULES.lock().unwrap();
+    let modules = modules.get_or_insert_with(HashMap::new);
+    if let Some((compiled, module)) = modules.get(path) {
+        if *compiled == modified {
+            return Ok(module.clone());
+        }
+    }
+    let module = Module::from_file(engine(), path).map_err(|err| io::Error::other(format!("{err}: {path:?}")))?;
+    modules.insert(path.to_path_buf(), (modified, module.clone()));
+    Ok(module)
+}
+
+/// Make an operating system pipe, returns the pair of (reader, writer).
+fn pipe() -> Result<(File, File), io::Error> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    unsafe { Ok((File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1]))) }
+}
+
+fn wasi_file(file: File) -> Box<wasi_common::sync::file::File> {
+    Box::new(wasi_common::sync::file::File::from_cap_std(
+        cap_std::fs::File::from_std(file),
+    ))
+}
+
+/// Run the program until it exits, returns its exit code.
+fn run(module: Module, wasi: WasiCtx, interrupt: Arc<AtomicBool>) -> Result<i32, wasmtime::Error> {
+    let mut linker = Linker::new(engine());
+    wasi_common::sync::add_to_linker(&mut linker, |wasi: &mut WasiCtx| wasi)?;
+    let mut store = Store::new(engine(), wasi);
+    store.set_epoch_deadline(1);
+    store.epoch_deadline_callback(move |_| {
+        if interrupt.load(Ordering::Relaxed) {
+            Err(wasmtime::Error::msg("interrupted after the controller timed out"))
+        } else {
+            Ok(UpdateDeadline::Continue(1))
+        }
+    });
+    let instance = linker.instantiate(&mut store, &module)?;
+    let start = instance.get_typed_func::<(), ()>(&mut store, "_start")?;
+    match start.call(&mut store, ()) {
+        Ok(()) => Ok(0),
+        Err(err) => match err.downcast_ref::<I32Exit>() {
+            Some(exit) => Ok(exit.0),
+            None => Err(err),
+        },
+    }
+}
+
+/// Thread which is running a WebAssembly controller.
+#[derive(Debug)]
+pub(super) struct Guest {
+    thread: JoinHandle<()>,
+    /// Exit code or error message, once the program has stopped.
+    exit: Arc<OnceLock<Result<i32, String>>>,
+    /// Stops the program the next time that it checks the epoch.
+    interrupt: Arc<AtomicBool>,
+}
+
+impl Guest {
+    /// Fill in the status of the program.
+    pub(super) fn status(&self, status: &mut ControllerStatus) {
+        status.alive = !self.thread.is_finished();
+        match self.exit.get() {
+            Some(Ok(code)) => status.exit_code = Some(*code),
+            Some(Err(error)) => status.stderr.push(error.clone()),
+            None => {}
+        }
+    }
+
+    /// Did the program fail, either with an error or a non-zero exit code?
+    pub(super) fn failed(&self) -> bool {
+        self.exit.get().is_some_and(|exit| exit != &Ok(0))
+    }
+
+    /// Stop the program, since it can not be killed.
+    pub(super) fn interrupt(&self) {
+        self.interrupt.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Controller {
+    /// Load a controller program which was compiled to WebAssembly,
+    /// and run it in a sandbox inside of this process.
+    ///
+    /// Argument environment is the file path of the current environment specification file.
+    ///
+    /// Argument population is a name and a key into the environment spec's "populations" table.
+    ///
+    /// Argument command is the command line invocation for the controller program.  
+    /// The first string in the list is the ".wasm" file, the remaining strings are its command line arguments.  
+    pub fn new_wasm(environment: impl AsRef<Path>, population: &str, command: &[String]) -> Result<Self, io::Error> {
+        Self::spawn_wasm(environment, population, command, &ControllerOptions::default())
+    }
+
+    /// The sandbox has no filesystem access, so the working directory option is ignored.
+    pub(super) fn spawn_wasm(
+        environment: impl AsRef<Path>,
+        population: &str,
+        command: &[String],
+        options: &ControllerOptions,
+    ) -> Result<Self, io::Error> {
+        let env = _clean_path(environment)?;
+        let prog = _clean_path(&command[0])?;
+        let module = load_module(&prog)?;
+
+        let env_vars: Vec<(String, String)> = options.env_vars.clone().into_iter().collect();
+        let (ctrl_stdin, stdin) = pipe()?;
+        let (stdout, ctrl_stdout) = pipe()?;
+        let wasi = WasiCtxBuilder::new()
+            .args(command)
+            .map_err(io::Error::other)?
+            .envs(&env_vars)
+            .map_err(io::Error::other)?
+            .stdin(wasi_file(ctrl_stdin))
+            .stdout(wasi_file(ctrl_stdout))
+            .inherit_stderr()
+            .build();
+
+        let exit = Arc::new(OnceLock::new());
+        let interrupt = Arc::new(AtomicBool::new(false));
+        let name = prog.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let thread = std::thread::Builder::new().name(name).spawn({
+            let exit = Arc::clone(&exit);
+            let interrupt = Arc::clone(&interrupt);
+            move || {
+                let result = run(module, wasi, interrupt).map_err(|err| format!("WASM controller error: {err:#}"));
+                let _ = exit.set(result);
+            }
+        })?;
+        let guest = Guest {
+            thread,
+            exit,
+            interrupt,
+        };
+
+        let stdout_fd = stdout.as_raw_fd();
+        let mut ctrl = Self::connect(
+            env,
+            population,
+            command,
+            options,
+            Process::Wasm(guest),
+            Box::new(stdin),
+            Box::new(stdout),
+        )?;
+        ctrl.stdout_fd = Some(stdout_fd);
+        ctrl.handshake()?;
+        Ok(ctrl)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ctrl::{ControllerDead, ControllerTimeout};
+
+    fn write_module(name: &str, wat: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("npc_maker_wasm_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, wat).unwrap();
+        path
+    }
+
+    #[test]
+    fn modified_modules() {
+        let path = write_module("modified.wasm", r#"(module (func (export "a")))"#);
+        let exports = |module: Module| {
+            module
+                .exports()
+                .map(|export| export.name().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(exports(load_module(&path).unwrap()), ["a"]);
+        std::fs::write(&path, r#"(module (func (export "b")))"#).unwrap();
+        let file = File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(1)).unwrap();
+        assert_eq!(exports(load_module(&path).unwrap()), ["b"]);
+    }
+
+    #[test]
+    fn interrupt_after_timeout() {
+        let path = write_module("spin.wasm", r#"(module (func (export "_start") (loop (br 0))))"#);
+        let env = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+        let command = [path.to_string_lossy().into_owned()];
+        let options = ControllerOptions::default().timeout(0.2);
+        let mut ctrl = Controller::spawn_wasm(env, "pop", &command, &options).unwrap();
+        let error = ctrl.ping().unwrap_err();
+        assert!(ControllerTimeout::from_io(&error).is_some());
+        let start = std::time::Instant::now();
+        while ctrl.status().alive {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let error = ctrl.reset().unwrap_err();
+        let status = &ControllerDead::from_io(&error).unwrap().status;
+        assert!(status.stderr[0].contains("interrupted"));
+    }
+}
@@ -4,15 +4,26 @@
 //! communicate with the evolutionary algorithm and the main NPC Maker program.
 //! Environments should use stderr to report any unformatted or diagnostic messages
 //! (see [eprintln!()]).
+//!
+//! By default every message is flushed to stdout as soon as it is sent. Use
+//! [set_flush_policy()] to send messages in batches instead, which reduces the
+//! number of system calls for environments that send many messages. Buffered
+//! messages are flushed whenever [poll()] finds no new requests, and by calling
+//! [flush()], which environments should do before exiting.
 
-use crate::env_spec::EnvironmentSpec;
+use crate::ctrl::{
+    read_line_limited, Controller, ControllerOutOfMemory, FlushPolicy, SavedController, DEFAULT_BINARY_LIMIT,
+};
+use crate::env_spec::{EnvironmentSpec, SettingsSpec};
 use crate::messages::{Request, Response};
-use crate::serde_utils::JsonIoError;
+use crate::Error;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::io::{self, BufRead, Write};
+use std::io::{self, Write};
 use std::os::fd::AsRawFd;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
 
 /// Display mode for environments.
 #[derive(Debug, Default, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
@@ -32,31 +43,90 @@ pub enum Mode {
     Headless,
 }
 
+/// Manifest of the controllers which were saved alongside an environment.
+///
+/// Environments which receive a `Save` request should save their controllers
+/// with this, and restore them upon receiving the matching `Load` request.
+/// The controllers are stored in a directory next to the environment's own
+/// save file, named after the save file with the extension ".controllers".
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SaveBundle {
+    /// Maps user assigned keys to saved controllers.  
+    /// The controller save file paths are relative to the bundle directory.  
+    pub controllers: HashMap<String, SavedController>,
+}
+
+impl SaveBundle {
+    /// Get the directory containing the controllers for the given environment save file.
+    pub fn directory(save_path: impl AsRef<Path>) -> PathBuf {
+        let mut directory = save_path.as_ref().as_os_str().to_os_string();
+        directory.push(".controllers");
+        directory.into()
+    }
+
+    /// Save a collection of controllers, identified by user assigned keys.
+    ///
+    /// Argument save_path is the path given in the `Save` request.
+    pub fn save<'a>(
+        save_path: impl AsRef<Path>,
+        controllers: impl IntoIterator<Item = (String, &'a mut Controller)>,
+    ) -> Result<Self, Error> {
+        let directory = Self::directory(save_path);
+        std::fs::create_dir_all(&directory)?;
+        let mut bundle = Self::default();
+        for (index, (key, controller)) in controllers.into_iter().enumerate() {
+            let file_name = PathBuf::from(format!("{index}"));
+            let mut saved = controller.checkpoint(directory.join(&file_name))?;
+            saved.path = file_name;
+            bundle.controllers.insert(key, saved);
+        }
+        let manifest = std::fs::File::create(directory.join("manifest.json"))?;
+        serde_json::to_writer(io::BufWriter::new(manifest), &bundle)?;
+        Ok(bundle)
+    }
+
+    /// Restart all of the controllers that were saved with the given environment save file.
+    ///
+    /// Argument save_path is the path given in the `Load` request.
+    pub fn load(save_path: impl AsRef<Path>) -> Result<HashMap<String, Controller>, Error> {
+        let directory = Self::directory(save_path);
+        let manifest = std::fs::read_to_string(directory.join("manifest.json"))?;
+        let bundle: Self = serde_json::from_str(&manifest)?;
+        let mut controllers = HashMap::with_capacity(bundle.controllers.len());
+        for (key, mut saved) in bundle.controllers {
+            saved.path = directory.join(&saved.path);
+            controllers.insert(key, Controller::restore(&saved)?);
+        }
+        Ok(controllers)
+    }
+}
+
 /// Read the command line arguments for an environment program.
 ///
 /// Environment implementations *must* call this function for initialization purposes.
 ///
 /// Returns a tuple of (environment-specification, graphics-mode, settings-dict)
-pub fn get_args() -> (EnvironmentSpec, Mode, HashMap<String, String>) {
-    init();
+pub fn get_args() -> Result<(EnvironmentSpec, Mode, HashMap<String, String>), Error> {
+    init()?;
+    parse_args(std::env::args())
+}
+
+fn parse_args(
+    mut arg_iter: impl Iterator<Item = String>,
+) -> Result<(EnvironmentSpec, Mode, HashMap<String, String>), Error> {
     // Read the command line arguments.
-    let mut arg_iter = std::env::args();
     let _program = arg_iter.next();
     let spec_file = arg_iter.next();
     let mode = arg_iter.next();
     let mut settings: Vec<String> = arg_iter.collect();
     // Read the environment specification file.
     let Some(spec_file) = spec_file else {
-        panic!("Argument Error: missing environment specification")
+        return Err(Error::Argument("missing environment specification".to_string()));
     };
     let spec_file = Path::new(&spec_file)
         .canonicalize()
-        .unwrap_or_else(|err| panic!("File Error: {err}: {spec_file:?}"));
-    let spec_data =
-        std::fs::read_to_string(&spec_file).unwrap_or_else(|err| panic!("File Error: {err}: {spec_file:?}"));
-    let mut env_spec: EnvironmentSpec =
-        serde_json::from_str(&spec_data).unwrap_or_else(|err| panic!("JSON Decode Error: {err}: {spec_file:?}"));
-    env_spec.spec = spec_file;
+        .map_err(|err| Error::spec(&spec_file, err))?;
+    let env_spec = EnvironmentSpec::new(spec_file)?;
     // Read the graphics mode.
     let mode = if let Some(mode) = mode {
         let mode = mode.trim().to_ascii_lowercase();
@@ -65,7 +135,9 @@ pub fn get_args() -> (EnvironmentSpec, Mode, HashMap<String, String>) {
         } else if mode == "headless" {
             Mode::Headless
         } else {
-            panic!("Argument Error: expected either \"graphical\" or \"headless\", got \"{mode}\"");
+            return Err(Error::Argument(format!(
+                "expected either \"graphical\" or \"headless\", got \"{mode}\""
+            )));
         }
     } else {
         Mode::default()
@@ -80,22 +152,31 @@ pub fn get_args() -> (EnvironmentSpec, Mode, HashMap<String, String>) {
     for chunk in &mut settings {
         let item = std::mem::take(&mut chunk[0]);
         let value = std::mem::take(&mut chunk[1]);
-        if !defaults.contains_key(&item) {
-            panic!("Argument Error: unexpected parameter \"{item}\"")
-        }
+        let Some(item_spec) = env_spec.settings.iter().find(|item_spec| item_spec.name() == item) else {
+            return Err(Error::Argument(format!("unexpected parameter \"{item}\"")));
+        };
+        item_spec.validate_value(&value).map_err(Error::Argument)?;
+        // Lists are always given to the environment as JSON arrays.
+        let value = match item_spec {
+            SettingsSpec::List { .. } => {
+                serde_json::to_string(&SettingsSpec::parse_list(&value).map_err(Error::Argument)?)?
+            }
+            _ => value,
+        };
         defaults.insert(item, value);
     }
     if !settings.into_remainder().is_empty() {
-        panic!("Argument Error: odd number of settings, expected key-value pairs");
+        return Err(Error::Argument(
+            "odd number of settings, expected key-value pairs".to_string(),
+        ));
     }
-    //
-    return (env_spec, mode, defaults);
+    Ok((env_spec, mode, defaults))
 }
 
-fn init() {
+fn init() -> Result<(), io::Error> {
     #[cfg(target_family = "unix")]
     {
-        change_blocking_fd(io::stdin().as_raw_fd(), false);
+        crate::ctrl::change_blocking_fd(io::stdin().as_raw_fd(), false)
     }
     #[cfg(target_family = "windows")]
     {
@@ -103,50 +184,34 @@ fn init() {
     }
 }
 
-#[cfg(target_family = "unix")]
-fn change_blocking_fd(fd: std::os::unix::io::RawFd, blocking: bool) {
-    unsafe {
-        let flags = libc::fcntl(fd, libc::F_GETFL);
-        if flags < 0 {
-            panic!("libc file control error");
-        }
-        let error = libc::fcntl(
-            fd,
-            libc::F_SETFL,
-            if blocking {
-                flags & !libc::O_NONBLOCK
-            } else {
-                flags | libc::O_NONBLOCK
-            },
-        );
-        if error < 0 {
-            panic!("libc file control error");
-        }
-    }
-}
-
 /// Check for messages from the main NPC Maker program.
 ///
 /// Callers *must* call the `get_args()` function before this, for initialization purposes.
 ///
 /// This function is non-blocking and returns `None` if there are no new
 /// messages. This decodes the JSON messages and returns `Request` objects.
-pub fn poll() -> Result<Option<Request>, JsonIoError> {
+pub fn poll() -> Result<Option<Request>, Error> {
     // Read a line from stdin, non blocking.
     let mut line = String::new();
-    if let Err(error) = io::stdin().lock().read_line(&mut line) {
+    if let Err(error) = read_line_limited(&mut io::stdin().lock(), &mut line, DEFAULT_BINARY_LIMIT) {
         if error.kind() == io::ErrorKind::WouldBlock {
-            io::stdout().flush()?;
+            flush()?;
             return Ok(None);
         } else {
             return Err(error.into());
         }
     }
+    parse_request(&line)
+}
+
+/// Decode one line of the JSON messages which [poll()] receives.
+///
+/// Returns `None` for blank lines.
+pub fn parse_request(line: &str) -> Result<Option<Request>, Error> {
     let line = line.trim();
     if line.is_empty() {
         return Ok(None);
     }
-    // Parse the message.
     match serde_json::from_str(line) {
         Err(error) => {
             if false {
@@ -155,23 +220,92 @@ pub fn poll() -> Result<Option<Request>, JsonIoError> {
                 Ok(None)
             } else {
                 // Propagate errors to the caller.
-                Err(error.into())
+                let message = format!("malformed request {line:?}: {error}");
+                Err(Error::Protocol(io::Error::new(io::ErrorKind::InvalidData, message)))
             }
         }
-        Ok(message) => Ok(Some(message)),
+        Ok(message) => {
+            #[cfg(feature = "tracing")]
+            trace_request(&message);
+            Ok(Some(message))
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+fn trace_request(message: &Request) {
+    match message {
+        Request::Birth {
+            population, individual, ..
+        } => tracing::debug!(%population, individual, "birth"),
+        message => tracing::debug!(?message, "request"),
     }
 }
 
-fn write_msg(message: &Response) -> Result<(), JsonIoError> {
-    let mut stdout = io::stdout().lock();
-    serde_json::to_writer(&mut stdout, message)?;
-    write!(stdout, "\n")?;
+/// Messages which have been sent but not yet flushed to stdout.
+struct Outbox {
+    policy: FlushPolicy,
+    buffer: Vec<u8>,
+    messages: usize,
+    oldest: Option<Instant>,
+}
+
+static OUTBOX: Mutex<Outbox> = Mutex::new(Outbox {
+    policy: FlushPolicy::IMMEDIATE,
+    buffer: Vec::new(),
+    messages: 0,
+    oldest: None,
+});
+
+impl Outbox {
+    fn flush(&mut self) -> Result<(), io::Error> {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(&self.buffer)?;
+        stdout.flush()?;
+        self.buffer.clear();
+        self.messages = 0;
+        self.oldest = None;
+        Ok(())
+    }
+}
+
+fn outbox() -> std::sync::MutexGuard<'static, Outbox> {
+    OUTBOX.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Decide when to flush the messages which this environment sends to stdout.
+///
+/// The default policy is [FlushPolicy::IMMEDIATE]. Any buffered messages are
+/// checked against the new policy right away.
+pub fn set_flush_policy(policy: FlushPolicy) -> Result<(), Error> {
+    let mut outbox = outbox();
+    outbox.policy = policy;
+    if policy.is_due(outbox.messages, outbox.oldest) {
+        outbox.flush()?;
+    }
+    Ok(())
+}
+
+/// Send all of the buffered messages now, instead of waiting for the flush policy.
+pub fn flush() -> Result<(), Error> {
+    Ok(outbox().flush()?)
+}
+
+fn write_msg(message: &Response) -> Result<(), Error> {
+    let mut outbox = outbox();
+    serde_json::to_writer(&mut outbox.buffer, message)?;
+    outbox.buffer.push(b'\n');
+    outbox.messages += 1;
+    outbox.oldest.get_or_insert_with(Instant::now);
+    if outbox.policy.is_due(outbox.messages, outbox.oldest) {
+        outbox.flush()?;
+    }
     Ok(())
 }
 
 /// Acknowledge that the given message has been received and successfully acted upon.
 /// The message should have originated from the `poll()` function.
-pub fn ack(message: &Request) -> Result<(), JsonIoError> {
+pub fn ack(message: &Request) -> Result<(), Error> {
     // Birth messages don't need to be acknowledged.
     if let Request::Birth { .. } = message {
         return Ok(());
@@ -182,65 +316,98 @@ pub fn ack(message: &Request) -> Result<(), JsonIoError> {
 /// Request a new individual from the evolutionary algorithm.
 ///
 /// Argument population is optional if the environment contains exactly one population.
-pub fn request_new(population: Option<&str>) -> Result<(), JsonIoError> {
+pub fn request_new(population: Option<&str>) -> Result<(), Error> {
     write_msg(&Response::New {
-        population: population.map(|pop| pop.to_string()),
+        population: population.unwrap_or_default().to_string(),
     })
 }
 
 /// Request to mate two specific individuals together to produce a child individual.
 ///
-/// Argument population is optional if the environment contains exactly one population.
-pub fn request_mate(population: Option<&str>, parent1: u64, parent2: u64) -> Result<(), JsonIoError> {
+/// Both parents must still be alive, and the child joins their population.
+pub fn request_mate(parent1: u64, parent2: u64) -> Result<(), Error> {
     write_msg(&Response::Mate {
-        population: population.map(|pop| pop.to_string()),
-        parent1,
-        parent2,
+        parents: vec![parent1, parent2],
     })
 }
 
 /// Report an individual's score or reproductive fitness to the evolutionary algorithm.
 ///
 /// This should be called *before* calling "report_death" on the individual.
-///
-/// Argument population is optional if the environment contains exactly one population.
-/// Argument individual is optional if the environment contains exactly one individual.
-pub fn report_score(population: Option<&str>, individual: Option<u64>, score: f64) -> Result<(), JsonIoError> {
-    write_msg(&Response::Score {
-        population: population.map(|pop| pop.to_string()),
-        individual,
-        score,
-    })
+pub fn report_score(individual: u64, score: f64) -> Result<(), Error> {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(individual, score, "score");
+    write_msg(&Response::Score { score, individual })
 }
 
 /// Report arbitrary extraneous information about an individual to the NPC Maker program.
 ///
 /// Argument info is a mapping of string key-value pairs.
+pub fn report_info(individual: u64, info: HashMap<String, String>) -> Result<(), Error> {
+    write_msg(&Response::Info { info, individual })
+}
+
+/// Record on an individual that its controller ran out of memory, see [ControllerOutOfMemory].
 ///
-/// Argument population is optional if the environment contains exactly one population.
-/// Argument individual is optional if the environment contains exactly one individual.
-pub fn report_info(
-    population: Option<&str>,
-    individual: Option<u64>,
-    info: HashMap<String, String>,
-) -> Result<(), JsonIoError> {
-    write_msg(&Response::Info {
-        population: population.map(|pop| pop.to_string()),
-        individual,
-        info,
-    })
+/// This reports the info key "out_of_memory" with the memory limit in bytes.
+/// Populations which declare their telemetry should declare it as an integer.
+pub fn report_out_of_memory(individual: u64, error: &ControllerOutOfMemory) -> Result<(), Error> {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(individual, limit = error.limit, "out of memory");
+    let info = HashMap::from([("out_of_memory".to_string(), error.limit.to_string())]);
+    report_info(individual, info)
 }
 
-// Notify the evolutionary algorithm that the given individual has died.
-//
-// If the individual had a score or reproductive fitness then it should be
-// reported using the "report_score()" function *before* calling this method.
+/// Notify the evolutionary algorithm that the given individual has died.
 ///
-/// Argument population is optional if the environment contains exactly one population.
-/// Argument individual is optional if the environment contains exactly one individual.
-pub fn report_death(population: Option<&str>, individual: Option<u64>) -> Result<(), JsonIoError> {
-    write_msg(&Response::Death {
-        population: population.map(|pop| pop.to_string()),
-        individual,
-    })
+/// If the individual had a score or reproductive fitness then it should be
+/// reported using the "report_score()" function *before* calling this method.
+pub fn report_death(individual: u64) -> Result<(), Error> {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(individual, "death");
+    write_msg(&Response::Death { individual })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn argument_errors() {
+        let args = |args: &[&str]| parse_args(args.iter().map(|arg| arg.to_string()));
+        assert!(matches!(args(&["env"]), Err(Error::Argument(_))));
+        assert!(matches!(args(&["env", "missing.env"]), Err(Error::Spec { .. })));
+        let dir = std::env::temp_dir().join(format!("npc_maker_get_args_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let spec = r#"{
+            "name": "arena",
+            "path": "arena.exe",
+            "settings": [
+                {"name": "size", "type": "Integer", "minimum": 1, "maximum": 100, "default": 10},
+                {"name": "tags", "type": "List", "default": []}
+            ]
+        }"#;
+        std::fs::write(dir.join("arena.env"), spec).unwrap();
+        std::fs::write(dir.join("broken.env"), "{").unwrap();
+        let spec_file = dir.join("arena.env").to_string_lossy().into_owned();
+        let broken_file = dir.join("broken.env").to_string_lossy().into_owned();
+        let valid = args(&["env", &spec_file, "headless", "size", "20", "tags", "red, blue"]);
+        let bad_mode = args(&["env", &spec_file, "fullscreen"]);
+        let bad_value = args(&["env", &spec_file, "headless", "size", "200"]);
+        let odd = args(&["env", &spec_file, "headless", "size"]);
+        let bad_list = args(&["env", &spec_file, "headless", "tags", "[\"red\", 3"]);
+        let broken = args(&["env", &broken_file]);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let (env_spec, mode, settings) = valid.unwrap();
+        assert_eq!(env_spec.name, "arena");
+        assert_eq!(mode, Mode::Headless);
+        assert_eq!(settings["size"], "20");
+        assert_eq!(settings["tags"], r#"["red","blue"]"#);
+        assert!(matches!(bad_mode, Err(Error::Argument(_))));
+        assert!(matches!(bad_value, Err(Error::Argument(_))));
+        assert!(matches!(odd, Err(Error::Argument(_))));
+        assert!(matches!(bad_list, Err(Error::Argument(_))));
+        assert!(matches!(broken, Err(Error::Spec { .. })));
+    }
 }
@@ -1,8 +1,10 @@
 //! Structure of environment specification files.
 
-use crate::serde_utils::{deserialize_positive, multiline_string, required_string, JsonIoError};
+use crate::serde_utils::{deserialize_positive, f64_symbols, multiline_string, required_string};
+use crate::Error;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
@@ -30,8 +32,8 @@ pub struct EnvironmentSpec {
     pub settings: Vec<SettingsSpec>,
 
     /// User facing documentation message.
-    #[serde(default, deserialize_with = "multiline_string")]
-    pub description: String,
+    #[serde(default)]
+    pub description: Description,
 
     /// Request environmental control over the mating process.
     #[serde(default)]
@@ -52,40 +54,262 @@ pub struct EnvironmentSpec {
 
 impl EnvironmentSpec {
     /// Load an environment specification from a JSON file.
-    pub fn new(path: impl AsRef<Path>) -> Result<Self, JsonIoError> {
+    ///
+    /// Specifications may extend a base specification with the `"extends"` attribute.
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, Error> {
         let path = path.as_ref(); // Convert into a proper &Path.
-        let spec = std::fs::read_to_string(path)?;
-        // .unwrap_or_else(|err| panic!("error reading file {path:?} {err}"));
-        let mut this: EnvironmentSpec = serde_json::from_str(&spec)?;
-        // .unwrap_or_else(|err| panic!("error parsing JSON file {path:?} {err}",));
+        let spec = read_spec(path, &mut Vec::new())?;
+        let mut this: EnvironmentSpec = serde_json::from_value(spec).map_err(|err| Error::spec(path, err))?;
         this.spec = path.into();
         Ok(this)
     }
 
-    /// Sanity checks on the environment specification file, panics on failure.
-    pub fn validate(&self) -> Result<(), String> {
+    /// Sanity checks on the environment specification file.
+    pub fn validate(&self) -> Result<(), Error> {
         let Self { spec, path, .. } = self;
+        let invalid = |message: String| Error::spec(spec, message);
         if spec == &PathBuf::default() {
-            return Err("environment specification was not loaded from file".to_string());
+            return Err(invalid(
+                "environment specification was not loaded from file".to_string(),
+            ));
         }
         // Check that the environment program exists.
         if !path.exists() {
-            return Err(format!("file not found {path:?}"));
+            return Err(invalid(format!("file not found {path:?}")));
         }
         if !path.is_file() {
-            return Err(format!("not a file {path:?}"));
+            return Err(invalid(format!("not a file {path:?}")));
+        }
+        // Check that the default settings are acceptable.
+        // File paths are not checked because they are relative to the environment's working directory.
+        for item in &self.settings {
+            if !matches!(item, SettingsSpec::FilePath { .. }) {
+                item.validate_value(&item.default())
+                    .map_err(|err| invalid(format!("invalid default, {err}")))?;
+            }
         }
         // Check that the interface GIN's are unique.
         for pop_spec in &self.populations {
             let unique_gins: HashSet<u64> = pop_spec.interfaces.iter().map(|interface| interface.gin).collect();
             if unique_gins.len() < pop_spec.interfaces.len() {
-                return Err(format!("interface has duplicate \"gin\", in file: {spec:?}"));
+                return Err(invalid("interface has duplicate \"gin\"".to_string()));
+            }
+            // Check that the units are known.
+            for interface in &pop_spec.interfaces {
+                if let Some(unit) = &interface.unit {
+                    crate::units::Unit::parse(unit).map_err(invalid)?;
+                }
+            }
+            // Check the declared telemetry.
+            let telemetry_names: HashSet<&str> = pop_spec.telemetry.iter().map(|item| item.name.as_str()).collect();
+            if telemetry_names.len() < pop_spec.telemetry.len() {
+                return Err(invalid("duplicate telemetry name".to_string()));
+            }
+            if let Some(item) = pop_spec
+                .telemetry
+                .iter()
+                .find(|item| item.frequency.is_some_and(|frequency| frequency <= 0.0))
+            {
+                return Err(invalid(format!(
+                    "telemetry \"{}\" frequency must be positive",
+                    item.name
+                )));
+            }
+            // Check that each interface belongs to at most one module.
+            let mut module_names = HashSet::new();
+            let mut assigned = HashSet::new();
+            for module in &pop_spec.modules {
+                if !module_names.insert(&module.name) {
+                    return Err(invalid(format!("duplicate module name \"{}\"", module.name)));
+                }
+                for name in &module.interfaces {
+                    if !pop_spec.interfaces.iter().any(|interface| &interface.name == name) {
+                        return Err(invalid(format!(
+                            "module \"{}\" has unknown interface \"{name}\"",
+                            module.name
+                        )));
+                    }
+                    if !assigned.insert(name) {
+                        return Err(invalid(format!("interface \"{name}\" is in multiple modules")));
+                    }
+                }
             }
         }
         Ok(())
     }
 }
 
+/// Read a specification file as raw JSON, and merge it with the base specification which it extends.
+///
+/// The attribute `"extends"` is the file path of the base specification,
+/// relative to this file. Base specifications may extend other specifications.
+/// The attributes of this file override the attributes of the base.
+/// Populations, settings, modules, and telemetry are matched by name, and interfaces are
+/// matched by gin, so that individual items can be overridden or added.
+///
+/// Argument stack contains the files which are currently being read, for detecting cycles.
+fn read_spec(path: &Path, stack: &mut Vec<PathBuf>) -> Result<Value, Error> {
+    let invalid = |message: String| Error::spec(path, message);
+    let canonical = path.canonicalize().map_err(|err| invalid(err.to_string()))?;
+    if stack.contains(&canonical) {
+        return Err(invalid("cyclic \"extends\"".to_string()));
+    }
+    let text = std::fs::read_to_string(path).map_err(|err| invalid(err.to_string()))?;
+    let mut spec: Value = serde_json::from_str(&text).map_err(|err| invalid(err.to_string()))?;
+    let Some(extends) = spec.as_object_mut().and_then(|spec| spec.remove("extends")) else {
+        return Ok(spec);
+    };
+    let Some(extends) = extends.as_str().map(PathBuf::from) else {
+        return Err(invalid("\"extends\" is not a file path".to_string()));
+    };
+    stack.push(canonical);
+    let mut base = read_spec(&path.parent().unwrap_or(Path::new("")).join(&extends), stack)?;
+    stack.pop();
+    // The environment program's path is relative to the file which contains it.
+    if let Some(program) = base.get_mut("path") {
+        if let Some(program_path) = program.as_str().map(Path::new) {
+            if program_path.is_relative() {
+                let program_path = extends.parent().unwrap_or(Path::new("")).join(program_path);
+                *program = Value::String(program_path.to_string_lossy().into_owned());
+            }
+        }
+    }
+    merge_spec(&mut base, spec, "");
+    Ok(base)
+}
+
+/// Merge the overrides into the base. Argument field is the name of the attribute being merged.
+fn merge_spec(base: &mut Value, overrides: Value, field: &str) {
+    let key = match field {
+        "populations" | "settings" | "modules" | "telemetry" => Some("name"),
+        "interfaces" => Some("gin"),
+        _ => None,
+    };
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (field, value) in overrides {
+                match base.get_mut(&field) {
+                    Some(base_value) => merge_spec(base_value, value, &field),
+                    None => {
+                        base.insert(field, value);
+                    }
+                }
+            }
+        }
+        (Value::Array(base), Value::Array(overrides)) if key.is_some() => {
+            let key = key.unwrap();
+            for item in overrides {
+                let existing = item
+                    .get(key)
+                    .and_then(|id| base.iter_mut().find(|base_item| base_item.get(key) == Some(id)));
+                match existing {
+                    Some(base_item) => merge_spec(base_item, item, ""),
+                    None => base.push(item),
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+/// User facing documentation message, which may be translated into several languages.
+///
+/// In the specification file this is either a string, or an object which maps
+/// language tags to strings, for example `{"en": "Hello", "de": "Hallo"}`.
+/// In both cases a list of strings may be given in place of a single string,
+/// and the strings are concatenated together.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Description {
+    Text(String),
+    Localized(BTreeMap<String, String>),
+}
+
+impl Default for Description {
+    fn default() -> Self {
+        Self::Text(String::new())
+    }
+}
+
+impl Description {
+    /// Get the message in the given language, for example "de" or "de-AT".
+    ///
+    /// Falls back to the base language ("de-AT" to "de"), then to English
+    /// ("en"), and then to any available translation.
+    pub fn get(&self, locale: &str) -> &str {
+        let translations = match self {
+            Self::Text(text) => return text,
+            Self::Localized(translations) => translations,
+        };
+        let language = locale.split(['-', '_']).next().unwrap_or(locale);
+        [locale, language, "en"]
+            .into_iter()
+            .find_map(|tag| translations.get(tag))
+            .or_else(|| translations.values().next())
+            .map_or("", String::as_str)
+    }
+
+    /// Get the message in English.
+    pub fn as_str(&self) -> &str {
+        self.get("en")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.as_str().is_empty()
+    }
+}
+
+impl std::fmt::Display for Description {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<&str> for Description {
+    fn from(text: &str) -> Self {
+        Self::Text(text.to_string())
+    }
+}
+
+impl PartialEq<str> for Description {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Description {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl<'de> Deserialize<'de> for Description {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Multiline(#[serde(deserialize_with = "multiline_string")] String);
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Text(Multiline),
+            Localized(BTreeMap<String, Multiline>),
+        }
+
+        Ok(match Raw::deserialize(deserializer)? {
+            Raw::Text(Multiline(text)) => Self::Text(text),
+            Raw::Localized(translations) => Self::Localized(
+                translations
+                    .into_iter()
+                    .map(|(tag, Multiline(text))| (tag, text))
+                    .collect(),
+            ),
+        })
+    }
+}
+
 /// Description for each specific population within an environment.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct PopulationSpec {
@@ -93,12 +317,126 @@ pub struct PopulationSpec {
     pub name: String,
 
     /// User facing documentation message.
-    #[serde(default, deserialize_with = "multiline_string")]
-    pub description: String,
+    #[serde(default)]
+    pub description: Description,
 
     /// Genetic interface for this lifeform’s body.
     #[serde(default)]
     pub interfaces: Vec<InterfaceSpec>,
+
+    /// Separate controllers which together make up each individual's control system.
+    /// If empty then each individual has a single controller.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modules: Vec<ModuleSpec>,
+
+    /// Info keys which the environment reports about each individual.
+    /// If empty then the info is not declared and any keys are accepted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub telemetry: Vec<TelemetrySpec>,
+}
+
+impl PopulationSpec {
+    /// Check the info which the environment reported about an individual against the declared telemetry.
+    ///
+    /// Populations which do not declare their telemetry accept any info.
+    pub fn validate_info<'a>(&self, info: impl IntoIterator<Item = (&'a str, &'a str)>) -> Result<(), String> {
+        if self.telemetry.is_empty() {
+            return Ok(());
+        }
+        for (key, value) in info {
+            let Some(item) = self.telemetry.iter().find(|item| item.name == key) else {
+                return Err(format!(
+                    "undeclared telemetry \"{key}\" in population \"{}\"",
+                    self.name
+                ));
+            };
+            item.validate_value(value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Description of one info key which an environment reports about its individuals.
+///
+/// Declaring the telemetry documents it for downstream analysis, and lets the
+/// NPC Maker catch keys which were renamed or changed type.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TelemetrySpec {
+    /// Info key, must be unique within the population.
+    #[serde(deserialize_with = "required_string")]
+    pub name: String,
+
+    /// Data type of the values.
+    #[serde(default, rename = "type")]
+    pub r#type: TelemetryType,
+
+    /// Unit of measurement, for display. This is not checked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+
+    /// Expected number of reports per second. If missing then the value is
+    /// reported once, or only occasionally.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency: Option<f64>,
+
+    /// User facing documentation message.
+    #[serde(default)]
+    pub description: Description,
+}
+
+/// Data type of a [TelemetrySpec]. Info values are always transmitted as strings.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TelemetryType {
+    #[serde(alias = "float")]
+    Real,
+    #[serde(alias = "int")]
+    Integer,
+    #[serde(alias = "bool")]
+    Boolean,
+    #[default]
+    #[serde(alias = "string", alias = "str")]
+    String,
+}
+
+impl TelemetrySpec {
+    /// Check that a reported value is acceptable for this info key.
+    pub fn validate_value(&self, value: &str) -> Result<(), String> {
+        let name = &self.name;
+        let value = value.trim();
+        let valid = match self.r#type {
+            TelemetryType::Real => value.parse::<f64>().is_ok(),
+            TelemetryType::Integer => value.parse::<i64>().is_ok(),
+            TelemetryType::Boolean => matches!(value.to_ascii_lowercase().as_str(), "true" | "false"),
+            TelemetryType::String => true,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(format!(
+                "telemetry \"{name}\" is not of type {:?}, found {value:?}",
+                self.r#type
+            ))
+        }
+    }
+}
+
+/// Description of one part of a modular control system.
+///
+/// See [ControllerGroup](crate::ctrl::ControllerGroup).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ModuleSpec {
+    /// Name of the module, must be unique within the population.
+    #[serde(deserialize_with = "required_string")]
+    pub name: String,
+
+    /// User facing documentation message.
+    #[serde(default)]
+    pub description: Description,
+
+    /// Names of the interfaces which are connected to this module.
+    #[serde(default)]
+    pub interfaces: Vec<String>,
 }
 
 /// Description of the interface between a body and its genotype.
@@ -115,9 +453,13 @@ pub struct InterfaceSpec {
     #[serde(default)]
     pub chromosome_types: Vec<Arc<str>>,
 
+    /// Unit of measurement for the values of this interface, see the [units](crate::units) module.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+
     /// User facing documentation message.
-    #[serde(default, deserialize_with = "multiline_string")]
-    pub description: String,
+    #[serde(default)]
+    pub description: Description,
 }
 
 /// Description of an environmental parameter.
@@ -132,16 +474,19 @@ pub enum SettingsSpec {
     Real {
         name: String,
 
-        #[serde(default, deserialize_with = "multiline_string")]
-        description: String,
+        #[serde(default)]
+        description: Description,
 
         /// Lower bound on the range of allowable values, inclusive.
+        #[serde(with = "f64_symbols")]
         minimum: f64,
 
         /// Upper bound on the range of allowable values, inclusive.
+        #[serde(with = "f64_symbols")]
         maximum: f64,
 
         /// Initial value for new environments.
+        #[serde(with = "f64_symbols")]
         default: f64,
     },
 
@@ -149,8 +494,8 @@ pub enum SettingsSpec {
     Integer {
         name: String,
 
-        #[serde(default, deserialize_with = "multiline_string")]
-        description: String,
+        #[serde(default)]
+        description: Description,
 
         /// Lower bound on the range of allowable values, inclusive.
         minimum: i64,
@@ -166,8 +511,8 @@ pub enum SettingsSpec {
     Boolean {
         name: String,
 
-        #[serde(default, deserialize_with = "multiline_string")]
-        description: String,
+        #[serde(default)]
+        description: Description,
 
         /// Initial value for new environments.
         default: bool,
@@ -177,8 +522,8 @@ pub enum SettingsSpec {
     Enumeration {
         name: String,
 
-        #[serde(default, deserialize_with = "multiline_string")]
-        description: String,
+        #[serde(default)]
+        description: Description,
 
         /// Names of all of the variants of the enumeration.
         values: Vec<String>,
@@ -186,6 +531,48 @@ pub enum SettingsSpec {
         /// Initial value for new environments.
         default: String,
     },
+
+    #[serde(alias = "path")]
+    FilePath {
+        name: String,
+
+        #[serde(default)]
+        description: Description,
+
+        /// Acceptable file extensions, without the leading period.
+        /// If empty then all file extensions are accepted.
+        #[serde(default)]
+        extensions: Vec<String>,
+
+        /// Require that the file already exists.
+        #[serde(default)]
+        exists: bool,
+
+        /// Initial value for new environments.
+        default: PathBuf,
+    },
+
+    #[serde(alias = "list")]
+    List {
+        name: String,
+
+        #[serde(default)]
+        description: Description,
+
+        /// Initial value for new environments.
+        default: Vec<String>,
+    },
+
+    #[serde(alias = "color")]
+    Color {
+        name: String,
+
+        #[serde(default)]
+        description: Description,
+
+        /// Initial value for new environments, in the hexadecimal format "#RRGGBB".
+        default: String,
+    },
 }
 
 impl SettingsSpec {
@@ -196,16 +583,22 @@ impl SettingsSpec {
             Self::Integer { name, .. } => name,
             Self::Boolean { name, .. } => name,
             Self::Enumeration { name, .. } => name,
+            Self::FilePath { name, .. } => name,
+            Self::List { name, .. } => name,
+            Self::Color { name, .. } => name,
         }
     }
 
     /// User facing documentation message.
-    pub fn description(&self) -> &str {
+    pub fn description(&self) -> &Description {
         match self {
             Self::Real { description, .. } => description,
             Self::Integer { description, .. } => description,
             Self::Boolean { description, .. } => description,
             Self::Enumeration { description, .. } => description,
+            Self::FilePath { description, .. } => description,
+            Self::List { description, .. } => description,
+            Self::Color { description, .. } => description,
         }
     }
 
@@ -216,6 +609,9 @@ impl SettingsSpec {
             Self::Integer { .. } => "Integer",
             Self::Boolean { .. } => "Boolean",
             Self::Enumeration { .. } => "Enumeration",
+            Self::FilePath { .. } => "FilePath",
+            Self::List { .. } => "List",
+            Self::Color { .. } => "Color",
         }
     }
 
@@ -225,6 +621,89 @@ impl SettingsSpec {
             Self::Integer { default, .. } => default.to_string(),
             Self::Boolean { default, .. } => default.to_string(),
             Self::Enumeration { default, .. } => default.to_string(),
+            Self::FilePath { default, .. } => default.to_string_lossy().into_owned(),
+            Self::List { default, .. } => serde_json::to_string(default).unwrap(),
+            Self::Color { default, .. } => default.to_string(),
+        }
+    }
+
+    /// Check that a value, as given on the command line, is acceptable for this setting.
+    pub fn validate_value(&self, value: &str) -> Result<(), String> {
+        let name = self.name();
+        match self {
+            Self::Real { minimum, maximum, .. } => {
+                let value = f64_symbols::parse(value).map_err(|err| format!("setting \"{name}\" {err}"))?;
+                if !(*minimum..=*maximum).contains(&value) {
+                    return Err(format!("setting \"{name}\" is out of range [{minimum}, {maximum}]"));
+                }
+            }
+            Self::Integer { minimum, maximum, .. } => {
+                let value: i64 = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("setting \"{name}\" is not an integer"))?;
+                if !(*minimum..=*maximum).contains(&value) {
+                    return Err(format!("setting \"{name}\" is out of range [{minimum}, {maximum}]"));
+                }
+            }
+            Self::Boolean { .. } => {
+                if !matches!(value.trim().to_ascii_lowercase().as_str(), "true" | "false") {
+                    return Err(format!("setting \"{name}\" is not a boolean"));
+                }
+            }
+            Self::Enumeration { values, .. } => {
+                if !values.iter().any(|variant| variant == value) {
+                    return Err(format!("setting \"{name}\" is not one of {values:?}"));
+                }
+            }
+            Self::FilePath { extensions, exists, .. } => {
+                let path = Path::new(value);
+                if !extensions.is_empty() {
+                    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+                    if !extensions.iter().any(|ext| ext.eq_ignore_ascii_case(extension)) {
+                        return Err(format!(
+                            "setting \"{name}\" must have one of the file extensions {extensions:?}"
+                        ));
+                    }
+                }
+                if *exists && !path.exists() {
+                    return Err(format!("setting \"{name}\" file not found {path:?}"));
+                }
+            }
+            Self::List { .. } => {
+                Self::parse_list(value).map_err(|err| format!("setting \"{name}\" {err}"))?;
+            }
+            Self::Color { .. } => {
+                Self::parse_color(value).map_err(|err| format!("setting \"{name}\" {err}"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse the value of a List setting, which is either a JSON array of strings or a comma separated list.
+    pub fn parse_list(value: &str) -> Result<Vec<String>, String> {
+        let value = value.trim();
+        if value.starts_with('[') {
+            serde_json::from_str(value).map_err(|err| format!("is not a list of strings, {err}"))
+        } else if value.is_empty() {
+            Ok(vec![])
+        } else {
+            Ok(value.split(',').map(|item| item.trim().to_string()).collect())
+        }
+    }
+
+    /// Parse the value of a Color setting, in the format "#RRGGBB" or "#RGB", into its red, green, and blue components.
+    pub fn parse_color(value: &str) -> Result<[u8; 3], String> {
+        let error = || format!("is not a color \"{value}\", expected \"#RRGGBB\"");
+        let hex = value.trim().strip_prefix('#').ok_or_else(error)?;
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(error());
+        }
+        let component = |digits: &str| u8::from_str_radix(digits, 16).unwrap();
+        match hex.len() {
+            6 => Ok([component(&hex[0..2]), component(&hex[2..4]), component(&hex[4..6])]),
+            3 => Ok([0, 1, 2].map(|index| component(&hex[index..index + 1]) * 17)),
+            _ => Err(error()),
         }
     }
 }
@@ -232,3 +711,136 @@ impl SettingsSpec {
 const fn default_one() -> u32 {
     1
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extends() {
+        let dir = std::env::temp_dir().join(format!("npc_maker_extends_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("common")).unwrap();
+        let base = r#"{
+            "name": "arena",
+            "path": "arena.exe",
+            "description": "base arena",
+            "populations": [{
+                "name": "robot",
+                "interfaces": [
+                    {"gin": 1, "name": "left_wheel"},
+                    {"gin": 2, "name": "right_wheel"}
+                ]
+            }],
+            "settings": [{"name": "size", "type": "Integer", "minimum": 1, "maximum": 100, "default": 10}]
+        }"#;
+        std::fs::write(dir.join("common/base.env"), base).unwrap();
+        let variant = r#"{
+            "extends": "common/base.env",
+            "name": "big_arena",
+            "populations": [{
+                "name": "robot",
+                "interfaces": [
+                    {"gin": 2, "name": "right_wheel", "description": "overridden"},
+                    {"gin": 3, "name": "camera"}
+                ]
+            }],
+            "settings": [{"name": "size", "type": "Integer", "minimum": 1, "maximum": 100, "default": 50}]
+        }"#;
+        std::fs::write(dir.join("variant.env"), variant).unwrap();
+        std::fs::write(dir.join("cycle_a.env"), r#"{"extends": "cycle_b.env"}"#).unwrap();
+        std::fs::write(dir.join("cycle_b.env"), r#"{"extends": "cycle_a.env"}"#).unwrap();
+        let spec = EnvironmentSpec::new(dir.join("variant.env"));
+        let cycle = EnvironmentSpec::new(dir.join("cycle_a.env"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let spec = spec.unwrap();
+        assert_eq!(spec.name, "big_arena");
+        assert_eq!(spec.path, Path::new("common/arena.exe"));
+        assert_eq!(spec.description, "base arena");
+        let interfaces = &spec.populations[0].interfaces;
+        let names: Vec<_> = interfaces.iter().map(|interface| interface.name.as_str()).collect();
+        assert_eq!(names, ["left_wheel", "right_wheel", "camera"]);
+        assert_eq!(interfaces[1].description, "overridden");
+        assert_eq!(spec.settings.len(), 1);
+        assert_eq!(spec.settings[0].default(), "50");
+        let Err(Error::Spec { message, .. }) = cycle else {
+            panic!()
+        };
+        assert!(message.contains("cyclic"));
+    }
+
+    #[test]
+    fn settings_types() {
+        let settings: Vec<SettingsSpec> = serde_json::from_str(
+            r##"[
+            {"name": "map", "type": "path", "extensions": ["png", "bmp"], "default": "maps/default.png"},
+            {"name": "names", "type": "list", "default": ["alice", "bob"]},
+            {"name": "background", "type": "color", "default": "#204080"},
+            {"name": "heading", "type": "Real", "minimum": "-pi", "maximum": "pi", "default": "pi/2"}
+        ]"##,
+        )
+        .unwrap();
+        let [map, names, background, heading] = &settings[..] else {
+            panic!()
+        };
+        assert_eq!(map.r#type(), "FilePath");
+        assert_eq!(map.default(), "maps/default.png");
+        assert!(map.validate_value("maps/other.PNG").is_ok());
+        assert!(map.validate_value("maps/other.txt").is_err());
+        assert_eq!(names.default(), r#"["alice","bob"]"#);
+        assert!(names.validate_value("carol, dave").is_ok());
+        assert_eq!(SettingsSpec::parse_list(" carol, dave").unwrap(), ["carol", "dave"]);
+        assert_eq!(SettingsSpec::parse_list(r#"["a,b"]"#).unwrap(), ["a,b"]);
+        assert!(names.validate_value("[1, 2]").is_err());
+        assert_eq!(SettingsSpec::parse_color(&background.default()), Ok([0x20, 0x40, 0x80]));
+        assert_eq!(SettingsSpec::parse_color("#fff"), Ok([255, 255, 255]));
+        assert!(background.validate_value("red").is_err());
+        assert!(background.validate_value("#12345").is_err());
+        assert_eq!(heading.default(), (std::f64::consts::PI / 2.0).to_string());
+        assert!(heading.validate_value("-pi/4").is_ok());
+        assert!(heading.validate_value("tau").is_err());
+    }
+
+    #[test]
+    fn telemetry() {
+        let pop_spec: PopulationSpec = serde_json::from_str(
+            r#"{"name": "robot", "telemetry": [
+                {"name": "distance", "type": "Real", "unit": "m", "frequency": 10},
+                {"name": "collisions", "type": "int"},
+                {"name": "escaped", "type": "bool"},
+                {"name": "cause_of_death"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(pop_spec.telemetry[3].r#type, TelemetryType::String);
+        assert!(pop_spec
+            .validate_info([("distance", "1.5"), ("collisions", "3"), ("escaped", "True")])
+            .is_ok());
+        assert!(pop_spec.validate_info([("distance", "far")]).is_err());
+        assert!(pop_spec.validate_info([("collisions", "2.5")]).is_err());
+        assert!(pop_spec.validate_info([("dist", "1.5")]).is_err());
+        let undeclared: PopulationSpec = serde_json::from_str(r#"{"name": "robot"}"#).unwrap();
+        assert!(undeclared.validate_info([("anything", "goes")]).is_ok());
+        assert!(serde_json::from_str::<TelemetrySpec>(r#"{"name": "x", "type": "Complex"}"#).is_err());
+    }
+
+    #[test]
+    fn localized_descriptions() {
+        let interface: InterfaceSpec = serde_json::from_str(
+            r#"{"gin": 1, "name": "eye", "description": {"en": ["Light ", "sensor"], "de": "Lichtsensor"}}"#,
+        )
+        .unwrap();
+        let description = &interface.description;
+        assert_eq!(description.get("de"), "Lichtsensor");
+        assert_eq!(description.get("de-AT"), "Lichtsensor");
+        assert_eq!(description.get("fr"), "Light sensor");
+        assert_eq!(description, "Light sensor");
+        let text: Description = serde_json::from_str(r#""plain""#).unwrap();
+        assert_eq!(text.get("de"), "plain");
+        let french: Description = serde_json::from_str(r#"{"fr": "Capteur"}"#).unwrap();
+        assert_eq!(french.get("en"), "Capteur");
+        assert!(Description::default().is_empty());
+        let json = serde_json::to_string(description).unwrap();
+        assert_eq!(serde_json::from_str::<Description>(&json).unwrap(), *description);
+    }
+}
@@ -0,0 +1,133 @@
+//! Error type for the whole crate.
+
+#[cfg(feature = "spawn")]
+use crate::ctrl::{ControllerDead, ControllerOutOfMemory, ControllerPanic, ControllerTimeout, ProtocolError};
+use crate::ctrl::{MessageError, MessageTooLarge};
+use std::io;
+use std::path::PathBuf;
+
+/// Errors which can occur while using this crate.
+///
+/// Controller methods return [io::Error], with typed errors inside of them
+/// (see [ControllerDead] and friends). Converting them into this type sorts
+/// them into the appropriate variant.
+///
+/// This type also converts back into [io::Error] and [serde_json::Error], so
+/// that applications which return those types can use the `?` operator on the
+/// functions of this crate. The original error is returned unchanged if there
+/// is one.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    /// Invalid command line arguments.
+    #[error("argument error: {0}")]
+    Argument(String),
+
+    /// Environment specification which can not be read or is not valid.
+    #[error("{message}, in file: {path:?}")]
+    Spec { path: PathBuf, message: String },
+
+    /// Message which does not follow the protocol.
+    #[error("protocol error: {0}")]
+    Protocol(#[source] io::Error),
+
+    /// Failure of another program, for example a controller which died or stopped responding.
+    #[error("{0}")]
+    Process(#[source] io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("I/O error: {0}")]
+    Io(#[source] io::Error),
+}
+
+impl Error {
+    pub(crate) fn spec(path: impl Into<PathBuf>, message: impl ToString) -> Self {
+        Self::Spec {
+            path: path.into(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        if MessageError::from_io(&error).is_some() || MessageTooLarge::from_io(&error).is_some() {
+            return Self::Protocol(error);
+        }
+        #[cfg(feature = "spawn")]
+        if ProtocolError::from_io(&error).is_some() {
+            return Self::Protocol(error);
+        }
+        #[cfg(feature = "spawn")]
+        if ControllerDead::from_io(&error).is_some()
+            || ControllerOutOfMemory::from_io(&error).is_some()
+            || ControllerPanic::from_io(&error).is_some()
+            || ControllerTimeout::from_io(&error).is_some()
+        {
+            return Self::Process(error);
+        }
+        Self::Io(error)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Io(error) | Error::Process(error) | Error::Protocol(error) => error,
+            Error::Json(error) => error.into(),
+            Error::Argument(_) => io::Error::new(io::ErrorKind::InvalidInput, error),
+            Error::Spec { .. } => io::Error::new(io::ErrorKind::InvalidData, error),
+        }
+    }
+}
+
+impl From<Error> for serde_json::Error {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Json(error) => error,
+            Error::Io(error) | Error::Process(error) => serde_json::Error::io(error),
+            error => serde::de::Error::custom(error),
+        }
+    }
+}
+
+/// Result type for the whole crate.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let not_found = io::Error::new(io::ErrorKind::NotFound, "missing");
+        let error = Error::from(not_found);
+        assert!(matches!(error, Error::Io(_)));
+        let back = io::Error::from(error);
+        assert_eq!(back.kind(), io::ErrorKind::NotFound);
+        assert_eq!(back.to_string(), "missing");
+        let argument = io::Error::from(Error::Argument("bad".to_string()));
+        assert_eq!(argument.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(argument.to_string(), "argument error: bad");
+        let json_error = serde_json::from_str::<u64>("x").unwrap_err();
+        let line = json_error.line();
+        let json_error = serde_json::Error::from(Error::from(json_error));
+        assert_eq!(json_error.line(), line);
+        let protocol = io::Error::new(io::ErrorKind::InvalidData, "oops");
+        let protocol = serde_json::Error::from(Error::Protocol(protocol));
+        assert_eq!(protocol.to_string(), "protocol error: oops");
+        // Protocol errors keep the original error.
+        let too_large = io::Error::new(io::ErrorKind::InvalidData, MessageTooLarge { num_bytes: 9, limit: 8 });
+        let error = Error::from(too_large);
+        assert!(matches!(error, Error::Protocol(_)));
+        let back = io::Error::from(error);
+        assert_eq!(back.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(MessageTooLarge::from_io(&back).unwrap().limit, 8);
+        // Both directions work with the "?" operator.
+        fn read() -> io::Result<u64> {
+            Ok(serde_json::from_str::<u64>("5").map_err(Error::from)?)
+        }
+        assert_eq!(read().unwrap(), 5);
+    }
+}
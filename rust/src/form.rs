@@ -0,0 +1,326 @@
+//! Presentation-agnostic model of an environment's settings menu.
+//!
+//! Graphical and text front-ends use a [SettingsForm] to show and edit the
+//! settings of an environment, instead of each front-end deriving its own
+//! widgets from the [SettingsSpec]. The form holds the current value of each
+//! setting as text, exactly as the user typed it, along with its validation
+//! error if there is one. Front-ends draw one widget for each [Field], and
+//! choose the kind of widget from the field's [Input].
+//!
+//! Once every field is valid, [SettingsForm::to_args()] produces the command
+//! line arguments for launching the environment. The form is also
+//! serializable to JSON, for front-ends which are written in other languages.
+
+use crate::env_spec::{Description, EnvironmentSpec, SettingsSpec};
+use crate::serde_utils::f64_symbols;
+use crate::Error;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Kind of input widget for a setting, along with its constraints.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum Input {
+    /// Number in the inclusive range `[minimum, maximum]`.
+    Real { minimum: f64, maximum: f64 },
+
+    /// Whole number in the inclusive range `[minimum, maximum]`.
+    Integer { minimum: i64, maximum: i64 },
+
+    /// Either "true" or "false".
+    Checkbox,
+
+    /// One of the given values.
+    Choice { values: Vec<String> },
+
+    /// File path with one of the given extensions, or any extension if empty.
+    File { extensions: Vec<String>, exists: bool },
+
+    /// List of strings.
+    List,
+
+    /// Color in the hexadecimal format "#RRGGBB".
+    Color,
+}
+
+impl From<&SettingsSpec> for Input {
+    fn from(spec: &SettingsSpec) -> Self {
+        match spec {
+            SettingsSpec::Real { minimum, maximum, .. } => Self::Real {
+                minimum: *minimum,
+                maximum: *maximum,
+            },
+            SettingsSpec::Integer { minimum, maximum, .. } => Self::Integer {
+                minimum: *minimum,
+                maximum: *maximum,
+            },
+            SettingsSpec::Boolean { .. } => Self::Checkbox,
+            SettingsSpec::Enumeration { values, .. } => Self::Choice { values: values.clone() },
+            SettingsSpec::FilePath { extensions, exists, .. } => Self::File {
+                extensions: extensions.clone(),
+                exists: *exists,
+            },
+            SettingsSpec::List { .. } => Self::List,
+            SettingsSpec::Color { .. } => Self::Color,
+        }
+    }
+}
+
+/// One setting in a [SettingsForm].
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub description: Description,
+    pub input: Input,
+    pub default: String,
+    value: String,
+    error: Option<String>,
+    #[serde(skip)]
+    spec: SettingsSpec,
+}
+
+impl Field {
+    pub fn new(spec: &SettingsSpec) -> Self {
+        Self {
+            name: spec.name().to_string(),
+            description: spec.description().clone(),
+            input: spec.into(),
+            default: spec.default(),
+            value: spec.default(),
+            error: None,
+            spec: spec.clone(),
+        }
+    }
+
+    /// Specification of this setting.
+    pub fn spec(&self) -> &SettingsSpec {
+        &self.spec
+    }
+
+    /// Current value, as text.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Change the value of this setting.
+    ///
+    /// Invalid values are kept so that the user can finish editing them,
+    /// and the form can not be used until they are fixed.
+    pub fn set(&mut self, value: impl Into<String>) -> Result<(), String> {
+        self.value = value.into();
+        self.error = self.spec.validate_value(&self.value).err();
+        match &self.error {
+            None => Ok(()),
+            Some(error) => Err(error.clone()),
+        }
+    }
+
+    /// Restore the default value.
+    pub fn reset(&mut self) {
+        self.value.clone_from(&self.default);
+        self.error = None;
+    }
+
+    /// Error message for the current value, or None if the value is valid.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.error.is_none()
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.value == self.default
+    }
+
+    /// Current value of a Real or Integer setting, or None if it is invalid.
+    pub fn as_f64(&self) -> Option<f64> {
+        f64_symbols::parse(&self.value).ok().filter(|_| self.is_valid())
+    }
+
+    /// Current value of an Integer setting, or None if it is invalid.
+    pub fn as_i64(&self) -> Option<i64> {
+        self.value.trim().parse().ok().filter(|_| self.is_valid())
+    }
+
+    /// Current value of a Boolean setting, or None if it is invalid.
+    pub fn as_bool(&self) -> Option<bool> {
+        self.value
+            .trim()
+            .to_ascii_lowercase()
+            .parse()
+            .ok()
+            .filter(|_| self.is_valid())
+    }
+
+    /// Current value of a List setting, or None if it is invalid.
+    pub fn as_list(&self) -> Option<Vec<String>> {
+        SettingsSpec::parse_list(&self.value).ok().filter(|_| self.is_valid())
+    }
+
+    /// Current value of a Color setting as red, green, and blue components, or None if it is invalid.
+    pub fn as_color(&self) -> Option<[u8; 3]> {
+        SettingsSpec::parse_color(&self.value).ok().filter(|_| self.is_valid())
+    }
+
+    /// Value in the format which the environment receives on its command line.
+    fn argument(&self) -> String {
+        match (&self.spec, self.as_list()) {
+            (SettingsSpec::List { .. }, Some(list)) => serde_json::to_string(&list).unwrap(),
+            _ => self.value.clone(),
+        }
+    }
+}
+
+/// Editable settings menu for an environment.
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct SettingsForm {
+    pub fields: Vec<Field>,
+}
+
+impl SettingsForm {
+    /// Make a form with the default value for each setting.
+    pub fn new(settings: &[SettingsSpec]) -> Self {
+        Self {
+            fields: settings.iter().map(Field::new).collect(),
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    pub fn field_mut(&mut self, name: &str) -> Option<&mut Field> {
+        self.fields.iter_mut().find(|field| field.name == name)
+    }
+
+    /// Change the value of one setting, see [Field::set()].
+    pub fn set(&mut self, name: &str, value: impl Into<String>) -> Result<(), Error> {
+        let field = self
+            .field_mut(name)
+            .ok_or_else(|| Error::Argument(format!("unrecognized setting \"{name}\"")))?;
+        field.set(value).map_err(Error::Argument)
+    }
+
+    /// Change the values of several settings, for example from a saved
+    /// configuration. All of the values are applied, even if some are invalid.
+    ///
+    /// Returns the first error.
+    pub fn apply<K: AsRef<str>, V: Into<String>>(
+        &mut self,
+        settings: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<(), Error> {
+        let mut result = Ok(());
+        for (name, value) in settings {
+            let status = self.set(name.as_ref(), value);
+            if result.is_ok() {
+                result = status;
+            }
+        }
+        result
+    }
+
+    /// Restore the default value of every setting.
+    pub fn reset(&mut self) {
+        self.fields.iter_mut().for_each(Field::reset);
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.fields.iter().all(Field::is_valid)
+    }
+
+    /// Returns pairs of (name, error message) for every invalid setting.
+    pub fn errors(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields
+            .iter()
+            .filter_map(|field| Some((field.name.as_str(), field.error()?)))
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        match self.errors().next() {
+            None => Ok(()),
+            Some((_, error)) => Err(Error::Argument(error.to_string())),
+        }
+    }
+
+    /// Returns the settings which differ from their defaults, indexed by name.
+    ///
+    /// This is the format of the settings in experiment configuration files.
+    pub fn to_settings(&self) -> Result<BTreeMap<String, String>, Error> {
+        self.check()?;
+        Ok(self
+            .fields
+            .iter()
+            .filter(|field| !field.is_default())
+            .map(|field| (field.name.clone(), field.argument()))
+            .collect())
+    }
+
+    /// Returns the command line arguments for launching the environment,
+    /// as key-value pairs for every setting which differs from its default.
+    pub fn to_args(&self) -> Result<Vec<String>, Error> {
+        Ok(self
+            .to_settings()?
+            .into_iter()
+            .flat_map(|(name, value)| [name, value])
+            .collect())
+    }
+}
+
+impl From<&EnvironmentSpec> for SettingsForm {
+    fn from(env_spec: &EnvironmentSpec) -> Self {
+        Self::new(&env_spec.settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn form() {
+        let settings: Vec<SettingsSpec> = serde_json::from_str(
+            r##"[
+            {"name": "size", "type": "Integer", "minimum": 1, "maximum": 100, "default": 10},
+            {"name": "speed", "type": "Real", "minimum": 0, "maximum": "pi", "default": 1.5},
+            {"name": "walls", "type": "bool", "default": true},
+            {"name": "names", "type": "list", "default": ["alice"]},
+            {"name": "sky", "type": "color", "default": "#8080ff"}
+        ]"##,
+        )
+        .unwrap();
+        let mut form = SettingsForm::new(&settings);
+        assert!(form.is_valid());
+        assert!(form.to_args().unwrap().is_empty());
+        assert_eq!(form.field("size").unwrap().as_i64(), Some(10));
+        assert_eq!(
+            form.field("speed").unwrap().input,
+            Input::Real {
+                minimum: 0.0,
+                maximum: std::f64::consts::PI
+            }
+        );
+        assert_eq!(form.field("walls").unwrap().as_bool(), Some(true));
+        assert_eq!(form.field("sky").unwrap().as_color(), Some([0x80, 0x80, 0xff]));
+        // Invalid values are kept until they are fixed.
+        assert!(form.set("size", "1000").is_err());
+        assert_eq!(form.field("size").unwrap().value(), "1000");
+        assert_eq!(form.field("size").unwrap().as_i64(), None);
+        assert_eq!(form.errors().count(), 1);
+        assert!(form.to_args().is_err());
+        assert!(form.set("colour", "red").is_err());
+        form.apply([("size", "20"), ("speed", "pi/2"), ("names", "bob, carol")])
+            .unwrap();
+        assert!(form.is_valid());
+        assert_eq!(
+            form.to_args().unwrap(),
+            ["names", r#"["bob","carol"]"#, "size", "20", "speed", "pi/2"]
+        );
+        let json = serde_json::to_value(&form).unwrap();
+        assert_eq!(json["fields"][0]["value"], "20");
+        assert_eq!(json["fields"][0]["input"]["type"], "Integer");
+        form.reset();
+        assert!(form.to_settings().unwrap().is_empty());
+    }
+}
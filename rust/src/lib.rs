@@ -1,7 +1,22 @@
-//!
-
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "spawn")]
+pub mod conformance;
 pub mod ctrl;
+#[cfg(feature = "env")]
 pub mod env_api;
 pub mod env_spec;
+mod error;
+pub mod form;
+#[cfg(feature = "spawn")]
 pub mod messages;
+#[cfg(feature = "plot")]
+pub mod plot;
+pub mod prelude;
+#[cfg(feature = "python")]
+mod python;
+#[allow(dead_code)]
 mod serde_utils;
+pub mod units;
+
+pub use error::{Error, Result};
@@ -1,5 +1,6 @@
 //! Message structures for communicating between the environments and the NPC Maker.
 
+use crate::ctrl::ControllerOptions;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -54,6 +55,9 @@ pub enum Request {
         population: String,
         individual: u64,
         controller: Vec<String>,
+        /// Environment variables and working directory for the controller program.
+        #[serde(default, skip_serializing_if = "ControllerOptions::is_empty")]
+        controller_options: Box<ControllerOptions>,
         genotype: serde_json::Value,
     },
 }
@@ -128,93 +132,64 @@ mod tests {
                     " ".to_string(),
                     ",.,<>.,.,.,><>,".to_string(),
                 ],
+                controller_options: Box::default(),
                 genotype: serde_json::json!([]),
             },
             Request::Birth {
                 population: "pop1".to_string(),
                 individual: 43,
                 controller: vec![],
+                controller_options: Box::default(),
                 genotype: serde_json::json!([{}, {}, {}]),
             },
         ];
         let mut info = HashMap::new();
         info.insert("my_key".to_string(), "my_value".to_string());
         let mut all_responses = vec![
-            Response::New { population: None },
             Response::New {
-                population: Some("my pop1".to_string()),
+                population: String::new(),
             },
             Response::New {
-                population: Some(" ".to_string()),
+                population: "my pop1".to_string(),
             },
-            Response::Mate {
-                population: None,
-                parent1: 5,
-                parent2: 7,
-            },
-            Response::Mate {
-                population: Some("pop 3".to_string()),
-                parent1: 5,
-                parent2: 8,
+            Response::New {
+                population: " ".to_string(),
             },
+            Response::Mate { parents: vec![5, 7] },
+            Response::Mate { parents: vec![5, 8] },
             Response::Score {
-                population: None,
-                individual: Some(42),
+                individual: 42,
                 score: 42.2,
             },
             Response::Score {
-                population: Some("ends with a number 3".to_string()),
-                individual: Some(21),
+                individual: 21,
                 score: 7.7,
             },
             Response::Info {
-                population: None,
-                individual: Some(101),
-                info: HashMap::new(),
-            },
-            Response::Info {
-                population: None,
-                individual: None,
+                individual: 101,
                 info: HashMap::new(),
             },
             Response::Info {
-                population: None,
-                individual: None,
+                individual: 85,
                 info: info.clone(),
             },
-            Response::Info {
-                population: Some("pop10".to_string()),
-                individual: Some(85),
-                info: info.clone(),
-            },
-            Response::Death {
-                population: None,
-                individual: Some(99),
-            },
-            Response::Death {
-                population: Some("2".to_string()),
-                individual: Some(99),
-            },
+            Response::Death { individual: 99 },
         ];
         for msg in &all_requests {
-            all_responses.push(Response::Ack(msg.clone()));
+            all_responses.push(Response::Ack { ack: msg.clone() });
         }
 
-        println!("REQUESTS:");
         for msg in all_requests {
             let json = serde_json::to_string(&msg).unwrap();
-            dbg!(&json);
-            let recv = serde_json::from_str(&json).unwrap();
+            let recv: Request = serde_json::from_str(&json).unwrap();
             assert_eq!(msg, recv);
-            assert!(!json.contains("\n"));
+            assert!(!json.contains('\n'));
         }
-        println!("RESPONSES:");
         for msg in all_responses {
             let json = serde_json::to_string(&msg).unwrap();
-            dbg!(&json);
-            let recv = serde_json::from_str(&json).unwrap();
+            let recv: Response = serde_json::from_str(&json).unwrap();
             assert_eq!(msg, recv);
-            assert!(!json.contains("\n"));
+            assert!(!json.contains('\n'));
         }
     }
 
@@ -241,6 +216,7 @@ mod tests {
                 population: "pop1".to_string(),
                 individual: 1234,
                 controller: vec!["/usr/bin/q".to_string()],
+                controller_options: Box::default(),
                 genotype: serde_json::json! {
                     [
                         {"name": 6, "type": "foo"},
@@ -0,0 +1,195 @@
+//! Plots of the scores over the course of an experiment.
+//!
+//! The python evolution service writes a statistics log for each population,
+//! named "statistics.ndjson" in the population's directory. Each line of the log
+//! is a JSON object which describes one generation. This module renders the best
+//! and mean score of each generation as a line chart, in either SVG or PNG format.
+//!
+//! Use the command line program `npc_plot`, or the python bindings
+//! `npc_maker_rs.plot_scores()`, or call [plot_scores()] directly.
+
+use crate::Error;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use serde::Deserialize;
+use std::io;
+use std::path::Path;
+
+/// Summary of the scores of one generation, one line of the statistics log.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Statistics {
+    pub generation: u64,
+
+    /// Number of individuals who died in this generation.
+    #[serde(default)]
+    pub individuals: u64,
+
+    /// Highest score in this generation, or None if nobody was scored.
+    #[serde(default)]
+    pub best: Option<f64>,
+
+    /// Average score in this generation, or None if nobody was scored.
+    #[serde(default)]
+    pub mean: Option<f64>,
+
+    /// Lowest score in this generation, or None if nobody was scored.
+    #[serde(default)]
+    pub worst: Option<f64>,
+}
+
+/// Scores of one population, for plotting.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoreCurve {
+    /// Name of the population, for the legend.
+    pub label: String,
+    pub statistics: Vec<Statistics>,
+}
+
+impl ScoreCurve {
+    /// Read a statistics log. The label is the name of the log's directory.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let file_error = |kind, error: &dyn std::fmt::Display| {
+            Error::Io(io::Error::new(kind, format!("{error}, in file: {path:?}")))
+        };
+        let text = std::fs::read_to_string(path).map_err(|error| file_error(error.kind(), &error))?;
+        let statistics = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<Statistics>, _>>()
+            .map_err(|error| file_error(io::ErrorKind::InvalidData, &error))?;
+        let label = path
+            .canonicalize()
+            .ok()
+            .and_then(|path| Some(path.parent()?.file_name()?.to_string_lossy().into_owned()))
+            .unwrap_or_default();
+        Ok(Self { label, statistics })
+    }
+}
+
+fn plot_error(error: impl std::fmt::Display) -> Error {
+    Error::Io(io::Error::other(format!("failed to plot the scores: {error}")))
+}
+
+/// Draw the best and mean scores of each generation into an image file.
+///
+/// The image format is determined by the file extension: ".svg" or ".png".
+pub fn plot_scores(curves: &[ScoreCurve], output: impl AsRef<Path>, title: &str) -> Result<(), Error> {
+    let output = output.as_ref();
+    let size = (960, 600);
+    let extension = output.extension().unwrap_or_default().to_string_lossy().to_lowercase();
+    match extension.as_str() {
+        "svg" => draw(SVGBackend::new(output, size).into_drawing_area(), curves, title),
+        "png" => draw(BitMapBackend::new(output, size).into_drawing_area(), curves, title),
+        _ => Err(Error::Argument(format!(
+            "unrecognized image format {:?}, expected \".svg\" or \".png\"",
+            output
+        ))),
+    }
+}
+
+fn draw<DB: DrawingBackend>(root: DrawingArea<DB, Shift>, curves: &[ScoreCurve], title: &str) -> Result<(), Error> {
+    let points = |curve: &ScoreCurve, field: fn(&Statistics) -> Option<f64>| -> Vec<(f64, f64)> {
+        curve
+            .statistics
+            .iter()
+            .filter_map(|stats| Some((stats.generation as f64, field(stats).filter(|x| x.is_finite())?)))
+            .collect()
+    };
+    let best = |stats: &Statistics| stats.best;
+    let mean = |stats: &Statistics| stats.mean;
+    // Find the range of the axes.
+    let all_points: Vec<(f64, f64)> = curves
+        .iter()
+        .flat_map(|curve| points(curve, best).into_iter().chain(points(curve, mean)))
+        .collect();
+    let max_x = all_points.iter().map(|(x, _)| *x).fold(1.0, f64::max);
+    let mut min_y = all_points.iter().map(|(_, y)| *y).fold(f64::INFINITY, f64::min);
+    let mut max_y = all_points.iter().map(|(_, y)| *y).fold(f64::NEG_INFINITY, f64::max);
+    if all_points.is_empty() {
+        (min_y, max_y) = (0.0, 1.0);
+    } else if min_y == max_y {
+        (min_y, max_y) = (min_y - 0.5, max_y + 0.5);
+    }
+    let margin = 0.05 * (max_y - min_y);
+    //
+    root.fill(&WHITE).map_err(plot_error)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, ("sans-serif", 24))
+        .margin(15)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0.0..max_x, (min_y - margin)..(max_y + margin))
+        .map_err(plot_error)?;
+    chart
+        .configure_mesh()
+        .x_desc("Generation")
+        .y_desc("Score")
+        .draw()
+        .map_err(plot_error)?;
+    for (index, curve) in curves.iter().enumerate() {
+        let color = Palette99::pick(index).to_rgba();
+        let label = |name| {
+            if curve.label.is_empty() {
+                name
+            } else {
+                format!("{} {}", curve.label, name)
+            }
+        };
+        chart
+            .draw_series(LineSeries::new(points(curve, best), color.stroke_width(2)))
+            .map_err(plot_error)?
+            .label(label("best".to_string()))
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2)));
+        let faded = color.mix(0.5);
+        chart
+            .draw_series(LineSeries::new(points(curve, mean), faded))
+            .map_err(plot_error)?
+            .label(label("mean".to_string()))
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], faded));
+    }
+    if !curves.is_empty() {
+        chart
+            .configure_series_labels()
+            .position(SeriesLabelPosition::LowerRight)
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()
+            .map_err(plot_error)?;
+    }
+    root.present().map_err(plot_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plot() {
+        let dir = std::env::temp_dir().join(format!("npc_maker_plot_{}", std::process::id()));
+        let population = dir.join("xor");
+        std::fs::create_dir_all(&population).unwrap();
+        let log = population.join("statistics.ndjson");
+        std::fs::write(
+            &log,
+            "{\"generation\": 0, \"individuals\": 2, \"best\": 3.0, \"mean\": 2.0, \"worst\": 1.0}\n\
+             {\"generation\": 1, \"individuals\": 2, \"best\": null, \"mean\": null, \"worst\": null}\n\
+             \n\
+             {\"generation\": 2, \"best\": 5.0, \"mean\": 4.0}\n",
+        )
+        .unwrap();
+        let curve = ScoreCurve::load(&log).unwrap();
+        assert_eq!(curve.label, "xor");
+        assert_eq!(curve.statistics.len(), 3);
+        assert_eq!(curve.statistics[1].best, None);
+        let svg = dir.join("scores.svg");
+        plot_scores(std::slice::from_ref(&curve), &svg, "Scores").unwrap();
+        assert!(std::fs::read_to_string(&svg).unwrap().contains("<svg"));
+        plot_scores(&[], dir.join("empty.svg"), "Scores").unwrap();
+        assert!(plot_scores(&[curve], dir.join("scores.jpg"), "Scores").is_err());
+        std::fs::write(&log, "not json\n").unwrap();
+        assert!(ScoreCurve::load(&log).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
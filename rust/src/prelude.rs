@@ -0,0 +1,22 @@
+//! Commonly used items, for glob importing.
+//!
+//! ```
+//! use npc_maker::prelude::*;
+//!
+//! fn load(path: &str) -> Result<EnvironmentSpec> {
+//!     EnvironmentSpec::new(path)
+//! }
+//! ```
+
+pub use crate::ctrl::Interfaces;
+pub use crate::env_spec::{EnvironmentSpec, PopulationSpec, SettingsSpec};
+pub use crate::error::{Error, Result};
+
+#[cfg(feature = "ctrl")]
+pub use crate::ctrl::{main_loop, API};
+
+#[cfg(feature = "spawn")]
+pub use crate::ctrl::{Controller, ControllerOptions, ControllerStatus, FlushPolicy};
+
+#[cfg(feature = "env")]
+pub use crate::env_api::{self, Mode};
@@ -0,0 +1,331 @@
+//! Python bindings, for using this crate from python programs.
+//!
+//! The python module `npc_maker_rs` exposes the [Controller], with its
+//! [ControllerOptions] as keyword arguments, and the [EnvironmentSpec].
+//! The environment and evolution APIs are implemented by the python package
+//! `npc_maker`, which can use these bindings in place of its own
+//! implementation of the controller protocol.
+//!
+//! Build the python module with maturin: `maturin develop --features python-extension`
+
+// The pyo3 macros convert errors into PyErr, even when they already are.
+#![allow(clippy::useless_conversion)]
+
+use crate::ctrl::{Capability, Controller, ControllerOptions, SwapMode, ValueEncoding};
+use crate::env_spec::EnvironmentSpec;
+use crate::Error;
+use pyo3::exceptions::{PyOSError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use std::collections::HashMap;
+use std::io;
+
+impl From<Error> for PyErr {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Argument(_) | Error::Spec { .. } | Error::Json(_) => PyValueError::new_err(error.to_string()),
+            Error::Protocol(_) => PyRuntimeError::new_err(error.to_string()),
+            Error::Process(_) | Error::Io(_) => PyOSError::new_err(error.to_string()),
+        }
+    }
+}
+
+fn convert(error: io::Error) -> PyErr {
+    Error::from(error).into()
+}
+
+/// Instance of a control system, see [Controller].
+#[pyclass(name = "Controller", unsendable)]
+pub struct PyController(Controller);
+
+#[pymethods]
+impl PyController {
+    /// Start a controller program.
+    ///
+    /// The keyword arguments are the [ControllerOptions].
+    #[new]
+    #[pyo3(signature = (environment, population, command, *, env_vars = None, working_dir = None,
+        timeout = None, trace = None, value_encoding = "text", query_capabilities = false))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        environment: &str,
+        population: &str,
+        command: Vec<String>,
+        env_vars: Option<HashMap<String, String>>,
+        working_dir: Option<&str>,
+        timeout: Option<f64>,
+        trace: Option<&str>,
+        value_encoding: &str,
+        query_capabilities: bool,
+    ) -> PyResult<Self> {
+        let mut options = ControllerOptions::default();
+        for (key, value) in env_vars.unwrap_or_default() {
+            options = options.env_var(key, value);
+        }
+        if let Some(path) = working_dir {
+            options = options.working_dir(path);
+        }
+        if let Some(seconds) = timeout {
+            options = options.timeout(seconds);
+        }
+        if let Some(path) = trace {
+            options = options.trace(path);
+        }
+        let Some(encoding) = ValueEncoding::from_name(value_encoding) else {
+            return Err(PyValueError::new_err(format!(
+                "unknown value encoding {value_encoding:?}"
+            )));
+        };
+        options = options.value_encoding(encoding);
+        if query_capabilities {
+            options = options.query_capabilities();
+        }
+        Ok(Self(
+            Controller::with_options(environment, population, &command, &options).map_err(convert)?,
+        ))
+    }
+
+    fn get_environment(&self) -> String {
+        self.0.get_environment().to_string_lossy().into_owned()
+    }
+
+    fn get_population(&self) -> &str {
+        self.0.get_population()
+    }
+
+    fn get_command(&self) -> Vec<String> {
+        self.0.get_command().to_vec()
+    }
+
+    fn is_alive(&mut self) -> bool {
+        self.0.is_alive()
+    }
+
+    fn new_genotype(&mut self, genotype: &str) -> PyResult<()> {
+        self.0.new_genotype(genotype).map_err(convert)
+    }
+
+    /// Replace the genotype, optionally carrying over the controller's internal state.
+    #[pyo3(signature = (genotype, preserve_state = false))]
+    fn swap_genotype(&mut self, genotype: &str, preserve_state: bool) -> PyResult<()> {
+        let mode = if preserve_state {
+            SwapMode::PreserveState
+        } else {
+            SwapMode::Reinitialize
+        };
+        self.0.swap_genotype(genotype, mode).map_err(convert)
+    }
+
+    /// Returns None if the genotype is valid, otherwise a description of the problem.
+    fn validate_genome(&mut self, genotype: &str) -> PyResult<Option<String>> {
+        Ok(self.0.validate_genome(genotype).map_err(convert)?.err())
+    }
+
+    /// Names of the optional operations which the controller implements,
+    /// or None if the controller was not asked, see the "query_capabilities" argument.
+    fn capabilities(&self) -> Option<Vec<String>> {
+        let capabilities = self.0.capabilities()?;
+        let mut names: Vec<String> = capabilities
+            .iter()
+            .map(|capability| capability.name().into_owned())
+            .collect();
+        names.sort();
+        Some(names)
+    }
+
+    fn supports(&self, capability: &str) -> PyResult<bool> {
+        match Capability::from_name(capability) {
+            Some(capability) => Ok(self.0.supports(capability)),
+            None => Err(PyValueError::new_err(format!("unknown capability {capability:?}"))),
+        }
+    }
+
+    fn value_encoding(&self) -> &'static str {
+        self.0.value_encoding().name()
+    }
+
+    fn seed(&mut self, seed: u64) -> PyResult<()> {
+        self.0.seed(seed).map_err(convert)
+    }
+
+    fn reset(&mut self) -> PyResult<()> {
+        self.0.reset().map_err(convert)
+    }
+
+    fn advance(&mut self, dt: f64) -> PyResult<()> {
+        self.0.advance(dt).map_err(convert)
+    }
+
+    fn set_input(&mut self, gin: u64, value: &str) -> PyResult<()> {
+        self.0.set_input(gin, value).map_err(convert)
+    }
+
+    fn set_inputs(&mut self, inputs: HashMap<u64, String>) -> PyResult<()> {
+        let inputs: Vec<(u64, &str)> = inputs.iter().map(|(gin, value)| (*gin, value.as_str())).collect();
+        self.0.set_inputs(&inputs).map_err(convert)
+    }
+
+    fn set_input_f64(&mut self, gin: u64, value: f64) -> PyResult<()> {
+        self.0.set_input_f64(gin, value).map_err(convert)
+    }
+
+    fn set_binary(&mut self, gin: u64, value: &[u8]) -> PyResult<()> {
+        self.0.set_binary(gin, value).map_err(convert)
+    }
+
+    fn get_outputs(&mut self, gins: Vec<u64>) -> PyResult<HashMap<u64, String>> {
+        self.0.get_outputs(&gins).map_err(convert)
+    }
+
+    /// Returns the round trip time, in seconds.
+    fn ping(&mut self) -> PyResult<f64> {
+        Ok(self.0.ping().map_err(convert)?.as_secs_f64())
+    }
+
+    fn get_epigenome(&self) -> HashMap<String, String> {
+        self.0.get_epigenome().clone()
+    }
+
+    fn save(&mut self, path: &str) -> PyResult<()> {
+        self.0.save(path).map_err(convert)
+    }
+
+    fn load(&mut self, path: &str) -> PyResult<()> {
+        self.0.load(path).map_err(convert)
+    }
+
+    /// Re-establish the connection to a controller server, see [Controller::reconnect()].
+    fn reconnect(&mut self) -> PyResult<()> {
+        self.0.reconnect().map_err(convert)
+    }
+
+    fn quit(&mut self) -> PyResult<()> {
+        self.0.quit().map_err(convert)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.0)
+    }
+}
+
+/// Environment specification, see [EnvironmentSpec].
+#[pyclass(name = "EnvironmentSpec", frozen)]
+pub struct PyEnvironmentSpec(EnvironmentSpec);
+
+#[pymethods]
+impl PyEnvironmentSpec {
+    /// Read and validate an environment specification file.
+    #[new]
+    fn new(path: &str) -> PyResult<Self> {
+        Ok(Self(EnvironmentSpec::new(path)?))
+    }
+
+    #[getter]
+    fn spec(&self) -> String {
+        self.0.spec.to_string_lossy().into_owned()
+    }
+
+    #[getter]
+    fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    #[getter]
+    fn path(&self) -> String {
+        self.0.path.to_string_lossy().into_owned()
+    }
+
+    #[getter]
+    fn description(&self) -> &str {
+        self.0.description.as_str()
+    }
+
+    /// Names of the populations.
+    #[getter]
+    fn populations(&self) -> Vec<String> {
+        self.0.populations.iter().map(|pop| pop.name.clone()).collect()
+    }
+
+    /// Names of the settings.
+    #[getter]
+    fn settings(&self) -> Vec<String> {
+        self.0.settings.iter().map(|item| item.name().to_string()).collect()
+    }
+
+    /// Serialize the whole specification to a JSON string.
+    fn to_json(&self) -> PyResult<String> {
+        Ok(serde_json::to_string(&self.0).map_err(Error::from)?)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("EnvironmentSpec({:?})", self.0.spec)
+    }
+}
+
+/// Plot the scores from statistics logs into an SVG or PNG image, see [crate::plot].
+#[cfg(feature = "plot")]
+#[pyfunction]
+#[pyo3(signature = (statistics, output, title = "Scores"))]
+fn plot_scores(statistics: Vec<String>, output: &str, title: &str) -> PyResult<()> {
+    let curves = statistics
+        .iter()
+        .map(crate::plot::ScoreCurve::load)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(crate::plot::plot_scores(&curves, output, title)?)
+}
+
+#[pymodule]
+fn npc_maker_rs(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyController>()?;
+    module.add_class::<PyEnvironmentSpec>()?;
+    #[cfg(feature = "plot")]
+    module.add_function(wrap_pyfunction!(plot_scores, module)?)?;
+    Ok(())
+}
+
+// The extension module does not link to the python library, so it can not be tested on its own.
+#[cfg(all(test, not(feature = "python-extension")))]
+mod tests {
+    use super::*;
+    use pyo3::types::PyDict;
+
+    const SMOKE_TEST: &str = r#"
+spec = npc_maker_rs.EnvironmentSpec(root + "/../examples/cartpole/cartpole.env")
+assert spec.name == "Cartpole"
+assert spec.populations == ["cartpole"]
+script = """while read line; do case "$line" in
+    C) echo C:validate ;;
+    O*) echo 1:2.5 ;;
+    V*) echo V:too short ;;
+    Q) exit ;;
+esac; done"""
+ctrl = npc_maker_rs.Controller(root + "/Cargo.toml", "pop", ["/bin/sh", "-c", script],
+                               timeout=10.0, query_capabilities=True)
+assert ctrl.capabilities() == ["validate"]
+assert ctrl.supports("validate") and not ctrl.supports("save")
+assert ctrl.value_encoding() == "text"
+ctrl.new_genotype("[]")
+ctrl.set_input(1, "0.5")
+assert ctrl.get_outputs([1]) == {1: "2.5"}
+assert ctrl.validate_genome("[]") == "too short"
+ctrl.quit()
+try:
+    npc_maker_rs.Controller(root + "/Cargo.toml", "pop", ["true"], value_encoding="utf-16")
+except ValueError:
+    pass
+else:
+    raise AssertionError("expected a ValueError")
+"#;
+
+    #[test]
+    fn smoke_test() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let globals = PyDict::new_bound(py);
+            globals
+                .set_item("npc_maker_rs", pyo3::wrap_pymodule!(npc_maker_rs)(py))
+                .unwrap();
+            globals.set_item("root", env!("CARGO_MANIFEST_DIR")).unwrap();
+            py.run_bound(SMOKE_TEST, Some(&globals), None).unwrap();
+        });
+    }
+}
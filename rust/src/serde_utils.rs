@@ -2,16 +2,6 @@ use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashSet;
 use std::sync::{Arc, Mutex};
 
-/// Error type for manipulating JSON files.
-#[derive(thiserror::Error, Debug)]
-pub enum JsonIoError {
-    #[error("message")]
-    Json(#[from] serde_json::Error),
-
-    #[error("message")]
-    Io(#[from] std::io::Error),
-}
-
 /// Custom default value for serde.  
 /// Usage: `#[serde(default="default_one")]`  
 pub fn default_one() -> f64 {
@@ -20,23 +10,84 @@ pub fn default_one() -> f64 {
 
 /// Converts between common symbolic representations of magic numbers and their
 /// actual f64 values.
+///
+/// Deserializing accepts the symbols "inf", "nan", "pi", "tau", and "e", and
+/// simple expressions which multiply and divide them, for example "-pi/2" or
+/// "2*tau". Serializing uses the simplest symbolic form which exactly equals
+/// the value, and otherwise falls back to a plain number.
 pub mod f64_symbols {
     use super::*;
 
+    /// Expressions to try when serializing, in order of preference.
+    const SYMBOLS: &[&str] = &[
+        "pi", "tau", "e", "pi/2", "pi/3", "pi/4", "pi/6", "pi/8", "pi/12", "2*pi/3", "3*pi/4", "3*pi/2",
+    ];
+
+    /// Evaluate a symbol or simple expression.
+    ///
+    /// Expressions are products and quotients of numbers and symbols, evaluated
+    /// from left to right, with an optional leading minus sign.
+    pub fn parse(text: &str) -> Result<f64, String> {
+        let text = text.trim();
+        let (sign, expression) = match text.strip_prefix('-') {
+            Some(rest) => (-1.0, rest),
+            None => (1.0, text),
+        };
+        let mut value = 1.0;
+        let mut operator = '*';
+        let mut rest = expression;
+        loop {
+            let end = rest.find(['*', '/']).unwrap_or(rest.len());
+            let term = match rest[..end].trim() {
+                "inf" => f64::INFINITY,
+                "nan" => f64::NAN,
+                "pi" => std::f64::consts::PI,
+                "tau" => std::f64::consts::TAU,
+                "e" => std::f64::consts::E,
+                number => number
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|_| !number.is_empty() && !number.starts_with(['+', '-']))
+                    .ok_or_else(|| format!("invalid number \"{text}\""))?,
+            };
+            if operator == '*' {
+                value *= term;
+            } else {
+                value /= term;
+            }
+            let Some(next) = rest[end..].chars().next() else { break };
+            operator = next;
+            rest = &rest[end + 1..];
+        }
+        Ok(sign * value)
+    }
+
+    /// Find the symbolic representation of a value, if it has one.
+    pub fn symbol(value: f64) -> Option<String> {
+        if value.is_nan() {
+            return Some("nan".to_string());
+        }
+        if value.is_infinite() {
+            return Some(if value > 0.0 { "inf" } else { "-inf" }.to_string());
+        }
+        SYMBOLS.iter().find_map(|&symbol| {
+            if parse(symbol) == Ok(value) {
+                Some(symbol.to_string())
+            } else if parse(symbol) == Ok(-value) {
+                Some(format!("-{symbol}"))
+            } else {
+                None
+            }
+        })
+    }
+
     pub fn serialize<S>(value: &f64, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        if *value == f64::INFINITY {
-            serializer.serialize_str("inf")
-        } else if *value == f64::NEG_INFINITY {
-            serializer.serialize_str("-inf")
-        } else if *value == std::f64::consts::PI {
-            serializer.serialize_str("pi")
-        } else if *value == 2.0 * std::f64::consts::PI {
-            serializer.serialize_str("tau")
-        } else {
-            serializer.serialize_f64(*value)
+        match symbol(*value) {
+            Some(symbol) => serializer.serialize_str(&symbol),
+            None => serializer.serialize_f64(*value),
         }
     }
 
@@ -45,31 +96,17 @@ pub mod f64_symbols {
         D: Deserializer<'de>,
     {
         #[derive(Deserialize)]
+        #[serde(untagged)]
         enum Number {
-            #[serde(rename = "-inf")]
-            NegInf,
-
-            #[serde(rename = "inf")]
-            PosInf,
-
-            #[serde(rename = "pi")]
-            Pi,
-
-            #[serde(rename = "tau")]
-            Tau,
-
-            #[serde(untagged)]
             Finite(f64),
+            Symbol(String),
         }
 
         let num = Number::deserialize(deserializer)?;
-        Ok(match num {
-            Number::PosInf => f64::INFINITY,
-            Number::NegInf => f64::NEG_INFINITY,
-            Number::Pi => std::f64::consts::PI,
-            Number::Tau => 2.0 * std::f64::consts::PI,
-            Number::Finite(value) => value,
-        })
+        match num {
+            Number::Finite(value) => Ok(value),
+            Number::Symbol(text) => parse(&text).map_err(serde::de::Error::custom),
+        }
     }
 }
 
@@ -89,9 +126,7 @@ where
     D: serde::Deserializer<'de>,
 {
     let value = f64::deserialize(deserializer)?;
-    if value < 0.0 {
-        Err(serde::de::Error::custom("value not in range [0, 1]"))
-    } else if value > 1.0 {
+    if !(0.0..=1.0).contains(&value) {
         Err(serde::de::Error::custom("value not in range [0, 1]"))
     } else {
         Ok(value)
@@ -235,6 +270,36 @@ mod tests {
         }
     }
 
+    #[test]
+    fn f64_expressions() {
+        use std::f64::consts::{E, PI, TAU};
+        assert_eq!(f64_symbols::parse("pi/2"), Ok(PI / 2.0));
+        assert_eq!(f64_symbols::parse(" -pi "), Ok(-PI));
+        assert_eq!(f64_symbols::parse("2*tau"), Ok(2.0 * TAU));
+        assert_eq!(f64_symbols::parse("3 * pi / 4"), Ok(3.0 * PI / 4.0));
+        assert_eq!(f64_symbols::parse("e"), Ok(E));
+        assert_eq!(f64_symbols::parse("1.5e3"), Ok(1500.0));
+        assert!(f64_symbols::parse("nan").unwrap().is_nan());
+        assert!(f64_symbols::parse("pi/").is_err());
+        assert!(f64_symbols::parse("--pi").is_err());
+        assert!(f64_symbols::parse("pie").is_err());
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        #[serde(transparent)]
+        struct Container(#[serde(with = "f64_symbols")] f64);
+        for (value, json_str) in [
+            (PI / 2.0, r#""pi/2""#),
+            (-PI, r#""-pi""#),
+            (2.0 * TAU, "12.566370614359172"),
+            (0.25, "0.25"),
+            (f64::NAN, r#""nan""#),
+        ] {
+            assert_eq!(serde_json::to_string(&Container(value)).unwrap(), json_str);
+            let roundtrip: Container = serde_json::from_str(json_str).unwrap();
+            assert!(roundtrip.0 == value || value.is_nan() && roundtrip.0.is_nan());
+        }
+    }
+
     #[test]
     fn fraction() {
         #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -255,7 +320,7 @@ mod tests {
             "0.9999999999999999", // 1.0_f64.next_down()
             "0.99999999999999999999999999999999999",
         ] {
-            let _valid_value: Container = dbg!(serde_json::from_str(&dbg!(valid_str))).unwrap();
+            let _valid_value: Container = dbg!(serde_json::from_str(dbg!(valid_str))).unwrap();
         }
         for invalid_str in [
             "-0.1",
@@ -266,7 +331,7 @@ mod tests {
             "1.0000000000000002",      // 0.0_f64::next_up()
         ] {
             let invalid_error: Result<Container, _> =
-                dbg!(serde_json::from_str(&dbg!(invalid_str)));
+                dbg!(serde_json::from_str(dbg!(invalid_str)));
             if let Err(msg) = &invalid_error {
                 eprintln!("{msg}"); // Check error message formatting.
                 eprintln!("{msg:?}"); // Check error message formatting.
@@ -294,7 +359,7 @@ mod tests {
         assert_eq!(foo_val.c, "test123");
         assert_eq!(
             foo_val.a.as_ptr(),
-            get_static_str(&("foobar".to_string())).as_ptr()
+            get_static_str("foobar").as_ptr()
         );
     }
 
@@ -0,0 +1,116 @@
+//! Units of measurement for interfaces, see [InterfaceSpec::unit](crate::env_spec::InterfaceSpec::unit).
+//!
+//! Controllers should check that the environment sends values in the units
+//! that they expect, and convert between compatible units where necessary.
+
+use std::f64::consts::PI;
+
+/// Physical quantity which a unit measures. Values can only be converted
+/// between units of the same dimension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dimension {
+    Length,
+    Angle,
+    Time,
+    /// Dimensionless fraction.
+    Ratio,
+}
+
+/// Unit of measurement.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Unit {
+    /// Canonical name of this unit.
+    pub name: &'static str,
+
+    pub dimension: Dimension,
+
+    /// Size of this unit, in the base unit of its dimension.
+    pub scale: f64,
+
+    /// Range of valid values, inclusive, if the unit is bounded.
+    pub range: Option<(f64, f64)>,
+}
+
+const fn unit(name: &'static str, dimension: Dimension, scale: f64) -> Unit {
+    Unit {
+        name,
+        dimension,
+        scale,
+        range: None,
+    }
+}
+
+/// All known units, with their aliases.
+const UNITS: &[(&[&str], Unit)] = &[
+    (&["meters", "m"], unit("meters", Dimension::Length, 1.0)),
+    (&["centimeters", "cm"], unit("centimeters", Dimension::Length, 0.01)),
+    (&["millimeters", "mm"], unit("millimeters", Dimension::Length, 0.001)),
+    (&["kilometers", "km"], unit("kilometers", Dimension::Length, 1000.0)),
+    (&["radians", "rad"], unit("radians", Dimension::Angle, 1.0)),
+    (&["degrees", "deg"], unit("degrees", Dimension::Angle, PI / 180.0)),
+    (&["seconds", "s"], unit("seconds", Dimension::Time, 1.0)),
+    (&["milliseconds", "ms"], unit("milliseconds", Dimension::Time, 0.001)),
+    (
+        &["normalized"],
+        Unit {
+            name: "normalized",
+            dimension: Dimension::Ratio,
+            scale: 1.0,
+            range: Some((0.0, 1.0)),
+        },
+    ),
+    (&["percent", "%"], unit("percent", Dimension::Ratio, 0.01)),
+];
+
+impl Unit {
+    /// Look up a unit by its name or abbreviation.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let name = name.trim();
+        UNITS
+            .iter()
+            .find(|(aliases, _)| aliases.contains(&name))
+            .map(|(_, unit)| *unit)
+            .ok_or_else(|| format!("unknown unit \"{name}\""))
+    }
+
+    /// Convert a value from this unit into another unit of the same dimension.
+    pub fn convert(&self, value: f64, into: &Unit) -> Result<f64, String> {
+        if self.dimension != into.dimension {
+            return Err(format!("can not convert {} into {}", self.name, into.name));
+        }
+        Ok(value * self.scale / into.scale)
+    }
+
+    /// Check that a value is within the valid range of this unit.
+    pub fn validate(&self, value: f64) -> Result<(), String> {
+        match self.range {
+            Some((min, max)) if !(min..=max).contains(&value) => {
+                Err(format!("{value} {} is out of range [{min}, {max}]", self.name))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Convert a value between two units, given by name.
+pub fn convert(value: f64, from: &str, into: &str) -> Result<f64, String> {
+    Unit::parse(from)?.convert(value, &Unit::parse(into)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        assert_eq!(Unit::parse("m"), Unit::parse("meters"));
+        assert!(Unit::parse("furlongs").is_err());
+        assert_eq!(convert(250.0, "cm", "meters"), Ok(2.5));
+        assert!((convert(180.0, "deg", "rad").unwrap() - PI).abs() < 1e-12);
+        assert!(convert(1.0, "meters", "seconds").is_err());
+        let normalized = Unit::parse("normalized").unwrap();
+        assert!(normalized.validate(0.5).is_ok());
+        assert!(normalized.validate(1.5).is_err());
+        assert!(Unit::parse("radians").unwrap().validate(100.0).is_ok());
+    }
+}